@@ -0,0 +1,182 @@
+//! Daily statistics appended to the `analytics` tab from the config, so that the coordinators get
+//! trend charts in Sheets.
+//!
+//! Every saved submission is counted per help kind in Redis together with the time it took to
+//! fill in the form, from the consent to the confirmation. Soon after midnight in the local time
+//! zone the row of the previous day is appended: the date, the started and the confirmed forms
+//! from [`crate::funnel`], the completion rate, the median time to fill in a form in minutes and
+//! the submissions of every help kind. Only one instance appends the row of a day.
+
+use std::collections::HashMap;
+
+use google_sheets4::api::ValueRange;
+
+use crate::{
+    config::{self, Config},
+    funnel, redis_connection,
+    shutdown::Shutdown,
+    state::{Contact, HelpKind},
+    AppState,
+};
+
+/// How long the counts of a day are kept; the row is written the next day, or later after a
+/// downtime.
+const DAY_TTL_SECS: usize = 8 * 24 * 60 * 60;
+
+fn submissions_key(day: chrono::NaiveDate) -> String {
+    redis_connection::key(&format!("analytics:{}", day.format("%Y-%m-%d")))
+}
+
+fn fill_times_key(day: chrono::NaiveDate) -> String {
+    redis_connection::key(&format!("analytics:{}:fill_secs", day.format("%Y-%m-%d")))
+}
+
+fn written_key(day: chrono::NaiveDate) -> String {
+    redis_connection::key(&format!("analytics:{}:written", day.format("%Y-%m-%d")))
+}
+
+/// Headers of the tab, as written by [`crate::sheets::provisioning`].
+pub fn headers(config: &Config) -> Vec<String> {
+    [
+        "Дата",
+        "Почали анкету",
+        "Підтвердили",
+        "Завершеність, %",
+        "Медіана заповнення, хв",
+    ]
+    .into_iter()
+    .map(str::to_owned)
+    .chain(
+        config
+            .help_kinds
+            .iter()
+            .map(|definition| definition.title().to_owned()),
+    )
+    .collect()
+}
+
+/// Counts the saved submission; a failure is only logged.
+pub async fn record_submission(app_state: &AppState, help_kind: &HelpKind, contact: &Contact) {
+    let day = config::local_now().naive_local().date();
+    let mut pipe = redis::pipe();
+    pipe.hincr(submissions_key(day), help_kind.as_str(), 1)
+        .ignore()
+        .expire(submissions_key(day), DAY_TTL_SECS)
+        .ignore();
+    if let Some(consented_at) = contact.consented_at {
+        let fill_secs = (chrono::Utc::now() - consented_at).num_seconds().max(0);
+        pipe.rpush(fill_times_key(day), fill_secs)
+            .ignore()
+            .expire(fill_times_key(day), DAY_TTL_SECS)
+            .ignore();
+    }
+    let recorded: redis::RedisResult<()> = pipe.query_async(&mut app_state.redis.clone()).await;
+    if let Err(err) = recorded {
+        tracing::warn!(
+            "Failed to count the submission for the analytics: {:?}",
+            err
+        );
+    }
+}
+
+/// Appends the row of the previous day once it is over.
+pub async fn run(app_state: std::sync::Arc<AppState>, mut shutdown: Shutdown) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(600));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.requested() => return,
+        }
+        if let Err(err) = append_previous_day(&app_state).await {
+            tracing::warn!("Failed to append the daily statistics: {:?}", err);
+        }
+    }
+}
+
+async fn append_previous_day(app_state: &AppState) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let day = config::local_now().naive_local().date() - chrono::Duration::days(1);
+    let config = app_state.config();
+    let destination = match &config.analytics {
+        Some(destination) if !app_state.skips_external_writes() => destination,
+        _ => return Ok(()),
+    };
+    let mut redis = app_state.redis.clone();
+    // Claimed before appending, so that the instances behind a load balancer append it once.
+    let claimed: bool = redis::cmd("SET")
+        .arg(written_key(day))
+        .arg(1)
+        .arg("NX")
+        .arg("EX")
+        .arg(DAY_TTL_SECS)
+        .query_async::<_, Option<String>>(&mut redis)
+        .await?
+        .is_some();
+    if !claimed {
+        return Ok(());
+    }
+
+    let funnel_counts = funnel::day_counts(&mut redis, day).await?;
+    let submissions: HashMap<String, u64> = redis.hgetall(submissions_key(day)).await?;
+    let mut fill_secs: Vec<i64> = redis.lrange(fill_times_key(day), 0, -1).await?;
+    fill_secs.sort_unstable();
+
+    let started = funnel_counts
+        .get("form_started")
+        .copied()
+        .unwrap_or_default();
+    let confirmed = funnel_counts.get("confirmed").copied().unwrap_or_default();
+    let completion = (confirmed * 100)
+        .checked_div(started)
+        .map(|completion| completion.to_string())
+        .unwrap_or_default();
+    let median_minutes = match fill_secs.len() {
+        0 => String::new(),
+        len if len % 2 == 1 => format!("{:.1}", fill_secs[len / 2] as f64 / 60.0),
+        len => format!(
+            "{:.1}",
+            (fill_secs[len / 2 - 1] + fill_secs[len / 2]) as f64 / 120.0
+        ),
+    };
+    let row: Vec<String> = [
+        day.format("%Y-%m-%d").to_string(),
+        started.to_string(),
+        confirmed.to_string(),
+        completion,
+        median_minutes,
+    ]
+    .into_iter()
+    .chain(config.help_kinds.iter().map(|definition| {
+        submissions
+            .get(definition.slug.as_str())
+            .copied()
+            .unwrap_or_default()
+            .to_string()
+    }))
+    .collect();
+
+    let result = app_state
+        .sheets_api
+        .spreadsheets()
+        .values_append(
+            ValueRange {
+                major_dimension: Some("ROWS".to_owned()),
+                range: None,
+                values: Some(vec![row]),
+            },
+            &destination.spreadsheet_id,
+            &destination.sheet,
+        )
+        .value_input_option("USER_ENTERED")
+        .insert_data_option("INSERT_ROWS")
+        .doit()
+        .await;
+    if let Err(err) = result {
+        // Released, so that the next attempt appends the row.
+        let _: () = redis.del(written_key(day)).await?;
+        return Err(err.into());
+    }
+    tracing::info!("Appended the statistics of {}", day);
+    Ok(())
+}
@@ -0,0 +1,353 @@
+//! Assignment of requests to volunteers.
+//!
+//! Requests posted to volunteers carry "take" / "decline" buttons. The first verified volunteer
+//! who takes the request gets it assigned: the assignee column of the sheet row is filled in and
+//! both the volunteer and the requester are notified.
+//!
+//! Coordinators can also assign a request with `/assign <reference id> <@username>` to a verified
+//! volunteer who has registered with the bot.
+
+use google_sheets4::api::ValueRange;
+use teloxide::{
+    payloads::{AnswerCallbackQuerySetters, SendMessageSetters},
+    prelude2::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup, ParseMode, User},
+};
+
+use crate::{
+    audit, html, lifecycle, matching,
+    outgoing_webhooks::{self, Event, Payload},
+    public_feed, redis_connection,
+    sheets::{self, find_submission},
+    sla, texts, volunteers, AppState, ThrottledBot,
+};
+
+const CALLBACK_PREFIX: &str = "assign:";
+
+fn assignment_key(reference_id: &str) -> String {
+    redis_connection::key(&format!("assignment:{reference_id}"))
+}
+
+pub fn assignment_keyboard(
+    texts: &texts::Texts,
+    language: texts::Language,
+    reference_id: &str,
+) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![vec![
+        InlineKeyboardButton::callback(
+            texts.get(language, "button_take").to_owned(),
+            format!("{CALLBACK_PREFIX}take:{reference_id}"),
+        ),
+        InlineKeyboardButton::callback(
+            texts.get(language, "button_decline").to_owned(),
+            format!("{CALLBACK_PREFIX}skip:{reference_id}"),
+        ),
+    ]])
+}
+
+/// Button taking the request, for the messages with several requests.
+pub fn take_button(
+    texts: &texts::Texts,
+    language: texts::Language,
+    reference_id: &str,
+) -> InlineKeyboardButton {
+    InlineKeyboardButton::callback(
+        texts.render(
+            language,
+            "button_take_request",
+            &[("reference_id", &reference_id)],
+        ),
+        format!("{CALLBACK_PREFIX}take:{reference_id}"),
+    )
+}
+
+/// Telegram user id of the volunteer the request is assigned to.
+pub async fn assignee(
+    mut redis: redis::aio::MultiplexedConnection,
+    reference_id: &str,
+) -> anyhow::Result<Option<i64>> {
+    use redis::AsyncCommands;
+
+    Ok(redis.get(assignment_key(reference_id)).await?)
+}
+
+/// Human-readable identification of a volunteer for the sheet and notifications.
+pub fn volunteer_display_name(user: &User) -> String {
+    match &user.username {
+        Some(username) => format!("{} (@{username}, id {})", user.full_name(), user.id),
+        None => format!("{} (id {})", user.full_name(), user.id),
+    }
+}
+
+/// Atomically assigns the request to the volunteer, who takes it or is assigned by the
+/// coordinator `assigned_by`.
+///
+/// Returns `false` if the request has already been assigned to somebody else, or its status does
+/// not allow the assignment anymore, see [`lifecycle`].
+#[tracing::instrument(skip(bot, app_state, volunteer, assigned_by), fields(volunteer_id = volunteer.id))]
+pub async fn assign(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    reference_id: &str,
+    volunteer: &User,
+    assigned_by: Option<&User>,
+) -> anyhow::Result<bool> {
+    use redis::AsyncCommands;
+
+    let indexed = match find_submission(app_state.redis.clone(), reference_id).await? {
+        Some(indexed) => indexed,
+        None => anyhow::bail!("unknown submission {}", reference_id),
+    };
+    if !lifecycle::current(app_state, reference_id)
+        .await?
+        .can_become(lifecycle::Status::Assigned)
+    {
+        return Ok(false);
+    }
+    let assigned: bool = app_state
+        .redis
+        .clone()
+        .set_nx(assignment_key(reference_id), volunteer.id)
+        .await?;
+    if !assigned {
+        return Ok(false);
+    }
+    sla::unwatch(app_state.redis.clone(), reference_id).await?;
+    matching::close_request(app_state.redis.clone(), reference_id).await?;
+
+    let assignee = volunteer_display_name(volunteer);
+    let assignee_column = app_state
+        .config()
+        .column(&indexed.submission.help_kind, "assignee");
+    if app_state.skips_external_writes() {
+        tracing::info!(
+            "Not writing the assignee of {} to the spreadsheet",
+            reference_id
+        );
+    } else if let Some(assignee_column) = assignee_column {
+        app_state
+            .sheets_api
+            .spreadsheets()
+            .values_update(
+                ValueRange {
+                    major_dimension: Some("ROWS".to_owned()),
+                    range: None,
+                    values: Some(vec![vec![format!("'{}", assignee)]]),
+                },
+                &indexed.submission.spreadsheet_id(),
+                &indexed.submission.cell_range(assignee_column)?,
+            )
+            .value_input_option("USER_ENTERED")
+            .doit()
+            .await?;
+    }
+    let actor = assigned_by.map_or_else(|| assignee.clone(), volunteer_display_name);
+    if let Err(invalid) =
+        lifecycle::transition(app_state, reference_id, lifecycle::Status::Assigned, &actor).await?
+    {
+        // The status changed since the check above.
+        tracing::warn!("Assigned {} anyway: {}", reference_id, invalid);
+    } else if let Err(err) =
+        sheets::status::write_status(app_state, &indexed.submission, lifecycle::Status::Assigned)
+            .await
+    {
+        tracing::warn!(
+            "Failed to write the status of {} to the spreadsheet: {:?}",
+            reference_id,
+            err
+        );
+    }
+    audit::record(
+        app_state,
+        &actor,
+        audit::Action::RequestAssigned,
+        reference_id,
+        if assigned_by.is_some() { &assignee } else { "" },
+    )
+    .await;
+    outgoing_webhooks::send(
+        app_state,
+        &Payload::new(Event::Assigned, &indexed.submission).with_assignee(&assignee),
+    );
+
+    if let Err(err) = public_feed::mark_taken(bot, app_state, reference_id).await {
+        tracing::warn!(
+            "Failed to update the public post of {}: {:?}",
+            reference_id,
+            err
+        );
+    }
+
+    let texts = app_state.texts();
+    let volunteer_language = texts::language_of(app_state.redis.clone(), volunteer.id).await;
+    bot.send_message(
+        volunteer.id,
+        texts.render(
+            volunteer_language,
+            "assignment_assigned_to_volunteer",
+            &[("reference_id", &html::code(reference_id))],
+        ),
+    )
+    .parse_mode(ParseMode::Html)
+    .await?;
+    let requester_language = texts::language_of(app_state.redis.clone(), indexed.chat_id).await;
+    bot.send_message(
+        indexed.chat_id,
+        texts.render(
+            requester_language,
+            "assignment_assigned_to_requester",
+            &[
+                ("reference_id", &html::code(reference_id)),
+                ("volunteer", &html::escape(&volunteer.full_name())),
+            ],
+        ),
+    )
+    .parse_mode(ParseMode::Html)
+    .await?;
+    Ok(true)
+}
+
+pub fn is_assignment_callback(query: CallbackQuery) -> bool {
+    query
+        .data
+        .as_deref()
+        .is_some_and(|data| data.starts_with(CALLBACK_PREFIX))
+}
+
+#[tracing::instrument(skip_all, fields(user_id = query.from.id, data = ?query.data))]
+pub async fn handle_callback(
+    bot: ThrottledBot,
+    query: CallbackQuery,
+    app_state: std::sync::Arc<AppState>,
+    language: texts::Language,
+) -> anyhow::Result<()> {
+    let data = query.data.as_deref().unwrap_or_default();
+    let (action, reference_id) = match data
+        .strip_prefix(CALLBACK_PREFIX)
+        .and_then(|data| data.split_once(':'))
+    {
+        Some(parsed) => parsed,
+        None => {
+            tracing::warn!("assignment: unexpected callback data {:?}", data);
+            bot.answer_callback_query(query.id).await?;
+            return Ok(());
+        }
+    };
+
+    let texts = app_state.texts();
+    let answer = match action {
+        "take" => {
+            volunteers::remember_username(app_state.redis.clone(), &query.from).await?;
+            if !volunteers::is_verified(app_state.redis.clone(), query.from.id).await? {
+                texts.get(language, "assignment_not_verified")
+            } else if assign(&bot, &app_state, reference_id, &query.from, None).await? {
+                if let Some(message) = &query.message {
+                    bot.edit_message_text(
+                        message.chat.id,
+                        message.id,
+                        texts.render(
+                            language,
+                            "assignment_taken_by",
+                            &[
+                                ("text", &message.text().unwrap_or_default()),
+                                ("volunteer", &volunteer_display_name(&query.from)),
+                            ],
+                        ),
+                    )
+                    .await?;
+                }
+                texts.get(language, "assignment_taken")
+            } else {
+                texts.get(language, "assignment_already_taken")
+            }
+        }
+        "skip" => texts.get(language, "offer_declined"),
+        _ => {
+            tracing::warn!("assignment: unexpected callback action {:?}", action);
+            ""
+        }
+    };
+    bot.answer_callback_query(query.id).text(answer).await?;
+    Ok(())
+}
+
+/// Handles `/assign <reference id> <@username>` of a coordinator.
+pub async fn handle_assign_command(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    msg: &Message,
+    args: &str,
+    language: texts::Language,
+) -> anyhow::Result<()> {
+    let texts = app_state.texts();
+    let mut args = args.split_whitespace();
+    let (reference_id, username) = match (args.next(), args.next()) {
+        (Some(reference_id), Some(username)) => (reference_id, username),
+        _ => {
+            bot.send_message(msg.chat.id, texts.get(language, "assign_usage"))
+                .await?;
+            return Ok(());
+        }
+    };
+    if find_submission(app_state.redis.clone(), reference_id)
+        .await?
+        .is_none()
+    {
+        bot.send_message(
+            msg.chat.id,
+            texts.render(
+                language,
+                "assign_not_found",
+                &[("reference_id", &reference_id)],
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+    let volunteer = match volunteers::find_by_username(app_state.redis.clone(), username).await? {
+        Some(volunteer)
+            if volunteers::is_verified(app_state.redis.clone(), volunteer.id).await? =>
+        {
+            volunteer
+        }
+        _ => {
+            bot.send_message(
+                msg.chat.id,
+                texts.render(language, "assign_not_volunteer", &[("username", &username)]),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    let status = lifecycle::current(app_state, reference_id).await?;
+    if !status.can_become(lifecycle::Status::Assigned) {
+        bot.send_message(
+            msg.chat.id,
+            texts.render(
+                language,
+                "assign_wrong_status",
+                &[("reference_id", &reference_id), ("status", &status.label())],
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+    let text = if assign(bot, app_state, reference_id, &volunteer, msg.from()).await? {
+        texts.render(
+            language,
+            "assign_done",
+            &[
+                ("reference_id", &reference_id),
+                ("volunteer", &volunteer_display_name(&volunteer)),
+            ],
+        )
+    } else {
+        texts.render(
+            language,
+            "assign_already_assigned",
+            &[("reference_id", &reference_id)],
+        )
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
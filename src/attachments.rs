@@ -0,0 +1,103 @@
+//! Photos attached to submissions, e.g. of documents, damage or medical prescriptions.
+//!
+//! Photos sent at a question which accepts attachments are remembered by their Telegram file id.
+//! Once the submission is confirmed they are uploaded to the Google Drive folder from the config,
+//! and the links to them are written to the attachments column of the sheet. The files inherit the
+//! sharing settings of the folder, so only the people it is shared with can open them.
+
+use google_drive3::DriveHub;
+use teloxide::{net::Download, prelude2::*};
+
+use crate::ThrottledBot;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Attachment {
+    file_id: String,
+    file_unique_id: String,
+    mime_type: String,
+    /// Link to the copy uploaded to Google Drive.
+    #[serde(default)]
+    pub link: Option<String>,
+}
+
+impl Attachment {
+    /// The photo or the image sent as a file in the message.
+    pub fn from_message(msg: &Message) -> Option<Self> {
+        if let Some(photo) = msg.photo().and_then(|sizes| sizes.last()) {
+            return Some(Self {
+                file_id: photo.file_id.clone(),
+                file_unique_id: photo.file_unique_id.clone(),
+                mime_type: "image/jpeg".to_owned(),
+                link: None,
+            });
+        }
+        let document = msg.document()?;
+        let mime_type = document.mime_type.as_ref()?;
+        if mime_type.type_() != "image" {
+            return None;
+        }
+        Some(Self {
+            file_id: document.file_id.clone(),
+            file_unique_id: document.file_unique_id.clone(),
+            mime_type: mime_type.to_string(),
+            link: None,
+        })
+    }
+}
+
+/// Uploads the attachments which have not been uploaded yet to the folder.
+///
+/// An attachment which fails to upload is logged and left without a link, so that the submission
+/// itself is not lost.
+#[tracing::instrument(skip_all, fields(count = attachments.len()))]
+pub async fn upload_all(
+    bot: &ThrottledBot,
+    drive_api: &DriveHub,
+    folder_id: &str,
+    attachments: &mut [Attachment],
+) {
+    for attachment in attachments
+        .iter_mut()
+        .filter(|attachment| attachment.link.is_none())
+    {
+        match upload(bot, drive_api, folder_id, attachment).await {
+            Ok(link) => attachment.link = Some(link),
+            Err(err) => tracing::warn!(
+                "Failed to upload attachment {}: {:?}",
+                attachment.file_unique_id,
+                err
+            ),
+        }
+    }
+}
+
+async fn upload(
+    bot: &ThrottledBot,
+    drive_api: &DriveHub,
+    folder_id: &str,
+    attachment: &Attachment,
+) -> anyhow::Result<String> {
+    let file = bot.get_file(&attachment.file_id).await?;
+    let mut contents = Vec::new();
+    bot.download_file(&file.file_path, &mut contents).await?;
+
+    let mime_type: mime::Mime = attachment
+        .mime_type
+        .parse()
+        .map_err(|()| anyhow::anyhow!("Invalid MIME type {}", attachment.mime_type))?;
+    let extension = mime_type.1.as_str().to_owned();
+    let (_, uploaded) = drive_api
+        .files()
+        .create(google_drive3::api::File {
+            name: Some(format!("{}.{extension}", attachment.file_unique_id)),
+            parents: Some(vec![folder_id.to_owned()]),
+            ..Default::default()
+        })
+        .supports_all_drives(true)
+        .param("fields", "id,webViewLink")
+        .upload(std::io::Cursor::new(contents), mime_type)
+        .await?;
+    uploaded
+        .web_view_link
+        .ok_or_else(|| anyhow::anyhow!("Drive API did not return the link to the file"))
+}
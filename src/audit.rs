@@ -0,0 +1,102 @@
+//! Audit log of the significant actions.
+//!
+//! Every entry is logged with the `audit` target, and appended to the `audit_log` tab from the
+//! config when there is one: time, who performed the action, the action, what it was performed on
+//! and the details. Rows are only ever appended. A failure to record an entry is logged and does
+//! not fail the action itself.
+
+use google_sheets4::api::ValueRange;
+
+use crate::AppState;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Action {
+    SubmissionSaved,
+    VolunteerApproved,
+    VolunteerRejected,
+    RequestAssigned,
+    RequestClosed,
+    DataDeleted,
+    ConfigReloaded,
+    SpreadsheetContinued,
+    IntakePaused,
+    IntakeResumed,
+    ChatAttached,
+    ChatDetached,
+    StatusChanged,
+}
+
+impl Action {
+    fn name(self) -> &'static str {
+        match self {
+            Self::SubmissionSaved => "submission_saved",
+            Self::VolunteerApproved => "volunteer_approved",
+            Self::VolunteerRejected => "volunteer_rejected",
+            Self::RequestAssigned => "request_assigned",
+            Self::RequestClosed => "request_closed",
+            Self::DataDeleted => "data_deleted",
+            Self::ConfigReloaded => "config_reloaded",
+            Self::SpreadsheetContinued => "spreadsheet_continued",
+            Self::IntakePaused => "intake_paused",
+            Self::IntakeResumed => "intake_resumed",
+            Self::ChatAttached => "chat_attached",
+            Self::ChatDetached => "chat_detached",
+            Self::StatusChanged => "status_changed",
+        }
+    }
+}
+
+/// Records that `actor` (a user id with the name for coordinators and volunteers) performed the
+/// action on `subject`, e.g. a reference id or a user id.
+pub async fn record(
+    app_state: &AppState,
+    actor: &str,
+    action: Action,
+    subject: &str,
+    details: &str,
+) {
+    let now = chrono::Utc::now();
+    tracing::info!(
+        target: "audit",
+        actor,
+        action = action.name(),
+        subject,
+        details,
+        "audit entry"
+    );
+    let destination = match &app_state.config().audit_log {
+        Some(destination) if !app_state.skips_external_writes() => destination.clone(),
+        _ => return,
+    };
+    let row = vec![
+        now.to_rfc3339(),
+        format!("'{actor}"),
+        action.name().to_owned(),
+        format!("'{subject}"),
+        format!("'{details}"),
+    ];
+    let result = app_state
+        .sheets_api
+        .spreadsheets()
+        .values_append(
+            ValueRange {
+                major_dimension: Some("ROWS".to_owned()),
+                range: None,
+                values: Some(vec![row]),
+            },
+            &destination.spreadsheet_id,
+            &destination.sheet,
+        )
+        .value_input_option("USER_ENTERED")
+        .insert_data_option("INSERT_ROWS")
+        .doit()
+        .await;
+    if let Err(err) = result {
+        tracing::error!(
+            "Failed to record {} of {} in the audit log: {:?}",
+            action.name(),
+            subject,
+            err
+        );
+    }
+}
@@ -0,0 +1,72 @@
+//! Handling the updates of one chat strictly one after another.
+//!
+//! The dispatcher handles updates concurrently, so two fast taps on keyboard buttons could run two
+//! handlers of the same chat at once, and the second one would overwrite the dialogue state
+//! written by the first one. The first step of the handler tree takes the lock of the chat, which
+//! stays in the dependencies of the update, and so is held, until the update is handled.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use teloxide::types::{Update, UpdateKind};
+use tokio::sync::OwnedMutexGuard;
+
+#[derive(Default)]
+pub struct ChatLocks(Mutex<HashMap<i64, Arc<tokio::sync::Mutex<()>>>>);
+
+/// The lock of the chat of the update being handled; `None` for updates without a chat.
+#[derive(Clone)]
+pub struct ChatLock {
+    _held: Option<Arc<Held>>,
+}
+
+struct Held {
+    chat_id: i64,
+    locks: Arc<ChatLocks>,
+    _guard: OwnedMutexGuard<()>,
+}
+
+impl Drop for Held {
+    fn drop(&mut self) {
+        let mut locks = self.locks.0.lock().unwrap();
+        // Nobody else waits for the chat when only the map and this guard refer to its mutex.
+        if locks
+            .get(&self.chat_id)
+            .is_some_and(|mutex| Arc::strong_count(mutex) <= 2)
+        {
+            locks.remove(&self.chat_id);
+        }
+    }
+}
+
+pub fn chat_id(update: &Update) -> Option<i64> {
+    match &update.kind {
+        UpdateKind::Message(message) | UpdateKind::EditedMessage(message) => Some(message.chat.id),
+        UpdateKind::CallbackQuery(query) => Some(
+            query
+                .message
+                .as_ref()
+                .map_or(query.from.id, |message| message.chat.id),
+        ),
+        _ => None,
+    }
+}
+
+/// Waits until the updates of the same chat received earlier are handled.
+pub async fn lock(update: Update, locks: Arc<ChatLocks>) -> ChatLock {
+    let chat_id = match chat_id(&update) {
+        Some(chat_id) => chat_id,
+        None => return ChatLock { _held: None },
+    };
+    let mutex = locks.0.lock().unwrap().entry(chat_id).or_default().clone();
+    let guard = mutex.lock_owned().await;
+    ChatLock {
+        _held: Some(Arc::new(Held {
+            chat_id,
+            locks,
+            _guard: guard,
+        })),
+    }
+}
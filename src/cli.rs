@@ -0,0 +1,163 @@
+//! The command line: running the bot and the maintenance commands, all configured from the same
+//! environment, see [`Environment::from_env`].
+
+use std::io::Write;
+
+use clap::{Parser, Subcommand};
+use teloxide::dispatching2::dialogue::Storage;
+
+use crate::{
+    redis_connection,
+    sheets::{
+        replay, tracked_submissions, IndexedSubmission, TrackedSubmission, SUBMISSION_CHATS_KEY,
+    },
+    telemetry, AppState, Environment,
+};
+
+#[derive(Debug, Parser)]
+#[clap(version, about)]
+pub struct Cli {
+    /// Keeps the dialogue states in memory and only logs the submissions, for local development.
+    #[clap(long, global = true)]
+    dev: bool,
+    /// Writes nothing to Google and sends the notifications to the sandbox chat, to rehearse with
+    /// the production bot.
+    #[clap(long, global = true)]
+    dry_run: bool,
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Runs the bot until it is asked to shut down; the default.
+    Run,
+    /// Appends the rows which failed to be written to the spreadsheets again, oldest first.
+    ReplayQueue,
+    /// Prints the tracked submissions with the chats of their requesters and the cells of their
+    /// rows as JSON lines.
+    Export,
+    /// Rewrites the stored dialogue states in the current version, format and encryption key.
+    ///
+    /// Stop the bot first, or the states of the chats it handles meanwhile may be overwritten.
+    Migrate,
+}
+
+impl Cli {
+    pub async fn execute(self) -> anyhow::Result<()> {
+        telemetry::init();
+        let result = match Environment::from_env(self.dev, self.dry_run).await {
+            Ok(environment) => match self.command.unwrap_or(Command::Run) {
+                Command::Run => crate::run(environment).await,
+                Command::ReplayQueue => replay_queue(environment).await,
+                Command::Export => export(environment).await,
+                Command::Migrate => migrate(environment).await,
+            },
+            Err(err) => Err(err),
+        };
+        telemetry::shutdown();
+        result
+    }
+}
+
+async fn replay_queue(environment: Environment) -> anyhow::Result<()> {
+    let replayed = replay::replay(&environment.app_state).await?;
+    tracing::info!(
+        "Appended the rows of {:?}, skipped {} saved meanwhile",
+        replayed.appended,
+        replayed.skipped
+    );
+    Ok(())
+}
+
+/// A tracked submission as exported.
+#[derive(serde::Serialize)]
+struct ExportedSubmission {
+    #[serde(flatten)]
+    indexed: IndexedSubmission,
+    /// `None` if the row is not in a spreadsheet or could not be read.
+    row: Option<Vec<String>>,
+}
+
+async fn export(environment: Environment) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let app_state = &environment.app_state;
+    let mut redis = app_state.redis.clone();
+    let mut chat_ids: Vec<i64> = redis
+        .smembers(redis_connection::key(SUBMISSION_CHATS_KEY))
+        .await?;
+    chat_ids.sort_unstable();
+    for chat_id in chat_ids {
+        for submission in tracked_submissions(redis.clone(), chat_id).await? {
+            let row = read_row(app_state, &submission).await;
+            let stdout = std::io::stdout();
+            let mut stdout = stdout.lock();
+            serde_json::to_writer(
+                &mut stdout,
+                &ExportedSubmission {
+                    indexed: IndexedSubmission {
+                        chat_id,
+                        submission,
+                    },
+                    row,
+                },
+            )?;
+            writeln!(stdout)?;
+        }
+    }
+    Ok(())
+}
+
+/// The cells of the row of the submission as the spreadsheet has them now, e.g. with the status set
+/// by the volunteers.
+async fn read_row(app_state: &AppState, submission: &TrackedSubmission) -> Option<Vec<String>> {
+    // The dev mode has no credentials to read the spreadsheets with.
+    if app_state.dev_mode {
+        return None;
+    }
+    let spreadsheet_id = submission.spreadsheet_id.as_ref()?;
+    match app_state
+        .sheets_api
+        .spreadsheets()
+        .values_get(spreadsheet_id, &submission.range)
+        .doit()
+        .await
+    {
+        Ok((_, value_range)) => value_range.values.and_then(|rows| rows.into_iter().next()),
+        Err(err) => {
+            tracing::warn!(
+                "Failed to read the row of {}: {:?}",
+                submission.reference_id,
+                err
+            );
+            None
+        }
+    }
+}
+
+/// Reading a state migrates it, see [`crate::state::version`], so it only has to be written back.
+async fn migrate(environment: Environment) -> anyhow::Result<()> {
+    let storage = environment.storage;
+    let (mut migrated, mut unreadable) = (0, 0);
+    for chat_id in storage.chat_ids().await? {
+        match storage.clone().get_dialogue(chat_id).await {
+            Ok(Some(state)) => {
+                storage.clone().update_dialogue(chat_id, state).await?;
+                migrated += 1;
+            }
+            Ok(None) => {}
+            Err(err) if err.is_unreadable() => {
+                tracing::warn!("Failed to read the state of chat {}: {}", chat_id, err);
+                unreadable += 1;
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+    tracing::info!(
+        "Migrated {} dialogue states, {} could not be read",
+        migrated,
+        unreadable
+    );
+    Ok(())
+}
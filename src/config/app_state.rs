@@ -0,0 +1,103 @@
+use google_sheets4::Sheets;
+use teloxide::{prelude2::*, types::Message};
+
+use super::Config;
+use crate::{sheets::SubmissionSink, texts, ThrottledBot};
+
+/// What the handlers share: the reloadable config and texts, the Google APIs and Redis.
+pub struct AppState {
+    /// Swapped as a whole on `/reload_config` or SIGHUP.
+    pub config: arc_swap::ArcSwap<Config>,
+    pub texts: arc_swap::ArcSwap<texts::Texts>,
+    pub sheets_api: Sheets,
+    pub drive_api: google_drive3::DriveHub,
+    /// Where the confirmed submissions are appended; only the log in the dev mode and the dry run.
+    pub submission_sink: std::sync::Arc<dyn SubmissionSink>,
+    pub redis: redis::aio::MultiplexedConnection,
+    /// Encrypts the personal data kept in Redis, see [`crate::state::encryption`].
+    pub cipher: Option<crate::state::encryption::Cipher>,
+    /// Submissions are logged instead of saved to the spreadsheets, see [`dev_mode`].
+    pub dev_mode: bool,
+    /// Writes to Google are logged instead of made, see [`dry_run`].
+    pub dry_run: bool,
+    /// Group where coordinators receive submissions and reply to requesters; the sandbox chat in
+    /// the dry run.
+    pub admin_chat_id: Option<i64>,
+    /// Chat where new requests are posted when no volunteer has the matching tags; the sandbox
+    /// chat in the dry run.
+    pub volunteers_chat_id: Option<i64>,
+    /// The deliveries running in the background, awaited on shutdown.
+    pub(crate) tasks: crate::shutdown::Tasks,
+}
+
+impl AppState {
+    pub fn config(&self) -> std::sync::Arc<Config> {
+        self.config.load_full()
+    }
+
+    pub fn texts(&self) -> std::sync::Arc<texts::Texts> {
+        self.texts.load_full()
+    }
+
+    /// Re-reads the config and the texts; nothing is swapped if either of them fails to load.
+    pub fn reload(&self) -> anyhow::Result<()> {
+        let config = Config::from_env()?;
+        let texts = texts::Texts::from_env()?;
+        self.config.store(std::sync::Arc::new(config));
+        self.texts.store(std::sync::Arc::new(texts));
+        tracing::info!("Reloaded config and texts");
+        Ok(())
+    }
+
+    /// Whether the spreadsheets and Drive are left alone, in the dev mode or the dry run.
+    pub fn skips_external_writes(&self) -> bool {
+        self.dev_mode || self.dry_run
+    }
+
+    /// The chat to send a notification for the coordinator or volunteer in `chat_id` to: the chat
+    /// itself, or the sandbox chat in the dry run.
+    pub fn notified_chat(&self, chat_id: i64) -> Option<i64> {
+        if self.dry_run {
+            self.admin_chat_id
+        } else {
+            Some(chat_id)
+        }
+    }
+
+    /// Whether the user is one of the configured admins or a member of the coordinators group.
+    pub async fn is_coordinator(&self, bot: &ThrottledBot, user_id: i64) -> anyhow::Result<bool> {
+        if self.config().admins.contains(&user_id) {
+            return Ok(true);
+        }
+        match self.admin_chat_id {
+            Some(admin_chat_id) => Ok(bot
+                .get_chat_member(admin_chat_id, user_id)
+                .await?
+                .is_present()),
+            None => Ok(false),
+        }
+    }
+
+    /// Whether the message comes from the coordinators group or from one of the configured admins.
+    pub fn is_admin_message(&self, msg: &Message) -> bool {
+        Some(msg.chat.id) == self.admin_chat_id
+            || msg
+                .from()
+                .is_some_and(|user| self.config().admins.contains(&user.id))
+    }
+}
+
+/// Whether to run for local development, with the `--dev` flag or `COLLECT_VOLUNTEERS_BOT_DEV=1`:
+/// the dialogue states are kept in memory and submissions are logged instead of saved, so no
+/// Google credentials are needed.
+pub fn dev_mode(flag: bool) -> bool {
+    flag || std::env::var("COLLECT_VOLUNTEERS_BOT_DEV").is_ok_and(|value| value == "1")
+}
+
+/// Whether to rehearse with the production bot and storage, with the `--dry-run` flag or
+/// `COLLECT_VOLUNTEERS_BOT_DRY_RUN=1`: submissions are logged instead of saved, nothing else is
+/// written to the spreadsheets or Drive either, and the notifications for coordinators and
+/// volunteers go to the chat in `COLLECT_VOLUNTEERS_BOT_SANDBOX_CHAT_ID`.
+pub fn dry_run(flag: bool) -> bool {
+    flag || std::env::var("COLLECT_VOLUNTEERS_BOT_DRY_RUN").is_ok_and(|value| value == "1")
+}
@@ -0,0 +1,587 @@
+//! Deployment configuration loaded from a JSON file.
+//!
+//! The path is taken from `COLLECT_VOLUNTEERS_BOT_CONFIG`; without it the built-in defaults are
+//! used. [`AppState`] keeps the loaded config for the handlers and swaps it on reload.
+
+use crate::{
+    form::Form,
+    sheets,
+    state::HelpKind,
+    texts::{Language, Texts},
+};
+
+mod app_state;
+
+static TIMEZONE: once_cell::sync::OnceCell<chrono_tz::Tz> = once_cell::sync::OnceCell::new();
+
+/// Reads the timezone of the volunteers, `COLLECT_VOLUNTEERS_BOT_TIMEZONE` as in the tz database,
+/// e.g. `Europe/Warsaw`; Europe/Kyiv by default.
+pub fn init_timezone() -> anyhow::Result<()> {
+    let timezone = match std::env::var("COLLECT_VOLUNTEERS_BOT_TIMEZONE") {
+        Ok(name) => name
+            .parse()
+            .map_err(|err| anyhow::anyhow!("Invalid timezone {:?}: {}", name, err))?,
+        Err(_) => chrono_tz::Europe::Kyiv,
+    };
+    let _ = TIMEZONE.set(timezone);
+    Ok(())
+}
+
+/// The current time in the timezone of the volunteers, see [`init_timezone`].
+pub fn local_now() -> chrono::DateTime<chrono_tz::Tz> {
+    let timezone = TIMEZONE.get().copied().unwrap_or(chrono_tz::Europe::Kyiv);
+    chrono::Utc::now().with_timezone(&timezone)
+}
+
+pub use app_state::{dev_mode, dry_run, AppState};
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Config {
+    /// Telegram user ids allowed to run admin commands outside of the coordinators group.
+    #[serde(default)]
+    pub admins: Vec<i64>,
+    /// Kinds of help offered on the keyboards, replacing the built-in ones.
+    #[serde(default = "builtin_help_kinds")]
+    pub help_kinds: Vec<HelpKindDefinition>,
+    /// Spreadsheets/tabs submissions are routed to by (help kind, region).
+    #[serde(default)]
+    pub destinations: Vec<DestinationRoute>,
+    /// Questions shared by all help kinds, replacing the built-in flow.
+    #[serde(default)]
+    pub form: Option<Form>,
+    /// Questions keyed by flow id (see [`HelpKindDefinition::flow`]), replacing the shared flow.
+    #[serde(default)]
+    pub forms: std::collections::HashMap<String, Form>,
+    /// Ask the questions of the form with inline keyboards and edit the question message in place
+    /// when it is answered with a button, instead of sending a new message for every question.
+    #[serde(default)]
+    pub edit_questions_in_place: bool,
+    /// Google Drive folder photos attached to submissions are uploaded to; photos are not
+    /// accepted without it.
+    #[serde(default)]
+    pub drive_folder_id: Option<String>,
+    /// Speech-to-text provider for the answers sent as voice messages; voice messages are not
+    /// accepted without it.
+    #[serde(default)]
+    pub transcription: Option<crate::transcription::Provider>,
+    /// LLM parsing the questionnaires pasted as one message into the answers of the form, see
+    /// [`crate::questionnaire`]; such messages are taken for an answer to the first question
+    /// without it.
+    #[serde(default)]
+    pub questionnaire_parser: Option<crate::questionnaire::Parser>,
+    /// Page with the privacy policy, linked from the consent asked before the form.
+    #[serde(default)]
+    pub privacy_policy_url: Option<String>,
+    /// Tab the audit log is appended to, see [`crate::audit`].
+    #[serde(default)]
+    pub audit_log: Option<Destination>,
+    /// Creates the spreadsheets of the destinations without a `spreadsheet_id` at startup, see
+    /// [`crate::sheets::provisioning`].
+    #[serde(default)]
+    pub provisioning: Option<Provisioning>,
+    /// HTTP endpoints notified of the submission events, see [`crate::outgoing_webhooks`].
+    #[serde(default)]
+    pub webhooks: Vec<crate::outgoing_webhooks::OutgoingWebhook>,
+    /// Addresses emailed about the critical requests and the failing spreadsheets, see
+    /// [`crate::email`].
+    #[serde(default)]
+    pub email: Option<crate::email::EmailNotifications>,
+    /// Provider of the SMS confirming the submissions and their statuses, see [`crate::sms`].
+    #[serde(default)]
+    pub sms: Option<crate::sms::SmsProvider>,
+    /// Donations offered through Telegram Payments, see [`crate::donations`].
+    #[serde(default)]
+    pub donations: Option<crate::donations::Donations>,
+    /// Tab the daily statistics are appended to, see [`crate::analytics`].
+    #[serde(default)]
+    pub analytics: Option<Destination>,
+    /// Survey of the requesters whose requests were completed, see [`crate::feedback`].
+    #[serde(default)]
+    pub feedback: Option<crate::feedback::Feedback>,
+    /// Public channel the anonymized open requests are posted to, see [`crate::public_feed`].
+    #[serde(default)]
+    pub public_channel: Option<crate::public_feed::PublicChannel>,
+    /// Alternative prompts of the questions shown to random users, see [`crate::experiments`].
+    #[serde(default)]
+    pub experiments: Vec<crate::experiments::Experiment>,
+    /// Geocoder and router the drivers are ranked by the distance with, see [`crate::geo`].
+    #[serde(default)]
+    pub geo: Option<crate::geo::Geo>,
+    /// Chat the requests with urgent medical needs are posted to, see [`crate::medical`].
+    #[serde(default)]
+    pub medical_volunteers: Option<crate::medical::MedicalVolunteers>,
+    /// Tags the requests get by the keywords in the answers, replacing the built-in ones, see
+    /// [`crate::volunteers`].
+    #[serde(default = "crate::volunteers::builtin_request_tags")]
+    pub tags: Vec<crate::volunteers::RequestTag>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            admins: Vec::new(),
+            help_kinds: builtin_help_kinds(),
+            destinations: Vec::new(),
+            form: None,
+            forms: Default::default(),
+            edit_questions_in_place: false,
+            drive_folder_id: None,
+            transcription: None,
+            questionnaire_parser: None,
+            privacy_policy_url: None,
+            audit_log: None,
+            provisioning: None,
+            webhooks: Vec::new(),
+            email: None,
+            sms: None,
+            donations: None,
+            experiments: Vec::new(),
+            analytics: None,
+            feedback: None,
+            public_channel: None,
+            geo: None,
+            medical_volunteers: None,
+            tags: crate::volunteers::builtin_request_tags(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HelpDirection {
+    /// Listed after "Я можу допомогти".
+    Providing,
+    /// Listed after "Я потребую допомоги".
+    Needed,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct HelpKindDefinition {
+    pub slug: HelpKind,
+    /// Text of the keyboard button which selects the kind.
+    pub label: String,
+    /// Name shown to coordinators and volunteers; defaults to the label.
+    #[serde(default)]
+    pub title: Option<String>,
+    pub direction: HelpDirection,
+    /// Prefix of the reference ids, e.g. `EVA`.
+    pub reference_prefix: String,
+    /// Key of [`Config::forms`] with the questions of the kind; defaults to the slug.
+    #[serde(default)]
+    pub flow: Option<String>,
+    /// Spreadsheet/tab used when no destination route matches.
+    pub destination: Destination,
+    /// Names of the values in the columns of the rows, in order, for tabs laid out differently
+    /// from the built-in [`crate::sheets::schema::DEFAULT_COLUMNS`]: `reference_id`, `status`,
+    /// `assignee`, `created_at`, `created_at_utc`, `language`, `attachments`, `consented_at`,
+    /// `submission_id`, `source`, `trip`, `map_link`, `medical`, `tags`, `telegram_user_id`, `telegram_username`,
+    /// `telegram_link`, the field of any question, or an empty string for a column the bot leaves
+    /// empty.
+    #[serde(default)]
+    pub columns: Option<Vec<String>>,
+    #[serde(flatten)]
+    pub append: AppendOptions,
+    /// Stop offering the kind, e.g. while there are more drivers than requests; coordinators can
+    /// also pause it with `/pause`, see [`crate::intake`].
+    #[serde(default)]
+    pub paused: bool,
+    /// Slack and Discord channels the new submissions are posted to, see [`crate::team_chats`].
+    #[serde(default)]
+    pub team_chats: Vec<crate::team_chats::TeamChat>,
+    /// Start parameter of the links opening the form of the kind, e.g. `evacuation` for
+    /// `t.me/<bot>?start=evacuation`; the slug opens it too.
+    #[serde(default)]
+    pub deep_link: Option<String>,
+}
+
+/// How the rows are appended, as in the `spreadsheets.values.append` method of the Sheets API.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize)]
+pub struct AppendOptions {
+    /// The table the rows are appended to: columns of the tab in A1 notation, e.g. `B:Z`, or a
+    /// named range; the whole tab by default.
+    #[serde(default)]
+    pub range: Option<String>,
+    #[serde(default)]
+    pub value_input_option: ValueInputOption,
+    /// Whether the rows overwrite the empty rows below the table or new rows are inserted; the
+    /// former by default.
+    #[serde(default)]
+    pub insert_data_option: Option<InsertDataOption>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ValueInputOption {
+    /// The values are stored as they are.
+    Raw,
+    /// The values are parsed as if typed in, except the ones quoted by the bot.
+    #[default]
+    UserEntered,
+}
+
+impl ValueInputOption {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Raw => "RAW",
+            Self::UserEntered => "USER_ENTERED",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum InsertDataOption {
+    Overwrite,
+    InsertRows,
+}
+
+impl InsertDataOption {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Overwrite => "OVERWRITE",
+            Self::InsertRows => "INSERT_ROWS",
+        }
+    }
+}
+
+impl HelpKindDefinition {
+    fn builtin(
+        slug: &str,
+        label: &str,
+        title: &str,
+        direction: HelpDirection,
+        reference_prefix: &str,
+        spreadsheet_id: &str,
+    ) -> Self {
+        Self {
+            slug: HelpKind::new(slug),
+            label: label.to_owned(),
+            title: Some(title.to_owned()),
+            direction,
+            reference_prefix: reference_prefix.to_owned(),
+            flow: None,
+            columns: None,
+            append: AppendOptions::default(),
+            paused: false,
+            team_chats: Vec::new(),
+            deep_link: None,
+            destination: Destination {
+                spreadsheet_id: spreadsheet_id.to_owned(),
+                sheet: default_sheet(),
+                monthly: false,
+            },
+        }
+    }
+
+    /// The button label translated into the language, see `help_kind_{slug}` in the texts.
+    pub fn label_in<'a>(&'a self, texts: &'a Texts, language: Language) -> &'a str {
+        texts
+            .translation(language, &format!("help_kind_{}", self.slug.as_str()))
+            .unwrap_or(&self.label)
+    }
+
+    pub fn title(&self) -> &str {
+        self.title.as_deref().unwrap_or(&self.label)
+    }
+
+    /// Names of the values in the columns of the rows; the extra fields of the form follow the
+    /// built-in ones without a name.
+    pub fn columns(&self) -> Vec<&str> {
+        match &self.columns {
+            Some(columns) => columns.iter().map(String::as_str).collect(),
+            None => sheets::schema::default_columns(),
+        }
+    }
+
+    fn flow(&self) -> &str {
+        self.flow.as_deref().unwrap_or_else(|| self.slug.as_str())
+    }
+}
+
+/// The kinds of help the bot was released with.
+pub fn builtin_help_kinds() -> Vec<HelpKindDefinition> {
+    use HelpDirection::{Needed, Providing};
+
+    vec![
+        HelpKindDefinition::builtin(
+            HelpKind::PROVIDING_DRIVER,
+            "Я водій з власним авто",
+            "Водій з власним авто",
+            Providing,
+            "DRV",
+            "117bcR8cksBSNUFNP51AAdr9pNMlJsFhwXL0NcbtW99A",
+        ),
+        HelpKindDefinition::builtin(
+            HelpKind::PROVIDING_COLLECTING_HUMANITARIAN_HELP,
+            "Можу збирати гуманітарну чи фінансову допомогу",
+            "Збір гуманітарної чи фінансової допомоги",
+            Providing,
+            "COL",
+            "1lfBO5dLNDW_ymL2aySJwtOqRAAttGaWp3QFPWYL5JlI",
+        ),
+        HelpKindDefinition::builtin(
+            HelpKind::PROVIDING_USEFUL_CONTACT,
+            "Корисні контакти",
+            "Корисні контакти",
+            Providing,
+            "CON",
+            "1K69NNDU2YnHnI9QSPO9FcUgjFZw70uPjncKNYTTWKHM",
+        ),
+        HelpKindDefinition::builtin(
+            HelpKind::NEED_EVACUATION,
+            "Евакуація",
+            "Евакуація",
+            Needed,
+            "EVA",
+            "1as4OGhZLULiQFqjgbHqnbed2xbiA4fCBjyYRbXPzHCU",
+        ),
+        HelpKindDefinition::builtin(
+            HelpKind::NEED_HUMANITARIAN_HELP,
+            "Потрібна гуманітарна допомога",
+            "Потрібна гуманітарна допомога",
+            Needed,
+            "HUM",
+            "1MM-8rxEcoD0GGqdTmudgchqpLIcaTygTN1x95nNzpJE",
+        ),
+    ]
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct DestinationRoute {
+    pub help_kind: HelpKind,
+    /// Oblast as spelled in [`crate::regions::OBLASTS`]; `None` matches any region.
+    #[serde(default)]
+    pub region: Option<String>,
+    #[serde(flatten)]
+    pub destination: Destination,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Provisioning {
+    /// Google accounts the created spreadsheets are shared with as editors.
+    #[serde(default)]
+    pub share_with: Vec<String>,
+    /// JSON file with the ids of the created spreadsheets by destination, see
+    /// [`Config::destinations_mut`]; they fill in the missing ids whenever the config is loaded.
+    pub state_path: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Destination {
+    /// Empty for a spreadsheet to be created, see [`Config::provisioning`].
+    #[serde(default)]
+    pub spreadsheet_id: String,
+    /// The tab; with `monthly` the one with the header row the tabs of the months copy.
+    #[serde(default = "default_sheet")]
+    pub sheet: String,
+    /// Append to a tab of the current month instead, e.g. `Sheet1 2024-03`, created when the
+    /// first submission of the month is saved.
+    #[serde(default)]
+    pub monthly: bool,
+}
+
+impl Destination {
+    /// The tab the rows saved at `now` go to.
+    pub fn tab(&self, now: &chrono::DateTime<chrono_tz::Tz>) -> String {
+        if self.monthly {
+            format!("{} {}", self.sheet, now.format("%Y-%m"))
+        } else {
+            self.sheet.clone()
+        }
+    }
+}
+
+impl Provisioning {
+    /// The ids of the created spreadsheets by destination; none before the first is created.
+    pub fn load(&self) -> anyhow::Result<std::collections::BTreeMap<String, String>> {
+        match std::fs::read_to_string(&self.state_path) {
+            Ok(contents) => Ok(serde_json::from_str(&contents)?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Default::default()),
+            Err(err) => Err(anyhow::anyhow!(
+                "failed to read provisioned spreadsheets {}: {}",
+                self.state_path,
+                err
+            )),
+        }
+    }
+
+    pub fn store(
+        &self,
+        provisioned: &std::collections::BTreeMap<String, String>,
+    ) -> anyhow::Result<()> {
+        std::fs::write(&self.state_path, serde_json::to_string_pretty(provisioned)?).map_err(
+            |err| {
+                anyhow::anyhow!(
+                    "failed to write provisioned spreadsheets {}: {}",
+                    self.state_path,
+                    err
+                )
+            },
+        )
+    }
+}
+
+fn default_sheet() -> String {
+    "Sheet1".to_owned()
+}
+
+impl Config {
+    pub fn help_kind(&self, help_kind: &HelpKind) -> Option<&HelpKindDefinition> {
+        self.help_kinds
+            .iter()
+            .find(|definition| definition.slug == *help_kind)
+    }
+
+    /// Finds the kind of help by the text of its keyboard button.
+    pub fn help_kind_by_label(
+        &self,
+        direction: HelpDirection,
+        label: &str,
+        texts: &Texts,
+        language: Language,
+    ) -> Option<&HelpKindDefinition> {
+        self.help_kinds.iter().find(|definition| {
+            definition.direction == direction && definition.label_in(texts, language) == label
+        })
+    }
+
+    /// Finds the kind of help by the start parameter of a link, see
+    /// [`HelpKindDefinition::deep_link`].
+    pub fn help_kind_by_deep_link(&self, deep_link: &str) -> Option<&HelpKindDefinition> {
+        self.help_kinds.iter().find(|definition| {
+            definition.deep_link.as_deref() == Some(deep_link)
+                || definition.slug.as_str().eq_ignore_ascii_case(deep_link)
+        })
+    }
+
+    /// Name of the kind of help for coordinators; the slug if the kind was removed from the config.
+    pub fn help_kind_title<'a>(&'a self, help_kind: &'a HelpKind) -> &'a str {
+        self.help_kind(help_kind)
+            .map_or_else(|| help_kind.as_str(), HelpKindDefinition::title)
+    }
+
+    /// The questions asked for the help kind, with the questions limited to other kinds dropped.
+    pub fn form(&self, help_kind: &HelpKind) -> Form {
+        let flow = self
+            .help_kind(help_kind)
+            .map_or_else(|| help_kind.as_str(), HelpKindDefinition::flow);
+        match self.forms.get(flow).or(self.form.as_ref()) {
+            Some(form) => form.for_help_kind(help_kind),
+            None => Form::builtin().for_help_kind(help_kind),
+        }
+    }
+
+    /// Names of the values in the columns of the rows of the help kind, see
+    /// [`HelpKindDefinition::columns`]; the built-in layout is assumed for kinds no longer
+    /// configured.
+    pub fn columns(&self, help_kind: &HelpKind) -> Vec<&str> {
+        match self.help_kind(help_kind) {
+            Some(definition) => definition.columns(),
+            None => sheets::schema::default_columns(),
+        }
+    }
+
+    /// Index of the named column in the rows of the help kind.
+    pub fn column(&self, help_kind: &HelpKind, name: &str) -> Option<usize> {
+        self.columns(help_kind)
+            .iter()
+            .position(|column| *column == name)
+    }
+
+    pub fn from_env() -> anyhow::Result<Self> {
+        let path = match std::env::var("COLLECT_VOLUNTEERS_BOT_CONFIG") {
+            Ok(path) => path,
+            Err(_) => return Ok(Self::default()),
+        };
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|err| anyhow::anyhow!("failed to read config {}: {}", path, err))?;
+        let mut config: Self = serde_json::from_str(&contents)?;
+        crate::outgoing_webhooks::resolve_secrets(&mut config.webhooks)?;
+        if let Some(provisioning) = config.provisioning.clone() {
+            let provisioned = provisioning.load()?;
+            for (key, _, destination) in config.destinations_mut() {
+                if destination.spreadsheet_id.is_empty() {
+                    if let Some(spreadsheet_id) = provisioned.get(&key) {
+                        destination.spreadsheet_id = spreadsheet_id.clone();
+                    }
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    /// Every destination of the config with the key its created spreadsheet is kept under in
+    /// [`Provisioning::state_path`] and the title it is created with.
+    pub fn destinations_mut(&mut self) -> Vec<(String, String, &mut Destination)> {
+        let titles: std::collections::HashMap<HelpKind, String> = self
+            .help_kinds
+            .iter()
+            .map(|definition| (definition.slug.clone(), definition.title().to_owned()))
+            .collect();
+        let title = |help_kind: &HelpKind| {
+            titles
+                .get(help_kind)
+                .cloned()
+                .unwrap_or_else(|| help_kind.as_str().to_owned())
+        };
+        let mut destinations = Vec::new();
+        for definition in &mut self.help_kinds {
+            destinations.push((
+                format!("help_kinds/{}", definition.slug.as_str()),
+                title(&definition.slug),
+                &mut definition.destination,
+            ));
+        }
+        for route in &mut self.destinations {
+            let region = route.region.as_deref();
+            destinations.push((
+                format!(
+                    "destinations/{}/{}",
+                    route.help_kind.as_str(),
+                    region.unwrap_or("*")
+                ),
+                format!(
+                    "{} — {}",
+                    title(&route.help_kind),
+                    region.unwrap_or("інші області")
+                ),
+                &mut route.destination,
+            ));
+        }
+        if let Some(audit_log) = &mut self.audit_log {
+            destinations.push(("audit_log".to_owned(), "Журнал дій".to_owned(), audit_log));
+        }
+        if let Some(analytics) = &mut self.analytics {
+            destinations.push(("analytics".to_owned(), "Статистика".to_owned(), analytics));
+        }
+        if let Some(feedback) = &mut self.feedback {
+            destinations.push((
+                "feedback".to_owned(),
+                "Відгуки".to_owned(),
+                &mut feedback.destination,
+            ));
+        }
+        destinations
+    }
+
+    /// Picks the spreadsheet/tab for a submission: a route for the region wins over a route for
+    /// any region, which wins over the spreadsheet of the help kind definition.
+    pub fn destination(
+        &self,
+        definition: &HelpKindDefinition,
+        region: Option<&str>,
+    ) -> Destination {
+        let routes = || {
+            self.destinations
+                .iter()
+                .filter(move |route| route.help_kind == definition.slug)
+        };
+        routes()
+            .find(|route| route.region.is_some() && route.region.as_deref() == region)
+            .or_else(|| routes().find(|route| route.region.is_none()))
+            .map_or_else(
+                || definition.destination.clone(),
+                |route| route.destination.clone(),
+            )
+    }
+}
@@ -0,0 +1,211 @@
+//! Donations through Telegram Payments, offered after the submissions of the help kinds collecting
+//! humanitarian help.
+//!
+//! The payment provider token, issued by BotFather for the provider of the organization, is taken
+//! from `COLLECT_VOLUNTEERS_BOT_PAYMENTS_PROVIDER_TOKEN`. Every successful payment is appended to
+//! the donations tab from the config: time, Telegram id and username of the donor, amount,
+//! currency and the charge ids of Telegram and the provider.
+
+use google_sheets4::api::ValueRange;
+use teloxide::{
+    payloads::{AnswerCallbackQuerySetters, SendMessageSetters},
+    prelude2::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup, LabeledPrice},
+};
+
+use crate::{
+    config::{self, Destination},
+    sheets::quoted_tab,
+    state::HelpKind,
+    texts::Language,
+    AppState, ThrottledBot,
+};
+
+const CALLBACK_PREFIX: &str = "donate:";
+
+/// Prefix of the invoice payloads, so that other payments are not recorded as donations.
+const PAYLOAD_PREFIX: &str = "donation:";
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Donations {
+    /// ISO 4217 code, e.g. `UAH`.
+    pub currency: String,
+    /// The amounts offered on the buttons, in the currency units.
+    pub amounts: Vec<u32>,
+    /// Kinds of help after whose submissions the donations are offered.
+    #[serde(default = "default_help_kinds")]
+    pub help_kinds: Vec<HelpKind>,
+    /// Tab the successful payments are appended to.
+    pub destination: Destination,
+}
+
+fn default_help_kinds() -> Vec<HelpKind> {
+    vec![HelpKind::new(
+        HelpKind::PROVIDING_COLLECTING_HUMANITARIAN_HELP,
+    )]
+}
+
+fn provider_token() -> anyhow::Result<String> {
+    std::env::var("COLLECT_VOLUNTEERS_BOT_PAYMENTS_PROVIDER_TOKEN")
+        .map_err(|_| anyhow::anyhow!("COLLECT_VOLUNTEERS_BOT_PAYMENTS_PROVIDER_TOKEN is not set"))
+}
+
+/// Offers to donate after the submission of the help kind, if donations are accepted for it.
+pub async fn offer(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    chat_id: i64,
+    language: Language,
+    help_kind: &HelpKind,
+) -> anyhow::Result<()> {
+    let config = app_state.config();
+    let donations = match &config.donations {
+        Some(donations) if donations.help_kinds.contains(help_kind) => donations,
+        _ => return Ok(()),
+    };
+    let buttons: Vec<_> = donations
+        .amounts
+        .iter()
+        .map(|amount| {
+            InlineKeyboardButton::callback(
+                format!("{amount} {}", donations.currency),
+                format!("{CALLBACK_PREFIX}{amount}"),
+            )
+        })
+        .collect();
+    bot.send_message(chat_id, app_state.texts().get(language, "donation_offer"))
+        .reply_markup(InlineKeyboardMarkup::new(vec![buttons]))
+        .await?;
+    Ok(())
+}
+
+pub fn is_donation_callback(query: CallbackQuery) -> bool {
+    query
+        .data
+        .as_deref()
+        .is_some_and(|data| data.starts_with(CALLBACK_PREFIX))
+}
+
+/// Sends the invoice of the amount on the button.
+#[tracing::instrument(skip_all, fields(user_id = query.from.id, data = ?query.data))]
+pub async fn handle_callback(
+    bot: ThrottledBot,
+    query: CallbackQuery,
+    app_state: std::sync::Arc<AppState>,
+    language: Language,
+) -> anyhow::Result<()> {
+    let config = app_state.config();
+    let amount = query
+        .data
+        .as_deref()
+        .and_then(|data| data.strip_prefix(CALLBACK_PREFIX))
+        .and_then(|amount| amount.parse::<u32>().ok());
+    let (donations, amount) = match (&config.donations, amount) {
+        (Some(donations), Some(amount)) if donations.amounts.contains(&amount) => {
+            (donations, amount)
+        }
+        _ => {
+            bot.answer_callback_query(query.id)
+                .text(app_state.texts().get(language, "donation_unavailable"))
+                .await?;
+            return Ok(());
+        }
+    };
+    let texts = app_state.texts();
+    bot.send_invoice(
+        query.from.id,
+        texts.get(language, "donation_title"),
+        texts.get(language, "donation_description"),
+        format!("{PAYLOAD_PREFIX}{amount}"),
+        provider_token()?,
+        donations.currency.clone(),
+        vec![LabeledPrice::new(
+            texts.get(language, "donation_title"),
+            // In the smallest units of the currency.
+            amount as i32 * 100,
+        )],
+    )
+    .await?;
+    bot.answer_callback_query(query.id).await?;
+    Ok(())
+}
+
+/// Confirms the checkout of the donation invoices; Telegram cancels it after 10 seconds without
+/// an answer.
+#[tracing::instrument(skip_all, fields(user_id = query.from.id))]
+pub async fn handle_pre_checkout_query(
+    bot: ThrottledBot,
+    query: PreCheckoutQuery,
+) -> anyhow::Result<()> {
+    let ok = query.invoice_payload.starts_with(PAYLOAD_PREFIX);
+    bot.answer_pre_checkout_query(query.id, ok).await?;
+    Ok(())
+}
+
+pub fn is_successful_payment(msg: Message) -> bool {
+    msg.successful_payment().is_some()
+}
+
+/// Thanks the donor and records the payment.
+#[tracing::instrument(skip_all, fields(chat_id = msg.chat.id))]
+pub async fn handle_successful_payment(
+    bot: ThrottledBot,
+    msg: Message,
+    app_state: std::sync::Arc<AppState>,
+    language: Language,
+) -> anyhow::Result<()> {
+    let payment = match msg.successful_payment() {
+        Some(payment) => payment,
+        None => return Ok(()),
+    };
+    tracing::info!(
+        "Received a donation of {} {:?}",
+        payment.total_amount,
+        payment.currency
+    );
+    bot.send_message(
+        msg.chat.id,
+        app_state.texts().get(language, "donation_thanks"),
+    )
+    .await?;
+
+    let config = app_state.config();
+    let destination = match &config.donations {
+        Some(donations) => &donations.destination,
+        None => return Ok(()),
+    };
+    if app_state.skips_external_writes() {
+        tracing::info!("Not saving the donation to {:?}", destination.sheet);
+        return Ok(());
+    }
+    let user = msg.from();
+    let row = vec![
+        format!("'{}", config::local_now().to_rfc3339()),
+        user.map(|user| user.id.to_string()).unwrap_or_default(),
+        user.and_then(|user| user.username.as_ref())
+            .map(|username| format!("'@{username}"))
+            .unwrap_or_default(),
+        format!("{:.2}", f64::from(payment.total_amount) / 100.0),
+        // The `Debug` of the currency is its ISO 4217 code.
+        format!("{:?}", payment.currency),
+        format!("'{}", payment.telegram_payment_charge_id),
+        format!("'{}", payment.provider_payment_charge_id),
+    ];
+    app_state
+        .sheets_api
+        .spreadsheets()
+        .values_append(
+            ValueRange {
+                major_dimension: Some("ROWS".to_owned()),
+                range: None,
+                values: Some(vec![row]),
+            },
+            &destination.spreadsheet_id,
+            &quoted_tab(&destination.sheet),
+        )
+        .value_input_option("USER_ENTERED")
+        .insert_data_option("INSERT_ROWS")
+        .doit()
+        .await?;
+    Ok(())
+}
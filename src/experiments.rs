@@ -0,0 +1,127 @@
+//! A/B tests of the wording of the questions of the form.
+//!
+//! An experiment gives a question alternative prompts: the variant `v` of the question with the
+//! field `f` asks the text `question_f:v`, usually added with `COLLECT_VOLUNTEERS_BOT_TEXTS`. A
+//! variant without the text keeps the regular prompt, so a `control` variant needs none. Every
+//! started form is assigned a random variant of each experiment on its questions, kept in the
+//! contact of the dialogue state so that the user sees the same wording until the end. How many
+//! forms got each variant, answered its question and were confirmed is counted with
+//! [`crate::funnel`] and shown by `/stats`.
+
+use std::collections::HashMap;
+
+use rand::seq::SliceRandom;
+
+use crate::{config::Config, form::Form, funnel, state::Contact, AppState};
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Experiment {
+    /// Field of the question whose prompt is varied.
+    pub field: String,
+    /// Names of the variants, e.g. `control` and `short`.
+    pub variants: Vec<String>,
+}
+
+/// Id of the text asking the question in the variant.
+pub fn prompt_text_id(field: &str, variant: &str) -> String {
+    format!("question_{field}:{variant}")
+}
+
+/// Picks the variants of the experiments on the questions of the form and counts them.
+pub async fn assign(app_state: &AppState, config: &Config, form: &Form, contact: &mut Contact) {
+    for experiment in &config.experiments {
+        if !form
+            .questions
+            .iter()
+            .any(|question| question.field == experiment.field)
+        {
+            continue;
+        }
+        // The generator is not `Send`, so it must not live across the await.
+        let variant = experiment.variants.choose(&mut rand::thread_rng()).cloned();
+        if let Some(variant) = variant {
+            contact
+                .prompt_variants
+                .insert(experiment.field.clone(), variant.clone());
+            record(app_state, &experiment.field, &variant, "assigned").await;
+        }
+    }
+}
+
+/// Counts the answer to the question if it is under an experiment.
+pub async fn record_answered(app_state: &AppState, contact: &Contact, field: &str) {
+    if let Some(variant) = contact.prompt_variants.get(field) {
+        record(app_state, field, variant, "answered").await;
+    }
+}
+
+/// Counts the confirmed submission for all the variants it was asked with.
+pub async fn record_confirmed(app_state: &AppState, contact: &Contact) {
+    for (field, variant) in &contact.prompt_variants {
+        record(app_state, field, variant, "confirmed").await;
+    }
+}
+
+async fn record(app_state: &AppState, field: &str, variant: &str, outcome: &str) {
+    funnel::record(
+        app_state,
+        funnel::Step::Experiment {
+            field,
+            variant,
+            outcome,
+        },
+    )
+    .await;
+}
+
+/// Lines of `/stats` with the answer and the completion rates of every variant.
+pub fn render_stats(counts: &HashMap<String, u64>) -> Vec<String> {
+    // (field, variant) -> (assigned, answered, confirmed)
+    let mut variants: Vec<((&str, &str), [u64; 3])> = Vec::new();
+    for (step, count) in counts {
+        let mut parts = match step.strip_prefix("experiment:") {
+            Some(step) => step.rsplitn(3, ':'),
+            None => continue,
+        };
+        let (outcome, variant, field) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(outcome), Some(variant), Some(field)) => (outcome, variant, field),
+            _ => continue,
+        };
+        let index = match outcome {
+            "assigned" => 0,
+            "answered" => 1,
+            "confirmed" => 2,
+            _ => continue,
+        };
+        match variants
+            .iter_mut()
+            .find(|(key, _)| *key == (field, variant))
+        {
+            Some((_, counts)) => counts[index] += count,
+            None => {
+                let mut counts = [0; 3];
+                counts[index] = *count;
+                variants.push(((field, variant), counts));
+            }
+        }
+    }
+    if variants.is_empty() {
+        return Vec::new();
+    }
+    variants.sort();
+    let rate = |count: u64, assigned: u64| {
+        (count * 100)
+            .checked_div(assigned)
+            .map(|rate| format!(" ({rate}%)"))
+            .unwrap_or_default()
+    };
+    let mut lines = vec![String::new(), "Експерименти:".to_owned()];
+    for ((field, variant), [assigned, answered, confirmed]) in variants {
+        lines.push(format!(
+            "«{field}», варіант «{variant}»: показано {assigned}, відповіли {answered}{}, підтвердили {confirmed}{}",
+            rate(answered, assigned),
+            rate(confirmed, assigned),
+        ));
+    }
+    lines
+}
@@ -0,0 +1,219 @@
+//! Export of the submission rows of a help kind to a CSV or XLSX document, for the coordinators
+//! who work offline: `/export evacuation 2024-03-01..2024-03-31 xlsx`.
+//!
+//! The rows are read from every tab of the kind, see [`rotation::destinations_of`], the tabs of
+//! the months in the range for the destinations marked monthly, and filtered by the date they
+//! were created on.
+
+use chrono::NaiveDate;
+use teloxide::{payloads::SendDocumentSetters, prelude2::*, types::InputFile};
+
+use crate::{
+    config::{self, HelpKindDefinition},
+    sheets::{quoted_tab, rotation},
+    state::HelpKind,
+    AppState, ThrottledBot,
+};
+
+const USAGE: &str = "Використання: /export <вид допомоги> <з>..<до> [csv|xlsx], \
+                     наприклад: /export evacuation 2024-03-01..2024-03-31 xlsx";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Csv,
+    Xlsx,
+}
+
+/// What the command asks for.
+#[derive(Debug)]
+struct Request {
+    help_kind: HelpKind,
+    from: NaiveDate,
+    to: NaiveDate,
+    format: Format,
+}
+
+impl Request {
+    /// Parses `<kind> <from>..<to> [csv|xlsx]`; a single date is the range of one day.
+    fn parse(args: &str) -> Option<Self> {
+        let mut args = args.split_whitespace();
+        let help_kind = HelpKind::new(args.next()?);
+        let range = args.next()?;
+        let (from, to) = range.split_once("..").unwrap_or((range, range));
+        let format = match args.next() {
+            None | Some("csv") => Format::Csv,
+            Some("xlsx") => Format::Xlsx,
+            Some(_) => return None,
+        };
+        let request = Self {
+            help_kind,
+            from: from.parse().ok()?,
+            to: to.parse().ok()?,
+            format,
+        };
+        (args.next().is_none() && request.from <= request.to).then_some(request)
+    }
+}
+
+#[tracing::instrument(skip_all, fields(chat_id = msg.chat.id))]
+pub async fn handle_export(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    msg: &Message,
+    args: &str,
+) -> anyhow::Result<()> {
+    let request = match Request::parse(args) {
+        Some(request) => request,
+        None => {
+            bot.send_message(msg.chat.id, USAGE).await?;
+            return Ok(());
+        }
+    };
+    let config = app_state.config();
+    let definition = match config.help_kind(&request.help_kind) {
+        Some(definition) => definition,
+        None => {
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "Невідомий вид допомоги {:?}.\n{USAGE}",
+                    request.help_kind.as_str()
+                ),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    let rows = fetch_rows(app_state, &config, definition, &request).await?;
+    let count = rows.len().saturating_sub(1);
+    let file_name = format!(
+        "{}_{}_{}.{}",
+        definition.slug.as_str(),
+        request.from,
+        request.to,
+        match request.format {
+            Format::Csv => "csv",
+            Format::Xlsx => "xlsx",
+        }
+    );
+    let data = match request.format {
+        Format::Csv => csv(&rows),
+        Format::Xlsx => xlsx(&rows)?,
+    };
+    bot.send_document(msg.chat.id, InputFile::memory(data).file_name(file_name))
+        .caption(format!(
+            "{}: {} заявок з {} по {}",
+            definition.title(),
+            count,
+            request.from,
+            request.to
+        ))
+        .await?;
+    Ok(())
+}
+
+/// The header row followed by the rows created in the range.
+async fn fetch_rows(
+    app_state: &AppState,
+    config: &config::Config,
+    definition: &HelpKindDefinition,
+    request: &Request,
+) -> anyhow::Result<Vec<Vec<String>>> {
+    let created_at_column = config
+        .column(&definition.slug, "created_at")
+        .ok_or_else(|| anyhow::anyhow!("The rows of {:?} have no date", definition.slug))?;
+    let (from, to) = (request.from.to_string(), request.to.to_string());
+    let mut header = None;
+    let mut rows = Vec::new();
+    for destination in
+        rotation::destinations_of(app_state.redis.clone(), config, definition).await?
+    {
+        for tab in tabs(&destination, request.from, request.to) {
+            let result = app_state
+                .sheets_api
+                .spreadsheets()
+                .values_get(&destination.spreadsheet_id, &quoted_tab(&tab))
+                .doit()
+                .await;
+            let values = match result {
+                Ok((_, value_range)) => value_range.values.unwrap_or_default(),
+                // The tabs of the months without submissions do not exist.
+                Err(err) => {
+                    tracing::warn!(
+                        "Failed to export {} {:?}: {:?}",
+                        destination.spreadsheet_id,
+                        tab,
+                        err
+                    );
+                    continue;
+                }
+            };
+            let mut values = values.into_iter();
+            if let Some(first) = values.next() {
+                header.get_or_insert(first);
+            }
+            rows.extend(values.filter(|row| {
+                // The dates start with the day, e.g. `2024-03-01T12:00:00+02:00`.
+                let day = row
+                    .get(created_at_column)
+                    .map(|created_at| created_at.trim().get(..10).unwrap_or_default())
+                    .unwrap_or_default();
+                from.as_str() <= day && day <= to.as_str()
+            }));
+        }
+    }
+    Ok(header.into_iter().chain(rows).collect())
+}
+
+/// The tabs of the destination with the rows created from `from` to `to`.
+fn tabs(destination: &config::Destination, from: NaiveDate, to: NaiveDate) -> Vec<String> {
+    if !destination.monthly {
+        return vec![destination.sheet.clone()];
+    }
+    let mut tabs: Vec<String> = Vec::new();
+    let mut day = from;
+    while day <= to {
+        let tab = format!("{} {}", destination.sheet, day.format("%Y-%m"));
+        if tabs.last() != Some(&tab) {
+            tabs.push(tab);
+        }
+        day = match day.succ_opt() {
+            Some(day) => day,
+            None => break,
+        };
+    }
+    tabs
+}
+
+fn csv(rows: &[Vec<String>]) -> Vec<u8> {
+    let mut csv = String::new();
+    for row in rows {
+        let cells: Vec<String> = row
+            .iter()
+            .map(|cell| {
+                if cell.contains(&[',', '"', '\n', '\r'][..]) {
+                    format!("\"{}\"", cell.replace('"', "\"\""))
+                } else {
+                    cell.clone()
+                }
+            })
+            .collect();
+        csv.push_str(&cells.join(","));
+        csv.push_str("\r\n");
+    }
+    // The byte order mark makes Excel read the file as UTF-8.
+    let mut data = "\u{feff}".as_bytes().to_vec();
+    data.extend(csv.into_bytes());
+    data
+}
+
+fn xlsx(rows: &[Vec<String>]) -> anyhow::Result<Vec<u8>> {
+    let mut workbook = rust_xlsxwriter::Workbook::new();
+    let worksheet = workbook.add_worksheet();
+    for (row_number, row) in rows.iter().enumerate() {
+        for (column, cell) in row.iter().enumerate() {
+            worksheet.write_string(row_number as u32, column as u16, cell)?;
+        }
+    }
+    Ok(workbook.save_to_buffer()?)
+}
@@ -0,0 +1,385 @@
+//! Feedback survey of the requesters whose requests were completed.
+//!
+//! When the status of a request for help changes to "виконано", the survey is scheduled for
+//! `after_days` later. The requester is asked whether the help actually arrived, to rate it from
+//! 1 to 5 and to leave a comment, which can be skipped. The answers are appended to the feedback
+//! tab from the config: time, reference id, whether the help arrived, the rating and the comment.
+
+use google_sheets4::api::ValueRange;
+use teloxide::{
+    payloads::{EditMessageTextSetters, SendMessageSetters},
+    prelude2::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+use crate::{
+    config::{self, Destination, HelpDirection},
+    redis_connection, retry,
+    sheets::{quoted_tab, TrackedSubmission},
+    shutdown::Shutdown,
+    texts::{self, Language},
+    AppState, ThrottledBot,
+};
+
+/// Redis sorted set of the scheduled surveys scored by the unix timestamp they are due at.
+const SCHEDULED_KEY: &str = "feedback:scheduled";
+
+const CALLBACK_PREFIX: &str = "feedback:";
+
+/// How long a comment is waited for after the rating.
+const COMMENT_TTL_SECS: usize = 24 * 60 * 60;
+
+pub const HEADERS: &[&str] = &[
+    "Час",
+    "Номер заявки",
+    "Допомога надійшла",
+    "Оцінка",
+    "Коментар",
+];
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Feedback {
+    /// Days after the completion the survey is sent.
+    #[serde(default = "default_after_days")]
+    pub after_days: u32,
+    /// Tab the answers are appended to.
+    pub destination: Destination,
+}
+
+fn default_after_days() -> u32 {
+    3
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct Survey {
+    chat_id: i64,
+    reference_id: String,
+}
+
+/// The answers waiting for the comment.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct PendingComment {
+    reference_id: String,
+    arrived: bool,
+    rating: u8,
+}
+
+fn pending_comment_key(chat_id: i64) -> String {
+    redis_connection::key(&format!("feedback:comment:{chat_id}"))
+}
+
+/// Schedules the survey about the completed request, if it is a request for help.
+pub async fn schedule(
+    app_state: &AppState,
+    chat_id: i64,
+    submission: &TrackedSubmission,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let config = app_state.config();
+    let feedback = match &config.feedback {
+        Some(feedback) => feedback,
+        None => return Ok(()),
+    };
+    let requested_help = config
+        .help_kind(&submission.help_kind)
+        .is_some_and(|definition| definition.direction == HelpDirection::Needed);
+    if !requested_help {
+        return Ok(());
+    }
+    let due_at = chrono::Utc::now() + chrono::Duration::days(feedback.after_days.into());
+    let survey = serde_json::to_string(&Survey {
+        chat_id,
+        reference_id: submission.reference_id.clone(),
+    })?;
+    let _: () = app_state
+        .redis
+        .clone()
+        .zadd(
+            redis_connection::key(SCHEDULED_KEY),
+            survey,
+            due_at.timestamp(),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Sends the surveys which are due.
+pub async fn run(bot: ThrottledBot, app_state: std::sync::Arc<AppState>, mut shutdown: Shutdown) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(600));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.requested() => return,
+        }
+        if let Err(err) = send_due_surveys(&bot, &app_state).await {
+            tracing::warn!("Failed to send the feedback surveys: {:?}", err);
+        }
+    }
+}
+
+async fn send_due_surveys(bot: &ThrottledBot, app_state: &AppState) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let mut redis = app_state.redis.clone();
+    let due: Vec<String> = redis
+        .zrangebyscore(
+            redis_connection::key(SCHEDULED_KEY),
+            0,
+            chrono::Utc::now().timestamp(),
+        )
+        .await?;
+    for survey in due {
+        // Only the instance which removes the survey sends it.
+        let removed: bool = redis
+            .zrem(redis_connection::key(SCHEDULED_KEY), &survey)
+            .await?;
+        if !removed {
+            continue;
+        }
+        let survey: Survey = match serde_json::from_str(&survey) {
+            Ok(survey) => survey,
+            Err(err) => {
+                tracing::warn!("Dropping an unreadable feedback survey: {:?}", err);
+                continue;
+            }
+        };
+        let language = texts::language_of(redis.clone(), survey.chat_id).await;
+        let texts = app_state.texts();
+        let text = texts.render(
+            language,
+            "feedback_question",
+            &[("reference_id", &survey.reference_id)],
+        );
+        let keyboard = InlineKeyboardMarkup::new(vec![vec![
+            InlineKeyboardButton::callback(
+                texts.get(language, "button_feedback_yes").to_owned(),
+                format!("{CALLBACK_PREFIX}{}:yes", survey.reference_id),
+            ),
+            InlineKeyboardButton::callback(
+                texts.get(language, "button_feedback_no").to_owned(),
+                format!("{CALLBACK_PREFIX}{}:no", survey.reference_id),
+            ),
+        ]]);
+        let sent = retry::on_flood(|| {
+            bot.send_message(survey.chat_id, text.clone())
+                .reply_markup(keyboard.clone())
+        })
+        .await;
+        // The requester may have blocked the bot since.
+        if let Err(err) = sent {
+            tracing::warn!(
+                "Failed to send the feedback survey about {}: {:?}",
+                survey.reference_id,
+                err
+            );
+        }
+    }
+    Ok(())
+}
+
+pub fn is_feedback_callback(query: CallbackQuery) -> bool {
+    query
+        .data
+        .as_deref()
+        .is_some_and(|data| data.starts_with(CALLBACK_PREFIX))
+}
+
+/// Handles `feedback:<reference id>:<yes|no>[:<rating>]` and `feedback:skip`.
+#[tracing::instrument(skip_all, fields(user_id = query.from.id, data = ?query.data))]
+pub async fn handle_callback(
+    bot: ThrottledBot,
+    query: CallbackQuery,
+    app_state: std::sync::Arc<AppState>,
+    language: Language,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    bot.answer_callback_query(query.id.clone()).await?;
+    let message = match &query.message {
+        Some(message) => message,
+        None => return Ok(()),
+    };
+    let data = query
+        .data
+        .as_deref()
+        .and_then(|data| data.strip_prefix(CALLBACK_PREFIX))
+        .unwrap_or_default();
+    let texts = app_state.texts();
+    if data == "skip" {
+        bot.edit_message_reply_markup(message.chat.id, message.id)
+            .await?;
+        let pending: Option<String> = app_state
+            .redis
+            .clone()
+            .get(pending_comment_key(message.chat.id))
+            .await?;
+        if let Some(pending) = pending {
+            save(
+                &bot,
+                &app_state,
+                message.chat.id,
+                language,
+                serde_json::from_str(&pending)?,
+                "",
+            )
+            .await?;
+        }
+        return Ok(());
+    }
+
+    let mut parts = data.split(':');
+    let (reference_id, arrived, rating) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(reference_id), Some(arrived @ ("yes" | "no")), rating) => (
+            reference_id,
+            arrived == "yes",
+            rating.and_then(|rating| rating.parse::<u8>().ok()),
+        ),
+        _ => {
+            tracing::warn!("feedback: unexpected callback data {:?}", data);
+            return Ok(());
+        }
+    };
+    match rating {
+        None => {
+            let answer = if arrived { "yes" } else { "no" };
+            let keyboard = InlineKeyboardMarkup::new(vec![(1..=5)
+                .map(|rating| {
+                    InlineKeyboardButton::callback(
+                        rating.to_string(),
+                        format!("{CALLBACK_PREFIX}{reference_id}:{answer}:{rating}"),
+                    )
+                })
+                .collect::<Vec<_>>()]);
+            bot.edit_message_text(
+                message.chat.id,
+                message.id,
+                texts.render(
+                    language,
+                    "feedback_rating",
+                    &[("reference_id", &reference_id)],
+                ),
+            )
+            .reply_markup(keyboard)
+            .await?;
+        }
+        Some(rating) => {
+            let pending = PendingComment {
+                reference_id: reference_id.to_owned(),
+                arrived,
+                rating: rating.clamp(1, 5),
+            };
+            let _: () = app_state
+                .redis
+                .clone()
+                .set_ex(
+                    pending_comment_key(message.chat.id),
+                    serde_json::to_string(&pending)?,
+                    COMMENT_TTL_SECS,
+                )
+                .await?;
+            bot.edit_message_text(
+                message.chat.id,
+                message.id,
+                texts.get(language, "feedback_comment"),
+            )
+            .reply_markup(InlineKeyboardMarkup::new(vec![vec![
+                InlineKeyboardButton::callback(
+                    texts.get(language, "button_feedback_skip").to_owned(),
+                    format!("{CALLBACK_PREFIX}skip"),
+                ),
+            ]]))
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Finds the answers of a requester whose message is the comment of the survey.
+pub async fn find_pending_comment(
+    msg: Message,
+    app_state: std::sync::Arc<AppState>,
+) -> Option<PendingComment> {
+    use redis::AsyncCommands;
+
+    if !msg.chat.is_private() || msg.text().is_none_or(|text| text.starts_with('/')) {
+        return None;
+    }
+    let pending: redis::RedisResult<Option<String>> = app_state
+        .redis
+        .clone()
+        .get(pending_comment_key(msg.chat.id))
+        .await;
+    match pending {
+        Ok(pending) => serde_json::from_str(&pending?).ok(),
+        Err(err) => {
+            tracing::warn!("Failed to load the pending feedback comment: {:?}", err);
+            None
+        }
+    }
+}
+
+#[tracing::instrument(skip_all, fields(chat_id = msg.chat.id))]
+pub async fn handle_comment(
+    bot: ThrottledBot,
+    msg: Message,
+    app_state: std::sync::Arc<AppState>,
+    language: Language,
+    pending: PendingComment,
+) -> anyhow::Result<()> {
+    let comment = msg.text().unwrap_or_default();
+    save(&bot, &app_state, msg.chat.id, language, pending, comment).await
+}
+
+async fn save(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    chat_id: i64,
+    language: Language,
+    pending: PendingComment,
+    comment: &str,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let _: () = app_state
+        .redis
+        .clone()
+        .del(pending_comment_key(chat_id))
+        .await?;
+    bot.send_message(chat_id, app_state.texts().get(language, "feedback_thanks"))
+        .await?;
+
+    let config = app_state.config();
+    let destination = match &config.feedback {
+        Some(feedback) => &feedback.destination,
+        None => return Ok(()),
+    };
+    if app_state.skips_external_writes() {
+        tracing::info!("Not saving the feedback to {:?}", destination.sheet);
+        return Ok(());
+    }
+    let row = vec![
+        format!("'{}", config::local_now().to_rfc3339()),
+        format!("'{}", pending.reference_id),
+        if pending.arrived { "так" } else { "ні" }.to_owned(),
+        pending.rating.to_string(),
+        format!("'{comment}"),
+    ];
+    app_state
+        .sheets_api
+        .spreadsheets()
+        .values_append(
+            ValueRange {
+                major_dimension: Some("ROWS".to_owned()),
+                range: None,
+                values: Some(vec![row]),
+            },
+            &destination.spreadsheet_id,
+            &quoted_tab(&destination.sheet),
+        )
+        .value_input_option("USER_ENTERED")
+        .insert_data_option("INSERT_ROWS")
+        .doit()
+        .await?;
+    Ok(())
+}
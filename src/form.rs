@@ -0,0 +1,784 @@
+//! Declarative definition of the questions asked to fill in a [`Contact`].
+//!
+//! A form is an ordered list of questions. The next question to ask is the first one whose field
+//! has no answer yet, so the dialogue state only needs to keep the contact being filled in. All help
+//! kinds share one flow in which a question can be limited to some help kinds, e.g. the number of
+//! passengers is only asked for evacuation. The flow can be redefined in the config, as a whole or
+//! per help kind; fields the code does not know about are kept in [`Contact::extra`] and written
+//! to the sheet after the built-in columns.
+
+use teloxide::types::{
+    CallbackQuery, InlineKeyboardButton, InlineKeyboardButtonKind, InlineKeyboardMarkup,
+    KeyboardButton, KeyboardMarkup, KeyboardRemove, ReplyMarkup,
+};
+
+use crate::{
+    regions,
+    state::{Contact, HelpCategory, HelpKind, Urgency},
+    texts::{Language, Texts},
+};
+
+/// Prefix of the callback data of the inline keyboards shown when questions are edited in place.
+const CALLBACK_PREFIX: &str = "form:";
+
+/// Callback data of the options of a multiple choice, followed by their index; a picked option is
+/// marked with [`PICKED_MARK`] on its button.
+const TOGGLE_PREFIX: &str = "form:toggle:";
+
+/// Callback data of the button finishing a multiple choice.
+const DONE_DATA: &str = "form:done";
+
+const PICKED_MARK: &str = "✅ ";
+
+/// Callback data of the button undoing the last answer under an inline keyboard.
+pub const UNDO_DATA: &str = "form:undo";
+
+const SKIP_HINT: &str = "(якшо нема, відправте повідомлення з текстом \"-\")";
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Question {
+    /// Name of the [`Contact`] field the answer is stored in.
+    pub field: String,
+    pub prompt: String,
+    #[serde(default)]
+    pub input: Input,
+    /// Message sent when the answer does not pass validation; defaults to the prompt.
+    #[serde(default)]
+    pub invalid_answer: Option<String>,
+    /// Help kinds the question is asked for; empty means all of them.
+    #[serde(default)]
+    pub help_kinds: Vec<HelpKind>,
+    /// Whether photos sent at this question are attached to the submission.
+    #[serde(default)]
+    pub accepts_attachments: bool,
+    /// Asks the question only when another answer includes an option, e.g. the quantity of a
+    /// category picked in a multiple choice.
+    #[serde(default)]
+    pub asked_if: Option<Condition>,
+    /// Checks of the answer on top of the ones of the input, in order; the first failed one is
+    /// explained to the user.
+    #[serde(default)]
+    pub validators: Vec<Validator>,
+    /// Label of the button finishing a multiple choice, see `button_done`.
+    #[serde(skip, default = "default_done_button")]
+    done_button: String,
+    /// The options of a choice as shown to the user, in their order; the options themselves when
+    /// empty. The answer is stored as the option, see [`Form::localized`].
+    #[serde(skip)]
+    option_labels: Vec<String>,
+}
+
+fn default_done_button() -> String {
+    "✔️ Готово".to_owned()
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Validator {
+    #[serde(flatten)]
+    pub rule: Rule,
+    /// Message sent when the check fails; defaults to the `validation_{rule}` text in the
+    /// language of the user.
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub enum Rule {
+    /// Not skipped with `-`.
+    Required,
+    MinLength {
+        chars: usize,
+    },
+    MaxLength {
+        chars: usize,
+    },
+    /// Matches the regular expression, e.g. `^\d{5}$` for a postal code; an invalid one fails
+    /// loading the configuration.
+    Regex {
+        #[serde(deserialize_with = "deserialize_regex")]
+        pattern: regex::Regex,
+    },
+    /// A number, possibly with a fraction.
+    Numeric,
+    /// One or more phone numbers of 10 to 13 digits, separated by commas.
+    Phone,
+}
+
+fn deserialize_regex<'de, D>(deserializer: D) -> Result<regex::Regex, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let pattern = <String as serde::Deserialize>::deserialize(deserializer)?;
+    regex::Regex::new(&pattern).map_err(serde::de::Error::custom)
+}
+
+impl Validator {
+    fn new(rule: Rule) -> Self {
+        Self { rule, error: None }
+    }
+
+    fn passes(&self, answer: &str) -> bool {
+        match &self.rule {
+            Rule::Required => answer != "-",
+            Rule::MinLength { chars } => answer.chars().count() >= *chars,
+            Rule::MaxLength { chars } => answer.chars().count() <= *chars,
+            Rule::Regex { pattern } => pattern.is_match(answer),
+            Rule::Numeric => answer.replace(',', ".").parse::<f64>().is_ok(),
+            Rule::Phone => answer.split([',', ';']).all(|number| {
+                let number = number.trim();
+                let digits = number.chars().filter(char::is_ascii_digit).count();
+                (10..=13).contains(&digits)
+                    && number
+                        .chars()
+                        .all(|c| c.is_ascii_digit() || " +-()".contains(c))
+            }),
+        }
+    }
+
+    /// The message explaining the failed check.
+    pub fn error(&self, texts: &Texts, language: Language) -> String {
+        if let Some(error) = &self.error {
+            return error.clone();
+        }
+        match &self.rule {
+            Rule::Required => texts.get(language, "validation_required").to_owned(),
+            Rule::MinLength { chars } => texts.render(
+                language,
+                "validation_min_length",
+                &[("chars", &chars.to_string())],
+            ),
+            Rule::MaxLength { chars } => texts.render(
+                language,
+                "validation_max_length",
+                &[("chars", &chars.to_string())],
+            ),
+            Rule::Regex { .. } => texts.get(language, "validation_regex").to_owned(),
+            Rule::Numeric => texts.get(language, "validation_numeric").to_owned(),
+            Rule::Phone => texts.get(language, "validation_phone").to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Condition {
+    pub field: String,
+    /// Option the answer to the field includes; the answers to a multiple choice are separated by
+    /// commas.
+    pub includes: String,
+}
+
+impl Condition {
+    fn holds(&self, contact: &Contact) -> bool {
+        contact.answer(&self.field).is_some_and(|answer| {
+            answer
+                .split(',')
+                .any(|option| option.trim().to_lowercase() == self.includes.to_lowercase())
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Input {
+    /// Any text.
+    #[default]
+    Text,
+    /// A whole number not smaller than `min`.
+    Number {
+        #[serde(default)]
+        min: u32,
+    },
+    /// One of the options shown on the keyboard.
+    Choice { options: Vec<String> },
+    /// One or more of the options toggled on the inline keyboard, or typed separated by commas.
+    MultipleChoice { options: Vec<String> },
+    /// Oblast picked from the paged keyboard.
+    Oblast,
+    /// Settlement of the previously picked oblast, picked from the keyboard or typed in.
+    City,
+}
+
+impl Input {
+    /// Id of the text explaining what kind of answer is expected, sent when the user answers with
+    /// a sticker, a voice message and the like.
+    pub fn expected_answer_text_id(&self) -> &'static str {
+        match self {
+            Input::Text => "expected_text_answer",
+            Input::Number { .. } => "expected_number_answer",
+            Input::Choice { .. } | Input::MultipleChoice { .. } | Input::Oblast | Input::City => {
+                "expected_choice_answer"
+            }
+        }
+    }
+}
+
+/// A message to send to the user together with its keyboard.
+pub struct Prompt {
+    pub text: String,
+    pub reply_markup: ReplyMarkup,
+}
+
+impl Prompt {
+    /// The keyboard of the prompt as an inline one, as only inline keyboards can be edited.
+    ///
+    /// The buttons are numbered in the callback data; the answer is the text of the pressed
+    /// button, see [`callback_answer`].
+    pub fn inline_keyboard(&self) -> Option<InlineKeyboardMarkup> {
+        let keyboard = match &self.reply_markup {
+            ReplyMarkup::Keyboard(keyboard) => keyboard,
+            ReplyMarkup::InlineKeyboard(keyboard) => return Some(keyboard.clone()),
+            _ => return None,
+        };
+        let mut index = 0;
+        Some(InlineKeyboardMarkup::new(keyboard.keyboard.iter().map(
+            |row| {
+                row.iter()
+                    .map(|button| {
+                        index += 1;
+                        InlineKeyboardButton::callback(
+                            button.text.clone(),
+                            format!("{CALLBACK_PREFIX}{index}"),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            },
+        )))
+    }
+}
+
+/// The option of a built-in choice in the language; the options of the other choices are shown as
+/// they are.
+fn option_label(field: &str, option: &str, texts: &Texts, language: Language) -> String {
+    let label = match field {
+        "urgency" => Urgency::from_label(option).map(|urgency| urgency.label_in(texts, language)),
+        "help_categories" => {
+            HelpCategory::from_label(option).map(|category| category.label_in(texts, language))
+        }
+        _ => None,
+    };
+    label.unwrap_or(option).to_owned()
+}
+
+pub fn is_form_callback(query: CallbackQuery) -> bool {
+    query
+        .data
+        .as_deref()
+        .is_some_and(|data| data.starts_with(CALLBACK_PREFIX))
+}
+
+/// Text of the button of a question message the user pressed; for the button finishing a
+/// multiple choice, the picked options separated by commas.
+pub fn callback_answer(query: &CallbackQuery) -> Option<String> {
+    let data = query.data.as_deref()?;
+    let mut buttons = query
+        .message
+        .as_ref()?
+        .reply_markup()?
+        .inline_keyboard
+        .iter()
+        .flatten();
+    if data == DONE_DATA {
+        let picked: Vec<&str> = buttons
+            .filter(|button| {
+                matches!(&button.kind, InlineKeyboardButtonKind::CallbackData(button_data) if button_data.starts_with(TOGGLE_PREFIX))
+            })
+            .filter_map(|button| button.text.strip_prefix(PICKED_MARK))
+            .collect();
+        return Some(picked.join(", "));
+    }
+    buttons
+        .find(|button| {
+            matches!(&button.kind, InlineKeyboardButtonKind::CallbackData(button_data) if button_data == data)
+        })
+        .map(|button| button.text.clone())
+}
+
+/// The keyboard of the multiple choice with the option of the pressed button picked or unpicked;
+/// `None` for the other buttons.
+pub fn toggled_keyboard(query: &CallbackQuery) -> Option<InlineKeyboardMarkup> {
+    let data = query.data.as_deref()?;
+    if !data.starts_with(TOGGLE_PREFIX) {
+        return None;
+    }
+    let mut keyboard = query.message.as_ref()?.reply_markup()?.clone();
+    let button = keyboard
+        .inline_keyboard
+        .iter_mut()
+        .flatten()
+        .find(|button| {
+            matches!(&button.kind, InlineKeyboardButtonKind::CallbackData(button_data) if button_data == data)
+        })?;
+    button.text = match button.text.strip_prefix(PICKED_MARK) {
+        Some(option) => option.to_owned(),
+        None => format!("{PICKED_MARK}{}", button.text),
+    };
+    Some(keyboard)
+}
+
+impl Question {
+    fn new(field: &str, prompt: &str, input: Input) -> Self {
+        Self {
+            field: field.to_owned(),
+            prompt: prompt.to_owned(),
+            input,
+            invalid_answer: None,
+            help_kinds: Vec::new(),
+            accepts_attachments: false,
+            asked_if: None,
+            validators: Vec::new(),
+            done_button: default_done_button(),
+            option_labels: Vec::new(),
+        }
+    }
+
+    /// The label of the option at the index.
+    fn option_label<'a>(&'a self, options: &'a [String], index: usize) -> &'a str {
+        self.option_labels.get(index).unwrap_or(&options[index])
+    }
+
+    /// The option the text of a button or a typed answer stands for.
+    fn find_option<'a>(&self, options: &'a [String], text: &str) -> Option<&'a String> {
+        let text = text.to_lowercase();
+        options.iter().enumerate().find_map(|(index, option)| {
+            (option.to_lowercase() == text
+                || self.option_label(options, index).to_lowercase() == text)
+                .then_some(option)
+        })
+    }
+
+    fn only_for(self, help_kinds: &[&str]) -> Self {
+        Self {
+            help_kinds: help_kinds.iter().map(|slug| HelpKind::new(slug)).collect(),
+            ..self
+        }
+    }
+
+    fn asked_if(self, field: &str, includes: &str) -> Self {
+        Self {
+            asked_if: Some(Condition {
+                field: field.to_owned(),
+                includes: includes.to_owned(),
+            }),
+            ..self
+        }
+    }
+
+    /// The first validator the answer fails.
+    pub fn failed_validator(&self, answer: &str) -> Option<&Validator> {
+        let answer = answer.trim();
+        self.validators
+            .iter()
+            .find(|validator| !validator.passes(answer))
+    }
+
+    pub fn applies_to(&self, help_kind: &HelpKind) -> bool {
+        self.help_kinds.is_empty() || self.help_kinds.contains(help_kind)
+    }
+
+    pub fn prompt(&self, contact: &Contact) -> Prompt {
+        Prompt {
+            text: self.prompt.clone(),
+            reply_markup: self.keyboard(contact, 0),
+        }
+    }
+
+    fn keyboard(&self, contact: &Contact, page: usize) -> ReplyMarkup {
+        match &self.input {
+            Input::Text | Input::Number { .. } => KeyboardRemove::new().into(),
+            Input::Choice { options } => KeyboardMarkup::new(vec![(0..options.len())
+                .map(|index| KeyboardButton::new(self.option_label(options, index)))
+                .collect::<Vec<_>>()])
+            .into(),
+            Input::MultipleChoice { options } => InlineKeyboardMarkup::new(
+                (0..options.len())
+                    .map(|index| {
+                        vec![InlineKeyboardButton::callback(
+                            self.option_label(options, index).to_owned(),
+                            format!("{TOGGLE_PREFIX}{index}"),
+                        )]
+                    })
+                    .chain(std::iter::once(vec![InlineKeyboardButton::callback(
+                        self.done_button.clone(),
+                        DONE_DATA.to_owned(),
+                    )])),
+            )
+            .into(),
+            Input::Oblast => regions::oblasts_keyboard(page).into(),
+            Input::City => {
+                regions::cities_keyboard(contact.region.as_deref().unwrap_or_default()).into()
+            }
+        }
+    }
+
+    fn invalid_answer(&self, contact: &Contact) -> Prompt {
+        Prompt {
+            text: self
+                .invalid_answer
+                .clone()
+                .unwrap_or_else(|| self.prompt.clone()),
+            reply_markup: self.keyboard(contact, 0),
+        }
+    }
+
+    /// Validates the answer and stores it in the contact, or returns the prompt to re-ask with.
+    pub fn accept(&self, contact: &mut Contact, text: &str) -> Result<(), Prompt> {
+        let text = text.trim();
+        let value = match &self.input {
+            Input::Text | Input::City => text.to_owned(),
+            Input::Number { min } => match text.parse::<u32>() {
+                Ok(number) if number >= *min => number.to_string(),
+                _ => return Err(self.invalid_answer(contact)),
+            },
+            Input::Choice { options } => match self.find_option(options, text) {
+                Some(option) => option.clone(),
+                None => return Err(self.invalid_answer(contact)),
+            },
+            Input::MultipleChoice { options } => {
+                let mut picked: Vec<&String> = Vec::new();
+                for answer in text
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|answer| !answer.is_empty())
+                {
+                    match self.find_option(options, answer) {
+                        Some(option) if !picked.contains(&option) => picked.push(option),
+                        Some(_) => {}
+                        None => return Err(self.invalid_answer(contact)),
+                    }
+                }
+                if picked.is_empty() {
+                    return Err(self.invalid_answer(contact));
+                }
+                picked
+                    .into_iter()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            }
+            Input::Oblast => {
+                if let Some(page) = regions::parse_page_button(text) {
+                    return Err(Prompt {
+                        text: self.prompt.clone(),
+                        reply_markup: self.keyboard(contact, page),
+                    });
+                }
+                match regions::find_oblast(text) {
+                    Some(oblast) => oblast.to_owned(),
+                    None => return Err(self.invalid_answer(contact)),
+                }
+            }
+        };
+        contact
+            .set_answer(&self.field, value)
+            .map_err(|_| self.invalid_answer(contact))
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(transparent)]
+pub struct Form {
+    pub questions: Vec<Question>,
+}
+
+impl Form {
+    /// The first question which has not been answered yet; `None` once the form is complete.
+    pub fn next_question(&self, contact: &Contact) -> Option<&Question> {
+        self.questions.iter().find(|question| {
+            question
+                .asked_if
+                .as_ref()
+                .is_none_or(|condition| condition.holds(contact))
+                && !contact.has_answer(&question.field)
+        })
+    }
+
+    /// The question answered last, in the order of the form, whose answer can be undone.
+    pub fn last_answered(&self, contact: &Contact) -> Option<&Question> {
+        self.questions
+            .iter()
+            .take_while(|question| {
+                self.next_question(contact)
+                    .is_none_or(|next| next.field != question.field)
+            })
+            .filter(|question| contact.has_answer(&question.field))
+            .last()
+    }
+
+    /// The form with the prompts translated into the language where the texts have them, see
+    /// `question_{field}` and `question_{field}_invalid`, and the options of the built-in choices
+    /// in the language.
+    pub fn localized(self, texts: &Texts, language: Language) -> Self {
+        let questions = self
+            .questions
+            .into_iter()
+            .map(|question| {
+                let translate = |suffix: &str| {
+                    texts
+                        .translation(language, &format!("question_{}{suffix}", question.field))
+                        .map(str::to_owned)
+                };
+                let prompt = translate("").unwrap_or_else(|| question.prompt.clone());
+                let invalid_answer =
+                    translate("_invalid").or_else(|| question.invalid_answer.clone());
+                let option_labels = match &question.input {
+                    Input::Choice { options } | Input::MultipleChoice { options } => options
+                        .iter()
+                        .map(|option| option_label(&question.field, option, texts, language))
+                        .collect(),
+                    _ => Vec::new(),
+                };
+                Question {
+                    prompt,
+                    invalid_answer,
+                    done_button: texts.get(language, "button_done").to_owned(),
+                    option_labels,
+                    ..question
+                }
+            })
+            .collect();
+        Self { questions }
+    }
+
+    /// The form with the prompts of the questions under experiment in the variants the contact
+    /// was assigned, see [`crate::experiments`].
+    pub fn with_variants(self, texts: &Texts, language: Language, contact: &Contact) -> Self {
+        let questions = self
+            .questions
+            .into_iter()
+            .map(|question| {
+                let prompt = contact
+                    .prompt_variants
+                    .get(&question.field)
+                    .and_then(|variant| {
+                        texts.translation(
+                            language,
+                            &crate::experiments::prompt_text_id(&question.field, variant),
+                        )
+                    })
+                    .map_or(question.prompt, str::to_owned);
+                Question { prompt, ..question }
+            })
+            .collect();
+        Self { questions }
+    }
+
+    /// The questions of the flow which are asked for the help kind.
+    pub fn for_help_kind(&self, help_kind: &HelpKind) -> Self {
+        Self {
+            questions: self
+                .questions
+                .iter()
+                .filter(|question| question.applies_to(help_kind))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// The flow used when the config does not define one.
+    pub fn builtin() -> Self {
+        const NEEDS: &[&str] = &[HelpKind::NEED_EVACUATION, HelpKind::NEED_HUMANITARIAN_HELP];
+        const EVACUATION: &[&str] = &[HelpKind::NEED_EVACUATION];
+        const HUMANITARIAN: &[&str] = &[HelpKind::NEED_HUMANITARIAN_HELP];
+        const DRIVER: &[&str] = &[HelpKind::PROVIDING_DRIVER];
+        let quantity = |category: HelpCategory, prompt: &str| {
+            Question::new(category.quantity_field(), prompt, Input::Text)
+                .only_for(HUMANITARIAN)
+                .asked_if("help_categories", category.label())
+        };
+
+        let questions = vec![
+            Question::new(
+                "full_name",
+                "Ваше ПІБ? (призвіще, імʼя, побатькові)",
+                Input::Text,
+            ),
+            Question {
+                validators: vec![Validator::new(Rule::Phone)],
+                ..Question::new("phone_numbers", "Контактні номери телефону?", Input::Text)
+            },
+            Question {
+                invalid_answer: Some("Оберіть область з клавіатури".to_owned()),
+                ..Question::new("region", "Оберіть область", Input::Oblast)
+            },
+            Question::new(
+                "city",
+                "Оберіть населений пункт або напишіть його назву",
+                Input::City,
+            ),
+            Question::new(
+                "street",
+                "Вулиця? Можна також надіслати геопозицію.",
+                Input::Text,
+            ),
+            Question::new("building", "Номер будинку та квартири?", Input::Text),
+            Question::new(
+                "address_notes",
+                &format!("Як вас знайти: підʼїзд, поверх, орієнтири? {SKIP_HINT}"),
+                Input::Text,
+            ),
+            Question {
+                invalid_answer: Some(
+                    "Оберіть один з варіантів: критично, протягом доби чи не терміново.".to_owned(),
+                ),
+                ..Question::new(
+                    "urgency",
+                    "Наскільки терміново потрібна допомога?",
+                    Input::Choice {
+                        options: Urgency::ALL
+                            .iter()
+                            .map(|urgency| urgency.label().to_owned())
+                            .collect(),
+                    },
+                )
+            }
+            .only_for(NEEDS),
+            Question {
+                invalid_answer: Some("Вкажіть кількість людей числом, наприклад: 3".to_owned()),
+                ..Question::new(
+                    "passengers",
+                    "Скільки людей потрібно евакуювати? (числом)",
+                    Input::Number { min: 1 },
+                )
+            }
+            .only_for(EVACUATION),
+            Question::new(
+                "children",
+                &format!("Чи є серед них діти? Вкажіть кількість та вік {SKIP_HINT}"),
+                Input::Text,
+            )
+            .only_for(EVACUATION),
+            Question::new(
+                "limited_mobility",
+                &format!("Чи є літні люди або люди з обмеженою мобільністю (лежачі, на візку)? Опишіть {SKIP_HINT}"),
+                Input::Text,
+            )
+            .only_for(EVACUATION),
+            Question::new(
+                "pets",
+                &format!("Чи будуть з вами домашні тварини? Які саме {SKIP_HINT}"),
+                Input::Text,
+            )
+            .only_for(EVACUATION),
+            Question::new(
+                "preferred_language",
+                "Якою мовою вам зручніше спілкуватися? (якшо українською, відправте повідомлення з текстом \"-\")",
+                Input::Text,
+            )
+            .only_for(NEEDS),
+            Question::new(
+                "accessibility_needs",
+                &format!("Чи потрібна особлива допомога: пересування на візку, порушення слуху чи зору? Опишіть {SKIP_HINT}"),
+                Input::Text,
+            )
+            .only_for(NEEDS),
+            Question::new(
+                "medical_needs",
+                &format!("Чи потрібні терміново ліки або медична допомога (інсулін, діаліз, перевʼязки)? Опишіть {SKIP_HINT}"),
+                Input::Text,
+            )
+            .only_for(NEEDS),
+            Question {
+                invalid_answer: Some(
+                    "Оберіть одну або кілька категорій через кому: їжа, ліки, одяг, генератор."
+                        .to_owned(),
+                ),
+                ..Question::new(
+                    "help_categories",
+                    "Що саме потрібно? Позначте одну або кілька категорій і натисніть «Готово» або напишіть кілька через кому.",
+                    Input::MultipleChoice {
+                        options: HelpCategory::ALL
+                            .iter()
+                            .map(|category| category.label().to_owned())
+                            .collect(),
+                    },
+                )
+            }
+            .only_for(HUMANITARIAN),
+            quantity(
+                HelpCategory::Food,
+                "Скільки їжі потрібно? (наприклад: 3 продуктові набори)",
+            ),
+            quantity(HelpCategory::Medicine, "Які ліки та скільки потрібно?"),
+            quantity(
+                HelpCategory::Clothes,
+                "Який одяг потрібен: для кого, розміри, кількість?",
+            ),
+            quantity(
+                HelpCategory::Generator,
+                "Генератор якої потужності потрібен і скільки?",
+            ),
+            Question::new(
+                "vehicle_type",
+                "Який у вас транспорт? (легкове авто, мінівен, бус, вантажівка тощо)",
+                Input::Text,
+            )
+            .only_for(DRIVER),
+            Question {
+                invalid_answer: Some("Вкажіть кількість місць числом, наприклад: 4".to_owned()),
+                ..Question::new(
+                    "seats",
+                    "Скільки пасажирів ви можете взяти? (числом)",
+                    Input::Number { min: 1 },
+                )
+            }
+            .only_for(DRIVER),
+            Question {
+                invalid_answer: Some(
+                    "Вкажіть відстань в кілометрах числом, наприклад: 300".to_owned(),
+                ),
+                ..Question::new(
+                    "fuel_range_km",
+                    "На яку відстань (в км) вам вистачає пального? (числом)",
+                    Input::Number { min: 0 },
+                )
+            }
+            .only_for(DRIVER),
+            Question::new(
+                "cities_covered",
+                "Які міста та населені пункти ви можете охопити? (через кому)",
+                Input::Text,
+            )
+            .only_for(DRIVER),
+            Question::new(
+                "bank_details",
+                &format!("Банківські реквізити для збору коштів? {SKIP_HINT}"),
+                Input::Text,
+            )
+            .only_for(&[HelpKind::PROVIDING_COLLECTING_HUMANITARIAN_HELP]),
+            Question {
+                accepts_attachments: true,
+                ..Question::new(
+                    "comments",
+                    &format!("Додатковий коментар? {SKIP_HINT}"),
+                    Input::Text,
+                )
+            },
+        ];
+        Self { questions }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translated_options_are_stored_in_ukrainian() {
+        let texts = Texts::from_env().unwrap();
+        let form = Form::builtin().localized(&texts, Language::En);
+        let question = form
+            .questions
+            .iter()
+            .find(|question| question.field == "urgency")
+            .unwrap();
+        let mut contact = Contact::default();
+        let answer = Urgency::Critical.label_in(&texts, Language::En);
+        assert!(question.accept(&mut contact, answer).is_ok());
+        assert!(matches!(contact.urgency, Some(Urgency::Critical)));
+        assert_eq!(
+            contact.answer("urgency").as_deref(),
+            Some(Urgency::Critical.label())
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Topics of the requests in the coordinators group, when it is a forum supergroup.
+//!
+//! Every saved submission gets its own topic named by its reference id, and everything about the
+//! request is posted there: the submission, the relayed messages, the problems with its row, the
+//! driver taking it and the status changes. The topic is closed once the request is done or
+//! cancelled. The Telegram client of the bot predates the topics, so the topics are created and
+//! closed with direct requests, and a message is put into a topic by replying to the message which
+//! opened it.
+
+use teloxide::{payloads::SendMessageSetters, prelude2::*};
+
+use crate::{redis_connection, AppState, ThrottledBot};
+
+/// Redis hash of the ids of the topics keyed by reference id.
+const TOPICS_KEY: &str = "forum_topics";
+
+static IS_FORUM: once_cell::sync::OnceCell<bool> = once_cell::sync::OnceCell::new();
+
+/// Calls the method of the Bot API directly.
+async fn call(
+    bot: &ThrottledBot,
+    method: &str,
+    body: serde_json::Value,
+) -> anyhow::Result<serde_json::Value> {
+    #[derive(serde::Deserialize)]
+    struct Response {
+        ok: bool,
+        #[serde(default)]
+        result: serde_json::Value,
+        #[serde(default)]
+        description: Option<String>,
+    }
+
+    let response: Response = reqwest::Client::new()
+        .post(format!(
+            "https://api.telegram.org/bot{}/{method}",
+            bot.inner().inner().token()
+        ))
+        .json(&body)
+        .send()
+        .await?
+        .json()
+        .await?;
+    if !response.ok {
+        anyhow::bail!(
+            "Telegram refused {}: {}",
+            method,
+            response.description.unwrap_or_default()
+        );
+    }
+    Ok(response.result)
+}
+
+/// Whether the coordinators group is a forum, asked once.
+async fn is_forum(bot: &ThrottledBot, admin_chat_id: i64) -> bool {
+    if let Some(is_forum) = IS_FORUM.get() {
+        return *is_forum;
+    }
+    match call(
+        bot,
+        "getChat",
+        serde_json::json!({ "chat_id": admin_chat_id }),
+    )
+    .await
+    {
+        Ok(chat) => *IS_FORUM.get_or_init(|| chat["is_forum"].as_bool().unwrap_or(false)),
+        Err(err) => {
+            tracing::warn!(
+                "Failed to check whether the coordinators group is a forum: {:?}",
+                err
+            );
+            false
+        }
+    }
+}
+
+/// Opens the topic of the request if the coordinators group is a forum, returning its id.
+pub async fn create(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    reference_id: &str,
+) -> anyhow::Result<Option<i32>> {
+    use redis::AsyncCommands;
+
+    let admin_chat_id = match app_state.admin_chat_id {
+        Some(admin_chat_id) if is_forum(bot, admin_chat_id).await => admin_chat_id,
+        _ => return Ok(None),
+    };
+    let topic = call(
+        bot,
+        "createForumTopic",
+        serde_json::json!({ "chat_id": admin_chat_id, "name": reference_id }),
+    )
+    .await?;
+    let topic_id = topic["message_thread_id"]
+        .as_i64()
+        .and_then(|topic_id| i32::try_from(topic_id).ok())
+        .ok_or_else(|| anyhow::anyhow!("createForumTopic returned no topic id: {}", topic))?;
+    let _: () = app_state
+        .redis
+        .clone()
+        .hset(redis_connection::key(TOPICS_KEY), reference_id, topic_id)
+        .await?;
+    Ok(Some(topic_id))
+}
+
+/// The topic of the request, if it has one.
+pub async fn topic(app_state: &AppState, reference_id: &str) -> anyhow::Result<Option<i32>> {
+    use redis::AsyncCommands;
+
+    Ok(app_state
+        .redis
+        .clone()
+        .hget(redis_connection::key(TOPICS_KEY), reference_id)
+        .await?)
+}
+
+/// Posts the text about the request to its topic, or to the coordinators group without one.
+pub async fn post(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    reference_id: &str,
+    text: String,
+) -> anyhow::Result<()> {
+    let admin_chat_id = match app_state.admin_chat_id {
+        Some(admin_chat_id) => admin_chat_id,
+        None => return Ok(()),
+    };
+    let request = bot.send_message(admin_chat_id, text);
+    match topic(app_state, reference_id).await? {
+        Some(topic_id) => request.reply_to_message_id(topic_id).await?,
+        None => request.await?,
+    };
+    Ok(())
+}
+
+/// Posts the new status of the request to its topic, closing it once the request is over.
+pub async fn post_status(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    reference_id: &str,
+    status: &str,
+    closed: bool,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let (admin_chat_id, topic_id) = match (
+        app_state.admin_chat_id,
+        topic(app_state, reference_id).await?,
+    ) {
+        (Some(admin_chat_id), Some(topic_id)) => (admin_chat_id, topic_id),
+        _ => return Ok(()),
+    };
+    bot.send_message(admin_chat_id, format!("Новий статус заявки: {status}"))
+        .reply_to_message_id(topic_id)
+        .await?;
+    if closed {
+        call(
+            bot,
+            "closeForumTopic",
+            serde_json::json!({ "chat_id": admin_chat_id, "message_thread_id": topic_id }),
+        )
+        .await?;
+        let _: () = app_state
+            .redis
+            .clone()
+            .hdel(redis_connection::key(TOPICS_KEY), reference_id)
+            .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,246 @@
+//! Funnel analytics: how many users reach each step of the dialogue, so that the coordinators see
+//! where users give up and can tune the wording of that step.
+//!
+//! Every step is counted in the Redis hash of its day, `funnel:<YYYY-MM-DD>` in the local time
+//! zone, and in the all-time `funnel:total` hash. `/stats [days]` shows the counts of the last
+//! days, 7 by default, to the coordinators, together with the results of the
+//! [`crate::experiments`]. When `COLLECT_VOLUNTEERS_BOT_METRICS_LISTEN_ADDR` is
+//! set, e.g. to `0.0.0.0:9090`, the all-time counts are also served to Prometheus at `/metrics`.
+
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+
+use axum::{extract::Extension, http::StatusCode, routing::get, Router};
+use teloxide::prelude2::*;
+
+use crate::{
+    config, experiments,
+    redact::Redacted,
+    redis_connection,
+    shutdown::Shutdown,
+    state::{Contact, HelpKind},
+    AppState, ThrottledBot,
+};
+
+const TOTAL_KEY: &str = "funnel:total";
+
+/// How long the counts of a day are kept.
+const DAY_TTL_SECS: usize = 90 * 24 * 60 * 60;
+
+/// The step abandoned at the confirmation of an answered form.
+const CONFIRMATION: &str = "confirmation";
+
+pub enum Step<'a> {
+    /// "I can help" or "I need help" was pressed, or a deep link to a kind of help was opened.
+    KindSelection,
+    /// The privacy notice was accepted and the first question of the form was asked.
+    FormStarted,
+    /// The question with the field was answered.
+    Answered(&'a str),
+    /// The submission was confirmed and saved.
+    Confirmed,
+    /// The form was cancelled or expired at the question with the field, or at the confirmation.
+    Abandoned(&'a str),
+    /// A form under an experiment got the variant, answered its question or was confirmed, see
+    /// [`crate::experiments`].
+    Experiment {
+        field: &'a str,
+        variant: &'a str,
+        outcome: &'a str,
+    },
+}
+
+impl Step<'_> {
+    fn name(&self) -> String {
+        match self {
+            Step::KindSelection => "kind_selection".to_owned(),
+            Step::FormStarted => "form_started".to_owned(),
+            Step::Answered(field) => format!("answered:{field}"),
+            Step::Confirmed => "confirmed".to_owned(),
+            Step::Abandoned(field) => format!("abandoned:{field}"),
+            Step::Experiment {
+                field,
+                variant,
+                outcome,
+            } => format!("experiment:{field}:{variant}:{outcome}"),
+        }
+    }
+}
+
+fn day_key(day: chrono::NaiveDate) -> String {
+    redis_connection::key(&format!("funnel:{}", day.format("%Y-%m-%d")))
+}
+
+/// Counts the step; a failure is only logged, as the analytics must not break the dialogue.
+pub async fn record(app_state: &AppState, step: Step<'_>) {
+    let name = step.name();
+    let day_key = day_key(config::local_now().naive_local().date());
+    let mut redis = app_state.redis.clone();
+    let recorded: redis::RedisResult<()> = redis::pipe()
+        .hincr(&day_key, &name, 1)
+        .ignore()
+        .expire(&day_key, DAY_TTL_SECS)
+        .ignore()
+        .hincr(redis_connection::key(TOTAL_KEY), &name, 1)
+        .ignore()
+        .query_async(&mut redis)
+        .await;
+    if let Err(err) = recorded {
+        tracing::warn!("Failed to record the funnel step {}: {:?}", name, err);
+    }
+}
+
+/// The counts of the steps reached on the day.
+pub async fn day_counts(
+    redis: &mut redis::aio::MultiplexedConnection,
+    day: chrono::NaiveDate,
+) -> redis::RedisResult<HashMap<String, u64>> {
+    use redis::AsyncCommands;
+
+    redis.hgetall(day_key(day)).await
+}
+
+/// Counts the form of the help kind as abandoned at its current question.
+pub async fn record_abandoned(
+    app_state: &AppState,
+    help_kind: &HelpKind,
+    contact: Option<&Contact>,
+) {
+    let contact = contact.cloned().unwrap_or_default();
+    let form = app_state.config().form(help_kind);
+    let field = form
+        .next_question(&contact)
+        .map_or(CONFIRMATION, |question| question.field.as_str());
+    record(app_state, Step::Abandoned(field)).await;
+}
+
+/// Replies to `/stats [days]` with the counts of the steps over the last days.
+pub async fn handle_stats(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    msg: &Message,
+    args: &str,
+) -> anyhow::Result<()> {
+    let days = match args.trim() {
+        "" => 7,
+        days => match days.parse::<u32>() {
+            Ok(days) if (1..=90).contains(&days) => days,
+            _ => {
+                bot.send_message(
+                    msg.chat.id,
+                    "Використання: /stats [кількість днів від 1 до 90]",
+                )
+                .await?;
+                return Ok(());
+            }
+        },
+    };
+    tracing::info!(
+        "Funnel stats for {} days requested by {:?}",
+        days,
+        Redacted(&msg.chat)
+    );
+    let today = config::local_now().naive_local().date();
+    let mut redis = app_state.redis.clone();
+    let mut counts: HashMap<String, u64> = HashMap::new();
+    for days_ago in 0..days {
+        let day = today - chrono::Duration::days(days_ago.into());
+        for (step, count) in day_counts(&mut redis, day).await? {
+            *counts.entry(step).or_default() += count;
+        }
+    }
+    bot.send_message(msg.chat.id, render_stats(days, &counts))
+        .await?;
+    Ok(())
+}
+
+fn render_stats(days: u32, counts: &HashMap<String, u64>) -> String {
+    let entered = counts.get("kind_selection").copied().unwrap_or_default();
+    let line = |label: &str, count: u64| match (count * 100).checked_div(entered) {
+        Some(percent) => format!("{label}: {count} ({percent}%)"),
+        None => format!("{label}: {count}"),
+    };
+    // The steps of the forms, most reached first.
+    let by_prefix = |prefix: &str| {
+        let mut steps: Vec<(&str, u64)> = counts
+            .iter()
+            .filter_map(|(step, count)| Some((step.strip_prefix(prefix)?, *count)))
+            .collect();
+        steps.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        steps
+    };
+
+    let mut lines = vec![
+        format!("Воронка за {days} дн.:"),
+        format!("Обрали вид допомоги: {entered}"),
+        line(
+            "Почали анкету",
+            counts.get("form_started").copied().unwrap_or_default(),
+        ),
+    ];
+    for (field, count) in by_prefix("answered:") {
+        lines.push(line(&format!("Відповіли на «{field}»"), count));
+    }
+    lines.push(line(
+        "Підтвердили заявку",
+        counts.get("confirmed").copied().unwrap_or_default(),
+    ));
+    let abandoned = by_prefix("abandoned:");
+    if !abandoned.is_empty() {
+        lines.push(String::new());
+        lines.push("Покинули на кроці:".to_owned());
+        for (field, count) in abandoned {
+            lines.push(line(&format!("«{field}»"), count));
+        }
+    }
+    lines.extend(experiments::render_stats(counts));
+    lines.join("\n")
+}
+
+/// Serves the all-time counts to Prometheus if `COLLECT_VOLUNTEERS_BOT_METRICS_LISTEN_ADDR` is set,
+/// until the shutdown.
+pub async fn serve_metrics(app_state: Arc<AppState>, mut shutdown: Shutdown) -> anyhow::Result<()> {
+    let listen_addr: SocketAddr = match std::env::var("COLLECT_VOLUNTEERS_BOT_METRICS_LISTEN_ADDR")
+    {
+        Ok(listen_addr) => listen_addr.parse()?,
+        Err(_) => return Ok(()),
+    };
+    let app = Router::new()
+        .route("/metrics", get(metrics))
+        .layer(Extension(app_state.clone()));
+    let server = axum::Server::try_bind(&listen_addr)?
+        .serve(app.into_make_service())
+        .with_graceful_shutdown(async move { shutdown.requested().await });
+    tracing::info!("Serving metrics on {}", listen_addr);
+    app_state.tasks.spawn(async move {
+        if let Err(err) = server.await {
+            tracing::error!("Metrics server failed: {:?}", err);
+        }
+    });
+    Ok(())
+}
+
+async fn metrics(Extension(app_state): Extension<Arc<AppState>>) -> (StatusCode, String) {
+    use redis::AsyncCommands;
+
+    let mut redis = app_state.redis.clone();
+    let counts: HashMap<String, u64> = match redis.hgetall(redis_connection::key(TOTAL_KEY)).await {
+        Ok(counts) => counts,
+        Err(err) => {
+            tracing::warn!("metrics: failed to read the funnel counts: {:?}", err);
+            return (StatusCode::SERVICE_UNAVAILABLE, String::new());
+        }
+    };
+    let mut steps: Vec<_> = counts.into_iter().collect();
+    steps.sort();
+    let mut body = String::from(
+        "# HELP connect_volunteers_bot_funnel_steps_total Times the users reached a step of the dialogue.\n\
+         # TYPE connect_volunteers_bot_funnel_steps_total counter\n",
+    );
+    for (step, count) in steps {
+        let step = step.replace('\\', "\\\\").replace('"', "\\\"");
+        body.push_str(&format!(
+            "connect_volunteers_bot_funnel_steps_total{{step=\"{step}\"}} {count}\n"
+        ));
+    }
+    (StatusCode::OK, body)
+}
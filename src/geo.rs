@@ -0,0 +1,226 @@
+//! Coordinates of the requesters and the drivers, so that the nearby drivers are offered an
+//! evacuation request first.
+//!
+//! The coordinates come from the location shared as the answer to the address question, or from
+//! geocoding the address with a Nominatim-compatible geocoder configured in `geo`. The drivers are
+//! ranked by the straight-line distance, or by the road distance when an OSRM server is
+//! configured too; the straight line is the fallback whenever OSRM fails.
+
+use crate::{state::Contact, AppState};
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Geo {
+    /// Base URL of a Nominatim-compatible geocoder, e.g. `https://nominatim.openstreetmap.org`.
+    pub geocoder_url: String,
+    /// Base URL of an OSRM server to rank the drivers by the road distance.
+    #[serde(default)]
+    pub osrm_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Coordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Coordinates {
+    /// Great-circle distance in kilometers.
+    pub fn haversine_km(self, other: Self) -> f64 {
+        const EARTH_RADIUS_KM: f64 = 6371.0;
+
+        let (lat1, lat2) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lon = (other.longitude - self.longitude).to_radians();
+        let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+    }
+}
+
+impl std::fmt::Display for Coordinates {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.5}, {:.5}", self.latitude, self.longitude)
+    }
+}
+
+/// The coordinates of the contact: the shared location, or the geocoded address.
+pub async fn locate(app_state: &AppState, contact: &Contact) -> Option<Coordinates> {
+    if contact.coordinates.is_some() {
+        return contact.coordinates;
+    }
+    let geo = app_state.config().geo.clone()?;
+    let address = contact.full_address();
+    if address.is_empty() {
+        return None;
+    }
+    match geocode(&geo, &address).await {
+        Ok(coordinates) => coordinates,
+        Err(err) => {
+            tracing::warn!("Failed to geocode an address: {:?}", err);
+            None
+        }
+    }
+}
+
+async fn geocode(geo: &Geo, address: &str) -> anyhow::Result<Option<Coordinates>> {
+    #[derive(serde::Deserialize)]
+    struct Place {
+        lat: String,
+        lon: String,
+    }
+
+    let places: Vec<Place> = reqwest::Client::new()
+        .get(format!("{}/search", geo.geocoder_url.trim_end_matches('/')))
+        .query(&[
+            ("q", address),
+            ("format", "json"),
+            ("limit", "1"),
+            ("countrycodes", "ua"),
+        ])
+        // Nominatim refuses the requests without an identifying user agent.
+        .header(reqwest::header::USER_AGENT, "collect-volunteers-bot")
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(match places.into_iter().next() {
+        Some(place) => Some(Coordinates {
+            latitude: place.lat.parse()?,
+            longitude: place.lon.parse()?,
+        }),
+        None => None,
+    })
+}
+
+/// Orders the items by their distance from the origin, the nearest first; the items without
+/// coordinates, or all of them without the origin, keep their order after the located ones.
+pub async fn nearest_first<T>(
+    app_state: &AppState,
+    origin: Option<Coordinates>,
+    items: Vec<T>,
+    coordinates: impl Fn(&T) -> Option<Coordinates>,
+) -> Vec<T> {
+    let origin = match origin {
+        Some(origin) => origin,
+        None => return items,
+    };
+    let located: Vec<Coordinates> = items.iter().filter_map(&coordinates).collect();
+    let mut distances: Vec<f64> = located
+        .iter()
+        .map(|destination| origin.haversine_km(*destination))
+        .collect();
+    if let Some(osrm_url) = app_state
+        .config()
+        .geo
+        .as_ref()
+        .and_then(|geo| geo.osrm_url.clone())
+    {
+        match road_distances_km(&osrm_url, origin, &located).await {
+            Ok(road_distances) => {
+                for (distance, road_distance) in distances.iter_mut().zip(road_distances) {
+                    if let Some(road_distance) = road_distance {
+                        *distance = road_distance;
+                    }
+                }
+            }
+            Err(err) => tracing::warn!("Failed to get the road distances: {:?}", err),
+        }
+    }
+    let mut distances = distances.into_iter();
+    let mut ranked: Vec<(Option<f64>, T)> = items
+        .into_iter()
+        .map(|item| {
+            let distance = coordinates(&item).and_then(|_| distances.next());
+            (distance, item)
+        })
+        .collect();
+    ranked.sort_by(|(a, _), (b, _)| match (a, b) {
+        (Some(a), Some(b)) => a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    });
+    ranked.into_iter().map(|(_, item)| item).collect()
+}
+
+/// Road distances from the origin to the destinations with the OSRM table service; `None` for the
+/// unreachable ones.
+async fn road_distances_km(
+    osrm_url: &str,
+    origin: Coordinates,
+    destinations: &[Coordinates],
+) -> anyhow::Result<Vec<Option<f64>>> {
+    #[derive(serde::Deserialize)]
+    struct Table {
+        code: String,
+        #[serde(default)]
+        distances: Vec<Vec<Option<f64>>>,
+    }
+
+    if destinations.is_empty() {
+        return Ok(Vec::new());
+    }
+    let points: Vec<String> = std::iter::once(origin)
+        .chain(destinations.iter().copied())
+        .map(|point| format!("{},{}", point.longitude, point.latitude))
+        .collect();
+    let table: Table = reqwest::Client::new()
+        .get(format!(
+            "{}/table/v1/driving/{}",
+            osrm_url.trim_end_matches('/'),
+            points.join(";")
+        ))
+        .query(&[("sources", "0"), ("annotations", "distance")])
+        .send()
+        .await?
+        .json()
+        .await?;
+    anyhow::ensure!(table.code == "Ok", "OSRM returned {}", table.code);
+    let from_origin = table.distances.into_iter().next().unwrap_or_default();
+    // The first one is the distance from the origin to itself.
+    Ok(from_origin
+        .into_iter()
+        .skip(1)
+        .map(|meters| meters.map(|meters| meters / 1000.0))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{nearest_first, Coordinates};
+    use crate::testing::Harness;
+
+    const KYIV: Coordinates = Coordinates {
+        latitude: 50.4501,
+        longitude: 30.5234,
+    };
+    const LVIV: Coordinates = Coordinates {
+        latitude: 49.8397,
+        longitude: 24.0297,
+    };
+    const KHARKIV: Coordinates = Coordinates {
+        latitude: 49.9935,
+        longitude: 36.2304,
+    };
+
+    #[test]
+    fn great_circle_distances_are_in_kilometers() {
+        assert!((KYIV.haversine_km(LVIV) - 467.5).abs() < 0.5);
+        assert!((LVIV.haversine_km(KYIV) - 467.5).abs() < 0.5);
+        assert_eq!(KYIV.haversine_km(KYIV), 0.0);
+    }
+
+    #[tokio::test]
+    async fn the_nearest_come_first_and_the_unlocated_last() -> anyhow::Result<()> {
+        let harness = Harness::new().await?;
+        let items = vec![
+            ("Lviv", Some(LVIV)),
+            ("unknown", None),
+            ("Kharkiv", Some(KHARKIV)),
+        ];
+        let ranked = nearest_first(&harness.app_state, Some(KYIV), items, |item| item.1).await;
+        let names: Vec<_> = ranked.iter().map(|item| item.0).collect();
+        assert_eq!(names, ["Kharkiv", "Lviv", "unknown"]);
+        Ok(())
+    }
+}
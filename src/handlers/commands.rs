@@ -0,0 +1,391 @@
+//! The bot commands.
+
+use teloxide::{
+    payloads::{SendMessageSetters, SetMyCommandsSetters},
+    prelude2::*,
+    utils::command::BotCommand,
+};
+
+use crate::{
+    assignment, audit, export, funnel,
+    handlers::{contact_form, keyboards::start_keyboard},
+    intake, live_chat, personal_data,
+    redact::Redacted,
+    search,
+    sheets::{
+        status::{self, CLOSED_STATUSES},
+        tracked_submissions,
+    },
+    state::{DialogueStorage, HelpKind, MyDialogue, State},
+    texts, triage, AppState, ThrottledBot,
+};
+
+#[derive(BotCommand, Clone)]
+#[command(rename = "lowercase", description = "Підтримуються такі команди:")]
+pub enum Command {
+    #[command(description = "скасувати поточну дію")]
+    Cancel,
+    #[command(description = "що робити далі")]
+    Help,
+    #[command(description = "виправити попередню відповідь анкети")]
+    Undo,
+    #[command(description = "переглянути статус ваших заявок")]
+    Status,
+    #[command(description = "змінити мову / change language / zmienić język")]
+    Language,
+    #[command(
+        rename = "snake_case",
+        description = "перечитати конфігурацію (лише для координаторів)"
+    )]
+    ReloadConfig,
+    #[command(rename = "snake_case", description = "видалити ваші дані")]
+    DeleteMyData,
+    #[command(description = "пошук заявок (лише для підтверджених волонтерів)")]
+    Search(String),
+    #[command(description = "вивантажити заявки до CSV або XLSX (лише для координаторів)")]
+    Export(String),
+    #[command(description = "воронка заявок за останні дні (лише для координаторів)")]
+    Stats(String),
+    #[command(description = "почати чат із заявником (лише для координаторів)")]
+    Attach(String),
+    #[command(description = "завершити чат із заявником")]
+    Detach,
+    #[command(description = "призначити заявку волонтерові (лише для координаторів)")]
+    Assign(String),
+    #[command(description = "закрити заявку з результатом (лише для координаторів)")]
+    Close(String),
+    #[command(description = "наступна заявка для розгляду (лише для координаторів)")]
+    Next,
+    #[command(description = "припинити прийом заявок виду допомоги (лише для координаторів)")]
+    Pause(String),
+    #[command(description = "відновити прийом заявок виду допомоги (лише для координаторів)")]
+    Resume(String),
+}
+
+#[tracing::instrument(skip_all, fields(chat_id = msg.chat.id))]
+pub async fn handle_command(
+    bot: ThrottledBot,
+    msg: Message,
+    command: Command,
+    app_state: std::sync::Arc<AppState>,
+    storage: std::sync::Arc<DialogueStorage>,
+    language: texts::Language,
+) -> anyhow::Result<()> {
+    match command {
+        Command::Cancel => {
+            if !msg.chat.is_private() {
+                tracing::info!("command: chat is not private: {:?}", Redacted(&msg.chat));
+                return Ok(());
+            }
+            let texts = app_state.texts();
+            let dialogue = MyDialogue::new(storage, msg.chat.id);
+            if let Some(State::AwaitingContactInformation {
+                help_kind, contact, ..
+            }) = dialogue.get().await?
+            {
+                funnel::record_abandoned(&app_state, &help_kind, contact.as_ref()).await;
+            }
+            dialogue.update(State::Start).await?;
+            bot.send_message(msg.chat.id, texts.get(language, "dialogue_cancelled"))
+                .reply_markup(start_keyboard(&texts, language))
+                .await?;
+        }
+        Command::Help => {
+            if !msg.chat.is_private() {
+                tracing::info!("command: chat is not private: {:?}", Redacted(&msg.chat));
+                return Ok(());
+            }
+            let texts = app_state.texts();
+            let state = MyDialogue::new(storage, msg.chat.id)
+                .get()
+                .await?
+                .unwrap_or_default();
+            let hint = match state {
+                State::Start => "help_start",
+                State::AwaitingLanguage => "help_language",
+                State::AwaitingKindOfHelpProviding | State::AwaitingKindOfHelpWanted => "help_kind",
+                State::AwaitingConsent { .. } => "help_consent",
+                State::AwaitingContactInformation { .. } => "help_form",
+                State::AwaitingVolunteerRegistration { .. } => "help_volunteer_registration",
+            };
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "{}\n\n{}",
+                    texts.get(language, hint),
+                    texts.get(language, "help_commands")
+                ),
+            )
+            .await?;
+        }
+        Command::Undo => {
+            if !msg.chat.is_private() {
+                tracing::info!("command: chat is not private: {:?}", Redacted(&msg.chat));
+                return Ok(());
+            }
+            let dialogue = MyDialogue::new(storage, msg.chat.id);
+            match dialogue.get().await? {
+                Some(State::AwaitingContactInformation {
+                    help_kind,
+                    contact,
+                    question_message_id,
+                    last_answer,
+                    ..
+                }) => {
+                    contact_form::undo_last_answer(
+                        &bot,
+                        &app_state,
+                        &dialogue,
+                        msg.chat.id,
+                        language,
+                        (help_kind, contact, question_message_id, last_answer, None),
+                    )
+                    .await?
+                }
+                _ => {
+                    bot.send_message(
+                        msg.chat.id,
+                        app_state.texts().get(language, "nothing_to_undo"),
+                    )
+                    .await?;
+                }
+            }
+        }
+        Command::Status => {
+            if !msg.chat.is_private() {
+                tracing::info!("command: chat is not private: {:?}", Redacted(&msg.chat));
+                return Ok(());
+            }
+            let texts = app_state.texts();
+            let submissions = tracked_submissions(app_state.redis.clone(), msg.chat.id).await?;
+            let mut lines = Vec::new();
+            for submission in &submissions {
+                // One unreadable row, e.g. of a deleted tab, does not hide the other statuses.
+                let status = match submission
+                    .fetch_status(&app_state.sheets_api, &app_state.config())
+                    .await
+                {
+                    Ok(status) => status,
+                    Err(err) => {
+                        tracing::warn!(
+                            "Failed to fetch the status of {}: {:?}",
+                            submission.reference_id,
+                            err
+                        );
+                        Some(texts.get(language, "status_unavailable").to_owned())
+                    }
+                };
+                if let Some(status) = &status {
+                    if CLOSED_STATUSES.contains(&status.trim().to_lowercase().as_str()) {
+                        continue;
+                    }
+                }
+                lines.push(format!(
+                    "{}: {}",
+                    submission.reference_id,
+                    status
+                        .as_deref()
+                        .unwrap_or_else(|| texts.get(language, "status_pending"))
+                ));
+            }
+            let text = if lines.is_empty() {
+                texts.get(language, "status_no_open_requests").to_owned()
+            } else {
+                texts.render(
+                    language,
+                    "status_open_requests",
+                    &[("requests", &lines.join("\n"))],
+                )
+            };
+            bot.send_message(msg.chat.id, text).await?;
+        }
+        Command::Language => {
+            if !msg.chat.is_private() {
+                tracing::info!("command: chat is not private: {:?}", Redacted(&msg.chat));
+                return Ok(());
+            }
+            MyDialogue::new(storage, msg.chat.id)
+                .update(State::AwaitingLanguage)
+                .await?;
+            bot.send_message(
+                msg.chat.id,
+                app_state.texts().get(language, "choose_language"),
+            )
+            .reply_markup(texts::language_keyboard())
+            .await?;
+        }
+        Command::ReloadConfig => {
+            if !app_state.is_admin_message(&msg) {
+                tracing::info!(
+                    "command: reload_config from non-admin {:?}",
+                    Redacted(&msg.chat)
+                );
+                return Ok(());
+            }
+            let (text, outcome) = match app_state.reload() {
+                Ok(()) => ("Конфігурацію перечитано.".to_owned(), "reloaded".to_owned()),
+                Err(err) => (
+                    format!("Не вдалося перечитати конфігурацію: {err:#}"),
+                    format!("failed: {err:#}"),
+                ),
+            };
+            audit::record(
+                &app_state,
+                &msg.from().map_or_else(
+                    || msg.chat.id.to_string(),
+                    assignment::volunteer_display_name,
+                ),
+                audit::Action::ConfigReloaded,
+                "config",
+                &outcome,
+            )
+            .await;
+            bot.send_message(msg.chat.id, text).await?;
+        }
+        Command::DeleteMyData => {
+            if !msg.chat.is_private() {
+                tracing::info!("command: chat is not private: {:?}", Redacted(&msg.chat));
+                return Ok(());
+            }
+            personal_data::ask_confirmation(&bot, &app_state, msg.chat.id, language).await?;
+        }
+        Command::Search(query) => search::handle_search(&bot, &app_state, &msg, &query).await?,
+        Command::Export(args) => {
+            if !app_state.is_admin_message(&msg) {
+                tracing::info!("command: export from non-admin {:?}", Redacted(&msg.chat));
+                return Ok(());
+            }
+            export::handle_export(&bot, &app_state, &msg, &args).await?;
+        }
+        Command::Stats(days) => {
+            if !app_state.is_admin_message(&msg) {
+                tracing::info!("command: stats from non-admin {:?}", Redacted(&msg.chat));
+                return Ok(());
+            }
+            funnel::handle_stats(&bot, &app_state, &msg, &days).await?;
+        }
+        Command::Attach(target) => live_chat::attach(&bot, &app_state, &msg, &target).await?,
+        Command::Detach => live_chat::detach(&bot, &app_state, &msg).await?,
+        Command::Assign(args) => {
+            if !app_state.is_admin_message(&msg) {
+                tracing::info!("command: assign from non-admin {:?}", Redacted(&msg.chat));
+                return Ok(());
+            }
+            assignment::handle_assign_command(&bot, &app_state, &msg, &args, language).await?;
+        }
+        Command::Close(args) => {
+            if !app_state.is_admin_message(&msg) {
+                tracing::info!("command: close from non-admin {:?}", Redacted(&msg.chat));
+                return Ok(());
+            }
+            status::handle_close_command(&bot, &app_state, &msg, &args).await?;
+        }
+        Command::Next => {
+            if !app_state.is_admin_message(&msg) {
+                tracing::info!("command: next from non-admin {:?}", Redacted(&msg.chat));
+                return Ok(());
+            }
+            triage::handle_next(&bot, &app_state, &msg).await?;
+        }
+        Command::Pause(slug) => set_paused(&bot, &app_state, &msg, slug.trim(), true).await?,
+        Command::Resume(slug) => set_paused(&bot, &app_state, &msg, slug.trim(), false).await?,
+    }
+
+    Ok(())
+}
+
+/// Registers the commands every user can send, so that Telegram suggests them, in each language.
+pub async fn register(bot: &ThrottledBot, texts: &texts::Texts) -> anyhow::Result<()> {
+    for language in texts::Language::ALL {
+        let commands: Vec<teloxide::types::BotCommand> =
+            ["start", "cancel", "undo", "status", "language", "help"]
+                .into_iter()
+                .map(|command| {
+                    teloxide::types::BotCommand::new(
+                        command,
+                        texts.get(language, &format!("command_{command}")),
+                    )
+                })
+                .collect();
+        // The default language is used for the clients in the languages without texts.
+        if language == texts::Language::default() {
+            bot.set_my_commands(commands.clone()).await?;
+        }
+        bot.set_my_commands(commands)
+            .language_code(language.code())
+            .await?;
+    }
+    Ok(())
+}
+
+/// Pauses or resumes the intake of the help kind with the slug, see [`intake`].
+async fn set_paused(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    msg: &Message,
+    slug: &str,
+    paused: bool,
+) -> anyhow::Result<()> {
+    if !app_state.is_admin_message(msg) {
+        tracing::info!(
+            "command: pause or resume from non-admin {:?}",
+            Redacted(&msg.chat)
+        );
+        return Ok(());
+    }
+    let config = app_state.config();
+    let definition = match config.help_kind(&HelpKind::new(slug)) {
+        Some(definition) => definition,
+        None => {
+            let slugs: Vec<&str> = config
+                .help_kinds
+                .iter()
+                .map(|definition| definition.slug.as_str())
+                .collect();
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "Невідомий вид допомоги {slug:?}. Доступні: {}.",
+                    slugs.join(", ")
+                ),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    if !paused && definition.paused {
+        bot.send_message(
+            msg.chat.id,
+            format!(
+                "Прийом заявок «{}» призупинено в конфігурації; відновіть його там.",
+                definition.title()
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+    let text = if paused {
+        intake::pause(app_state, &definition.slug).await?;
+        format!("Прийом заявок «{}» призупинено.", definition.title())
+    } else {
+        intake::resume(app_state, &definition.slug).await?;
+        format!("Прийом заявок «{}» відновлено.", definition.title())
+    };
+    audit::record(
+        app_state,
+        &msg.from().map_or_else(
+            || msg.chat.id.to_string(),
+            assignment::volunteer_display_name,
+        ),
+        if paused {
+            audit::Action::IntakePaused
+        } else {
+            audit::Action::IntakeResumed
+        },
+        definition.slug.as_str(),
+        "",
+    )
+    .await;
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
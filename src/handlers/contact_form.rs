@@ -0,0 +1,1334 @@
+//! The contact form: asking the questions, taking the answers, their corrections and the
+//! confirmation, and saving the submission.
+
+use teloxide::{
+    payloads::{EditMessageReplyMarkupSetters, EditMessageTextSetters, SendMessageSetters},
+    prelude2::*,
+    types::{
+        ChatAction, InlineKeyboardButton, KeyboardButton, KeyboardMarkup, ParseMode, ReplyMarkup,
+    },
+};
+
+use super::keyboards::{confirm_keyboard, start_keyboard};
+use crate::{
+    analytics, attachments, audit, donations, email, experiments, form, forum_topics, funnel, geo,
+    html, matching, medical, moderation,
+    outgoing_webhooks::{self, Event, Payload},
+    public_feed, questionnaire,
+    redact::Redacted,
+    relay, reminders,
+    sheets::{column_letters, rotation, track_submission},
+    sms,
+    state::{Contact, DialogueStorage, HelpKind, LastAnswer, MyDialogue, State},
+    team_chats, texts, transcription, triage, volunteers, AppState, ThrottledBot,
+    TrackedSubmission,
+};
+
+/// Sends the prompt of the next question and returns the id of its message.
+///
+/// With `edit_questions_in_place` the keyboards are inline ones, and a question answered with a
+/// button is replaced by the next one instead of sending a new message.
+pub async fn ask(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    chat_id: i64,
+    prompt: form::Prompt,
+    parse_mode: Option<ParseMode>,
+    previous_question: Option<i32>,
+    answered_with_button: bool,
+) -> anyhow::Result<i32> {
+    if !app_state.config().edit_questions_in_place {
+        // Only the inline keyboards of the multiple choices are answered with a button here.
+        if let (Some(message_id), true) = (previous_question, answered_with_button) {
+            remove_inline_keyboard(bot, chat_id, message_id).await;
+        }
+        let mut request = bot
+            .send_message(chat_id, prompt.text)
+            .reply_markup(prompt.reply_markup);
+        if let Some(parse_mode) = parse_mode {
+            request = request.parse_mode(parse_mode);
+        }
+        return Ok(request.await?.id);
+    }
+
+    let keyboard = prompt.inline_keyboard();
+    if let Some(message_id) = previous_question {
+        if answered_with_button {
+            let mut request = bot.edit_message_text(chat_id, message_id, prompt.text);
+            if let Some(keyboard) = keyboard {
+                request = request.reply_markup(keyboard);
+            }
+            if let Some(parse_mode) = parse_mode {
+                request = request.parse_mode(parse_mode);
+            }
+            request.await?;
+            return Ok(message_id);
+        }
+        // The answer was typed below the previous question, so its buttons are stale now.
+        remove_inline_keyboard(bot, chat_id, message_id).await;
+    }
+    let mut request = match keyboard {
+        Some(keyboard) => bot
+            .send_message(chat_id, prompt.text)
+            .reply_markup(keyboard),
+        None => bot
+            .send_message(chat_id, prompt.text)
+            .reply_markup(teloxide::types::KeyboardRemove::new()),
+    };
+    if let Some(parse_mode) = parse_mode {
+        request = request.parse_mode(parse_mode);
+    }
+    Ok(request.await?.id)
+}
+
+/// Removes the inline keyboard of the message, if it still has one.
+async fn remove_inline_keyboard(bot: &ThrottledBot, chat_id: i64, message_id: i32) {
+    if let Err(err) = bot.edit_message_reply_markup(chat_id, message_id).await {
+        tracing::debug!(
+            "Failed to remove the keyboard of message {} in chat {}: {:?}",
+            message_id,
+            chat_id,
+            err
+        );
+    }
+}
+
+/// The fields of [`State::AwaitingContactInformation`].
+pub type ContactFormState = (
+    HelpKind,
+    Option<Contact>,
+    Option<i32>,
+    Option<LastAnswer>,
+    Option<String>,
+);
+
+fn contact_form_state(
+    (help_kind, contact, question_message_id, last_answer, pending_transcription): ContactFormState,
+) -> State {
+    State::AwaitingContactInformation {
+        help_kind,
+        contact,
+        question_message_id,
+        last_answer,
+        pending_transcription,
+    }
+}
+
+/// An answer to the current question of the form.
+struct Answer<'a> {
+    text: &'a str,
+    /// The user's message with the answer; `None` when a button of an inline keyboard was pressed.
+    message_id: Option<i32>,
+}
+
+#[tracing::instrument(skip_all, fields(chat_id = msg.chat.id))]
+pub async fn handle_awaiting_contact_information(
+    bot: ThrottledBot,
+    msg: Message,
+    app_state: std::sync::Arc<AppState>,
+    dialogue: MyDialogue,
+    language: texts::Language,
+    mut form_state: ContactFormState,
+) -> anyhow::Result<()> {
+    if msg.text().is_some_and(|text| text.starts_with("/start")) {
+        return offer_resume(
+            &bot,
+            &app_state,
+            &dialogue,
+            msg.chat.id,
+            language,
+            form_state,
+        )
+        .await;
+    }
+    if let Some(attachment) = attachments::Attachment::from_message(&msg) {
+        let (help_kind, contact, ..) = &mut form_state;
+        let contact = contact.get_or_insert_with(Contact::default);
+        if accepts_attachments(&app_state, help_kind, contact) {
+            contact.attachments.push(attachment);
+            // Without a caption there is no answer to the question yet.
+            if msg.caption().is_none() {
+                dialogue.update(contact_form_state(form_state)).await?;
+                bot.send_message(
+                    msg.chat.id,
+                    app_state.texts().get(language, "attachment_received"),
+                )
+                .await?;
+                return Ok(());
+            }
+        }
+    }
+    if let Some(card) = msg.contact() {
+        let (help_kind, contact, ..) = &mut form_state;
+        let contact = contact.get_or_insert_with(Contact::default);
+        let current_field = app_state
+            .config()
+            .form(help_kind)
+            .next_question(contact)
+            .map(|question| question.field.clone());
+        if matches!(
+            current_field.as_deref(),
+            Some("full_name" | "phone_numbers")
+        ) {
+            contact.fill_from_card(card);
+            return ask_next_question(
+                &bot,
+                &app_state,
+                &dialogue,
+                msg.chat.id,
+                language,
+                form_state,
+            )
+            .await;
+        }
+    }
+    if let Some(location) = msg.location() {
+        let (help_kind, contact, ..) = &mut form_state;
+        let contact = contact.get_or_insert_with(Contact::default);
+        let current_field = app_state
+            .config()
+            .form(help_kind)
+            .next_question(contact)
+            .map(|question| question.field.clone());
+        if matches!(current_field.as_deref(), Some("address" | "street")) {
+            let coordinates = geo::Coordinates {
+                latitude: location.latitude,
+                longitude: location.longitude,
+            };
+            // The location stands for the street and the building.
+            if current_field.as_deref() == Some("street") {
+                contact.street = Some(coordinates.to_string());
+                contact.building = Some("-".to_owned());
+            } else {
+                contact.address = Some(coordinates.to_string());
+            }
+            contact.coordinates = Some(coordinates);
+            return ask_next_question(
+                &bot,
+                &app_state,
+                &dialogue,
+                msg.chat.id,
+                language,
+                form_state,
+            )
+            .await;
+        }
+    }
+    if msg.voice().is_some() && app_state.config().transcription.is_some() {
+        return transcribe_answer(&bot, &msg, &app_state, &dialogue, language, form_state).await;
+    }
+    // The caption of a photo or a document is as good as a text answer.
+    let msg_text = match msg.text().or_else(|| msg.caption()) {
+        Some(text) => text,
+        None => return explain_expected_answer(&bot, &msg, &app_state, language, form_state).await,
+    };
+    let texts = app_state.texts();
+    let msg_text = match form_state.4.take() {
+        Some(transcription) if msg_text == texts.get(language, "button_transcription_accept") => {
+            transcription
+        }
+        Some(_) if msg_text == texts.get(language, "button_transcription_reject") => {
+            return ask_next_question(
+                &bot,
+                &app_state,
+                &dialogue,
+                msg.chat.id,
+                language,
+                form_state,
+            )
+            .await;
+        }
+        _ => msg_text.to_owned(),
+    };
+    if msg_text == texts.get(language, "button_undo") {
+        return undo_last_answer(
+            &bot,
+            &app_state,
+            &dialogue,
+            msg.chat.id,
+            language,
+            form_state,
+        )
+        .await;
+    }
+    let accepted_questionnaire = msg_text == texts.get(language, "button_questionnaire_accept");
+    if accepted_questionnaire || msg_text == texts.get(language, "button_questionnaire_reject") {
+        if let Some(parsed) = questionnaire::take_pending(&app_state, msg.chat.id).await? {
+            if accepted_questionnaire {
+                form_state.1 = Some(parsed);
+            }
+            return ask_next_question(
+                &bot,
+                &app_state,
+                &dialogue,
+                msg.chat.id,
+                language,
+                form_state,
+            )
+            .await;
+        }
+    }
+    if app_state.config().questionnaire_parser.is_some()
+        && questionnaire::looks_like_questionnaire(&msg_text)
+        && answered_questions(&app_state, &form_state.0, form_state.1.as_ref()) == 0
+    {
+        return parse_questionnaire(
+            &bot, &msg, &app_state, &dialogue, language, form_state, &msg_text,
+        )
+        .await;
+    }
+    let answer = Answer {
+        text: &msg_text,
+        message_id: Some(msg.id),
+    };
+    answer_contact_question(
+        &bot,
+        &app_state,
+        &dialogue,
+        msg.chat.id,
+        language,
+        answer,
+        form_state,
+    )
+    .await
+}
+
+/// Transcribes the voice answer and asks the user whether it was heard right.
+async fn transcribe_answer(
+    bot: &ThrottledBot,
+    msg: &Message,
+    app_state: &AppState,
+    dialogue: &MyDialogue,
+    language: texts::Language,
+    mut form_state: ContactFormState,
+) -> anyhow::Result<()> {
+    let (provider, voice) = match (&app_state.config().transcription, msg.voice()) {
+        (Some(provider), Some(voice)) => (provider.clone(), voice),
+        _ => return Ok(()),
+    };
+    let texts = app_state.texts();
+    bot.send_chat_action(msg.chat.id, ChatAction::Typing)
+        .await?;
+    let transcription = match transcription::transcribe(bot, &provider, voice, language).await {
+        Ok(transcription) if !transcription.is_empty() => transcription,
+        Ok(_) => {
+            bot.send_message(msg.chat.id, texts.get(language, "voice_not_recognized"))
+                .await?;
+            return Ok(());
+        }
+        Err(err) => {
+            tracing::warn!("Failed to transcribe a voice message: {:?}", err);
+            bot.send_message(msg.chat.id, texts.get(language, "voice_not_recognized"))
+                .await?;
+            return Ok(());
+        }
+    };
+    bot.send_message(
+        msg.chat.id,
+        texts.render(
+            language,
+            "voice_transcription",
+            &[("transcription", &transcription)],
+        ),
+    )
+    .reply_markup(teloxide::types::KeyboardMarkup::new(vec![vec![
+        teloxide::types::KeyboardButton::new(texts.get(language, "button_transcription_accept")),
+        teloxide::types::KeyboardButton::new(texts.get(language, "button_transcription_reject")),
+    ]]))
+    .await?;
+    form_state.4 = Some(transcription);
+    dialogue.update(contact_form_state(form_state)).await?;
+    Ok(())
+}
+
+/// Adds the button undoing the previous answer to the keyboard of the question, unless nothing
+/// was answered yet.
+fn with_undo_button(
+    prompt: form::Prompt,
+    texts: &texts::Texts,
+    language: texts::Language,
+    form: &form::Form,
+    contact: &Contact,
+) -> form::Prompt {
+    if form.last_answered(contact).is_none() {
+        return prompt;
+    }
+    let undo = vec![KeyboardButton::new(texts.get(language, "button_undo"))];
+    let reply_markup = match prompt.reply_markup {
+        ReplyMarkup::InlineKeyboard(mut keyboard) => {
+            keyboard
+                .inline_keyboard
+                .push(vec![InlineKeyboardButton::callback(
+                    texts.get(language, "button_undo").to_owned(),
+                    form::UNDO_DATA.to_owned(),
+                )]);
+            keyboard.into()
+        }
+        ReplyMarkup::Keyboard(mut keyboard) => {
+            keyboard.keyboard.push(undo);
+            keyboard.into()
+        }
+        ReplyMarkup::KeyboardRemove(_) => KeyboardMarkup::new(vec![undo]).into(),
+        reply_markup => reply_markup,
+    };
+    form::Prompt {
+        reply_markup,
+        ..prompt
+    }
+}
+
+/// Clears the answer to the question answered last and asks it again.
+pub async fn undo_last_answer(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    dialogue: &MyDialogue,
+    chat_id: i64,
+    language: texts::Language,
+    mut form_state: ContactFormState,
+) -> anyhow::Result<()> {
+    let (help_kind, contact, ..) = &mut form_state;
+    let form = app_state.config().form(help_kind);
+    let contact = contact.get_or_insert_with(Contact::default);
+    match form.last_answered(contact) {
+        Some(question) => contact.clear_answer(&question.field),
+        None => {
+            bot.send_message(chat_id, app_state.texts().get(language, "nothing_to_undo"))
+                .await?;
+            return Ok(());
+        }
+    }
+    // Editing the message with the undone answer must not bring it back.
+    form_state.3 = None;
+    ask_next_question(bot, app_state, dialogue, chat_id, language, form_state).await
+}
+
+/// Shows the answers given so far to the user who started over in the middle of the form, with
+/// the buttons to continue it or to start it anew, see [`reminders::resume_keyboard`].
+async fn offer_resume(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    dialogue: &MyDialogue,
+    chat_id: i64,
+    language: texts::Language,
+    form_state: ContactFormState,
+) -> anyhow::Result<()> {
+    let contact = match &form_state.1 {
+        Some(contact) if answered_questions(app_state, &form_state.0, Some(contact)) > 0 => contact,
+        _ => {
+            return ask_next_question(bot, app_state, dialogue, chat_id, language, form_state).await
+        }
+    };
+    let answers = contact
+        .summary_fields(&app_state.texts(), language)
+        .into_iter()
+        .filter(|(_, value)| value != "-")
+        .map(|(label, value)| format!("{label}: {value}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let texts = app_state.texts();
+    bot.send_message(
+        chat_id,
+        texts.render(language, "form_resume", &[("answers", &answers)]),
+    )
+    .reply_markup(reminders::resume_keyboard(&texts, language))
+    .await?;
+    Ok(())
+}
+
+/// Clears the answers given so far and asks the first question of the form again; the consent
+/// and where the user came from are kept.
+pub async fn restart_form(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    dialogue: &MyDialogue,
+    chat_id: i64,
+    language: texts::Language,
+    mut form_state: ContactFormState,
+) -> anyhow::Result<()> {
+    let (help_kind, contact, ..) = &mut form_state;
+    if let Some(contact) = contact {
+        for question in &app_state.config().form(help_kind).questions {
+            contact.clear_answer(&question.field);
+        }
+        contact.attachments.clear();
+    }
+    form_state.3 = None;
+    ask_next_question(bot, app_state, dialogue, chat_id, language, form_state).await
+}
+
+/// Number of the questions of the form the contact has an answer to.
+fn answered_questions(
+    app_state: &AppState,
+    help_kind: &HelpKind,
+    contact: Option<&Contact>,
+) -> usize {
+    let contact = match contact {
+        Some(contact) => contact,
+        None => return 0,
+    };
+    app_state
+        .config()
+        .form(help_kind)
+        .questions
+        .iter()
+        .filter(|question| contact.has_answer(&question.field))
+        .count()
+}
+
+/// Parses the pasted questionnaire and asks the user whether it was understood right.
+async fn parse_questionnaire(
+    bot: &ThrottledBot,
+    msg: &Message,
+    app_state: &AppState,
+    dialogue: &MyDialogue,
+    language: texts::Language,
+    form_state: ContactFormState,
+    text: &str,
+) -> anyhow::Result<()> {
+    let config = app_state.config();
+    let parser = match &config.questionnaire_parser {
+        Some(parser) => parser.clone(),
+        None => return Ok(()),
+    };
+    let texts = app_state.texts();
+    let contact = form_state.1.clone().unwrap_or_default();
+    bot.send_chat_action(msg.chat.id, ChatAction::Typing)
+        .await?;
+    let parsed =
+        match questionnaire::parse(&parser, &config.form(&form_state.0), &contact, text).await {
+            Ok(parsed) => Some(parsed),
+            Err(err) => {
+                tracing::warn!("Failed to parse a questionnaire: {:?}", err);
+                None
+            }
+        };
+    let parsed = match parsed {
+        Some(parsed) if answered_questions(app_state, &form_state.0, Some(&parsed)) > 0 => parsed,
+        _ => {
+            bot.send_message(msg.chat.id, texts.get(language, "questionnaire_not_parsed"))
+                .await?;
+            return ask_next_question(bot, app_state, dialogue, msg.chat.id, language, form_state)
+                .await;
+        }
+    };
+    questionnaire::store_pending(app_state, msg.chat.id, &parsed).await?;
+    bot.send_message(
+        msg.chat.id,
+        texts.render(
+            language,
+            "questionnaire_parsed",
+            &[("answers", &parsed.summary(&texts, language))],
+        ),
+    )
+    .reply_markup(teloxide::types::KeyboardMarkup::new(vec![vec![
+        teloxide::types::KeyboardButton::new(texts.get(language, "button_questionnaire_accept")),
+        teloxide::types::KeyboardButton::new(texts.get(language, "button_questionnaire_reject")),
+    ]]))
+    .await?;
+    Ok(())
+}
+
+/// Asks the first question which has no answer yet, or for the confirmation once there is none,
+/// e.g. again after the user rejected the transcription of their answer.
+pub async fn ask_next_question(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    dialogue: &MyDialogue,
+    chat_id: i64,
+    language: texts::Language,
+    (help_kind, contact, question_message_id, last_answer, _): ContactFormState,
+) -> anyhow::Result<()> {
+    let texts = app_state.texts();
+    let contact = contact.unwrap_or_default();
+    let form = app_state
+        .config()
+        .form(&help_kind)
+        .localized(&texts, language)
+        .with_variants(&texts, language, &contact);
+    let (prompt, parse_mode) = match form.next_question(&contact) {
+        Some(question) => (
+            with_undo_button(question.prompt(&contact), &texts, language, &form, &contact),
+            None,
+        ),
+        None => (
+            confirmation_prompt(&texts, language, &contact),
+            Some(ParseMode::Html),
+        ),
+    };
+    let question_message_id = ask(
+        bot,
+        app_state,
+        chat_id,
+        prompt,
+        parse_mode,
+        question_message_id,
+        false,
+    )
+    .await?;
+    dialogue
+        .update(State::AwaitingContactInformation {
+            help_kind,
+            contact: Some(contact),
+            question_message_id: Some(question_message_id),
+            last_answer,
+            pending_transcription: None,
+        })
+        .await?;
+    Ok(())
+}
+
+/// Whether photos can be attached at the current question of the form.
+fn accepts_attachments(app_state: &AppState, help_kind: &HelpKind, contact: &Contact) -> bool {
+    let config = app_state.config();
+    config.drive_folder_id.is_some()
+        && config
+            .form(help_kind)
+            .next_question(contact)
+            .is_some_and(|question| question.accepts_attachments)
+}
+
+/// Tells the user who sent a sticker, a voice message or the like what answer is expected.
+async fn explain_expected_answer(
+    bot: &ThrottledBot,
+    msg: &Message,
+    app_state: &AppState,
+    language: texts::Language,
+    (help_kind, contact, ..): ContactFormState,
+) -> anyhow::Result<()> {
+    let texts = app_state.texts();
+    let contact = contact.unwrap_or_default();
+    let form = app_state
+        .config()
+        .form(&help_kind)
+        .localized(&texts, language)
+        .with_variants(&texts, language, &contact);
+    let (text, reply_markup) = match form.next_question(&contact) {
+        Some(question) => (
+            texts.render(
+                language,
+                question.input.expected_answer_text_id(),
+                &[("prompt", &question.prompt)],
+            ),
+            question.prompt(&contact).reply_markup,
+        ),
+        None => (
+            texts.get(language, "confirm_submission_invalid").to_owned(),
+            confirm_keyboard(&texts, language).into(),
+        ),
+    };
+    let request = bot.send_message(msg.chat.id, text);
+    // With questions edited in place the keyboard stays on the question message.
+    if app_state.config().edit_questions_in_place {
+        request.await?;
+    } else {
+        request.reply_markup(reply_markup).await?;
+    }
+    Ok(())
+}
+
+/// Handles the buttons of the question messages edited in place and of the multiple choices.
+#[tracing::instrument(skip_all, fields(user_id = query.from.id, data = ?query.data))]
+pub async fn handle_form_callback(
+    bot: ThrottledBot,
+    query: CallbackQuery,
+    app_state: std::sync::Arc<AppState>,
+    storage: std::sync::Arc<DialogueStorage>,
+    language: texts::Language,
+) -> anyhow::Result<()> {
+    bot.answer_callback_query(query.id.clone()).await?;
+    if let (Some(message), Some(keyboard)) = (&query.message, form::toggled_keyboard(&query)) {
+        bot.edit_message_reply_markup(message.chat.id, message.id)
+            .reply_markup(keyboard)
+            .await?;
+        return Ok(());
+    }
+    let (message, text) = match (&query.message, form::callback_answer(&query)) {
+        (Some(message), Some(text)) => (message, text),
+        _ => {
+            tracing::warn!("form: unexpected callback {:?}", query.data);
+            return Ok(());
+        }
+    };
+    let dialogue = MyDialogue::new(storage, message.chat.id);
+    match dialogue.get().await? {
+        Some(State::AwaitingContactInformation {
+            help_kind,
+            contact,
+            question_message_id,
+            last_answer,
+            ..
+        }) if question_message_id == Some(message.id) => {
+            let form_state = (help_kind, contact, question_message_id, last_answer, None);
+            if text == app_state.texts().get(language, "button_undo") {
+                return undo_last_answer(
+                    &bot,
+                    &app_state,
+                    &dialogue,
+                    message.chat.id,
+                    language,
+                    form_state,
+                )
+                .await;
+            }
+            let answer = Answer {
+                text: &text,
+                message_id: None,
+            };
+            answer_contact_question(
+                &bot,
+                &app_state,
+                &dialogue,
+                message.chat.id,
+                language,
+                answer,
+                form_state,
+            )
+            .await
+        }
+        // A button of a question which is not the current one any more.
+        _ => {
+            remove_inline_keyboard(&bot, message.chat.id, message.id).await;
+            Ok(())
+        }
+    }
+}
+
+#[tracing::instrument(skip_all, fields(chat_id = chat_id))]
+async fn answer_contact_question(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    dialogue: &MyDialogue,
+    chat_id: i64,
+    language: texts::Language,
+    answer: Answer<'_>,
+    (help_kind, contact, question_message_id, mut last_answer, _): ContactFormState,
+) -> anyhow::Result<()> {
+    let msg_text = answer.text;
+    let texts = app_state.texts();
+    let mut contact = contact.unwrap_or_default();
+    let form = app_state
+        .config()
+        .form(&help_kind)
+        .localized(&texts, language)
+        .with_variants(&texts, language, &contact);
+    if let Some(question) = form.next_question(&contact) {
+        let accepted = if moderation::rejects(&question.input, msg_text) {
+            Err(form::Prompt {
+                text: texts.render(
+                    language,
+                    "answer_rejected",
+                    &[("prompt", &question.prompt(&contact).text)],
+                ),
+                ..question.prompt(&contact)
+            })
+        } else if let Some(validator) = question.failed_validator(msg_text) {
+            Err(form::Prompt {
+                text: validator.error(&texts, language),
+                ..question.prompt(&contact)
+            })
+        } else {
+            question.accept(&mut contact, msg_text)
+        };
+        let (prompt, parse_mode) = match accepted {
+            Err(prompt) => (
+                with_undo_button(prompt, &texts, language, &form, &contact),
+                None,
+            ),
+            Ok(()) => {
+                funnel::record(app_state, funnel::Step::Answered(&question.field)).await;
+                experiments::record_answered(app_state, &contact, &question.field).await;
+                last_answer = answer.message_id.map(|message_id| LastAnswer {
+                    message_id,
+                    field: question.field.clone(),
+                });
+                match form.next_question(&contact) {
+                    Some(question) => (
+                        with_undo_button(
+                            question.prompt(&contact),
+                            &texts,
+                            language,
+                            &form,
+                            &contact,
+                        ),
+                        None,
+                    ),
+                    None => (
+                        confirmation_prompt(&texts, language, &contact),
+                        Some(ParseMode::Html),
+                    ),
+                }
+            }
+        };
+        let question_message_id = ask(
+            bot,
+            app_state,
+            chat_id,
+            prompt,
+            parse_mode,
+            question_message_id,
+            answer.message_id.is_none(),
+        )
+        .await?;
+        dialogue
+            .update(State::AwaitingContactInformation {
+                help_kind,
+                contact: Some(contact),
+                question_message_id: Some(question_message_id),
+                last_answer,
+                pending_transcription: None,
+            })
+            .await?;
+        return Ok(());
+    }
+
+    let confirmed = if msg_text == texts.get(language, "button_confirm") {
+        true
+    } else if msg_text == texts.get(language, "button_restart") {
+        false
+    } else {
+        bot.send_message(chat_id, texts.get(language, "confirm_submission_invalid"))
+            .await?;
+        return Ok(());
+    };
+    if app_state.config().edit_questions_in_place {
+        if let Some(message_id) = question_message_id {
+            remove_inline_keyboard(bot, chat_id, message_id).await;
+        }
+    }
+    if confirmed {
+        if contact.submission_id.is_none() {
+            contact.submission_id = Some(uuid::Uuid::new_v4());
+            dialogue
+                .update(State::AwaitingContactInformation {
+                    help_kind: help_kind.clone(),
+                    contact: Some(contact.clone()),
+                    question_message_id,
+                    last_answer: last_answer.clone(),
+                    pending_transcription: None,
+                })
+                .await?;
+        }
+        tracing::info!(
+            "Saving information: {:?} user submits {:?} {:?}...",
+            chat_id,
+            help_kind,
+            Redacted(&contact)
+        );
+        let config = app_state.config();
+        let definition = config
+            .help_kind(&help_kind)
+            .ok_or_else(|| anyhow::anyhow!("Unknown kind of help {:?}", help_kind))?;
+        // Saving takes a couple of seconds, so the confirmation keyboard is hidden right away to
+        // keep users from confirming twice.
+        bot.send_chat_action(chat_id, ChatAction::Typing).await?;
+        let saving_msg = bot
+            .send_message(chat_id, texts.get(language, "submission_saving"))
+            .reply_markup(teloxide::types::KeyboardRemove::new())
+            .await?;
+        if let Some(folder_id) = config
+            .drive_folder_id
+            .as_ref()
+            .filter(|_| !app_state.skips_external_writes())
+        {
+            attachments::upload_all(
+                bot,
+                &app_state.drive_api,
+                folder_id,
+                &mut contact.attachments,
+            )
+            .await;
+        }
+        contact.tags = volunteers::classify(&app_state.texts(), &config.tags, &contact);
+        let saved = async {
+            let destination = rotation::resolve(
+                app_state.redis.clone(),
+                config.destination(definition, contact.region.as_deref()),
+            )
+            .await?;
+            let submission = contact
+                .save(app_state, definition, &destination, language, chat_id)
+                .await?;
+            anyhow::Ok((destination, submission))
+        }
+        .await;
+        let (destination, submission) = match saved {
+            Ok(saved) => {
+                email::record_sheets_success();
+                saved
+            }
+            Err(err) => {
+                email::record_sheets_failure(app_state, &err).await;
+                bot.delete_message(chat_id, saving_msg.id).await?;
+                bot.send_message(chat_id, texts.get(language, "submission_failed"))
+                    .reply_markup(confirm_keyboard(&texts, language))
+                    .await?;
+                return Err(err);
+            }
+        };
+        track_submission(app_state.redis.clone(), chat_id, &submission).await?;
+        if !submission.mismatched_columns.is_empty() {
+            if let Err(err) = report_mismatch(bot, app_state, &submission).await {
+                tracing::warn!(
+                    "Failed to report the mismatch of {}: {:?}",
+                    submission.reference_id,
+                    err
+                );
+            }
+        }
+        if let Err(err) = rotation::check(bot, app_state, &destination, &submission).await {
+            tracing::warn!("Failed to continue {:?}: {:?}", destination, err);
+        }
+        audit::record(
+            app_state,
+            &chat_id.to_string(),
+            audit::Action::SubmissionSaved,
+            &submission.reference_id,
+            definition.slug.as_str(),
+        )
+        .await;
+        outgoing_webhooks::send(
+            app_state,
+            &Payload::new(Event::Created, &submission).with_answers(&app_state.texts(), &contact),
+        );
+        team_chats::post_submission(app_state, definition, &contact, &submission);
+        email::notify_urgent(app_state, &contact, &submission).await;
+        sms::notify_submission(app_state, language, &contact, &submission).await;
+        if let Err(err) =
+            relay::post_submission(bot, app_state, chat_id, &contact, &submission).await
+        {
+            tracing::warn!(
+                "Failed to post {} to the admin group: {:?}",
+                submission.reference_id,
+                err
+            );
+        }
+        if let Err(err) = medical::post_submission(bot, app_state, &contact, &submission).await {
+            tracing::warn!(
+                "Failed to post {} to the medical volunteers: {:?}",
+                submission.reference_id,
+                err
+            );
+        }
+        if let Err(err) = volunteers::post_to_tag_chats(bot, app_state, &contact, &submission).await
+        {
+            tracing::warn!(
+                "Failed to post {} to the chats of its tags: {:?}",
+                submission.reference_id,
+                err
+            );
+        }
+        if let Err(err) = triage::enqueue(app_state, &contact, &submission).await {
+            tracing::warn!(
+                "Failed to queue {} for triage: {:?}",
+                submission.reference_id,
+                err
+            );
+        }
+        if let Err(err) = public_feed::post_submission(bot, app_state, &contact, &submission).await
+        {
+            tracing::warn!(
+                "Failed to post {} to the public channel: {:?}",
+                submission.reference_id,
+                err
+            );
+        }
+        if let Err(err) =
+            volunteers::notify_matching_volunteers(bot, app_state, &contact, &submission).await
+        {
+            tracing::warn!(
+                "Failed to notify volunteers about {}: {:?}",
+                submission.reference_id,
+                err
+            );
+        }
+        if let Err(err) =
+            matching::register_submission(bot, app_state, chat_id, &contact, &submission).await
+        {
+            tracing::warn!(
+                "Failed to match {} with drivers: {:?}",
+                submission.reference_id,
+                err
+            );
+        }
+        funnel::record(app_state, funnel::Step::Confirmed).await;
+        experiments::record_confirmed(app_state, &contact).await;
+        analytics::record_submission(app_state, &help_kind, &contact).await;
+        let reference_id = submission.reference_id;
+        dialogue.update(State::Start).await?;
+        bot.edit_message_text(
+            chat_id,
+            saving_msg.id,
+            texts.render(
+                language,
+                "submission_sent",
+                &[("reference_id", &html::code(&reference_id))],
+            ),
+        )
+        .parse_mode(ParseMode::Html)
+        .await?;
+        // Reply keyboards cannot be attached to edited messages.
+        bot.send_message(chat_id, texts.get(language, "choose_start"))
+            .reply_markup(start_keyboard(&texts, language))
+            .await?;
+        donations::offer(bot, app_state, chat_id, language, &help_kind).await?;
+    } else {
+        funnel::record_abandoned(app_state, &help_kind, Some(&contact)).await;
+        dialogue.update(State::Start).await?;
+        bot.send_message(chat_id, texts.get(language, "submission_cancelled"))
+            .reply_markup(start_keyboard(&texts, language))
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Tells the coordinators that the row of the submission did not read back as it was submitted.
+async fn report_mismatch(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    submission: &TrackedSubmission,
+) -> anyhow::Result<()> {
+    let columns: Vec<String> = submission
+        .mismatched_columns
+        .iter()
+        .map(|&column| column_letters(column))
+        .collect();
+    forum_topics::post(
+        bot,
+        app_state,
+        &submission.reference_id,
+        format!(
+            "Заявку {} записано до таблиці з помилками (стовпці {}), навіть після повторного \
+             запису. Перевірте рядок {} у таблиці https://docs.google.com/spreadsheets/d/{}",
+            submission.reference_id,
+            columns.join(", "),
+            submission.range,
+            submission.spreadsheet_id()
+        ),
+    )
+    .await
+}
+
+fn confirmation_prompt(
+    texts: &texts::Texts,
+    language: texts::Language,
+    contact: &Contact,
+) -> form::Prompt {
+    form::Prompt {
+        text: texts.render(
+            language,
+            "confirm_submission",
+            &[("summary", &contact.summary_html(texts, language))],
+        ),
+        reply_markup: confirm_keyboard(texts, language).into(),
+    }
+}
+
+/// Corrects the answer to the last question when the user edits the message with it.
+#[tracing::instrument(skip_all, fields(chat_id = msg.chat.id))]
+pub async fn handle_edited_answer(
+    bot: ThrottledBot,
+    msg: Message,
+    app_state: std::sync::Arc<AppState>,
+    dialogue: MyDialogue,
+    language: texts::Language,
+) -> anyhow::Result<()> {
+    let text = match msg.text() {
+        Some(text) => text,
+        None => return Ok(()),
+    };
+    let (help_kind, contact, question_message_id, last_answer) = match dialogue.get().await? {
+        Some(State::AwaitingContactInformation {
+            help_kind,
+            contact: Some(contact),
+            question_message_id,
+            last_answer: Some(last_answer),
+            ..
+        }) if last_answer.message_id == msg.id => {
+            (help_kind, contact, question_message_id, last_answer)
+        }
+        _ => return Ok(()),
+    };
+    let texts = app_state.texts();
+    let form = app_state
+        .config()
+        .form(&help_kind)
+        .localized(&texts, language);
+    let question = match form
+        .questions
+        .iter()
+        .find(|question| question.field == last_answer.field)
+    {
+        Some(question) => question,
+        None => return Ok(()),
+    };
+    let mut corrected = contact.clone();
+    if moderation::rejects(&question.input, text)
+        || question.failed_validator(text).is_some()
+        || question.accept(&mut corrected, text).is_err()
+    {
+        bot.send_message(
+            msg.chat.id,
+            texts.get(language, "answer_correction_invalid"),
+        )
+        .reply_to_message_id(msg.id)
+        .await?;
+        return Ok(());
+    }
+    let changed = corrected.summary(&texts, texts::Language::default())
+        != contact.summary(&texts, texts::Language::default());
+    bot.send_message(msg.chat.id, texts.get(language, "answer_corrected"))
+        .reply_to_message_id(msg.id)
+        .await?;
+    // Once all the questions are answered, the summary waiting for confirmation is outdated.
+    let question_message_id = if changed && form.next_question(&corrected).is_none() {
+        Some(
+            ask(
+                &bot,
+                &app_state,
+                msg.chat.id,
+                confirmation_prompt(&texts, language, &corrected),
+                Some(ParseMode::Html),
+                question_message_id,
+                true,
+            )
+            .await?,
+        )
+    } else {
+        question_message_id
+    };
+    dialogue
+        .update(State::AwaitingContactInformation {
+            help_kind,
+            contact: Some(corrected),
+            question_message_id,
+            last_answer: Some(last_answer),
+            pending_transcription: None,
+        })
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Datelike;
+
+    use crate::{
+        html,
+        testing::{Harness, SentMessage},
+        texts::Language,
+        HelpKind, State,
+    };
+
+    const CHAT_ID: i64 = 42;
+
+    /// Opens the form for a useful contact.
+    async fn start_form(harness: &Harness) -> anyhow::Result<()> {
+        let config = harness.app_state.config();
+        let definition = config
+            .help_kind(&HelpKind::new(HelpKind::PROVIDING_USEFUL_CONTACT))
+            .expect("the built-in help kind is configured");
+        let label = definition
+            .label_in(&harness.app_state.texts(), Language::Uk)
+            .to_owned();
+        harness
+            .send_text(CHAT_ID, &harness.text(Language::Uk, "button_can_help"))
+            .await?;
+        harness.send_text(CHAT_ID, &label).await?;
+        harness
+            .send_text(CHAT_ID, &harness.text(Language::Uk, "button_consent"))
+            .await?;
+        Ok(())
+    }
+
+    fn question(harness: &Harness, field: &str) -> crate::form::Question {
+        harness
+            .app_state
+            .config()
+            .form(&HelpKind::new(HelpKind::PROVIDING_USEFUL_CONTACT))
+            .localized(&harness.app_state.texts(), Language::Uk)
+            .questions
+            .into_iter()
+            .find(|question| question.field == field)
+            .expect("the built-in form has the question")
+    }
+
+    /// Answers all the questions of the form, checking that each of them is asked in turn.
+    async fn fill_in_form(harness: &Harness) -> anyhow::Result<Vec<SentMessage>> {
+        let answers = [
+            ("full_name", "Тарас Шевченко"),
+            ("phone_numbers", "+380501234567"),
+            ("region", "Київська"),
+            ("city", "Бровари"),
+            ("street", "вул. Київська"),
+            ("building", "1"),
+            ("address_notes", "-"),
+            ("comments", "-"),
+        ];
+        let mut sent = Vec::new();
+        for (i, (_, answer)) in answers.iter().enumerate() {
+            sent = harness.send_text(CHAT_ID, answer).await?;
+            assert_eq!(sent.len(), 1);
+            if let Some((next_field, _)) = answers.get(i + 1) {
+                assert_eq!(sent[0].text, question(harness, next_field).prompt);
+            }
+        }
+        Ok(sent)
+    }
+
+    #[tokio::test]
+    async fn confirmed_submission_is_saved() -> anyhow::Result<()> {
+        let harness = Harness::new().await?;
+        start_form(&harness).await?;
+
+        let sent = fill_in_form(&harness).await?;
+        assert!(sent[0].text.contains("Тарас Шевченко"));
+        assert_eq!(
+            sent[0].keyboard,
+            vec![vec![
+                harness.text(Language::Uk, "button_confirm"),
+                harness.text(Language::Uk, "button_restart"),
+            ]]
+        );
+
+        let sent = harness
+            .send_text(CHAT_ID, &harness.text(Language::Uk, "button_confirm"))
+            .await?;
+        let config = harness.app_state.config();
+        let prefix = &config
+            .help_kind(&HelpKind::new(HelpKind::PROVIDING_USEFUL_CONTACT))
+            .expect("the built-in help kind is configured")
+            .reference_prefix;
+        let reference_id = format!("{prefix}-{}-00001", crate::config::local_now().year());
+        let texts = harness.app_state.texts();
+        assert_eq!(
+            sent,
+            vec![
+                SentMessage {
+                    chat_id: CHAT_ID,
+                    text: harness.text(Language::Uk, "submission_saving"),
+                    keyboard: Vec::new(),
+                    edited: false,
+                },
+                SentMessage {
+                    chat_id: CHAT_ID,
+                    text: texts.render(
+                        Language::Uk,
+                        "submission_sent",
+                        &[("reference_id", &html::code(&reference_id))],
+                    ),
+                    keyboard: Vec::new(),
+                    edited: true,
+                },
+                SentMessage {
+                    chat_id: CHAT_ID,
+                    text: harness.text(Language::Uk, "choose_start"),
+                    keyboard: vec![
+                        vec![
+                            harness.text(Language::Uk, "button_can_help"),
+                            harness.text(Language::Uk, "button_need_help"),
+                        ],
+                        vec![harness.text(Language::Uk, "button_register_volunteer")],
+                    ],
+                    edited: false,
+                },
+            ]
+        );
+        assert!(matches!(harness.state(CHAT_ID).await?, Some(State::Start)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn restart_cancels_submission() -> anyhow::Result<()> {
+        let harness = Harness::new().await?;
+        start_form(&harness).await?;
+        fill_in_form(&harness).await?;
+
+        let sent = harness
+            .send_text(CHAT_ID, &harness.text(Language::Uk, "button_restart"))
+            .await?;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(
+            sent[0].text,
+            harness.text(Language::Uk, "submission_cancelled")
+        );
+        assert!(matches!(harness.state(CHAT_ID).await?, Some(State::Start)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invalid_answer_repeats_question() -> anyhow::Result<()> {
+        let harness = Harness::new().await?;
+        start_form(&harness).await?;
+        harness.send_text(CHAT_ID, "Тарас Шевченко").await?;
+        harness.send_text(CHAT_ID, "+380501234567").await?;
+
+        let sent = harness.send_text(CHAT_ID, "Марс").await?;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(
+            Some(&sent[0].text),
+            question(&harness, "region").invalid_answer.as_ref()
+        );
+
+        let sent = harness.send_text(CHAT_ID, "Київська").await?;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].text, question(&harness, "city").prompt);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn undo_asks_previous_question_again() -> anyhow::Result<()> {
+        let harness = Harness::new().await?;
+        start_form(&harness).await?;
+        let sent = harness.send_text(CHAT_ID, "/undo").await?;
+        assert_eq!(sent[0].text, harness.text(Language::Uk, "nothing_to_undo"));
+
+        let sent = harness.send_text(CHAT_ID, "Тарас Шевчено").await?;
+        assert_eq!(
+            sent[0].keyboard,
+            vec![vec![harness.text(Language::Uk, "button_undo")]]
+        );
+        let sent = harness
+            .send_text(CHAT_ID, &harness.text(Language::Uk, "button_undo"))
+            .await?;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].text, question(&harness, "full_name").prompt);
+
+        let sent = harness.send_text(CHAT_ID, "Тарас Шевченко").await?;
+        assert_eq!(sent[0].text, question(&harness, "phone_numbers").prompt);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn start_in_the_middle_of_the_form_offers_to_resume() -> anyhow::Result<()> {
+        let harness = Harness::new().await?;
+        start_form(&harness).await?;
+        harness.send_text(CHAT_ID, "Тарас Шевченко").await?;
+
+        let sent = harness.send_text(CHAT_ID, "/start").await?;
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0].text.contains("ПІБ: Тарас Шевченко"));
+        assert!(matches!(
+            harness.state(CHAT_ID).await?,
+            Some(State::AwaitingContactInformation { contact: Some(contact), .. })
+                if contact.full_name.as_deref() == Some("Тарас Шевченко")
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failed_validator_is_explained() -> anyhow::Result<()> {
+        let harness = Harness::new().await?;
+        start_form(&harness).await?;
+        harness.send_text(CHAT_ID, "Тарас Шевченко").await?;
+
+        let sent = harness.send_text(CHAT_ID, "050 12").await?;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].text, harness.text(Language::Uk, "validation_phone"));
+
+        let sent = harness.send_text(CHAT_ID, "050 123 45 67").await?;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].text, question(&harness, "region").prompt);
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+//! Reply keyboards shared by the dialogue handlers.
+
+use std::collections::HashSet;
+
+use crate::{config, state::HelpKind, texts};
+
+pub fn start_keyboard(
+    texts: &texts::Texts,
+    language: texts::Language,
+) -> teloxide::types::KeyboardMarkup {
+    teloxide::types::KeyboardMarkup::new(vec![
+        vec![
+            teloxide::types::KeyboardButton::new(texts.get(language, "button_can_help")),
+            teloxide::types::KeyboardButton::new(texts.get(language, "button_need_help")),
+        ],
+        vec![teloxide::types::KeyboardButton::new(
+            texts.get(language, "button_register_volunteer"),
+        )],
+    ])
+}
+
+pub fn confirm_keyboard(
+    texts: &texts::Texts,
+    language: texts::Language,
+) -> teloxide::types::KeyboardMarkup {
+    teloxide::types::KeyboardMarkup::new(vec![vec![
+        teloxide::types::KeyboardButton::new(texts.get(language, "button_confirm")),
+        teloxide::types::KeyboardButton::new(texts.get(language, "button_restart")),
+    ]])
+}
+
+pub fn back_keyboard(
+    texts: &texts::Texts,
+    language: texts::Language,
+) -> teloxide::types::KeyboardMarkup {
+    teloxide::types::KeyboardMarkup::new(vec![vec![teloxide::types::KeyboardButton::new(
+        texts.get(language, "button_back"),
+    )]])
+}
+
+/// Keyboard with the kinds of help of the direction which are not paused and the way back to the
+/// start.
+pub fn help_kinds_keyboard(
+    config: &config::Config,
+    texts: &texts::Texts,
+    language: texts::Language,
+    direction: config::HelpDirection,
+    paused: &HashSet<HelpKind>,
+) -> teloxide::types::KeyboardMarkup {
+    let kinds = config
+        .help_kinds
+        .iter()
+        .filter(|definition| {
+            definition.direction == direction && !paused.contains(&definition.slug)
+        })
+        .map(|definition| {
+            teloxide::types::KeyboardButton::new(definition.label_in(texts, language))
+        })
+        .collect();
+    teloxide::types::KeyboardMarkup::new(vec![
+        kinds,
+        vec![teloxide::types::KeyboardButton::new(
+            texts.get(language, "button_back"),
+        )],
+    ])
+}
@@ -0,0 +1,155 @@
+//! Handlers of the updates: the commands, the dialogue states and the inline buttons.
+
+use std::sync::Arc;
+
+use teloxide::prelude2::*;
+
+use crate::{
+    assignment, chat_lock, donations, feedback, form, inline, live_chat, matching, personal_data,
+    relay, reminders, retention, search,
+    state::{self, DialogueStorage, State},
+    texts, volunteers, AppState,
+};
+
+pub mod commands;
+pub mod contact_form;
+pub mod keyboards;
+pub mod start;
+
+/// The whole tree of the handlers the dispatcher runs the updates through.
+pub fn schema() -> dptree::Handler<'static, dptree::di::DependencyMap, anyhow::Result<()>> {
+    dptree::entry()
+        .chain(dptree::filter_map_async(
+            |update: Update, locks: Arc<chat_lock::ChatLocks>| async move {
+                Some(chat_lock::lock(update, locks).await)
+            },
+        ))
+        .chain(dptree::filter_async(
+            |update: Update, app_state: Arc<AppState>| async move {
+                reminders::record_activity(update.clone(), app_state.clone()).await;
+                retention::record_activity(update, app_state).await;
+                true
+            },
+        ))
+        .chain(dptree::filter_async(state::version::state_is_readable))
+        .branch(
+            Update::filter_message()
+                .chain(dptree::filter_map_async(
+                    |msg: Message, app_state: Arc<AppState>| async move {
+                        Some(message_language(msg, app_state).await)
+                    },
+                ))
+                .branch(
+                    dptree::filter(donations::is_successful_payment)
+                        .endpoint(donations::handle_successful_payment),
+                )
+                .branch(
+                    dptree::filter_map_async(live_chat::find_peer)
+                        .endpoint(live_chat::handle_message),
+                )
+                .branch(
+                    dptree::filter_map_async(relay::find_requester)
+                        .endpoint(relay::handle_coordinator_reply),
+                )
+                .branch(
+                    dptree::filter_map_async(relay::find_coordinators)
+                        .endpoint(relay::handle_requester_reply),
+                )
+                .branch(
+                    dptree::entry()
+                        .filter_command::<commands::Command>()
+                        .endpoint(commands::handle_command),
+                )
+                .branch(
+                    dptree::filter_map_async(feedback::find_pending_comment)
+                        .endpoint(feedback::handle_comment),
+                )
+                .branch(
+                    dptree::entry()
+                        .enter_dialogue::<Message, DialogueStorage, State>()
+                        .dispatch_by::<State>(),
+                ),
+        )
+        .branch(
+            Update::filter_edited_message()
+                .chain(dptree::filter_map_async(
+                    |msg: Message, app_state: Arc<AppState>| async move {
+                        Some(message_language(msg, app_state).await)
+                    },
+                ))
+                .enter_dialogue::<Message, DialogueStorage, State>()
+                .endpoint(contact_form::handle_edited_answer),
+        )
+        .branch(Update::filter_inline_query().endpoint(inline::handle_inline_query))
+        .branch(Update::filter_pre_checkout_query().endpoint(donations::handle_pre_checkout_query))
+        .branch(
+            Update::filter_callback_query()
+                .chain(dptree::filter_map_async(
+                    |query: CallbackQuery, app_state: Arc<AppState>| async move {
+                        Some(callback_language(query, app_state).await)
+                    },
+                ))
+                .branch(
+                    dptree::filter(matching::is_matching_callback)
+                        .endpoint(matching::handle_callback),
+                )
+                .branch(
+                    dptree::filter(volunteers::is_volunteer_callback)
+                        .endpoint(volunteers::handle_callback),
+                )
+                .branch(
+                    dptree::filter(form::is_form_callback)
+                        .endpoint(contact_form::handle_form_callback),
+                )
+                .branch(
+                    dptree::filter(donations::is_donation_callback)
+                        .endpoint(donations::handle_callback),
+                )
+                .branch(
+                    dptree::filter(feedback::is_feedback_callback)
+                        .endpoint(feedback::handle_callback),
+                )
+                .branch(
+                    dptree::filter(personal_data::is_deletion_callback)
+                        .endpoint(personal_data::handle_callback),
+                )
+                .branch(
+                    dptree::filter(reminders::is_reminder_callback)
+                        .endpoint(reminders::handle_callback),
+                )
+                .branch(
+                    dptree::filter(search::is_search_callback).endpoint(search::handle_callback),
+                )
+                .branch(
+                    dptree::filter(assignment::is_assignment_callback)
+                        .endpoint(assignment::handle_callback),
+                ),
+        )
+}
+
+/// Language of the chat the message was sent to: the picked one, otherwise the one of the
+/// sender's Telegram client.
+pub async fn message_language(
+    msg: Message,
+    app_state: std::sync::Arc<AppState>,
+) -> texts::Language {
+    match texts::stored_language(app_state.redis.clone(), msg.chat.id).await {
+        Ok(Some(language)) => language,
+        Ok(None) => msg
+            .from()
+            .and_then(texts::Language::detect)
+            .unwrap_or_default(),
+        Err(err) => {
+            tracing::warn!("Failed to load language of chat {}: {:?}", msg.chat.id, err);
+            texts::Language::default()
+        }
+    }
+}
+
+/// Language of the user who pressed the inline button.
+pub async fn callback_language(
+    query: CallbackQuery,
+    app_state: std::sync::Arc<AppState>,
+) -> texts::Language {
+    texts::language_of(app_state.redis.clone(), query.from.id).await
+}
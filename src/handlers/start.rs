@@ -0,0 +1,536 @@
+//! The start of the dialogue: the language, the direction and the kind of help, and the consent to
+//! the privacy notice before the contact form.
+
+use teloxide::{payloads::SendMessageSetters, prelude2::*};
+
+use super::{
+    contact_form::ask,
+    keyboards::{back_keyboard, help_kinds_keyboard, start_keyboard},
+};
+use crate::{
+    config, experiments, funnel, intake,
+    redact::Redacted,
+    redis_connection,
+    state::{Contact, HelpKind, MyDialogue, State},
+    texts, AppState, ThrottledBot,
+};
+
+#[tracing::instrument(skip_all, fields(chat_id = msg.chat.id))]
+pub async fn handle_awaiting_language(
+    bot: ThrottledBot,
+    msg: Message,
+    app_state: std::sync::Arc<AppState>,
+    dialogue: MyDialogue,
+) -> anyhow::Result<()> {
+    let texts = app_state.texts();
+    let language = match msg.text().and_then(texts::Language::from_label) {
+        Some(language) => language,
+        None => {
+            bot.send_message(
+                msg.chat.id,
+                texts.get(texts::Language::default(), "choose_language"),
+            )
+            .reply_markup(texts::language_keyboard())
+            .await?;
+            return Ok(());
+        }
+    };
+    texts::store_language(app_state.redis.clone(), msg.chat.id, language).await?;
+    dialogue.update(State::Start).await?;
+    bot.send_message(msg.chat.id, texts.get(language, "choose_start"))
+        .reply_markup(start_keyboard(&texts, language))
+        .await?;
+    Ok(())
+}
+
+/// Redis key of the start parameter the chat came with, e.g. the code of a poster or of a partner
+/// organization, written to the `source` column of its submissions.
+fn source_key(chat_id: i64) -> String {
+    redis_connection::key(&format!("start_source:{chat_id}"))
+}
+
+/// How long the start parameter is kept for the submissions of the chat.
+const SOURCE_TTL_SECS: usize = 7 * 24 * 60 * 60;
+
+async fn store_source(
+    mut redis: redis::aio::MultiplexedConnection,
+    chat_id: i64,
+    source: &str,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    if !source.is_empty() {
+        let _: () = redis
+            .set_ex(source_key(chat_id), source, SOURCE_TTL_SECS)
+            .await?;
+    }
+    Ok(())
+}
+
+async fn stored_source(
+    mut redis: redis::aio::MultiplexedConnection,
+    chat_id: i64,
+) -> anyhow::Result<Option<String>> {
+    use redis::AsyncCommands;
+
+    Ok(redis.get(source_key(chat_id)).await?)
+}
+
+#[tracing::instrument(skip_all, fields(chat_id = msg.chat.id))]
+pub async fn handle_start(
+    bot: ThrottledBot,
+    msg: Message,
+    app_state: std::sync::Arc<AppState>,
+    dialogue: MyDialogue,
+    language: texts::Language,
+) -> anyhow::Result<()> {
+    if !msg.chat.is_private() {
+        tracing::info!("start: chat is not private: {:?}", Redacted(&msg.chat));
+        return Ok(());
+    }
+    let texts = app_state.texts();
+    if texts::stored_language(app_state.redis.clone(), msg.chat.id)
+        .await?
+        .is_none()
+    {
+        // Users whose Telegram client speaks one of our languages are not asked; they can still
+        // switch with /language.
+        if msg.from().and_then(texts::Language::detect).is_some() {
+            texts::store_language(app_state.redis.clone(), msg.chat.id, language).await?;
+        } else {
+            dialogue.update(State::AwaitingLanguage).await?;
+            bot.send_message(msg.chat.id, texts.get(language, "choose_language"))
+                .reply_markup(texts::language_keyboard())
+                .await?;
+            return Ok(());
+        }
+    }
+    let msg_text = msg.text().unwrap_or_default();
+    // `t.me/<bot>?start=<deep link>` sends `/start <deep link>`.
+    if let Some(deep_link) = msg_text.strip_prefix("/start ") {
+        store_source(app_state.redis.clone(), msg.chat.id, deep_link.trim()).await?;
+        let config = app_state.config();
+        if let Some(definition) = config.help_kind_by_deep_link(deep_link.trim()) {
+            if !intake::paused(&app_state).await?.contains(&definition.slug) {
+                funnel::record(&app_state, funnel::Step::KindSelection).await;
+                dialogue
+                    .update(State::AwaitingConsent {
+                        help_kind: definition.slug.clone(),
+                    })
+                    .await?;
+                return ask_consent(&bot, &app_state, msg.chat.id, language).await;
+            }
+            bot.send_message(
+                msg.chat.id,
+                texts.render(
+                    language,
+                    "help_kind_paused",
+                    &[("help_kind", &definition.label_in(&texts, language))],
+                ),
+            )
+            .reply_markup(start_keyboard(&texts, language))
+            .await?;
+            return Ok(());
+        }
+    }
+    if msg_text == texts.get(language, "button_can_help") {
+        dialogue.update(State::AwaitingKindOfHelpProviding).await?;
+        funnel::record(&app_state, funnel::Step::KindSelection).await;
+        bot.send_message(msg.chat.id, texts.get(language, "choose_help_providing"))
+            .reply_markup(help_kinds_keyboard(
+                &app_state.config(),
+                &texts,
+                language,
+                config::HelpDirection::Providing,
+                &intake::paused(&app_state).await?,
+            ))
+            .await?;
+    } else if msg_text == texts.get(language, "button_need_help") {
+        dialogue.update(State::AwaitingKindOfHelpWanted).await?;
+        funnel::record(&app_state, funnel::Step::KindSelection).await;
+        bot.send_message(msg.chat.id, texts.get(language, "choose_help_wanted"))
+            .reply_markup(help_kinds_keyboard(
+                &app_state.config(),
+                &texts,
+                language,
+                config::HelpDirection::Needed,
+                &intake::paused(&app_state).await?,
+            ))
+            .await?;
+    } else if msg_text == texts.get(language, "button_register_volunteer") {
+        dialogue
+            .update(State::AwaitingVolunteerRegistration {
+                registration: Default::default(),
+            })
+            .await?;
+        bot.send_message(msg.chat.id, texts.get(language, "volunteer_full_name"))
+            .reply_markup(back_keyboard(&texts, language))
+            .await?;
+    } else {
+        tracing::info!(
+            "start: received unexpected type of message {:?}",
+            Redacted(&msg)
+        );
+        bot.send_message(msg.chat.id, texts.get(language, "choose_start"))
+            .reply_markup(start_keyboard(&texts, language))
+            .await?;
+    }
+
+    Ok(())
+}
+
+pub async fn handle_awaiting_kind_of_help_providing(
+    bot: ThrottledBot,
+    msg: Message,
+    app_state: std::sync::Arc<AppState>,
+    dialogue: MyDialogue,
+    language: texts::Language,
+) -> anyhow::Result<()> {
+    handle_awaiting_kind_of_help(
+        bot,
+        msg,
+        app_state,
+        dialogue,
+        language,
+        config::HelpDirection::Providing,
+        "choose_help_providing",
+    )
+    .await
+}
+
+pub async fn handle_awaitig_kind_of_help_wanted(
+    bot: ThrottledBot,
+    msg: Message,
+    app_state: std::sync::Arc<AppState>,
+    dialogue: MyDialogue,
+    language: texts::Language,
+) -> anyhow::Result<()> {
+    handle_awaiting_kind_of_help(
+        bot,
+        msg,
+        app_state,
+        dialogue,
+        language,
+        config::HelpDirection::Needed,
+        "choose_help_wanted",
+    )
+    .await
+}
+
+#[tracing::instrument(skip_all, fields(chat_id = msg.chat.id))]
+async fn handle_awaiting_kind_of_help(
+    bot: ThrottledBot,
+    msg: Message,
+    app_state: std::sync::Arc<AppState>,
+    dialogue: MyDialogue,
+    language: texts::Language,
+    direction: config::HelpDirection,
+    options_text_id: &str,
+) -> anyhow::Result<()> {
+    let texts = app_state.texts();
+    let msg_text = msg.text().unwrap_or_default();
+    if msg_text == texts.get(language, "button_back") {
+        dialogue.update(State::Start).await?;
+        bot.send_message(msg.chat.id, texts.get(language, "choose_start"))
+            .reply_markup(start_keyboard(&texts, language))
+            .await?;
+        return Ok(());
+    }
+    let config = app_state.config();
+    let paused = intake::paused(&app_state).await?;
+    let chosen = match config.help_kind_by_label(direction, msg_text, &texts, language) {
+        Some(definition) if !paused.contains(&definition.slug) => Ok(definition.slug.clone()),
+        // The button of a kind paused after the keyboard was sent.
+        Some(definition) => Err(texts.render(
+            language,
+            "help_kind_paused",
+            &[("help_kind", &definition.label_in(&texts, language))],
+        )),
+        None => {
+            tracing::info!(
+                "handle_awaiting_kind_of_help: received unexpected type of message {:?}",
+                Redacted(&msg)
+            );
+            Err(texts.get(language, options_text_id).to_owned())
+        }
+    };
+    let help_kind = match chosen {
+        Ok(help_kind) => help_kind,
+        Err(text) => {
+            bot.send_message(msg.chat.id, text)
+                .reply_markup(help_kinds_keyboard(
+                    &config, &texts, language, direction, &paused,
+                ))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    dialogue
+        .update(State::AwaitingConsent { help_kind })
+        .await?;
+    ask_consent(&bot, &app_state, msg.chat.id, language).await
+}
+
+/// Asks to agree to the privacy notice, with the link to the privacy policy if there is one.
+pub async fn ask_consent(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    chat_id: i64,
+    language: texts::Language,
+) -> anyhow::Result<()> {
+    let texts = app_state.texts();
+    let mut text = texts.get(language, "consent_request").to_owned();
+    if let Some(url) = &app_state.config().privacy_policy_url {
+        text.push_str("\n\n");
+        text.push_str(&texts.render(language, "privacy_policy_link", &[("url", url)]));
+    }
+    bot.send_message(chat_id, text)
+        .reply_markup(teloxide::types::KeyboardMarkup::new(vec![
+            vec![teloxide::types::KeyboardButton::new(
+                texts.get(language, "button_consent"),
+            )],
+            vec![teloxide::types::KeyboardButton::new(
+                texts.get(language, "button_back"),
+            )],
+        ]))
+        .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all, fields(chat_id = msg.chat.id))]
+pub async fn handle_awaiting_consent(
+    bot: ThrottledBot,
+    msg: Message,
+    app_state: std::sync::Arc<AppState>,
+    dialogue: MyDialogue,
+    language: texts::Language,
+    (help_kind,): (HelpKind,),
+) -> anyhow::Result<()> {
+    let texts = app_state.texts();
+    let msg_text = msg.text().unwrap_or_default();
+    if msg_text == texts.get(language, "button_back") {
+        dialogue.update(State::Start).await?;
+        bot.send_message(msg.chat.id, texts.get(language, "choose_start"))
+            .reply_markup(start_keyboard(&texts, language))
+            .await?;
+    } else if msg_text == texts.get(language, "button_consent") {
+        start_contact_form(
+            &bot,
+            &msg,
+            &app_state,
+            &dialogue,
+            language,
+            help_kind,
+            chrono::Utc::now(),
+        )
+        .await?;
+    } else {
+        ask_consent(&bot, &app_state, msg.chat.id, language).await?;
+    }
+    Ok(())
+}
+
+/// Switches the dialogue to filling in the contact and asks the first question of the form.
+pub async fn start_contact_form(
+    bot: &ThrottledBot,
+    msg: &Message,
+    app_state: &AppState,
+    dialogue: &MyDialogue,
+    language: texts::Language,
+    help_kind: HelpKind,
+    consented_at: chrono::DateTime<chrono::Utc>,
+) -> anyhow::Result<()> {
+    let mut contact = Contact {
+        consented_at: Some(consented_at),
+        source: stored_source(app_state.redis.clone(), msg.chat.id).await?,
+        telegram_user_id: msg.from().map(|user| user.id),
+        telegram_username: msg.from().and_then(|user| user.username.clone()),
+        ..Contact::default()
+    };
+    let config = app_state.config();
+    let texts = app_state.texts();
+    let form = config.form(&help_kind).localized(&texts, language);
+    experiments::assign(app_state, &config, &form, &mut contact).await;
+    let form = form.with_variants(&texts, language, &contact);
+    let question_message_id = match form.next_question(&contact) {
+        Some(question) => Some(
+            ask(
+                bot,
+                app_state,
+                msg.chat.id,
+                question.prompt(&contact),
+                None,
+                None,
+                false,
+            )
+            .await?,
+        ),
+        None => None,
+    };
+    dialogue
+        .update(State::AwaitingContactInformation {
+            help_kind,
+            contact: Some(contact),
+            question_message_id,
+            last_answer: None,
+            pending_transcription: None,
+        })
+        .await?;
+    funnel::record(app_state, funnel::Step::FormStarted).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{config::HelpDirection, testing::Harness, texts::Language, State};
+
+    const CHAT_ID: i64 = 42;
+
+    fn start_keyboard(harness: &Harness, language: Language) -> Vec<Vec<String>> {
+        vec![
+            vec![
+                harness.text(language, "button_can_help"),
+                harness.text(language, "button_need_help"),
+            ],
+            vec![harness.text(language, "button_register_volunteer")],
+        ]
+    }
+
+    fn help_kind_labels(harness: &Harness, direction: HelpDirection) -> Vec<String> {
+        let texts = harness.app_state.texts();
+        harness
+            .app_state
+            .config()
+            .help_kinds
+            .iter()
+            .filter(|definition| definition.direction == direction)
+            .map(|definition| definition.label_in(&texts, Language::Uk).to_owned())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn unexpected_message_shows_start_keyboard() -> anyhow::Result<()> {
+        let harness = Harness::new().await?;
+
+        let sent = harness.send_text(CHAT_ID, "Привіт").await?;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].text, harness.text(Language::Uk, "choose_start"));
+        assert_eq!(sent[0].keyboard, start_keyboard(&harness, Language::Uk));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn language_command_switches_language() -> anyhow::Result<()> {
+        let harness = Harness::new().await?;
+
+        let sent = harness.send_text(CHAT_ID, "/language").await?;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].text, harness.text(Language::Uk, "choose_language"));
+        assert_eq!(
+            sent[0].keyboard,
+            vec![Language::ALL
+                .iter()
+                .map(|language| language.label().to_owned())
+                .collect::<Vec<_>>()]
+        );
+
+        let sent = harness.send_text(CHAT_ID, "English").await?;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].text, harness.text(Language::En, "choose_start"));
+        assert_eq!(sent[0].keyboard, start_keyboard(&harness, Language::En));
+        assert!(matches!(harness.state(CHAT_ID).await?, Some(State::Start)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn help_command_explains_current_step_and_cancel_returns_to_start() -> anyhow::Result<()>
+    {
+        let harness = Harness::new().await?;
+        harness
+            .send_text(CHAT_ID, &harness.text(Language::Uk, "button_can_help"))
+            .await?;
+
+        let sent = harness.send_text(CHAT_ID, "/help").await?;
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0]
+            .text
+            .starts_with(&harness.text(Language::Uk, "help_kind")));
+
+        let sent = harness.send_text(CHAT_ID, "/cancel").await?;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(
+            sent[0].text,
+            harness.text(Language::Uk, "dialogue_cancelled")
+        );
+        assert_eq!(sent[0].keyboard, start_keyboard(&harness, Language::Uk));
+        assert!(matches!(harness.state(CHAT_ID).await?, Some(State::Start)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn back_from_help_kinds_returns_to_start() -> anyhow::Result<()> {
+        let harness = Harness::new().await?;
+        let back = harness.text(Language::Uk, "button_back");
+
+        let sent = harness
+            .send_text(CHAT_ID, &harness.text(Language::Uk, "button_need_help"))
+            .await?;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(
+            sent[0].text,
+            harness.text(Language::Uk, "choose_help_wanted")
+        );
+        assert_eq!(
+            sent[0].keyboard,
+            vec![
+                help_kind_labels(&harness, HelpDirection::Needed),
+                vec![back.clone()]
+            ]
+        );
+
+        let sent = harness.send_text(CHAT_ID, &back).await?;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].text, harness.text(Language::Uk, "choose_start"));
+        assert_eq!(sent[0].keyboard, start_keyboard(&harness, Language::Uk));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn consent_opens_contact_form() -> anyhow::Result<()> {
+        let harness = Harness::new().await?;
+        let labels = help_kind_labels(&harness, HelpDirection::Providing);
+
+        harness
+            .send_text(CHAT_ID, &harness.text(Language::Uk, "button_can_help"))
+            .await?;
+        let sent = harness.send_text(CHAT_ID, &labels[0]).await?;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].text, harness.text(Language::Uk, "consent_request"));
+        assert_eq!(
+            sent[0].keyboard,
+            vec![
+                vec![harness.text(Language::Uk, "button_consent")],
+                vec![harness.text(Language::Uk, "button_back")],
+            ]
+        );
+
+        // Anything but the buttons repeats the request.
+        let sent = harness.send_text(CHAT_ID, "Так").await?;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].text, harness.text(Language::Uk, "consent_request"));
+
+        let sent = harness
+            .send_text(CHAT_ID, &harness.text(Language::Uk, "button_consent"))
+            .await?;
+        assert_eq!(sent.len(), 1);
+        match harness.state(CHAT_ID).await? {
+            Some(State::AwaitingContactInformation {
+                contact: Some(contact),
+                ..
+            }) => assert!(contact.consented_at.is_some()),
+            state => panic!("unexpected state {state:?}"),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+//! Building blocks of the messages sent with the HTML parse mode.
+//!
+//! Everything that comes from users or sheets must go through [`escape`] (or a helper built on it)
+//! before it is put into such a message, otherwise Telegram rejects the message.
+
+/// Escapes the characters Telegram treats as markup in HTML messages.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn bold(text: &str) -> String {
+    format!("<b>{}</b>", escape(text))
+}
+
+/// Monospace text, e.g. reference ids which are easy to copy this way.
+pub fn code(text: &str) -> String {
+    format!("<code>{}</code>", escape(text))
+}
+
+pub fn link(url: &str, text: &str) -> String {
+    format!("<a href=\"{}\">{}</a>", escape(url), escape(text))
+}
+
+/// Rewrites the Ukrainian phone numbers in the text into the international `+380XXXXXXXXX` form.
+///
+/// Telegram does not accept `tel:` links in messages, but it makes numbers in this form
+/// clickable on its own.
+pub fn phone_numbers(text: &str) -> String {
+    let mut formatted = String::new();
+    let mut last_end = 0;
+    for found in phone_number_regex().find_iter(text) {
+        formatted.push_str(&escape(&text[last_end..found.start()]));
+        let international =
+            international(found.as_str()).unwrap_or_else(|| found.as_str().to_owned());
+        formatted.push_str(&escape(&international));
+        last_end = found.end();
+    }
+    formatted.push_str(&escape(&text[last_end..]));
+    formatted
+}
+
+/// The first Ukrainian phone number in the text in the `+380XXXXXXXXX` form.
+pub fn first_phone_number(text: &str) -> Option<String> {
+    phone_number_regex()
+        .find_iter(text)
+        .find_map(|found| international(found.as_str()))
+}
+
+fn phone_number_regex() -> regex::Regex {
+    regex::Regex::new(r"\+?\d[\d \-()]{7,}\d").unwrap()
+}
+
+fn international(number: &str) -> Option<String> {
+    let digits: String = number.chars().filter(char::is_ascii_digit).collect();
+    match digits.len() {
+        10 if digits.starts_with('0') => Some(format!("+38{digits}")),
+        12 if digits.starts_with("380") => Some(format!("+{digits}")),
+        _ => None,
+    }
+}
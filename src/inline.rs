@@ -0,0 +1,62 @@
+//! Inline mode for the coordinators: typing `@bot EVA-2024-00123` in any chat offers a card with
+//! the summary of the request to share with their working group.
+//!
+//! Inline mode has to be enabled for the bot with BotFather's `/setinline`.
+
+use teloxide::{
+    payloads::AnswerInlineQuerySetters,
+    prelude2::*,
+    types::{
+        InlineQueryResult, InlineQueryResultArticle, InputMessageContent, InputMessageContentText,
+    },
+};
+
+use crate::{search::Found, sheets::find_submission, AppState, ThrottledBot};
+
+#[tracing::instrument(skip_all, fields(user_id = query.from.id))]
+pub async fn handle_inline_query(
+    bot: ThrottledBot,
+    query: InlineQuery,
+    app_state: std::sync::Arc<AppState>,
+) -> anyhow::Result<()> {
+    let reference_id = query.query.trim().to_uppercase();
+    let mut results = Vec::new();
+    if !reference_id.is_empty() && app_state.is_coordinator(&bot, query.from.id).await? {
+        if let Some(card) = card(&app_state, &reference_id).await? {
+            results.push(InlineQueryResult::Article(
+                InlineQueryResultArticle::new(
+                    reference_id.clone(),
+                    reference_id,
+                    InputMessageContent::Text(InputMessageContentText::new(card.clone())),
+                )
+                .description(card),
+            ));
+        }
+    }
+    bot.answer_inline_query(query.id, results)
+        // The cards have personal data and change with the statuses.
+        .is_personal(true)
+        .cache_time(0)
+        .await?;
+    Ok(())
+}
+
+/// The summary of the request from its row in the spreadsheet.
+async fn card(app_state: &AppState, reference_id: &str) -> anyhow::Result<Option<String>> {
+    let indexed = match find_submission(app_state.redis.clone(), reference_id).await? {
+        Some(indexed) => indexed,
+        None => return Ok(None),
+    };
+    let submission = indexed.submission;
+    let config = app_state.config();
+    let title = config.help_kind_title(&submission.help_kind);
+    let row = submission.fetch_row(&app_state.sheets_api).await?;
+    let columns = config.columns(&submission.help_kind);
+    Ok(Some(
+        row.and_then(|row| Found::from_row(title, &columns, &row))
+            .map_or_else(
+                || format!("{} ({})", submission.reference_id, title),
+                |found| found.render(false),
+            ),
+    ))
+}
@@ -0,0 +1,49 @@
+//! Pausing the intake of help kinds, e.g. driver registrations while there are more drivers than
+//! requests. A paused kind is not offered on the keyboards and its button is answered with an
+//! explanation; the forms already started can still be submitted.
+//!
+//! Kinds are paused in the config, see [`crate::config::HelpKindDefinition::paused`], or by the
+//! coordinators with `/pause` and `/resume`, which is kept in Redis.
+
+use std::collections::HashSet;
+
+use crate::{redis_connection, state::HelpKind, AppState};
+
+/// Redis set of the slugs of the kinds paused by the coordinators.
+const PAUSED_KEY: &str = "help_kinds:paused";
+
+/// The kinds paused in the config or by the coordinators.
+pub async fn paused(app_state: &AppState) -> anyhow::Result<HashSet<HelpKind>> {
+    use redis::AsyncCommands;
+
+    let mut redis = app_state.redis.clone();
+    let slugs: Vec<String> = redis.smembers(redis_connection::key(PAUSED_KEY)).await?;
+    let config = app_state.config();
+    Ok(config
+        .help_kinds
+        .iter()
+        .filter(|definition| definition.paused)
+        .map(|definition| definition.slug.clone())
+        .chain(slugs.iter().map(|slug| HelpKind::new(slug)))
+        .collect())
+}
+
+pub async fn pause(app_state: &AppState, help_kind: &HelpKind) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let mut redis = app_state.redis.clone();
+    let _: () = redis
+        .sadd(redis_connection::key(PAUSED_KEY), help_kind.as_str())
+        .await?;
+    Ok(())
+}
+
+pub async fn resume(app_state: &AppState, help_kind: &HelpKind) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let mut redis = app_state.redis.clone();
+    let _: () = redis
+        .srem(redis_connection::key(PAUSED_KEY), help_kind.as_str())
+        .await?;
+    Ok(())
+}
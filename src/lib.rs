@@ -0,0 +1,361 @@
+//! Telegram bot collecting the requests for help and the offers of it into Google Sheets.
+//!
+//! The binary only parses its [`cli`]; the handlers, the dialogue state and the submission sink are
+//! public so that they can be tested and reused.
+
+use anyhow::Context;
+use google_sheets4::{hyper, hyper_rustls, oauth2, Sheets};
+use teloxide::{
+    adaptors::{throttle::Limits, Throttle},
+    prelude2::*,
+    requests::RequesterExt,
+    RequestError,
+};
+use thiserror::Error;
+
+pub mod cli;
+pub mod config;
+pub mod handlers;
+pub mod sheets;
+pub mod state;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+mod analytics;
+mod assignment;
+mod attachments;
+mod audit;
+mod chat_lock;
+mod donations;
+mod email;
+mod experiments;
+mod export;
+mod feedback;
+mod form;
+mod forum_topics;
+mod funnel;
+mod geo;
+mod html;
+mod inline;
+mod intake;
+mod lifecycle;
+mod live_chat;
+mod matching;
+mod medical;
+mod moderation;
+mod outgoing_webhooks;
+mod personal_data;
+mod public_feed;
+mod questionnaire;
+mod redact;
+mod redis_connection;
+mod regions;
+mod relay;
+mod reminders;
+mod retention;
+mod retry;
+mod search;
+mod shutdown;
+mod sla;
+mod sms;
+mod team_chats;
+mod telemetry;
+mod texts;
+mod transcription;
+mod triage;
+mod volunteers;
+mod webhook;
+
+pub use config::AppState;
+pub use sheets::{SubmissionSink, TrackedSubmission};
+pub use state::{Contact, DialogueStorage, HelpKind, State};
+pub use texts::Language;
+
+/// The bot with its requests throttled to stay within Telegram's flood limits.
+pub type ThrottledBot = AutoSend<Throttle<Bot>>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("error from Telegram: {0}")]
+    TelegramError(#[from] RequestError),
+
+    #[error("error from storage: {0}")]
+    StorageError(#[from] state::StorageError),
+}
+
+/// The app state and the dialogue storage, set up from the environment the same way for every
+/// command of [`cli`].
+pub struct Environment {
+    pub app_state: std::sync::Arc<AppState>,
+    pub storage: std::sync::Arc<DialogueStorage>,
+}
+
+impl Environment {
+    /// `dev_mode` and `dry_run` are the flags of the command line, see [`config::dev_mode`] and
+    /// [`config::dry_run`].
+    pub async fn from_env(dev_mode: bool, dry_run: bool) -> anyhow::Result<Self> {
+        let dev_mode = config::dev_mode(dev_mode);
+        let dry_run = config::dry_run(dry_run);
+        config::init_timezone()?;
+        if dev_mode {
+            tracing::warn!(
+                "Dev mode: dialogues are kept in memory and submissions are only logged"
+            );
+        }
+
+        // Get an ApplicationSecret instance by some means. It contains the `client_id` and
+        // `client_secret`, among other things. The dev mode never talks to Google.
+        let secret: oauth2::ApplicationSecret = if dev_mode {
+            oauth2::ApplicationSecret::default()
+        } else {
+            serde_json::from_str(
+                &std::env::var("COLLECT_VOLUNTEERS_BOT_OAUTH2_SECRET")
+                    .context("Set COLLECT_VOLUNTEERS_BOT_OAUTH2_SECRET env variable")?,
+            )
+            .context("Invalid COLLECT_VOLUNTEERS_BOT_OAUTH2_SECRET")?
+        };
+        // Instantiate the authenticator. It will choose a suitable authentication flow for you,
+        // unless you replace  `None` with the desired Flow.
+        // Provide your own `AuthenticatorDelegate` to adjust the way it operates and get feedback
+        // about what's going on. You probably want to bring in your own `TokenStorage` to persist
+        // tokens and retrieve them from storage.
+        let auth = oauth2::InstalledFlowAuthenticator::builder(
+            secret.clone(),
+            oauth2::InstalledFlowReturnMethod::HTTPRedirect,
+        )
+        .hyper_client(sheets_http_client())
+        .persist_tokens_to_disk(std::env::current_dir()?.join("access_keys"))
+        .build()
+        .await?;
+        let sheets_api = Sheets::new(sheets_http_client(), auth.clone());
+        let submission_sink: std::sync::Arc<dyn sheets::SubmissionSink> = if dev_mode || dry_run {
+            std::sync::Arc::new(sheets::sink::LogSink)
+        } else {
+            std::sync::Arc::new(sheets::sink::SheetsSink::new(Sheets::new(
+                sheets_http_client(),
+                auth,
+            )))
+        };
+        // Google Drive is built against another `hyper-rustls`, so it cannot share the Sheets
+        // authenticator; its tokens are kept in a separate file.
+        let drive_auth = google_drive3::oauth2::InstalledFlowAuthenticator::builder(
+            secret,
+            google_drive3::oauth2::InstalledFlowReturnMethod::HTTPRedirect,
+        )
+        .persist_tokens_to_disk(std::env::current_dir()?.join("drive_access_keys"))
+        .build()
+        .await?;
+        let drive_api = google_drive3::DriveHub::new(drive_http_client(), drive_auth);
+
+        let redis_url = redis_connection::url_from_env()
+            .await
+            .context("Failed to configure Redis")?;
+        let redis = redis::Client::open(redis_url.as_str())?
+            .get_multiplexed_tokio_connection()
+            .await?;
+        // Redis or SQLite, see `state::storage`; the states are migrated when read, see
+        // `state::version`.
+        let storage = DialogueStorage::from_env(redis.clone(), dev_mode)
+            .await
+            .context("Failed to open the dialogue storage")?;
+
+        let chat_id = |name: &str| -> anyhow::Result<Option<i64>> {
+            std::env::var(name)
+                .ok()
+                .map(|value| value.parse().with_context(|| format!("Invalid {name}")))
+                .transpose()
+        };
+        let (admin_chat_id, volunteers_chat_id) = if dry_run {
+            let sandbox_chat_id = chat_id("COLLECT_VOLUNTEERS_BOT_SANDBOX_CHAT_ID")?;
+            tracing::warn!(
+                "Dry run: nothing is written to Google, notifications go to {:?}",
+                sandbox_chat_id
+            );
+            (sandbox_chat_id, sandbox_chat_id)
+        } else {
+            (
+                chat_id("COLLECT_VOLUNTEERS_BOT_ADMIN_CHAT_ID")?,
+                chat_id("COLLECT_VOLUNTEERS_BOT_VOLUNTEERS_CHAT_ID")?,
+            )
+        };
+
+        let config = config::Config::from_env().context("Failed to load config")?;
+        let texts = texts::Texts::from_env().context("Failed to load texts")?;
+
+        let app_state = std::sync::Arc::new(AppState {
+            config: arc_swap::ArcSwap::from_pointee(config),
+            texts: arc_swap::ArcSwap::from_pointee(texts),
+            sheets_api,
+            drive_api,
+            submission_sink,
+            redis,
+            cipher: state::encryption::Cipher::from_env()?,
+            dev_mode,
+            dry_run,
+            admin_chat_id,
+            volunteers_chat_id,
+            tasks: Default::default(),
+        });
+        Ok(Self { app_state, storage })
+    }
+}
+
+/// The HTTP client for the Google Sheets API and its authenticator.
+pub(crate) fn sheets_http_client(
+) -> hyper::Client<hyper_rustls::HttpsConnector<hyper::client::HttpConnector>> {
+    hyper::Client::builder().build(hyper_rustls::HttpsConnector::with_native_roots())
+}
+
+/// The HTTP client for the Google Drive API, built with the `hyper-rustls` it depends on.
+pub(crate) fn drive_http_client() -> hyper::Client<
+    google_drive3::hyper_rustls::HttpsConnector<google_drive3::hyper::client::HttpConnector>,
+> {
+    google_drive3::hyper::Client::builder().build(
+        google_drive3::hyper_rustls::HttpsConnectorBuilder::new()
+            .with_native_roots()
+            .https_or_http()
+            .enable_http1()
+            .build(),
+    )
+}
+
+/// Runs the bot configured in the environment until it is asked to shut down.
+pub async fn run(environment: Environment) -> anyhow::Result<()> {
+    tracing::info!("Starting bot...");
+    let Environment { app_state, storage } = environment;
+    // The dev mode has no credentials to read the spreadsheets with.
+    if !app_state.dev_mode {
+        sheets::provisioning::provision(&app_state)
+            .await
+            .context("Failed to create the missing spreadsheets")?;
+        sheets::access::verify_access(&app_state).await?;
+        sheets::schema::verify_headers(&app_state)
+            .await
+            .context("Failed to verify the headers of the submission tabs")?;
+    }
+    let bot = Bot::from_env().throttle(Limits::default()).auto_send();
+    let me = bot.get_me().await?;
+    handlers::commands::register(&bot, &app_state.texts())
+        .await
+        .context("Failed to register the bot commands")?;
+    let webhook_config =
+        webhook::WebhookConfig::from_env().context("Invalid webhook configuration")?;
+
+    let status_poll_interval = std::time::Duration::from_secs(
+        match std::env::var("COLLECT_VOLUNTEERS_BOT_STATUS_POLL_INTERVAL_SECS") {
+            Ok(value) => value.parse().context("Invalid status poll interval")?,
+            Err(_) => 300,
+        },
+    );
+    let chat_locks = std::sync::Arc::new(chat_lock::ChatLocks::default());
+    let (request_shutdown, shutdown) = shutdown::channel();
+    let status_poller = tokio::spawn(sheets::status::poll_submission_statuses(
+        bot.clone(),
+        app_state.clone(),
+        status_poll_interval,
+        shutdown.clone(),
+    ));
+
+    tokio::spawn(reload_on_sighup(app_state.clone()));
+    funnel::serve_metrics(app_state.clone(), shutdown.clone())
+        .await
+        .context("Failed to serve the metrics")?;
+    let sla_watcher = tokio::spawn(sla::run(
+        bot.clone(),
+        app_state.clone(),
+        sla::SlaConfig::from_env()?,
+        shutdown.clone(),
+    ));
+    let form_reminder = tokio::spawn(reminders::run(
+        bot.clone(),
+        app_state.clone(),
+        storage.clone(),
+        reminders::ReminderConfig::from_env()?,
+        shutdown.clone(),
+    ));
+    let feedback_surveys = tokio::spawn(feedback::run(
+        bot.clone(),
+        app_state.clone(),
+        shutdown.clone(),
+    ));
+    let analytics_writer = tokio::spawn(analytics::run(app_state.clone(), shutdown.clone()));
+    let retention_purger = match retention::RetentionConfig::from_env()? {
+        Some(config) => tokio::spawn(retention::run(
+            app_state.clone(),
+            storage.clone(),
+            config,
+            shutdown,
+        )),
+        None => tokio::spawn(async {}),
+    };
+
+    let handler = handlers::schema();
+
+    let mut dispatcher = Dispatcher::builder(bot.clone(), handler)
+        .dependencies(dptree::deps![app_state.clone(), storage, me, chat_locks])
+        .build();
+    shutdown::on_signal(request_shutdown, dispatcher.shutdown_token())?;
+    match webhook_config {
+        Some(webhook_config) => {
+            let listener = webhook::listener(&bot, webhook_config)
+                .await
+                .context("Failed to set up the webhook")?;
+            dispatcher
+                .dispatch_with_listener(
+                    listener,
+                    LoggingErrorHandler::with_custom_text("An error from the webhook listener"),
+                )
+                .await;
+        }
+        None => dispatcher.dispatch().await,
+    }
+    let _ = tokio::join!(
+        status_poller,
+        sla_watcher,
+        form_reminder,
+        analytics_writer,
+        feedback_surveys,
+        retention_purger
+    );
+    app_state.tasks.wait().await;
+    match sheets::replay::replay(&app_state).await {
+        Ok(replayed) if !replayed.appended.is_empty() => {
+            tracing::info!("Appended the queued rows of {:?}", replayed.appended);
+        }
+        Ok(_) => {}
+        Err(err) => tracing::warn!(
+            "Queued rows are left for the replay-queue command: {:?}",
+            err
+        ),
+    }
+    tracing::info!("Shut down");
+    Ok(())
+}
+
+/// Reloads the config and the texts whenever the process receives SIGHUP.
+async fn reload_on_sighup(app_state: std::sync::Arc<AppState>) {
+    let mut hangups = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(err) => {
+            tracing::warn!("Failed to listen for SIGHUP: {:?}", err);
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        let outcome = match app_state.reload() {
+            Ok(()) => "reloaded".to_owned(),
+            Err(err) => {
+                tracing::warn!("Failed to reload config: {:#}", err);
+                format!("failed: {err:#}")
+            }
+        };
+        audit::record(
+            &app_state,
+            "SIGHUP",
+            audit::Action::ConfigReloaded,
+            "config",
+            &outcome,
+        )
+        .await;
+    }
+}
@@ -0,0 +1,156 @@
+//! Lifecycle of a request: new → triaged → assigned → in progress → done, or cancelled.
+//!
+//! The status of every request is kept in Redis and the status column of the spreadsheet mirrors
+//! it. A request only moves forward, possibly skipping steps, e.g. a volunteer can take a new
+//! request right away, and can be cancelled at any point until it is done. Done and cancelled
+//! requests never change. Every transition is appended to the history of the request with the
+//! actor and the time, and recorded in the audit log.
+
+use crate::{audit, redis_connection, AppState};
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    New,
+    Triaged,
+    Assigned,
+    InProgress,
+    Done,
+    Cancelled,
+}
+
+impl Status {
+    const ALL: [Self; 6] = [
+        Self::New,
+        Self::Triaged,
+        Self::Assigned,
+        Self::InProgress,
+        Self::Done,
+        Self::Cancelled,
+    ];
+
+    /// The status as written in the status column of the spreadsheet.
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::New => "Нова",
+            Self::Triaged => "Розглянуто",
+            Self::Assigned => "Призначено",
+            Self::InProgress => "Взято в роботу",
+            Self::Done => "Виконано",
+            Self::Cancelled => "Скасовано",
+        }
+    }
+
+    /// Parses the status column of the spreadsheet, ignoring the case and the spaces around.
+    pub fn from_label(label: &str) -> Option<Self> {
+        let label = label.trim().to_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|status| status.label().to_lowercase() == label)
+    }
+
+    pub fn is_closed(self) -> bool {
+        matches!(self, Self::Done | Self::Cancelled)
+    }
+
+    pub fn can_become(self, next: Self) -> bool {
+        !self.is_closed() && (next == Self::Cancelled || next > self)
+    }
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct Transition {
+    from: Status,
+    to: Status,
+    actor: String,
+    at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("статус заявки не можна змінити з «{}» на «{}»", from.label(), to.label())]
+pub struct InvalidTransition {
+    pub from: Status,
+    pub to: Status,
+}
+
+pub fn status_key(reference_id: &str) -> String {
+    redis_connection::key(&format!("lifecycle:{reference_id}"))
+}
+
+pub fn history_key(reference_id: &str) -> String {
+    redis_connection::key(&format!("lifecycle:{reference_id}:history"))
+}
+
+/// The current status of the request; a request without one is new.
+pub async fn current(app_state: &AppState, reference_id: &str) -> anyhow::Result<Status> {
+    use redis::AsyncCommands;
+
+    let status: Option<String> = app_state
+        .redis
+        .clone()
+        .get(status_key(reference_id))
+        .await?;
+    Ok(match status {
+        Some(status) => serde_json::from_str(&status)?,
+        None => Status::New,
+    })
+}
+
+/// Moves the request to the status on behalf of `actor` if the transition is valid.
+pub async fn transition(
+    app_state: &AppState,
+    reference_id: &str,
+    to: Status,
+    actor: &str,
+) -> anyhow::Result<Result<(), InvalidTransition>> {
+    let from = current(app_state, reference_id).await?;
+    if !from.can_become(to) {
+        return Ok(Err(InvalidTransition { from, to }));
+    }
+    let transition = Transition {
+        from,
+        to,
+        actor: actor.to_owned(),
+        at: chrono::Utc::now(),
+    };
+    redis::pipe()
+        .atomic()
+        .set(status_key(reference_id), serde_json::to_string(&to)?)
+        .ignore()
+        .rpush(
+            history_key(reference_id),
+            serde_json::to_string(&transition)?,
+        )
+        .ignore()
+        .query_async::<_, ()>(&mut app_state.redis.clone())
+        .await?;
+    audit::record(
+        app_state,
+        actor,
+        audit::Action::StatusChanged,
+        reference_id,
+        &format!("{} → {}", from.label(), to.label()),
+    )
+    .await;
+    Ok(Ok(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_only_move_forward_until_closed() {
+        assert!(Status::New.can_become(Status::Triaged));
+        assert!(Status::New.can_become(Status::Assigned));
+        assert!(Status::Assigned.can_become(Status::InProgress));
+        assert!(Status::InProgress.can_become(Status::Done));
+        assert!(Status::Triaged.can_become(Status::Cancelled));
+        assert!(!Status::Assigned.can_become(Status::Triaged));
+        assert!(!Status::Assigned.can_become(Status::Assigned));
+        assert!(!Status::Done.can_become(Status::Cancelled));
+        assert!(!Status::Cancelled.can_become(Status::InProgress));
+    }
+}
@@ -0,0 +1,204 @@
+//! Live chat of a coordinator with a user, bypassing the dialogue.
+//!
+//! A coordinator sends `/attach <reference id or chat id>` to the bot in a private chat. While
+//! attached, every message of the user is copied to the coordinator and every message of the
+//! coordinator is copied to the user, whatever the state of the user's dialogue, which stays as it
+//! was. Commands are not relayed, so that both sides can still use them. `/detach`, or another
+//! `/attach`, of the coordinator ends the chat. A coordinator is attached to one user at a time
+//! and a user to one coordinator.
+
+use teloxide::prelude2::*;
+
+use crate::{
+    assignment, audit,
+    redact::Redacted,
+    redis_connection,
+    sheets::find_submission,
+    texts::{self, Language},
+    AppState, ThrottledBot,
+};
+
+fn user_key(chat_id: i64) -> String {
+    redis_connection::key(&format!("live_chat:user:{chat_id}"))
+}
+
+fn coordinator_key(chat_id: i64) -> String {
+    redis_connection::key(&format!("live_chat:coordinator:{chat_id}"))
+}
+
+/// The chat the messages of an attached side are copied to.
+#[derive(Debug, Clone, Copy)]
+pub struct Peer {
+    chat_id: i64,
+}
+
+/// Finds the other side of the live chat the private message belongs to, if any.
+pub async fn find_peer(msg: Message, app_state: std::sync::Arc<AppState>) -> Option<Peer> {
+    use redis::AsyncCommands;
+
+    if !msg.chat.is_private() || msg.text().is_some_and(|text| text.starts_with('/')) {
+        return None;
+    }
+    let mut redis = app_state.redis.clone();
+    let peer: redis::RedisResult<Option<i64>> = async {
+        match redis.get(coordinator_key(msg.chat.id)).await? {
+            Some(user_chat_id) => Ok(Some(user_chat_id)),
+            None => redis.get(user_key(msg.chat.id)).await,
+        }
+    }
+    .await;
+    match peer {
+        Ok(peer) => peer.map(|chat_id| Peer { chat_id }),
+        Err(err) => {
+            tracing::warn!("Failed to load the live chat peer: {:?}", err);
+            None
+        }
+    }
+}
+
+#[tracing::instrument(skip_all, fields(chat_id = msg.chat.id))]
+pub async fn handle_message(bot: ThrottledBot, msg: Message, peer: Peer) -> anyhow::Result<()> {
+    bot.copy_message(peer.chat_id, msg.chat.id, msg.id).await?;
+    Ok(())
+}
+
+/// Attaches the coordinator to the user with the submission of the reference id, or with the chat
+/// id.
+pub async fn attach(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    msg: &Message,
+    target: &str,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let coordinator = match msg.from() {
+        Some(user) if msg.chat.is_private() => user,
+        _ => {
+            bot.send_message(
+                msg.chat.id,
+                "Під'єднатися до заявника можна лише в особистому чаті з ботом.",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    if !app_state.is_coordinator(bot, coordinator.id).await? {
+        tracing::info!(
+            "command: attach from non-coordinator {:?}",
+            Redacted(&msg.chat)
+        );
+        return Ok(());
+    }
+    let target = target.trim();
+    let user_chat_id = match target.parse::<i64>() {
+        Ok(chat_id) => Some(chat_id),
+        Err(_) => find_submission(app_state.redis.clone(), target)
+            .await?
+            .map(|indexed| indexed.chat_id),
+    };
+    let user_chat_id = match user_chat_id {
+        Some(chat_id) if chat_id != msg.chat.id => chat_id,
+        _ => {
+            bot.send_message(
+                msg.chat.id,
+                "Використання: /attach <номер заявки або id чату заявника>",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    let mut redis = app_state.redis.clone();
+    let attached_coordinator: Option<i64> = redis.get(user_key(user_chat_id)).await?;
+    if let Some(attached_coordinator) = attached_coordinator {
+        if attached_coordinator != msg.chat.id {
+            bot.send_message(
+                msg.chat.id,
+                "До цього заявника вже під'єднано іншого координатора.",
+            )
+            .await?;
+            return Ok(());
+        }
+    }
+    detach_coordinator(bot, app_state, msg.chat.id).await?;
+    redis::pipe()
+        .set(coordinator_key(msg.chat.id), user_chat_id)
+        .ignore()
+        .set(user_key(user_chat_id), msg.chat.id)
+        .ignore()
+        .query_async::<_, ()>(&mut redis)
+        .await?;
+    audit::record(
+        app_state,
+        &assignment::volunteer_display_name(coordinator),
+        audit::Action::ChatAttached,
+        &user_chat_id.to_string(),
+        target,
+    )
+    .await;
+
+    let language = texts::language_of(redis, user_chat_id).await;
+    bot.send_message(
+        user_chat_id,
+        app_state.texts().get(language, "live_chat_attached"),
+    )
+    .await?;
+    bot.send_message(
+        msg.chat.id,
+        format!(
+            "Вас під'єднано до заявника {target}. Ваші повідомлення пересилаються йому, а його — \
+             вам. Щоб завершити, надішліть /detach."
+        ),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Ends the live chat of the coordinator.
+pub async fn detach(bot: &ThrottledBot, app_state: &AppState, msg: &Message) -> anyhow::Result<()> {
+    let text = if detach_coordinator(bot, app_state, msg.chat.id).await? {
+        "Чат із заявником завершено."
+    } else {
+        "Ви не під'єднані до жодного заявника."
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+/// Detaches the coordinator from the user, if attached, telling the user the chat is over.
+async fn detach_coordinator(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    coordinator_chat_id: i64,
+) -> anyhow::Result<bool> {
+    use redis::AsyncCommands;
+
+    let mut redis = app_state.redis.clone();
+    let user_chat_id: Option<i64> = redis.get(coordinator_key(coordinator_chat_id)).await?;
+    let user_chat_id = match user_chat_id {
+        Some(user_chat_id) => user_chat_id,
+        None => return Ok(false),
+    };
+    redis::pipe()
+        .del(coordinator_key(coordinator_chat_id))
+        .ignore()
+        .del(user_key(user_chat_id))
+        .ignore()
+        .query_async::<_, ()>(&mut redis)
+        .await?;
+    audit::record(
+        app_state,
+        &coordinator_chat_id.to_string(),
+        audit::Action::ChatDetached,
+        &user_chat_id.to_string(),
+        "",
+    )
+    .await;
+    let language: Language = texts::language_of(redis, user_chat_id).await;
+    bot.send_message(
+        user_chat_id,
+        app_state.texts().get(language, "live_chat_detached"),
+    )
+    .await?;
+    Ok(true)
+}
@@ -0,0 +1,666 @@
+//! Matching of registered drivers with evacuation requests.
+//!
+//! Drivers and open evacuation requests are kept in Redis. Whenever either side is submitted, it
+//! is cross-referenced with the other side by region and seat capacity, and the matched drivers
+//! are offered the request with an accept/decline inline keyboard. The first driver to accept
+//! gets the request assigned, see [`assignment::assign`], and the requester's contact details.
+//!
+//! The drivers are offered a request nearest first when both have coordinates, see [`geo`].
+//!
+//! Once a driver with a known number of seats takes a request, the open requests nearby which fit
+//! into the remaining seats are proposed to the driver as one trip. If the driver approves the
+//! manifest, all of them are assigned to the driver and their rows get the trip in the `trip`
+//! column, listing the requests of the trip.
+
+use std::collections::HashSet;
+
+use google_sheets4::api::ValueRange;
+use teloxide::{
+    payloads::{AnswerCallbackQuerySetters, SendMessageSetters},
+    prelude2::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+use crate::{
+    assignment, forum_topics,
+    geo::{self, Coordinates},
+    redis_connection, retry,
+    sheets::find_submission,
+    texts::{self, Language},
+    AppState, Contact, HelpKind, ThrottledBot, TrackedSubmission,
+};
+
+/// Redis hash of registered drivers keyed by reference id.
+pub(crate) const DRIVERS_KEY: &str = "matching:drivers";
+/// Redis hash of evacuation requests which have no driver yet keyed by reference id.
+pub(crate) const OPEN_REQUESTS_KEY: &str = "matching:open_evacuation_requests";
+
+const CALLBACK_PREFIX: &str = "match:";
+
+/// How far from the taken request the other requests of a trip may be.
+const TRIP_RADIUS_KM: f64 = 20.0;
+/// How long the driver can approve a proposed trip.
+const TRIP_PROPOSAL_TTL_SECS: usize = 2 * 60 * 60;
+
+fn trip_key(first_reference_id: &str) -> String {
+    redis_connection::key(&format!("matching:trip:{first_reference_id}"))
+}
+
+/// Requests proposed to the driver to take along with the first one, which the trip is named by.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct TripProposal {
+    driver_chat_id: i64,
+    reference_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct Driver {
+    chat_id: i64,
+    reference_id: String,
+    address: String,
+    #[serde(default)]
+    cities_covered: String,
+    #[serde(default)]
+    seats: Option<u32>,
+    #[serde(default)]
+    coordinates: Option<Coordinates>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct EvacuationRequest {
+    chat_id: i64,
+    reference_id: String,
+    address: String,
+    summary: String,
+    #[serde(default)]
+    passengers: Option<u32>,
+    #[serde(default)]
+    map_url: Option<String>,
+    #[serde(default)]
+    coordinates: Option<Coordinates>,
+}
+
+/// Splits an address into lowercased words long enough to identify a settlement or a region.
+fn region_tokens(address: &str) -> HashSet<String> {
+    address
+        .split(|c: char| !c.is_alphanumeric() && c != '\'' && c != 'ʼ')
+        .filter(|word| word.chars().count() >= 4)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn is_match(driver: &Driver, request: &EvacuationRequest) -> bool {
+    let mut driver_region = region_tokens(&driver.address);
+    driver_region.extend(region_tokens(&driver.cities_covered));
+    let fits = match (driver.seats, request.passengers) {
+        (Some(seats), Some(passengers)) => seats >= passengers,
+        _ => true,
+    };
+    fits && !driver_region.is_disjoint(&region_tokens(&request.address))
+}
+
+/// Whether the other request can be picked up on the same trip.
+fn is_nearby(request: &EvacuationRequest, other: &EvacuationRequest) -> bool {
+    match (request.coordinates, other.coordinates) {
+        (Some(a), Some(b)) => a.haversine_km(b) <= TRIP_RADIUS_KM,
+        _ => !region_tokens(&request.address).is_disjoint(&region_tokens(&other.address)),
+    }
+}
+
+/// The candidates, nearest first, which fit into the free seats, and the seats left after them.
+fn bundle(
+    candidates: Vec<EvacuationRequest>,
+    mut free_seats: u32,
+) -> (Vec<EvacuationRequest>, u32) {
+    let mut bundled = Vec::new();
+    for candidate in candidates {
+        let passengers = candidate.passengers.unwrap_or(1);
+        if passengers <= free_seats {
+            free_seats -= passengers;
+            bundled.push(candidate);
+        }
+    }
+    (bundled, free_seats)
+}
+
+async fn load_all<T: serde::de::DeserializeOwned>(
+    mut redis: redis::aio::MultiplexedConnection,
+    key: &str,
+) -> anyhow::Result<Vec<T>> {
+    use redis::AsyncCommands;
+
+    let entries: Vec<String> = redis.hvals(key).await?;
+    entries
+        .iter()
+        .map(|entry| Ok(serde_json::from_str(entry)?))
+        .collect()
+}
+
+/// Removes the request from the open ones, so that it is offered to no more drivers, e.g. once it
+/// is assigned or closed.
+pub async fn close_request(
+    mut redis: redis::aio::MultiplexedConnection,
+    reference_id: &str,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let _: () = redis
+        .hdel(redis_connection::key(OPEN_REQUESTS_KEY), reference_id)
+        .await?;
+    Ok(())
+}
+
+/// Registers a saved driver or evacuation request and offers the matching evacuation requests to
+/// drivers.
+#[tracing::instrument(skip_all, fields(reference_id = %submission.reference_id))]
+pub async fn register_submission(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    chat_id: i64,
+    contact: &Contact,
+    submission: &TrackedSubmission,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let mut redis = app_state.redis.clone();
+    let address = contact.full_address();
+    let coordinates = geo::locate(app_state, contact).await;
+    if submission.help_kind.is(HelpKind::PROVIDING_DRIVER) {
+        let driver = Driver {
+            chat_id,
+            reference_id: submission.reference_id.clone(),
+            address,
+            cities_covered: contact.cities_covered.clone().unwrap_or_default(),
+            seats: contact.seats,
+            coordinates,
+        };
+        let _: () = redis
+            .hset(
+                redis_connection::key(DRIVERS_KEY),
+                &driver.reference_id,
+                serde_json::to_string(&driver)?,
+            )
+            .await?;
+        let mut requests: Vec<EvacuationRequest> =
+            load_all(redis.clone(), &redis_connection::key(OPEN_REQUESTS_KEY)).await?;
+        requests.retain(|request| is_match(&driver, request));
+        let requests = geo::nearest_first(app_state, coordinates, requests, |request| {
+            request.coordinates
+        })
+        .await;
+        for request in &requests {
+            if let Err(err) = offer_request(bot, app_state, &driver, request).await {
+                tracing::warn!("Failed to offer {}: {:?}", request.reference_id, err);
+            }
+        }
+    } else if submission.help_kind.is(HelpKind::NEED_EVACUATION) {
+        let request = EvacuationRequest {
+            chat_id,
+            reference_id: submission.reference_id.clone(),
+            address,
+            summary: contact.summary(&app_state.texts(), Language::default()),
+            passengers: contact.passengers,
+            map_url: contact.map_url(),
+            coordinates,
+        };
+        let _: () = redis
+            .hset(
+                redis_connection::key(OPEN_REQUESTS_KEY),
+                &request.reference_id,
+                serde_json::to_string(&request)?,
+            )
+            .await?;
+        let mut drivers: Vec<Driver> =
+            load_all(redis.clone(), &redis_connection::key(DRIVERS_KEY)).await?;
+        drivers.retain(|driver| is_match(driver, &request));
+        // The nearby drivers are pinged first.
+        let drivers =
+            geo::nearest_first(app_state, coordinates, drivers, |driver| driver.coordinates).await;
+        // A driver who cannot be reached, e.g. because they blocked the bot, does not keep the
+        // request from the others.
+        for driver in &drivers {
+            if let Err(err) = offer_request(bot, app_state, driver, &request).await {
+                tracing::warn!(
+                    "Failed to offer the request to the driver {}: {:?}",
+                    driver.reference_id,
+                    err
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn offer_request(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    driver: &Driver,
+    request: &EvacuationRequest,
+) -> anyhow::Result<()> {
+    let chat_id = match app_state.notified_chat(driver.chat_id) {
+        Some(chat_id) => chat_id,
+        None => return Ok(()),
+    };
+    let texts = app_state.texts();
+    let language = texts::language_of(app_state.redis.clone(), driver.chat_id).await;
+    let text = texts.render(
+        language,
+        "matching_offer",
+        &[
+            ("reference_id", &request.reference_id),
+            ("address", &request.address),
+            ("map_url", &request.map_url.as_deref().unwrap_or("-")),
+            (
+                "passengers",
+                &request
+                    .passengers
+                    .map_or_else(|| "-".to_owned(), |passengers| passengers.to_string()),
+            ),
+        ],
+    );
+    let keyboard = InlineKeyboardMarkup::new(vec![vec![
+        InlineKeyboardButton::callback(
+            texts.get(language, "button_take").to_owned(),
+            format!("{CALLBACK_PREFIX}accept:{}", request.reference_id),
+        ),
+        InlineKeyboardButton::callback(
+            texts.get(language, "button_decline").to_owned(),
+            format!("{CALLBACK_PREFIX}decline:{}", request.reference_id),
+        ),
+    ]]);
+    retry::on_flood(|| {
+        bot.send_message(chat_id, text.clone())
+            .reply_markup(keyboard.clone())
+    })
+    .await?;
+    Ok(())
+}
+
+pub fn is_matching_callback(query: CallbackQuery) -> bool {
+    query
+        .data
+        .as_deref()
+        .is_some_and(|data| data.starts_with(CALLBACK_PREFIX))
+}
+
+#[tracing::instrument(skip_all, fields(user_id = query.from.id, data = ?query.data))]
+pub async fn handle_callback(
+    bot: ThrottledBot,
+    query: CallbackQuery,
+    app_state: std::sync::Arc<AppState>,
+    language: texts::Language,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let data = query.data.as_deref().unwrap_or_default();
+    let (action, reference_id) = match data
+        .strip_prefix(CALLBACK_PREFIX)
+        .and_then(|data| data.split_once(':'))
+    {
+        Some(parsed) => parsed,
+        None => {
+            tracing::warn!("matching: unexpected callback data {:?}", data);
+            bot.answer_callback_query(query.id).await?;
+            return Ok(());
+        }
+    };
+    let driver_chat_id = query.from.id;
+    if let Some(approved) = match action {
+        "trip_approve" => Some(true),
+        "trip_decline" => Some(false),
+        _ => None,
+    } {
+        return handle_trip_decision(&bot, &query, &app_state, language, reference_id, approved)
+            .await;
+    }
+
+    let texts = app_state.texts();
+    let reply_text = match action {
+        "accept" => {
+            let request: Option<String> = app_state
+                .redis
+                .clone()
+                .hget(redis_connection::key(OPEN_REQUESTS_KEY), reference_id)
+                .await?;
+            let request: Option<EvacuationRequest> = request
+                .map(|request| serde_json::from_str(&request))
+                .transpose()?;
+            match request {
+                // Only the driver who gets the request assigned takes it; the assignment removes
+                // it from the open ones.
+                Some(request)
+                    if assignment::assign(&bot, &app_state, reference_id, &query.from, None)
+                        .await? =>
+                {
+                    bot.send_message(
+                        driver_chat_id,
+                        texts.render(
+                            language,
+                            "matching_assigned_to_driver",
+                            &[
+                                ("reference_id", &request.reference_id),
+                                ("summary", &request.summary),
+                            ],
+                        ),
+                    )
+                    .await?;
+                    forum_topics::post(
+                        &bot,
+                        &app_state,
+                        &request.reference_id,
+                        format!(
+                            "Заявку {} взяв водій (Telegram id {}).",
+                            request.reference_id, driver_chat_id
+                        ),
+                    )
+                    .await?;
+                    if let Err(err) =
+                        propose_trip(&bot, &app_state, driver_chat_id, &request, language).await
+                    {
+                        tracing::warn!(
+                            "Failed to propose a trip with {}: {:?}",
+                            request.reference_id,
+                            err
+                        );
+                    }
+                    texts.get(language, "assignment_taken")
+                }
+                _ => texts.get(language, "matching_already_taken"),
+            }
+        }
+        "decline" => texts.get(language, "offer_declined"),
+        _ => {
+            tracing::warn!("matching: unexpected callback action {:?}", action);
+            ""
+        }
+    };
+
+    if let Some(message) = &query.message {
+        if !reply_text.is_empty() {
+            bot.edit_message_text(
+                message.chat.id,
+                message.id,
+                format!("Запит на евакуацію {reference_id}: {reply_text}"),
+            )
+            .await?;
+        }
+    }
+    bot.answer_callback_query(query.id).text(reply_text).await?;
+    Ok(())
+}
+
+/// Proposes the driver who has just taken the request to take the open requests nearby which fit
+/// into the remaining seats on the same trip.
+async fn propose_trip(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    driver_chat_id: i64,
+    request: &EvacuationRequest,
+    language: Language,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let mut redis = app_state.redis.clone();
+    let drivers: Vec<Driver> = load_all(redis.clone(), &redis_connection::key(DRIVERS_KEY)).await?;
+    let seats = drivers
+        .iter()
+        .filter(|driver| driver.chat_id == driver_chat_id)
+        .filter_map(|driver| driver.seats)
+        .max();
+    let free_seats = match seats {
+        Some(seats) => seats.saturating_sub(request.passengers.unwrap_or(1)),
+        None => return Ok(()),
+    };
+    let mut candidates: Vec<EvacuationRequest> =
+        load_all(redis.clone(), &redis_connection::key(OPEN_REQUESTS_KEY)).await?;
+    candidates.retain(|candidate| is_nearby(request, candidate));
+    let candidates = geo::nearest_first(app_state, request.coordinates, candidates, |candidate| {
+        candidate.coordinates
+    })
+    .await;
+    let (bundled, free_seats) = bundle(candidates, free_seats);
+    if bundled.is_empty() {
+        return Ok(());
+    }
+
+    let proposal = TripProposal {
+        driver_chat_id,
+        reference_ids: bundled
+            .iter()
+            .map(|candidate| candidate.reference_id.clone())
+            .collect(),
+    };
+    let _: () = redis
+        .set_ex(
+            trip_key(&request.reference_id),
+            serde_json::to_string(&proposal)?,
+            TRIP_PROPOSAL_TTL_SECS,
+        )
+        .await?;
+    let manifest: Vec<String> = bundled
+        .iter()
+        .map(|candidate| {
+            format!(
+                "• {}: {}, 👤 {}",
+                candidate.reference_id,
+                candidate.address,
+                candidate
+                    .passengers
+                    .map_or_else(|| "-".to_owned(), |passengers| passengers.to_string())
+            )
+        })
+        .collect();
+    let texts = app_state.texts();
+    let keyboard = InlineKeyboardMarkup::new(vec![vec![
+        InlineKeyboardButton::callback(
+            texts.get(language, "button_trip_approve").to_owned(),
+            format!("{CALLBACK_PREFIX}trip_approve:{}", request.reference_id),
+        ),
+        InlineKeyboardButton::callback(
+            texts.get(language, "button_trip_decline").to_owned(),
+            format!("{CALLBACK_PREFIX}trip_decline:{}", request.reference_id),
+        ),
+    ]]);
+    bot.send_message(
+        driver_chat_id,
+        texts.render(
+            language,
+            "trip_proposal",
+            &[
+                ("reference_id", &request.reference_id),
+                ("seats", &free_seats.to_string()),
+                ("requests", &manifest.join("\n")),
+            ],
+        ),
+    )
+    .reply_markup(keyboard)
+    .await?;
+    Ok(())
+}
+
+/// Assigns the requests of the approved trip to the driver, or drops the declined proposal.
+async fn handle_trip_decision(
+    bot: &ThrottledBot,
+    query: &CallbackQuery,
+    app_state: &AppState,
+    language: Language,
+    first_reference_id: &str,
+    approved: bool,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let texts = app_state.texts();
+    let mut redis = app_state.redis.clone();
+    let proposal: Option<String> = redis.get(trip_key(first_reference_id)).await?;
+    let proposal: TripProposal = match proposal {
+        Some(proposal) => serde_json::from_str(&proposal)?,
+        None => {
+            bot.answer_callback_query(query.id.clone())
+                .text(texts.get(language, "trip_expired"))
+                .await?;
+            return Ok(());
+        }
+    };
+    if proposal.driver_chat_id != query.from.id {
+        bot.answer_callback_query(query.id.clone()).await?;
+        return Ok(());
+    }
+    let _: () = redis.del(trip_key(first_reference_id)).await?;
+
+    let text = if approved {
+        let mut trip = vec![first_reference_id.to_owned()];
+        for reference_id in &proposal.reference_ids {
+            let request: Option<String> = redis
+                .hget(redis_connection::key(OPEN_REQUESTS_KEY), reference_id)
+                .await?;
+            // Only the requests still open are taken, as with a single request.
+            let request: EvacuationRequest = match request {
+                Some(request)
+                    if assignment::assign(bot, app_state, reference_id, &query.from, None)
+                        .await? =>
+                {
+                    serde_json::from_str(&request)?
+                }
+                _ => continue,
+            };
+            bot.send_message(
+                query.from.id,
+                texts.render(
+                    language,
+                    "matching_assigned_to_driver",
+                    &[
+                        ("reference_id", &request.reference_id),
+                        ("summary", &request.summary),
+                    ],
+                ),
+            )
+            .await?;
+            forum_topics::post(
+                bot,
+                app_state,
+                &request.reference_id,
+                format!(
+                    "Заявку {} взяв водій (Telegram id {}) у рейс {first_reference_id}.",
+                    request.reference_id, query.from.id
+                ),
+            )
+            .await?;
+            trip.push(request.reference_id);
+        }
+        if let Err(err) = link_trip_rows(app_state, &trip).await {
+            tracing::warn!(
+                "Failed to link the rows of the trip {}: {:?}",
+                first_reference_id,
+                err
+            );
+        }
+        texts.render(
+            language,
+            "trip_approved",
+            &[("reference_ids", &trip.join(", "))],
+        )
+    } else {
+        texts.get(language, "offer_declined").to_owned()
+    };
+    if let Some(message) = &query.message {
+        bot.edit_message_text(message.chat.id, message.id, text)
+            .await?;
+    }
+    bot.answer_callback_query(query.id.clone()).await?;
+    Ok(())
+}
+
+/// Writes the requests of the trip, the first one naming it, to their rows.
+async fn link_trip_rows(app_state: &AppState, trip: &[String]) -> anyhow::Result<()> {
+    if app_state.skips_external_writes() {
+        tracing::info!("Not writing the trip of {} to the spreadsheets", trip[0]);
+        return Ok(());
+    }
+    let cell = format!("'{}", trip.join(", "));
+    for reference_id in trip {
+        let submission = match find_submission(app_state.redis.clone(), reference_id).await? {
+            Some(indexed) => indexed.submission,
+            None => continue,
+        };
+        let trip_column = match app_state.config().column(&submission.help_kind, "trip") {
+            Some(trip_column) => trip_column,
+            None => continue,
+        };
+        app_state
+            .sheets_api
+            .spreadsheets()
+            .values_update(
+                ValueRange {
+                    major_dimension: Some("ROWS".to_owned()),
+                    range: None,
+                    values: Some(vec![vec![cell.clone()]]),
+                },
+                &submission.spreadsheet_id(),
+                &submission.cell_range(trip_column)?,
+            )
+            .value_input_option("USER_ENTERED")
+            .doit()
+            .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(
+        reference_id: &str,
+        address: &str,
+        passengers: Option<u32>,
+        coordinates: Option<(f64, f64)>,
+    ) -> EvacuationRequest {
+        EvacuationRequest {
+            chat_id: 1,
+            reference_id: reference_id.to_owned(),
+            address: address.to_owned(),
+            summary: String::new(),
+            passengers,
+            map_url: None,
+            coordinates: coordinates.map(|(latitude, longitude)| Coordinates {
+                latitude,
+                longitude,
+            }),
+        }
+    }
+
+    #[test]
+    fn requests_nearby_or_in_the_same_settlement_share_a_trip() {
+        let kramatorsk = request(
+            "EV-1",
+            "Краматорськ, вул. Миру, 1",
+            None,
+            Some((48.72, 37.56)),
+        );
+        let sloviansk = request("EV-2", "Слов'янськ", None, Some((48.85, 37.6)));
+        let kharkiv = request("EV-3", "Харків", None, Some((49.99, 36.23)));
+        let unlocated = request("EV-4", "м. Краматорськ", None, None);
+        assert!(is_nearby(&kramatorsk, &sloviansk));
+        assert!(!is_nearby(&kramatorsk, &kharkiv));
+        assert!(is_nearby(&kramatorsk, &unlocated));
+        assert!(!is_nearby(&kharkiv, &unlocated));
+    }
+
+    #[test]
+    fn trips_take_the_nearest_requests_which_fit_into_the_seats() {
+        let candidates = vec![
+            request("EV-1", "Краматорськ", Some(2), None),
+            request("EV-2", "Краматорськ", Some(3), None),
+            request("EV-3", "Краматорськ", None, None),
+            request("EV-4", "Краматорськ", Some(1), None),
+        ];
+        let (bundled, free_seats) = bundle(candidates, 4);
+        let reference_ids: Vec<_> = bundled
+            .iter()
+            .map(|request| request.reference_id.as_str())
+            .collect();
+        assert_eq!(reference_ids, ["EV-1", "EV-3", "EV-4"]);
+        assert_eq!(free_seats, 0);
+        assert!(bundle(Vec::new(), 4).0.is_empty());
+    }
+}
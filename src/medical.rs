@@ -0,0 +1,59 @@
+//! Requests with urgent medical needs, e.g. insulin, dialysis or bandaging, which the medical
+//! volunteers should see as well.
+//!
+//! A request is flagged when the answer to the question about the medical needs means there are
+//! any. The row of a flagged request gets [`LABEL`] in the `medical` column, and the request is
+//! posted to the chat of the medical volunteers besides the admin group, if the chat is configured.
+
+use teloxide::{prelude2::*, types::ParseMode};
+
+use crate::{html, texts::Language, triage, AppState, Contact, ThrottledBot, TrackedSubmission};
+
+/// Written to the `medical` column of the flagged requests.
+pub const LABEL: &str = "⚕️ Медична допомога";
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct MedicalVolunteers {
+    /// Id of the chat of the medical volunteers; the bot must be its member.
+    pub chat_id: i64,
+}
+
+pub fn is_flagged(contact: &Contact) -> bool {
+    triage::is_affirmative(contact.medical_needs.as_deref())
+}
+
+/// Posts the flagged request to the chat of the medical volunteers.
+pub async fn post_submission(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    contact: &Contact,
+    submission: &TrackedSubmission,
+) -> anyhow::Result<()> {
+    if !is_flagged(contact) {
+        return Ok(());
+    }
+    let config = app_state.config();
+    let chat_id = match &config.medical_volunteers {
+        Some(medical_volunteers) if !app_state.skips_external_writes() => {
+            medical_volunteers.chat_id
+        }
+        _ => return Ok(()),
+    };
+    let map = contact
+        .map_url()
+        .map(|map_url| format!("\n{}", html::link(&map_url, "Відкрити на карті")))
+        .unwrap_or_default();
+    bot.send_message(
+        chat_id,
+        format!(
+            "{} — заявка {} ({})\n\n{}{map}",
+            html::bold(LABEL),
+            html::code(&submission.reference_id),
+            html::escape(config.help_kind_title(&submission.help_kind)),
+            contact.summary_html(&app_state.texts(), Language::default()),
+        ),
+    )
+    .parse_mode(ParseMode::Html)
+    .await?;
+    Ok(())
+}
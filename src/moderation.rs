@@ -0,0 +1,132 @@
+//! Rejection of obscene and meaningless answers to the text questions, e.g. keyboard mashing or
+//! lone emoji, which would otherwise end up in the spreadsheet. The question is asked again with a
+//! note that the answer does not fit it.
+
+use crate::form::Input;
+
+/// Beginnings of the obscene words, matched at the start of every word ignoring the case.
+const OBSCENE_STEMS: &[&str] = &[
+    "хуй",
+    "хуя",
+    "хує",
+    "хуе",
+    "пізд",
+    "пизд",
+    "їба",
+    "єба",
+    "ёба",
+    "еба",
+    "заєб",
+    "заеб",
+    "виєб",
+    "уєб",
+    "бляд",
+    "підар",
+    "підор",
+    "пидор",
+    "fuck",
+    "shit",
+    "bitch",
+];
+
+/// Obscene words which begin place names and surnames too, e.g. `Сукачі`, so only matched whole.
+const OBSCENE_WORDS: &[&str] = &["блять", "сука", "суки", "суко", "мудак", "мудаки"];
+
+/// Longer words are taken for keyboard mashing, e.g. `фівапролджєячсмитьбю`; IBANs are shorter.
+const MAX_WORD_CHARS: usize = 40;
+
+/// Whether the answer to a question with the input is obscene or meaningless.
+pub fn rejects(input: &Input, answer: &str) -> bool {
+    match min_chars(input) {
+        Some(min_chars) => is_obscene(answer) || is_nonsense(answer, min_chars),
+        None => false,
+    }
+}
+
+/// The fewest characters of a meaningful answer to a question with the input; `None` for the
+/// inputs which are not moderated.
+fn min_chars(input: &Input) -> Option<usize> {
+    match input {
+        // Even the shortest names and settlements, e.g. `Ян` or `Ая`, take two letters.
+        Input::Text | Input::City => Some(2),
+        Input::Number { .. }
+        | Input::Choice { .. }
+        | Input::MultipleChoice { .. }
+        | Input::Oblast => None,
+    }
+}
+
+fn is_obscene(answer: &str) -> bool {
+    answer
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .any(|word| {
+            OBSCENE_WORDS.contains(&word) || OBSCENE_STEMS.iter().any(|stem| word.starts_with(stem))
+        })
+}
+
+fn is_nonsense(answer: &str, min_chars: usize) -> bool {
+    let answer = answer.trim();
+    // The answer to skip an optional question.
+    if answer == "-" {
+        return false;
+    }
+    // A lone digit is fine, e.g. the number of a building.
+    if answer.chars().count() < min_chars && !answer.chars().all(char::is_numeric) {
+        return true;
+    }
+    let mut chars = answer.chars().filter(|c| !c.is_whitespace());
+    let first = chars.next();
+    // Numbers like `111` are fine, e.g. the number of a building.
+    let repeated = answer.chars().count() >= 3
+        && first.is_some_and(|first| !first.is_numeric())
+        && chars.all(|c| Some(c) == first);
+    !answer.chars().any(char::is_alphanumeric)
+        || repeated
+        || answer
+            .split_whitespace()
+            .any(|word| word.chars().count() > MAX_WORD_CHARS && !word.contains(['/', '@']))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn obscene_and_meaningless_answers_are_rejected() {
+        for answer in [
+            "Хуйня якась",
+            "ааааа",
+            "))))",
+            "🙂",
+            "фівапролджєячсмитьбюйцукенгшщзхїфівапролд",
+        ] {
+            assert!(rejects(&Input::Text, answer), "{answer:?}");
+        }
+        for answer in [
+            "-",
+            "Тарас Шевченко",
+            "вул. Хлібна, 5",
+            "+380501234567",
+            "Потрібно хліба",
+        ] {
+            assert!(!rejects(&Input::Text, answer), "{answer:?}");
+        }
+        assert!(!rejects(&Input::Number { min: 0 }, "))))"));
+    }
+
+    #[test]
+    fn one_letter_answers_are_rejected() {
+        assert!(rejects(&Input::Text, "А"));
+        assert!(rejects(&Input::City, " б "));
+        assert!(!rejects(&Input::Text, "Ян"));
+        assert!(!rejects(&Input::Text, "5"));
+    }
+
+    #[test]
+    fn place_names_starting_with_obscene_words_are_accepted() {
+        assert!(!rejects(&Input::City, "Сукачі"));
+        assert!(!rejects(&Input::Text, "с. Сукачі, вул. Лісова, 3"));
+        assert!(rejects(&Input::Text, "Сука"));
+    }
+}
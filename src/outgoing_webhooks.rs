@@ -0,0 +1,218 @@
+//! HTTP webhooks notified of the submission events, so that external dispatch systems do not
+//! have to poll the spreadsheets.
+//!
+//! Each configured URL receives a JSON [`Payload`] in a POST request, signed with HMAC-SHA256 of
+//! the body with the `secret` of the webhook or, by default, the key from
+//! `COLLECT_VOLUNTEERS_BOT_OUTGOING_WEBHOOK_SECRET`: the `X-Signature` header is `sha256=` followed
+//! by the hex digest. The config is not loaded if a webhook has no secret. The requests are sent in
+//! the background and retried a few times, and the shutdown waits for them; nothing is sent in the
+//! dev mode or the dry run.
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{
+    texts::{Language, Texts},
+    AppState, Contact, TrackedSubmission,
+};
+
+const MAX_ATTEMPTS: u32 = 3;
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct OutgoingWebhook {
+    pub url: String,
+    /// The key of the signatures; `COLLECT_VOLUNTEERS_BOT_OUTGOING_WEBHOOK_SECRET` by default.
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// The events sent to the URL; all of them by default.
+    #[serde(default)]
+    pub events: Vec<Event>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    /// The submission was saved.
+    Created,
+    /// Coordinators set the status to "взято в роботу" in the spreadsheet.
+    Approved,
+    /// A volunteer took the request with the button.
+    Assigned,
+    /// Coordinators set the status to "виконано" in the spreadsheet.
+    Completed,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Payload<'a> {
+    pub event: Event,
+    pub occurred_at: chrono::DateTime<chrono::Utc>,
+    pub reference_id: &'a str,
+    pub help_kind: &'a str,
+    pub spreadsheet_id: String,
+    pub range: &'a str,
+    /// The answers of the form by their labels; only for [`Event::Created`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub answers: Option<std::collections::BTreeMap<String, String>>,
+    /// The volunteer who took the request; only for [`Event::Assigned`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assignee: Option<&'a str>,
+}
+
+impl<'a> Payload<'a> {
+    pub fn new(event: Event, submission: &'a TrackedSubmission) -> Self {
+        Self {
+            event,
+            occurred_at: chrono::Utc::now(),
+            reference_id: &submission.reference_id,
+            help_kind: submission.help_kind.as_str(),
+            spreadsheet_id: submission.spreadsheet_id(),
+            range: &submission.range,
+            answers: None,
+            assignee: None,
+        }
+    }
+
+    /// The answers with the labels in Ukrainian, as the coordinators see them.
+    pub fn with_answers(mut self, texts: &Texts, contact: &Contact) -> Self {
+        self.answers = Some(
+            contact
+                .summary_fields(texts, Language::default())
+                .into_iter()
+                .collect(),
+        );
+        self
+    }
+
+    pub fn with_assignee(mut self, assignee: &'a str) -> Self {
+        self.assignee = Some(assignee);
+        self
+    }
+}
+
+/// Sends the payload to every webhook subscribed to its event, in the background.
+pub fn send(app_state: &AppState, payload: &Payload<'_>) {
+    let webhooks: Vec<OutgoingWebhook> = app_state
+        .config()
+        .webhooks
+        .iter()
+        .filter(|webhook| webhook.events.is_empty() || webhook.events.contains(&payload.event))
+        .cloned()
+        .collect();
+    if webhooks.is_empty() {
+        return;
+    }
+    if app_state.skips_external_writes() {
+        tracing::info!(
+            "Not sending {:?} of {} to the webhooks",
+            payload.event,
+            payload.reference_id
+        );
+        return;
+    }
+    let body = match serde_json::to_vec(payload) {
+        Ok(body) => body,
+        Err(err) => {
+            tracing::error!("Failed to serialize the webhook payload: {:?}", err);
+            return;
+        }
+    };
+    for webhook in webhooks {
+        let secret = match &webhook.secret {
+            Some(secret) => secret,
+            None => {
+                tracing::error!("Not sending the unsigned webhook to {}", webhook.url);
+                continue;
+            }
+        };
+        let signature = signature(secret, &body);
+        app_state
+            .tasks
+            .spawn(deliver(webhook.url, body.clone(), Some(signature)));
+    }
+}
+
+/// Sets the default secret of the webhooks which have none; fails if there is no default.
+pub fn resolve_secrets(webhooks: &mut [OutgoingWebhook]) -> anyhow::Result<()> {
+    let default = std::env::var("COLLECT_VOLUNTEERS_BOT_OUTGOING_WEBHOOK_SECRET")
+        .ok()
+        .filter(|secret| !secret.is_empty());
+    for webhook in webhooks {
+        if webhook.secret.as_deref().is_none_or(str::is_empty) {
+            webhook.secret = Some(default.clone().ok_or_else(|| {
+                anyhow::anyhow!(
+                    "webhook {} has no secret and COLLECT_VOLUNTEERS_BOT_OUTGOING_WEBHOOK_SECRET is not set",
+                    webhook.url
+                )
+            })?);
+        }
+    }
+    Ok(())
+}
+
+fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Posts the JSON body to the URL, with the signature if there is one.
+#[tracing::instrument(skip(body, signature))]
+pub async fn deliver(url: String, body: Vec<u8>, signature: Option<String>) {
+    let client = reqwest::Client::new();
+    for attempt in 1..=MAX_ATTEMPTS {
+        let mut request = client
+            .post(&url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .timeout(std::time::Duration::from_secs(10))
+            .body(body.clone());
+        if let Some(signature) = &signature {
+            request = request.header("X-Signature", signature);
+        }
+        match request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+        {
+            Ok(_) => return,
+            Err(err) => {
+                tracing::warn!(
+                    "Failed to deliver the webhook (attempt {}): {}",
+                    attempt,
+                    err
+                );
+            }
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(5 * u64::from(attempt))).await;
+    }
+    tracing::error!("Gave up delivering the webhook");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signature_is_hmac_sha256() {
+        // RFC 4231, test case 2.
+        assert_eq!(
+            signature("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn webhooks_without_secret_are_rejected() {
+        let mut webhooks = vec![OutgoingWebhook {
+            url: "https://example.com/hook".to_owned(),
+            secret: Some("own".to_owned()),
+            events: Vec::new(),
+        }];
+        resolve_secrets(&mut webhooks).unwrap();
+        assert_eq!(webhooks[0].secret.as_deref(), Some("own"));
+
+        std::env::remove_var("COLLECT_VOLUNTEERS_BOT_OUTGOING_WEBHOOK_SECRET");
+        webhooks[0].secret = None;
+        assert!(resolve_secrets(&mut webhooks).is_err());
+    }
+}
@@ -0,0 +1,364 @@
+//! `/delete_my_data`: erasure of what the bot keeps about a user.
+//!
+//! After a confirmation the user's dialogue state and the index of their submissions are deleted,
+//! and optionally the personal data in the rows of their submissions is blanked in the
+//! spreadsheets. The rows themselves stay so that volunteers keep track of the requests they
+//! handled.
+
+use google_sheets4::api::{BatchUpdateValuesRequest, ValueRange};
+use teloxide::{
+    dispatching2::dialogue::Storage,
+    payloads::SendMessageSetters,
+    prelude2::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+use crate::{
+    audit,
+    handlers::keyboards::start_keyboard,
+    lifecycle, matching, questionnaire, redis_connection, reminders, retention,
+    sheets::{
+        replay, status, submissions_key, tracked_submissions, TrackedSubmission,
+        SUBMISSIONS_BY_ID_KEY, SUBMISSIONS_BY_REFERENCE_KEY, SUBMISSION_CHATS_KEY,
+    },
+    texts, triage, AppState, DialogueStorage, ThrottledBot,
+};
+
+const CALLBACK_PREFIX: &str = "delete_my_data:";
+
+/// Columns of the submission rows with personal data, see
+/// [`crate::config::HelpKindDefinition::columns`].
+const PERSONAL_COLUMNS: &[&str] = &[
+    "full_name",
+    "phone_numbers",
+    "address",
+    "street",
+    "building",
+    "address_notes",
+    "comments",
+    "children",
+    "limited_mobility",
+    "accessibility_needs",
+    "medical_needs",
+    "attachments",
+    "telegram_user_id",
+    "telegram_username",
+    "telegram_link",
+];
+
+pub async fn ask_confirmation(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    chat_id: i64,
+    language: texts::Language,
+) -> anyhow::Result<()> {
+    let texts = app_state.texts();
+    let button = |text_id: &str, action: &str| {
+        vec![InlineKeyboardButton::callback(
+            texts.get(language, text_id).to_owned(),
+            format!("{CALLBACK_PREFIX}{action}"),
+        )]
+    };
+    bot.send_message(chat_id, texts.get(language, "delete_my_data_confirm"))
+        .reply_markup(InlineKeyboardMarkup::new(vec![
+            button("button_delete_bot_data", "bot"),
+            button("button_delete_all_data", "all"),
+            button("button_delete_cancel", "cancel"),
+        ]))
+        .await?;
+    Ok(())
+}
+
+pub fn is_deletion_callback(query: CallbackQuery) -> bool {
+    query
+        .data
+        .as_deref()
+        .is_some_and(|data| data.starts_with(CALLBACK_PREFIX))
+}
+
+#[tracing::instrument(skip_all, fields(user_id = query.from.id, data = ?query.data))]
+pub async fn handle_callback(
+    bot: ThrottledBot,
+    query: CallbackQuery,
+    app_state: std::sync::Arc<AppState>,
+    storage: std::sync::Arc<DialogueStorage>,
+    language: texts::Language,
+) -> anyhow::Result<()> {
+    bot.answer_callback_query(query.id.clone()).await?;
+    let message = match &query.message {
+        Some(message) if message.chat.is_private() => message,
+        _ => return Ok(()),
+    };
+    bot.edit_message_reply_markup(message.chat.id, message.id)
+        .await?;
+
+    let texts = app_state.texts();
+    let redact_rows = match query
+        .data
+        .as_deref()
+        .and_then(|data| data.strip_prefix(CALLBACK_PREFIX))
+    {
+        Some("bot") => false,
+        Some("all") => true,
+        Some("cancel") => {
+            bot.send_message(
+                message.chat.id,
+                texts.get(language, "delete_my_data_cancelled"),
+            )
+            .await?;
+            return Ok(());
+        }
+        action => {
+            tracing::warn!("personal_data: unexpected callback action {:?}", action);
+            return Ok(());
+        }
+    };
+    let text_id = match erase(&app_state, storage, message.chat.id, redact_rows).await {
+        Ok(()) => "delete_my_data_done",
+        Err(err) => {
+            tracing::error!("Failed to erase the data of the user: {:?}", err);
+            "delete_my_data_failed"
+        }
+    };
+    bot.send_message(message.chat.id, texts.get(language, text_id))
+        .reply_markup(start_keyboard(&texts, language))
+        .await?;
+    Ok(())
+}
+
+/// Deletes the dialogue state and everything else kept about the chat and its submissions, blanking the personal data
+/// in the rows of the submissions first if asked to. Nothing is deleted if the rows cannot be
+/// updated, so that the user can try again.
+async fn erase(
+    app_state: &AppState,
+    storage: std::sync::Arc<DialogueStorage>,
+    chat_id: i64,
+    redact_rows: bool,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let submissions = tracked_submissions(app_state.redis.clone(), chat_id).await?;
+    if redact_rows {
+        // Submissions logged in the dev mode have no row.
+        for submission in submissions.iter().filter(|s| !s.range.is_empty()) {
+            redact_row(app_state, submission).await?;
+        }
+    }
+
+    // There may be no state to remove, e.g. right after a restart in the dev mode.
+    if let Err(err) = storage.remove_dialogue(chat_id).await {
+        tracing::debug!("No dialogue state to remove: {:?}", err);
+    }
+    for submission in &submissions {
+        forget_submission(app_state, submission).await?;
+    }
+    let mut redis = app_state.redis.clone();
+    let _: () = redis.del(submissions_key(chat_id)).await?;
+    let _: () = redis
+        .srem(redis_connection::key(SUBMISSION_CHATS_KEY), chat_id)
+        .await?;
+    forget_chat(app_state, chat_id).await?;
+    audit::record(
+        app_state,
+        &chat_id.to_string(),
+        audit::Action::DataDeleted,
+        &chat_id.to_string(),
+        &format!(
+            "{} submissions, rows redacted: {}",
+            submissions.len(),
+            redact_rows
+        ),
+    )
+    .await;
+    Ok(())
+}
+
+/// Deletes what is kept about the submission apart from its entry in the submissions list of its
+/// chat: the indices, the statuses, the triage and matching entries and the queued row.
+pub async fn forget_submission(
+    app_state: &AppState,
+    submission: &TrackedSubmission,
+) -> anyhow::Result<()> {
+    let reference_id = &submission.reference_id;
+    let mut redis = app_state.redis.clone();
+    let mut pipe = redis::pipe();
+    pipe.atomic()
+        .hdel(
+            redis_connection::key(SUBMISSIONS_BY_REFERENCE_KEY),
+            reference_id,
+        )
+        .del(status::last_status_key(reference_id))
+        .del(lifecycle::status_key(reference_id))
+        .del(lifecycle::history_key(reference_id))
+        .hdel(redis_connection::key(matching::DRIVERS_KEY), reference_id)
+        .hdel(
+            redis_connection::key(matching::OPEN_REQUESTS_KEY),
+            reference_id,
+        );
+    if let Some(submission_id) = submission.submission_id {
+        pipe.hdel(
+            redis_connection::key(SUBMISSIONS_BY_ID_KEY),
+            submission_id.to_string(),
+        );
+    }
+    pipe.query_async::<_, ()>(&mut redis).await?;
+    triage::dequeue(app_state, reference_id).await?;
+    replay::forget(app_state, reference_id).await
+}
+
+/// Deletes what is kept about the chat apart from its dialogue state and submissions: the
+/// language, the activity the reminders and the retention go by and the pending questionnaire.
+pub async fn forget_chat(app_state: &AppState, chat_id: i64) -> anyhow::Result<()> {
+    redis::pipe()
+        .atomic()
+        .del(texts::language_key(chat_id))
+        .del(questionnaire::pending_key(chat_id))
+        .zrem(redis_connection::key(reminders::ACTIVITY_KEY), chat_id)
+        .srem(redis_connection::key(reminders::REMINDED_KEY), chat_id)
+        .zrem(redis_connection::key(retention::LAST_ACTIVITY_KEY), chat_id)
+        .query_async::<_, ()>(&mut app_state.redis.clone())
+        .await?;
+    Ok(())
+}
+
+/// Blanks the personal data in the row of the submission.
+#[tracing::instrument(skip_all, fields(reference_id = %submission.reference_id))]
+pub async fn redact_row(
+    app_state: &AppState,
+    submission: &TrackedSubmission,
+) -> anyhow::Result<()> {
+    let config = app_state.config();
+    let data = PERSONAL_COLUMNS
+        .iter()
+        .filter_map(|name| config.column(&submission.help_kind, name))
+        .map(|column| {
+            Ok(ValueRange {
+                major_dimension: Some("ROWS".to_owned()),
+                range: Some(submission.cell_range(column)?),
+                values: Some(vec![vec![String::new()]]),
+            })
+        })
+        .collect::<anyhow::Result<_>>()?;
+    if app_state.skips_external_writes() {
+        tracing::info!("Not blanking the personal data in the spreadsheet");
+        return Ok(());
+    }
+    app_state
+        .sheets_api
+        .spreadsheets()
+        .values_batch_update(
+            BatchUpdateValuesRequest {
+                data: Some(data),
+                value_input_option: Some("RAW".to_owned()),
+                ..Default::default()
+            },
+            &submission.spreadsheet_id(),
+        )
+        .doit()
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use redis::AsyncCommands;
+
+    use super::{forget_chat, forget_submission};
+    use crate::{
+        config::Destination,
+        redis_connection::key,
+        sheets::{replay, TrackedSubmission},
+        testing::Harness,
+        HelpKind,
+    };
+
+    fn submission(reference_id: &str) -> TrackedSubmission {
+        TrackedSubmission {
+            reference_id: reference_id.to_owned(),
+            help_kind: HelpKind::new(HelpKind::NEED_EVACUATION),
+            spreadsheet_id: None,
+            range: String::new(),
+            saved_at: Some(chrono::Utc::now()),
+            submission_id: Some(uuid::Uuid::new_v4()),
+            mismatched_columns: Vec::new(),
+        }
+    }
+
+    /// Writes every key kept about the chat and its submission.
+    async fn remember(
+        harness: &Harness,
+        chat_id: i64,
+        submission: &TrackedSubmission,
+    ) -> anyhow::Result<()> {
+        let reference_id = submission.reference_id.as_str();
+        let submission_id = submission.submission_id.unwrap().to_string();
+        redis::pipe()
+            .hset(key("submissions:by_reference"), reference_id, chat_id)
+            .hset(key("submissions:by_id"), &submission_id, reference_id)
+            .set(key(&format!("submission_status:{reference_id}")), "new")
+            .set(key(&format!("lifecycle:{reference_id}")), "new")
+            .rpush(key(&format!("lifecycle:{reference_id}:history")), "new")
+            .hset(key("matching:drivers"), reference_id, "{}")
+            .hset(key("matching:open_evacuation_requests"), reference_id, "{}")
+            .zadd(key("triage:queue"), reference_id, 1)
+            .hset(key("triage:requests"), reference_id, "{}")
+            .set(key(&format!("language:{chat_id}")), "en")
+            .set(key(&format!("questionnaire:{chat_id}")), "{}")
+            .zadd(key("reminders:activity"), chat_id, 1)
+            .sadd(key("reminders:reminded"), chat_id)
+            .zadd(key("retention:last_activity"), chat_id, 1)
+            .query_async::<_, ()>(&mut harness.app_state.redis.clone())
+            .await?;
+        replay::enqueue(
+            &harness.app_state,
+            &replay::FailedWrite {
+                chat_id,
+                help_kind: submission.help_kind.clone(),
+                destination: Destination {
+                    spreadsheet_id: "spreadsheet".to_owned(),
+                    sheet: "Sheet1".to_owned(),
+                    monthly: false,
+                },
+                reference_id: reference_id.to_owned(),
+                row: vec!["row".to_owned()],
+                saved_at: chrono::Utc::now(),
+                submission_id: submission.submission_id.unwrap(),
+            },
+        )
+        .await
+    }
+
+    async fn keys(harness: &Harness) -> anyhow::Result<BTreeSet<String>> {
+        let mut redis = harness.app_state.redis.clone();
+        let mut keys = BTreeSet::new();
+        for key in redis.keys::<_, Vec<String>>(key("*")).await? {
+            let kind: String = redis::cmd("TYPE").arg(&key).query_async(&mut redis).await?;
+            let members: Vec<String> = match kind.as_str() {
+                "string" => vec![redis.get(&key).await?],
+                "list" => redis.lrange(&key, 0, -1).await?,
+                "hash" => redis.hkeys(&key).await?,
+                "set" => redis.smembers(&key).await?,
+                _ => redis.zrangebyscore(&key, "-inf", "+inf").await?,
+            };
+            keys.extend(members.into_iter().map(|member| format!("{key} {member}")));
+        }
+        Ok(keys)
+    }
+
+    #[tokio::test]
+    async fn forgets_everything_about_the_chat_and_submission() -> anyhow::Result<()> {
+        let harness = Harness::new().await?;
+        let (forgotten, kept) = (submission("EV-1"), submission("EV-2"));
+        remember(&harness, 1, &kept).await?;
+        let before = keys(&harness).await?;
+        remember(&harness, 2, &forgotten).await?;
+
+        forget_submission(&harness.app_state, &forgotten).await?;
+        forget_chat(&harness.app_state, 2).await?;
+
+        assert_eq!(keys(&harness).await?, before);
+        Ok(())
+    }
+}
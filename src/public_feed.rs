@@ -0,0 +1,180 @@
+//! Anonymized feed of the open requests in a public channel, to attract volunteers from outside.
+//!
+//! Every saved request for help of the configured kinds is posted to the channel with only its
+//! reference id, kind, oblast and urgency, never the names, phones or addresses. The post is edited
+//! when a volunteer takes the request and when it is done or cancelled. Nothing is posted in the
+//! dry run or the dev mode.
+
+use teloxide::prelude2::*;
+
+use crate::{
+    config::HelpDirection,
+    redis_connection,
+    state::{Contact, HelpKind},
+    AppState, ThrottledBot, TrackedSubmission,
+};
+
+/// Redis hash of the posts keyed by reference id.
+const POSTS_KEY: &str = "public_feed:posts";
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct PublicChannel {
+    /// Id of the channel, e.g. `-1001234567890`; the bot must be its administrator.
+    pub chat_id: i64,
+    /// Kinds of help whose requests are posted; all the requests for help by default.
+    #[serde(default)]
+    pub help_kinds: Vec<HelpKind>,
+    /// Appended to every post, e.g. how to reach the bot to help.
+    #[serde(default)]
+    pub call_to_action: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct Post {
+    message_id: i32,
+    /// The text without the status line.
+    body: String,
+    status: String,
+}
+
+impl Post {
+    fn text(&self) -> String {
+        format!("{}\n\n{}", self.status, self.body)
+    }
+}
+
+/// Posts the scrubbed request to the channel, if its kind is published.
+pub async fn post_submission(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    contact: &Contact,
+    submission: &TrackedSubmission,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let config = app_state.config();
+    let channel = match &config.public_channel {
+        Some(channel) if !app_state.skips_external_writes() => channel,
+        _ => return Ok(()),
+    };
+    let published = if channel.help_kinds.is_empty() {
+        config
+            .help_kind(&submission.help_kind)
+            .is_some_and(|definition| definition.direction == HelpDirection::Needed)
+    } else {
+        channel.help_kinds.contains(&submission.help_kind)
+    };
+    if !published {
+        return Ok(());
+    }
+    let mut lines = vec![
+        format!(
+            "Заявка {}: {}",
+            submission.reference_id,
+            config.help_kind_title(&submission.help_kind)
+        ),
+        format!(
+            "Область: {}",
+            contact.region.as_deref().unwrap_or("не вказано")
+        ),
+    ];
+    if let Some(urgency) = contact.urgency {
+        lines.push(format!("Терміновість: {}", urgency.label()));
+    }
+    if let Some(call_to_action) = &channel.call_to_action {
+        lines.push(String::new());
+        lines.push(call_to_action.clone());
+    }
+    let mut post = Post {
+        message_id: 0,
+        body: lines.join("\n"),
+        status: "🔴 Шукаємо волонтера".to_owned(),
+    };
+    let message = bot.send_message(channel.chat_id, post.text()).await?;
+    post.message_id = message.id;
+    let _: () = app_state
+        .redis
+        .clone()
+        .hset(
+            redis_connection::key(POSTS_KEY),
+            &submission.reference_id,
+            serde_json::to_string(&post)?,
+        )
+        .await?;
+    Ok(())
+}
+
+/// Marks the post of the request as taken by a volunteer.
+pub async fn mark_taken(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    reference_id: &str,
+) -> anyhow::Result<()> {
+    update(
+        bot,
+        app_state,
+        reference_id,
+        "🟡 Волонтер уже допомагає",
+        false,
+    )
+    .await
+}
+
+/// Reflects the status from the sheet in the post of the request; the post of a closed request
+/// is not edited anymore.
+pub async fn update_status(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    reference_id: &str,
+    normalized_status: &str,
+) -> anyhow::Result<()> {
+    match normalized_status {
+        "взято в роботу" => mark_taken(bot, app_state, reference_id).await,
+        "виконано" => update(bot, app_state, reference_id, "✅ Виконано", true).await,
+        "скасовано" => update(bot, app_state, reference_id, "⚪ Скасовано", true).await,
+        _ => Ok(()),
+    }
+}
+
+async fn update(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    reference_id: &str,
+    status: &str,
+    closed: bool,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let channel_id = match &app_state.config().public_channel {
+        Some(channel) => channel.chat_id,
+        None => return Ok(()),
+    };
+    let mut redis = app_state.redis.clone();
+    let post: Option<String> = redis
+        .hget(redis_connection::key(POSTS_KEY), reference_id)
+        .await?;
+    let mut post: Post = match post {
+        Some(post) => serde_json::from_str(&post)?,
+        None => return Ok(()),
+    };
+    // Telegram refuses to edit a message into the same text.
+    if post.status != status {
+        post.status = status.to_owned();
+        bot.edit_message_text(channel_id, post.message_id, post.text())
+            .await?;
+    }
+    if closed {
+        let _: () = redis
+            .hdel(redis_connection::key(POSTS_KEY), reference_id)
+            .await?;
+    } else {
+        let _: () = redis
+            .hset(
+                redis_connection::key(POSTS_KEY),
+                reference_id,
+                serde_json::to_string(&post)?,
+            )
+            .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,171 @@
+//! Questionnaires pasted as a single message, e.g. copied from another chat, parsed into the
+//! answers of the form by an LLM, so that the user does not answer every question again.
+//!
+//! Only the first message of the form with several lines is parsed, and only when the parser is
+//! configured in `questionnaire_parser`; its API key is taken from
+//! `COLLECT_VOLUNTEERS_BOT_LLM_API_KEY`. The text, with the personal data in it, is sent to the
+//! LLM provider. Every parsed answer passes the validation of its question; the user confirms what
+//! was understood, and the questions left without an answer are asked one by one as usual.
+
+use crate::{form::Form, redis_connection, state::encryption, AppState, Contact};
+
+/// How long the parsed answers wait for the user's confirmation.
+const PENDING_TTL_SECS: usize = 60 * 60;
+
+/// Fewer lines are taken for an answer to the first question.
+const MIN_LINES: usize = 3;
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Parser {
+    /// OpenAI-compatible `chat/completions` endpoint.
+    #[serde(default = "default_url")]
+    pub url: String,
+    #[serde(default = "default_model")]
+    pub model: String,
+}
+
+fn default_url() -> String {
+    "https://api.openai.com/v1/chat/completions".to_owned()
+}
+
+fn default_model() -> String {
+    "gpt-4o-mini".to_owned()
+}
+
+fn api_key() -> anyhow::Result<String> {
+    std::env::var("COLLECT_VOLUNTEERS_BOT_LLM_API_KEY")
+        .map_err(|_| anyhow::anyhow!("COLLECT_VOLUNTEERS_BOT_LLM_API_KEY is not set"))
+}
+
+pub(crate) fn pending_key(chat_id: i64) -> String {
+    redis_connection::key(&format!("questionnaire:{chat_id}"))
+}
+
+/// Whether the text looks like a whole questionnaire rather than an answer to one question.
+pub fn looks_like_questionnaire(text: &str) -> bool {
+    text.lines().filter(|line| !line.trim().is_empty()).count() >= MIN_LINES
+}
+
+/// Fills in the contact with the answers found in the text; the answers which do not pass the
+/// validation of their question are dropped.
+pub async fn parse(
+    parser: &Parser,
+    form: &Form,
+    contact: &Contact,
+    text: &str,
+) -> anyhow::Result<Contact> {
+    let answers = extract_answers(parser, form, text).await?;
+    let mut parsed = contact.clone();
+    for question in &form.questions {
+        if parsed.has_answer(&question.field) {
+            continue;
+        }
+        if let Some(answer) = answers.get(&question.field) {
+            if question.failed_validator(answer).is_some()
+                || question.accept(&mut parsed, answer).is_err()
+            {
+                tracing::info!("Dropped the parsed answer to {}", question.field);
+            }
+        }
+    }
+    Ok(parsed)
+}
+
+async fn extract_answers(
+    parser: &Parser,
+    form: &Form,
+    text: &str,
+) -> anyhow::Result<std::collections::BTreeMap<String, String>> {
+    #[derive(serde::Deserialize)]
+    struct Response {
+        choices: Vec<Choice>,
+    }
+    #[derive(serde::Deserialize)]
+    struct Choice {
+        message: ChoiceMessage,
+    }
+    #[derive(serde::Deserialize)]
+    struct ChoiceMessage {
+        content: String,
+    }
+
+    let questions = form
+        .questions
+        .iter()
+        .map(|question| format!("{}: {}", question.field, question.prompt))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let instructions = format!(
+        "Витягни з тексту користувача відповіді на питання анкети. Відповідай лише JSON-обʼєктом, \
+         де ключі — назви полів, а значення — відповіді рядком так, як їх написав би користувач. \
+         Пропусти поля, відповідей на які в тексті немає, нічого не вигадуй.\n\nПоля анкети:\n{questions}"
+    );
+    let response: Response = reqwest::Client::new()
+        .post(&parser.url)
+        .bearer_auth(api_key()?)
+        .json(&serde_json::json!({
+            "model": parser.model,
+            "response_format": { "type": "json_object" },
+            "messages": [
+                { "role": "system", "content": instructions },
+                { "role": "user", "content": text },
+            ],
+        }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let content = response
+        .choices
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("the LLM returned no choices"))?
+        .message
+        .content;
+    let answers: std::collections::BTreeMap<String, serde_json::Value> =
+        serde_json::from_str(&content)?;
+    Ok(answers
+        .into_iter()
+        .filter_map(|(field, answer)| match answer {
+            serde_json::Value::String(answer) => Some((field, answer)),
+            serde_json::Value::Number(answer) => Some((field, answer.to_string())),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Keeps the parsed contact until the user confirms it.
+pub async fn store_pending(
+    app_state: &AppState,
+    chat_id: i64,
+    contact: &Contact,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let _: () = app_state
+        .redis
+        .clone()
+        .set_ex(
+            pending_key(chat_id),
+            encryption::seal(app_state.cipher.as_ref(), contact)?,
+            PENDING_TTL_SECS,
+        )
+        .await?;
+    Ok(())
+}
+
+/// The parsed contact waiting for the user's confirmation, removed.
+pub async fn take_pending(app_state: &AppState, chat_id: i64) -> anyhow::Result<Option<Contact>> {
+    let contact: Option<Vec<u8>> = redis::pipe()
+        .atomic()
+        .get(pending_key(chat_id))
+        .del(pending_key(chat_id))
+        .ignore()
+        .query_async::<_, (Option<Vec<u8>>,)>(&mut app_state.redis.clone())
+        .await?
+        .0;
+    contact
+        .map(|contact| encryption::open(app_state.cipher.as_ref(), &contact))
+        .transpose()
+}
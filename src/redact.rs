@@ -0,0 +1,56 @@
+//! Redaction of personal data from the debug dumps written to the logs.
+//!
+//! Messages, chats and contacts are logged through [`Redacted`], which keeps their structure and
+//! ids but replaces the names, phone numbers, addresses and free texts with `<redacted>`.
+
+use std::fmt;
+
+/// Fields of the Telegram types and of [`crate::Contact`] with personal data.
+const PERSONAL_FIELDS: &[&str] = &[
+    "first_name",
+    "last_name",
+    "username",
+    "telegram_username",
+    "full_name",
+    "phone_number",
+    "phone_numbers",
+    "address",
+    "street",
+    "building",
+    "address_notes",
+    "city",
+    "cities_covered",
+    "comments",
+    "bank_details",
+    "text",
+    "caption",
+    "vcard",
+];
+
+/// Debug representation of the value with personal data replaced by `<redacted>`.
+pub struct Redacted<T>(pub T);
+
+impl<T: fmt::Debug> fmt::Debug for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&redact(&format!("{:?}", self.0)))
+    }
+}
+
+/// Replaces the values of [`PERSONAL_FIELDS`] and the phone numbers in the other strings of a
+/// debug dump.
+pub fn redact(dump: &str) -> String {
+    let field = regex::Regex::new(&format!(
+        r#"(?P<name>"?\b(?:{})\b"?\s*:\s*(?:Some\()?)"(?:[^"\\]|\\.)*""#,
+        PERSONAL_FIELDS.join("|")
+    ))
+    .unwrap();
+    let string = regex::Regex::new(r#""(?:[^"\\]|\\.)*""#).unwrap();
+    let phone = regex::Regex::new(r"\+?\d[\d \-()]{7,}\d").unwrap();
+
+    let redacted = field.replace_all(dump, r#"$name"<redacted>""#);
+    string
+        .replace_all(&redacted, |captures: &regex::Captures| {
+            phone.replace_all(&captures[0], "<redacted>").into_owned()
+        })
+        .into_owned()
+}
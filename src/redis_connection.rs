@@ -0,0 +1,89 @@
+//! Where and how to connect to Redis.
+//!
+//! `COLLECT_VOLUNTEERS_BOT_REDIS_URL` may use `rediss://` for TLS. The credentials can be kept out
+//! of the URL in `COLLECT_VOLUNTEERS_BOT_REDIS_USERNAME` and `COLLECT_VOLUNTEERS_BOT_REDIS_PASSWORD`.
+//!
+//! With Sentinel, `COLLECT_VOLUNTEERS_BOT_REDIS_SENTINELS` lists the comma-separated URLs of the
+//! sentinels, and the current master of `COLLECT_VOLUNTEERS_BOT_REDIS_SENTINEL_MASTER` (`mymaster`
+//! by default) is connected to with the scheme, credentials and database of the Redis URL. The
+//! master is resolved on start, so the bot has to be restarted after a failover, which is what
+//! happens anyway once its connection breaks.
+//!
+//! Redis Cluster is not supported: the dialogue storage keeps a single connection, so all the keys
+//! have to live on one node.
+//!
+//! Every key is prefixed with `COLLECT_VOLUNTEERS_BOT_REDIS_KEY_PREFIX` (none by default), e.g.
+//! `staging:`, so that several instances of the bot can share one Redis server.
+
+use anyhow::Context;
+
+static KEY_PREFIX: once_cell::sync::Lazy<String> = once_cell::sync::Lazy::new(|| {
+    std::env::var("COLLECT_VOLUNTEERS_BOT_REDIS_KEY_PREFIX").unwrap_or_default()
+});
+
+/// The Redis key with the configured prefix.
+pub fn key(name: &str) -> String {
+    format!("{}{name}", *KEY_PREFIX)
+}
+
+/// Builds the URL of the Redis server to connect to from the environment.
+pub async fn url_from_env() -> anyhow::Result<String> {
+    let mut url: url::Url = std::env::var("COLLECT_VOLUNTEERS_BOT_REDIS_URL")
+        .unwrap_or_else(|_| "redis://127.0.0.1:6379".to_owned())
+        .parse()
+        .context("Invalid Redis URL")?;
+
+    if let Ok(username) = std::env::var("COLLECT_VOLUNTEERS_BOT_REDIS_USERNAME") {
+        url.set_username(&username)
+            .map_err(|()| anyhow::anyhow!("Cannot set the Redis username"))?;
+    }
+    if let Ok(password) = std::env::var("COLLECT_VOLUNTEERS_BOT_REDIS_PASSWORD") {
+        url.set_password(Some(&password))
+            .map_err(|()| anyhow::anyhow!("Cannot set the Redis password"))?;
+    }
+
+    if let Ok(sentinels) = std::env::var("COLLECT_VOLUNTEERS_BOT_REDIS_SENTINELS") {
+        let master_name = std::env::var("COLLECT_VOLUNTEERS_BOT_REDIS_SENTINEL_MASTER")
+            .unwrap_or_else(|_| "mymaster".to_owned());
+        let (host, port) = master_address(&sentinels, &master_name).await?;
+        url.set_host(Some(&host))
+            .context("Invalid Redis master host")?;
+        url.set_port(Some(port))
+            .map_err(|()| anyhow::anyhow!("Cannot set the Redis master port"))?;
+    }
+
+    Ok(url.into())
+}
+
+/// Asks the sentinels one by one for the address of the master until one of them answers.
+async fn master_address(sentinels: &str, master_name: &str) -> anyhow::Result<(String, u16)> {
+    let mut last_error = anyhow::anyhow!("No Redis sentinels are configured");
+    for sentinel in sentinels
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+    {
+        let result: anyhow::Result<(String, u16)> = async {
+            let mut connection = redis::Client::open(sentinel)?
+                .get_async_connection()
+                .await?;
+            Ok(redis::cmd("SENTINEL")
+                .arg("get-master-addr-by-name")
+                .arg(master_name)
+                .query_async(&mut connection)
+                .await?)
+        }
+        .await;
+        match result {
+            Ok(address) => {
+                tracing::info!("Redis master {} is at {:?}", master_name, address);
+                return Ok(address);
+            }
+            Err(err) => {
+                tracing::warn!("Redis sentinel {} did not answer: {:?}", sentinel, err);
+                last_error = err;
+            }
+        }
+    }
+    Err(last_error.context(format!("Failed to resolve the Redis master {master_name}")))
+}
@@ -0,0 +1,168 @@
+//! Oblasts of Ukraine and their major settlements for the cascading region picker.
+
+use teloxide::types::{KeyboardButton, KeyboardMarkup};
+
+pub const OBLASTS: &[(&str, &[&str])] = &[
+    ("м. Київ", &["Київ"]),
+    (
+        "Київська",
+        &[
+            "Біла Церква",
+            "Бориспіль",
+            "Бровари",
+            "Ірпінь",
+            "Буча",
+            "Фастів",
+        ],
+    ),
+    ("Вінницька", &["Вінниця", "Жмеринка", "Могилів-Подільський"]),
+    ("Волинська", &["Луцьк", "Ковель", "Володимир"]),
+    (
+        "Дніпропетровська",
+        &[
+            "Дніпро",
+            "Кривий Ріг",
+            "Камʼянське",
+            "Нікополь",
+            "Павлоград",
+        ],
+    ),
+    (
+        "Донецька",
+        &[
+            "Краматорськ",
+            "Словʼянськ",
+            "Покровськ",
+            "Бахмут",
+            "Маріуполь",
+        ],
+    ),
+    ("Житомирська", &["Житомир", "Бердичів", "Коростень"]),
+    ("Закарпатська", &["Ужгород", "Мукачево", "Хуст"]),
+    (
+        "Запорізька",
+        &["Запоріжжя", "Мелітополь", "Бердянськ", "Енергодар"],
+    ),
+    (
+        "Івано-Франківська",
+        &["Івано-Франківськ", "Калуш", "Коломия"],
+    ),
+    (
+        "Кіровоградська",
+        &["Кропивницький", "Олександрія", "Світловодськ"],
+    ),
+    (
+        "Луганська",
+        &["Сєвєродонецьк", "Лисичанськ", "Рубіжне", "Старобільськ"],
+    ),
+    ("Львівська", &["Львів", "Дрогобич", "Червоноград", "Стрий"]),
+    ("Миколаївська", &["Миколаїв", "Первомайськ", "Вознесенськ"]),
+    (
+        "Одеська",
+        &["Одеса", "Ізмаїл", "Чорноморськ", "Білгород-Дністровський"],
+    ),
+    (
+        "Полтавська",
+        &["Полтава", "Кременчук", "Горішні Плавні", "Лубни"],
+    ),
+    ("Рівненська", &["Рівне", "Вараш", "Дубно"]),
+    ("Сумська", &["Суми", "Конотоп", "Шостка", "Охтирка"]),
+    ("Тернопільська", &["Тернопіль", "Чортків", "Кременець"]),
+    (
+        "Харківська",
+        &["Харків", "Ізюм", "Куп'янськ", "Лозова", "Чугуїв"],
+    ),
+    (
+        "Херсонська",
+        &["Херсон", "Нова Каховка", "Каховка", "Скадовськ"],
+    ),
+    (
+        "Хмельницька",
+        &["Хмельницький", "Камʼянець-Подільський", "Шепетівка"],
+    ),
+    ("Черкаська", &["Черкаси", "Умань", "Сміла"]),
+    ("Чернівецька", &["Чернівці", "Новодністровськ", "Хотин"]),
+    ("Чернігівська", &["Чернігів", "Ніжин", "Прилуки"]),
+    ("АР Крим", &["Сімферополь", "Севастополь", "Керч", "Ялта"]),
+];
+
+const OBLASTS_PER_PAGE: usize = 8;
+const PREVIOUS_PAGE_PREFIX: &str = "◀️ Назад";
+const NEXT_PAGE_PREFIX: &str = "Далі ▶️";
+
+fn page_count() -> usize {
+    OBLASTS.len().div_ceil(OBLASTS_PER_PAGE)
+}
+
+/// Returns the oblast with the given name as it is spelled in [`OBLASTS`].
+pub fn find_oblast(name: &str) -> Option<&'static str> {
+    OBLASTS
+        .iter()
+        .map(|(oblast, _)| *oblast)
+        .find(|oblast| *oblast == name.trim())
+}
+
+/// Parses a page navigation button, e.g. `Далі ▶️ (2/4)`, into the 0-based page it leads to.
+pub fn parse_page_button(text: &str) -> Option<usize> {
+    if !text.starts_with(PREVIOUS_PAGE_PREFIX) && !text.starts_with(NEXT_PAGE_PREFIX) {
+        return None;
+    }
+    let page: usize = text
+        .rsplit_once('(')?
+        .1
+        .split_once('/')?
+        .0
+        .trim()
+        .parse()
+        .ok()?;
+    page.checked_sub(1).filter(|page| *page < page_count())
+}
+
+/// Keyboard with one page of oblasts and the navigation between the pages.
+pub fn oblasts_keyboard(page: usize) -> KeyboardMarkup {
+    let pages = page_count();
+    let page = page.min(pages - 1);
+    let mut rows: Vec<Vec<KeyboardButton>> = OBLASTS
+        .iter()
+        .skip(page * OBLASTS_PER_PAGE)
+        .take(OBLASTS_PER_PAGE)
+        .map(|(oblast, _)| KeyboardButton::new(*oblast))
+        .collect::<Vec<_>>()
+        .chunks(2)
+        .map(|chunk| chunk.to_vec())
+        .collect();
+    let mut navigation = Vec::new();
+    if page > 0 {
+        navigation.push(KeyboardButton::new(format!(
+            "{PREVIOUS_PAGE_PREFIX} ({}/{pages})",
+            page
+        )));
+    }
+    if page + 1 < pages {
+        navigation.push(KeyboardButton::new(format!(
+            "{NEXT_PAGE_PREFIX} ({}/{pages})",
+            page + 2
+        )));
+    }
+    rows.push(navigation);
+    KeyboardMarkup::new(rows)
+}
+
+/// Keyboard with the major settlements of the oblast; any other settlement can be typed in.
+pub fn cities_keyboard(oblast: &str) -> KeyboardMarkup {
+    let cities = OBLASTS
+        .iter()
+        .find(|(name, _)| *name == oblast)
+        .map_or(&[][..], |(_, cities)| *cities);
+    KeyboardMarkup::new(
+        cities
+            .chunks(2)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .map(|city| KeyboardButton::new(*city))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>(),
+    )
+}
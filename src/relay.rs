@@ -0,0 +1,231 @@
+//! Lightweight anonymized support channel between coordinators in the admin group and
+//! requesters.
+//!
+//! Every saved submission is posted to the admin group. When a coordinator replies to such a
+//! post, the bot relays the reply text to the requester. When the requester replies to a relayed
+//! message, the bot posts their answer back to the admin group as a reply to the thread, which is
+//! in the topic of the request when the group is a forum, see [`crate::forum_topics`].
+
+use teloxide::{payloads::SendMessageSetters, prelude2::*, types::ParseMode};
+
+use crate::{
+    forum_topics, html, redis_connection,
+    texts::{self, Language},
+    AppState, Contact, ThrottledBot, TrackedSubmission,
+};
+
+/// The other side of a relayed conversation.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RelayTarget {
+    chat_id: i64,
+    message_id: i32,
+    reference_id: String,
+}
+
+fn admin_relay_key(message_id: i32) -> String {
+    redis_connection::key(&format!("relay:admin:{message_id}"))
+}
+
+fn user_relay_key(chat_id: i64, message_id: i32) -> String {
+    redis_connection::key(&format!("relay:user:{chat_id}:{message_id}"))
+}
+
+async fn load_relay_target(
+    mut redis: redis::aio::MultiplexedConnection,
+    key: String,
+) -> anyhow::Result<Option<RelayTarget>> {
+    use redis::AsyncCommands;
+
+    let target: Option<String> = redis.get(key).await?;
+    Ok(target
+        .map(|target| serde_json::from_str(&target))
+        .transpose()?)
+}
+
+async fn store_relay_target(
+    mut redis: redis::aio::MultiplexedConnection,
+    key: String,
+    target: &RelayTarget,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let _: () = redis.set(key, serde_json::to_string(target)?).await?;
+    Ok(())
+}
+
+/// Posts the saved submission to the admin group so coordinators can reply to it.
+#[tracing::instrument(skip_all, fields(reference_id = %submission.reference_id))]
+pub async fn post_submission(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    requester_chat_id: i64,
+    contact: &Contact,
+    submission: &TrackedSubmission,
+) -> anyhow::Result<()> {
+    let admin_chat_id = match app_state.admin_chat_id {
+        Some(admin_chat_id) => admin_chat_id,
+        None => return Ok(()),
+    };
+    let topic_id = forum_topics::create(bot, app_state, &submission.reference_id).await?;
+    let map = contact
+        .map_url()
+        .map(|map_url| format!("\n{}", html::link(&map_url, "Відкрити на карті")))
+        .unwrap_or_default();
+    let request = bot
+        .send_message(
+            admin_chat_id,
+            format!(
+                "Нова заявка {} ({})\n\n{}{map}\n\nДайте відповідь на це повідомлення, щоб написати заявнику.",
+                html::code(&submission.reference_id),
+                html::escape(app_state.config().help_kind_title(&submission.help_kind)),
+                contact.summary_html(&app_state.texts(), Language::default()),
+            ),
+        )
+        .parse_mode(ParseMode::Html);
+    let post = match topic_id {
+        Some(topic_id) => request.reply_to_message_id(topic_id).await?,
+        None => request.await?,
+    };
+    store_relay_target(
+        app_state.redis.clone(),
+        admin_relay_key(post.id),
+        &RelayTarget {
+            chat_id: requester_chat_id,
+            message_id: post.id,
+            reference_id: submission.reference_id.clone(),
+        },
+    )
+    .await
+}
+
+/// Finds the requester a coordinator's reply in the admin group is addressed to.
+pub async fn find_requester(
+    msg: Message,
+    app_state: std::sync::Arc<AppState>,
+) -> Option<RelayTarget> {
+    if Some(msg.chat.id) != app_state.admin_chat_id {
+        return None;
+    }
+    let replied_message = msg.reply_to_message()?;
+    match load_relay_target(app_state.redis.clone(), admin_relay_key(replied_message.id)).await {
+        Ok(target) => target,
+        Err(err) => {
+            tracing::warn!("Failed to load relay target: {:?}", err);
+            None
+        }
+    }
+}
+
+/// Finds the admin group thread a requester's reply is addressed to.
+pub async fn find_coordinators(
+    msg: Message,
+    app_state: std::sync::Arc<AppState>,
+) -> Option<RelayTarget> {
+    if !msg.chat.is_private() {
+        return None;
+    }
+    let replied_message = msg.reply_to_message()?;
+    match load_relay_target(
+        app_state.redis.clone(),
+        user_relay_key(msg.chat.id, replied_message.id),
+    )
+    .await
+    {
+        Ok(target) => target,
+        Err(err) => {
+            tracing::warn!("Failed to load relay target: {:?}", err);
+            None
+        }
+    }
+}
+
+#[tracing::instrument(skip_all, fields(chat_id = msg.chat.id))]
+pub async fn handle_coordinator_reply(
+    bot: ThrottledBot,
+    msg: Message,
+    app_state: std::sync::Arc<AppState>,
+    requester: RelayTarget,
+) -> anyhow::Result<()> {
+    let text = if let Some(text) = msg.text() {
+        text
+    } else {
+        bot.send_message(
+            msg.chat.id,
+            app_state
+                .texts()
+                .get(Language::default(), "relay_text_only_to_requester"),
+        )
+        .reply_to_message_id(msg.id)
+        .await?;
+        return Ok(());
+    };
+    let language = texts::language_of(app_state.redis.clone(), requester.chat_id).await;
+    let relayed = bot
+        .send_message(
+            requester.chat_id,
+            app_state.texts().render(
+                language,
+                "relay_message_to_requester",
+                &[("reference_id", &requester.reference_id), ("text", &text)],
+            ),
+        )
+        .await?;
+    store_relay_target(
+        app_state.redis.clone(),
+        user_relay_key(requester.chat_id, relayed.id),
+        &RelayTarget {
+            chat_id: msg.chat.id,
+            message_id: requester.message_id,
+            reference_id: requester.reference_id,
+        },
+    )
+    .await
+}
+
+#[tracing::instrument(skip_all, fields(chat_id = msg.chat.id))]
+pub async fn handle_requester_reply(
+    bot: ThrottledBot,
+    msg: Message,
+    app_state: std::sync::Arc<AppState>,
+    language: Language,
+    coordinators: RelayTarget,
+) -> anyhow::Result<()> {
+    let text = if let Some(text) = msg.text() {
+        text
+    } else {
+        bot.send_message(
+            msg.chat.id,
+            app_state
+                .texts()
+                .get(language, "relay_text_only_to_volunteers"),
+        )
+        .await?;
+        return Ok(());
+    };
+    let relayed = bot
+        .send_message(
+            coordinators.chat_id,
+            format!(
+                "Відповідь заявника щодо заявки {}:\n\n{}",
+                coordinators.reference_id, text
+            ),
+        )
+        .reply_to_message_id(coordinators.message_id)
+        .await?;
+    store_relay_target(
+        app_state.redis.clone(),
+        admin_relay_key(relayed.id),
+        &RelayTarget {
+            chat_id: msg.chat.id,
+            message_id: relayed.id,
+            reference_id: coordinators.reference_id,
+        },
+    )
+    .await?;
+    bot.send_message(
+        msg.chat.id,
+        app_state.texts().get(language, "relay_message_sent"),
+    )
+    .await?;
+    Ok(())
+}
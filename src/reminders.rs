@@ -0,0 +1,296 @@
+//! Reminders about forms left unfinished.
+//!
+//! The last activity of every chat is kept in Redis. When a user has been stuck in the middle of
+//! a form for the reminder period, the bot asks whether to continue with Continue/Cancel buttons.
+//! Once the expiry period passes without an answer, the dialogue is dropped so that the state of
+//! abandoned forms does not pile up in Redis. A user who sends `/start` in the middle of a form is
+//! shown what they have filled in and asked whether to continue or to start the form anew.
+
+use anyhow::Context;
+use teloxide::{
+    payloads::SendMessageSetters,
+    prelude2::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+use crate::{
+    chat_lock, funnel,
+    handlers::{
+        contact_form::{ask_next_question, restart_form},
+        keyboards::start_keyboard,
+    },
+    redis_connection, retry,
+    shutdown::Shutdown,
+    state::{DialogueStorage, MyDialogue, State},
+    texts, AppState, ThrottledBot,
+};
+
+/// Redis sorted set of chat ids scored by the unix timestamp of their last update.
+pub(crate) const ACTIVITY_KEY: &str = "reminders:activity";
+/// Redis set of chat ids which have been reminded since their last update.
+pub(crate) const REMINDED_KEY: &str = "reminders:reminded";
+
+const CALLBACK_PREFIX: &str = "reminder:";
+
+#[derive(Debug, Clone)]
+pub struct ReminderConfig {
+    pub remind_after: chrono::Duration,
+    pub expire_after: chrono::Duration,
+}
+
+impl ReminderConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let hours = |name: &str, default: i64| -> anyhow::Result<chrono::Duration> {
+            let hours = match std::env::var(name) {
+                Ok(value) => value
+                    .parse()
+                    .with_context(|| format!("Invalid number of hours in {name}"))?,
+                Err(_) => default,
+            };
+            Ok(chrono::Duration::hours(hours))
+        };
+        Ok(Self {
+            remind_after: hours("COLLECT_VOLUNTEERS_BOT_FORM_REMINDER_HOURS", 6)?,
+            expire_after: hours("COLLECT_VOLUNTEERS_BOT_FORM_EXPIRY_HOURS", 48)?,
+        })
+    }
+}
+
+/// Records the update as the last activity of its chat.
+pub async fn record_activity(update: Update, app_state: std::sync::Arc<AppState>) {
+    use redis::AsyncCommands;
+
+    let chat_id = match chat_lock::chat_id(&update) {
+        Some(chat_id) => chat_id,
+        None => return,
+    };
+    let mut redis = app_state.redis.clone();
+    let result: redis::RedisResult<()> = async {
+        let _: () = redis
+            .zadd(
+                redis_connection::key(ACTIVITY_KEY),
+                chat_id,
+                chrono::Utc::now().timestamp(),
+            )
+            .await?;
+        redis
+            .srem(redis_connection::key(REMINDED_KEY), chat_id)
+            .await
+    }
+    .await;
+    if let Err(err) = result {
+        tracing::warn!("Failed to record activity of chat {}: {:?}", chat_id, err);
+    }
+}
+
+pub async fn run(
+    bot: ThrottledBot,
+    app_state: std::sync::Arc<AppState>,
+    storage: std::sync::Arc<DialogueStorage>,
+    config: ReminderConfig,
+    mut shutdown: Shutdown,
+) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(300));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.requested() => return,
+        }
+        if let Err(err) = remind_stalled_forms(&bot, &app_state, &storage, &config).await {
+            tracing::warn!("Failed to remind about stalled forms: {:?}", err);
+        }
+    }
+}
+
+async fn remind_stalled_forms(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    storage: &std::sync::Arc<DialogueStorage>,
+    config: &ReminderConfig,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let mut redis = app_state.redis.clone();
+    let now = chrono::Utc::now().timestamp();
+    let stalled: Vec<(i64, i64)> = redis
+        .zrangebyscore_withscores(
+            redis_connection::key(ACTIVITY_KEY),
+            0,
+            now - config.remind_after.num_seconds(),
+        )
+        .await?;
+    for (chat_id, last_activity) in stalled {
+        // One chat failing, e.g. because the user blocked the bot, does not keep the others from
+        // being reminded.
+        if let Err(err) = remind_chat(bot, app_state, storage, config, chat_id, last_activity).await
+        {
+            tracing::warn!("Failed to remind chat {}: {:?}", chat_id, err);
+        }
+    }
+    Ok(())
+}
+
+/// Reminds the chat about its stalled form, or ends the form once it expires.
+async fn remind_chat(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    storage: &std::sync::Arc<DialogueStorage>,
+    config: &ReminderConfig,
+    chat_id: i64,
+    last_activity: i64,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let mut redis = app_state.redis.clone();
+    let now = chrono::Utc::now().timestamp();
+    let dialogue = MyDialogue::new(storage.clone(), chat_id);
+    let (help_kind, contact) = match dialogue.get().await? {
+        Some(State::AwaitingContactInformation {
+            help_kind, contact, ..
+        }) => (help_kind, contact),
+        _ => {
+            let _: () = redis
+                .zrem(redis_connection::key(ACTIVITY_KEY), chat_id)
+                .await?;
+            let _: () = redis
+                .srem(redis_connection::key(REMINDED_KEY), chat_id)
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let language = texts::language_of(redis.clone(), chat_id).await;
+    let texts = app_state.texts();
+    if now - last_activity >= config.expire_after.num_seconds() {
+        funnel::record_abandoned(app_state, &help_kind, contact.as_ref()).await;
+        dialogue.exit().await?;
+        let _: () = redis
+            .zrem(redis_connection::key(ACTIVITY_KEY), chat_id)
+            .await?;
+        let _: () = redis
+            .srem(redis_connection::key(REMINDED_KEY), chat_id)
+            .await?;
+        retry::on_flood(|| {
+            bot.send_message(chat_id, texts.get(language, "form_expired"))
+                .reply_markup(start_keyboard(&texts, language))
+        })
+        .await?;
+        return Ok(());
+    }
+
+    let newly_reminded: bool = redis
+        .sadd(redis_connection::key(REMINDED_KEY), chat_id)
+        .await?;
+    if newly_reminded {
+        retry::on_flood(|| {
+            bot.send_message(chat_id, texts.get(language, "form_reminder"))
+                .reply_markup(InlineKeyboardMarkup::new(vec![vec![
+                    InlineKeyboardButton::callback(
+                        texts.get(language, "button_form_continue").to_owned(),
+                        format!("{CALLBACK_PREFIX}continue"),
+                    ),
+                    InlineKeyboardButton::callback(
+                        texts.get(language, "button_form_cancel").to_owned(),
+                        format!("{CALLBACK_PREFIX}cancel"),
+                    ),
+                ]]))
+        })
+        .await?;
+    }
+    Ok(())
+}
+
+/// Buttons to continue the unfinished form where it was left or to answer its questions anew.
+pub fn resume_keyboard(texts: &texts::Texts, language: texts::Language) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![vec![
+        InlineKeyboardButton::callback(
+            texts.get(language, "button_form_continue").to_owned(),
+            format!("{CALLBACK_PREFIX}continue"),
+        ),
+        InlineKeyboardButton::callback(
+            texts.get(language, "button_form_restart").to_owned(),
+            format!("{CALLBACK_PREFIX}restart"),
+        ),
+    ]])
+}
+
+pub fn is_reminder_callback(query: CallbackQuery) -> bool {
+    query
+        .data
+        .as_deref()
+        .is_some_and(|data| data.starts_with(CALLBACK_PREFIX))
+}
+
+#[tracing::instrument(skip_all, fields(user_id = query.from.id, data = ?query.data))]
+pub async fn handle_callback(
+    bot: ThrottledBot,
+    query: CallbackQuery,
+    app_state: std::sync::Arc<AppState>,
+    storage: std::sync::Arc<DialogueStorage>,
+    language: texts::Language,
+) -> anyhow::Result<()> {
+    bot.answer_callback_query(query.id.clone()).await?;
+    let message = match &query.message {
+        Some(message) => message,
+        None => return Ok(()),
+    };
+    bot.edit_message_reply_markup(message.chat.id, message.id)
+        .await?;
+
+    let dialogue = MyDialogue::new(storage, message.chat.id);
+    let form_state = match dialogue.get().await? {
+        Some(State::AwaitingContactInformation {
+            help_kind,
+            contact,
+            question_message_id,
+            last_answer,
+            pending_transcription,
+        }) => (
+            help_kind,
+            contact,
+            question_message_id,
+            last_answer,
+            pending_transcription,
+        ),
+        // The form has been finished or has expired meanwhile.
+        _ => return Ok(()),
+    };
+    match query
+        .data
+        .as_deref()
+        .and_then(|data| data.strip_prefix(CALLBACK_PREFIX))
+    {
+        Some("continue") => {
+            ask_next_question(
+                &bot,
+                &app_state,
+                &dialogue,
+                message.chat.id,
+                language,
+                form_state,
+            )
+            .await?;
+        }
+        Some("restart") => {
+            restart_form(
+                &bot,
+                &app_state,
+                &dialogue,
+                message.chat.id,
+                language,
+                form_state,
+            )
+            .await?;
+        }
+        Some("cancel") => {
+            funnel::record_abandoned(&app_state, &form_state.0, form_state.1.as_ref()).await;
+            dialogue.update(State::Start).await?;
+            let texts = app_state.texts();
+            bot.send_message(message.chat.id, texts.get(language, "submission_cancelled"))
+                .reply_markup(start_keyboard(&texts, language))
+                .await?;
+        }
+        action => tracing::warn!("reminders: unexpected callback action {:?}", action),
+    }
+    Ok(())
+}
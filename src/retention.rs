@@ -0,0 +1,164 @@
+//! Deletion of the data kept longer than the retention period.
+//!
+//! With `COLLECT_VOLUNTEERS_BOT_RETENTION_DAYS` set, the dialogue states of the chats which have
+//! been inactive for that long are deleted together with their language and pending questionnaire,
+//! and so are the tracked submissions saved that long ago, together with everything kept about
+//! them. With `COLLECT_VOLUNTEERS_BOT_RETENTION_REDACT_ROWS=1` the personal
+//! data in the rows of those submissions is also blanked in the spreadsheets, like
+//! `/delete_my_data` does.
+
+use anyhow::Context;
+use teloxide::{dispatching2::dialogue::Storage, prelude2::*};
+
+use crate::{
+    chat_lock, personal_data, redis_connection,
+    sheets::{submissions_key, TrackedSubmission, SUBMISSION_CHATS_KEY},
+    shutdown::Shutdown,
+    AppState, DialogueStorage,
+};
+
+/// Redis sorted set of chat ids scored by the unix timestamp of their last update.
+pub(crate) const LAST_ACTIVITY_KEY: &str = "retention:last_activity";
+
+#[derive(Debug, Clone)]
+pub struct RetentionConfig {
+    pub period: chrono::Duration,
+    pub redact_rows: bool,
+}
+
+impl RetentionConfig {
+    /// `None` unless the retention period is configured.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let days: i64 = match std::env::var("COLLECT_VOLUNTEERS_BOT_RETENTION_DAYS") {
+            Ok(value) => value.parse().context("Invalid retention period")?,
+            Err(_) => return Ok(None),
+        };
+        Ok(Some(Self {
+            period: chrono::Duration::days(days),
+            redact_rows: std::env::var("COLLECT_VOLUNTEERS_BOT_RETENTION_REDACT_ROWS")
+                .is_ok_and(|value| value == "1"),
+        }))
+    }
+}
+
+/// Records the update as the last activity of its chat.
+pub async fn record_activity(update: Update, app_state: std::sync::Arc<AppState>) {
+    use redis::AsyncCommands;
+
+    let chat_id = match chat_lock::chat_id(&update) {
+        Some(chat_id) => chat_id,
+        None => return,
+    };
+    let result: redis::RedisResult<()> = app_state
+        .redis
+        .clone()
+        .zadd(
+            redis_connection::key(LAST_ACTIVITY_KEY),
+            chat_id,
+            chrono::Utc::now().timestamp(),
+        )
+        .await;
+    if let Err(err) = result {
+        tracing::warn!("Failed to record activity of chat {}: {:?}", chat_id, err);
+    }
+}
+
+pub async fn run(
+    app_state: std::sync::Arc<AppState>,
+    storage: std::sync::Arc<DialogueStorage>,
+    config: RetentionConfig,
+    mut shutdown: Shutdown,
+) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.requested() => return,
+        }
+        let cutoff = chrono::Utc::now() - config.period;
+        if let Err(err) = purge_dialogue_states(&app_state, &storage, cutoff).await {
+            tracing::warn!("Failed to purge old dialogue states: {:?}", err);
+        }
+        if let Err(err) = purge_submissions(&app_state, &config, cutoff).await {
+            tracing::warn!("Failed to purge old submissions: {:?}", err);
+        }
+    }
+}
+
+async fn purge_dialogue_states(
+    app_state: &AppState,
+    storage: &std::sync::Arc<DialogueStorage>,
+    cutoff: chrono::DateTime<chrono::Utc>,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let mut redis = app_state.redis.clone();
+    let chat_ids: Vec<i64> = redis
+        .zrangebyscore(
+            redis_connection::key(LAST_ACTIVITY_KEY),
+            0,
+            cutoff.timestamp(),
+        )
+        .await?;
+    for &chat_id in &chat_ids {
+        // Removing a state which is already gone fails in memory, which does not matter.
+        if let Err(err) = storage.clone().remove_dialogue(chat_id).await {
+            tracing::debug!("No dialogue state of chat {} to remove: {:?}", chat_id, err);
+        }
+        personal_data::forget_chat(app_state, chat_id).await?;
+    }
+    if !chat_ids.is_empty() {
+        tracing::info!("Purged the dialogue states of {} chats", chat_ids.len());
+    }
+    Ok(())
+}
+
+async fn purge_submissions(
+    app_state: &AppState,
+    config: &RetentionConfig,
+    cutoff: chrono::DateTime<chrono::Utc>,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let mut redis = app_state.redis.clone();
+    let chat_ids: Vec<i64> = redis
+        .smembers(redis_connection::key(SUBMISSION_CHATS_KEY))
+        .await?;
+    let mut purged = 0;
+    for chat_id in chat_ids {
+        // The raw entries are removed as they are, so that submissions tracked meanwhile stay.
+        let entries: Vec<String> = redis.lrange(submissions_key(chat_id), 0, -1).await?;
+        for entry in entries {
+            let submission: TrackedSubmission = serde_json::from_str(&entry)?;
+            if submission
+                .saved_at
+                .is_none_or(|saved_at| saved_at >= cutoff)
+            {
+                continue;
+            }
+            if config.redact_rows && !submission.range.is_empty() {
+                if let Err(err) = personal_data::redact_row(app_state, &submission).await {
+                    tracing::warn!(
+                        "Failed to redact the row of {}, retrying later: {:?}",
+                        submission.reference_id,
+                        err
+                    );
+                    continue;
+                }
+            }
+            let _: () = redis.lrem(submissions_key(chat_id), 1, &entry).await?;
+            personal_data::forget_submission(app_state, &submission).await?;
+            purged += 1;
+        }
+        let remaining: usize = redis.llen(submissions_key(chat_id)).await?;
+        if remaining == 0 {
+            let _: () = redis
+                .srem(redis_connection::key(SUBMISSION_CHATS_KEY), chat_id)
+                .await?;
+        }
+    }
+    if purged > 0 {
+        tracing::info!("Purged {} submissions", purged);
+    }
+    Ok(())
+}
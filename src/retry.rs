@@ -0,0 +1,35 @@
+//! Retries of the requests rejected by Telegram's flood control.
+//!
+//! The throttling adapter keeps the bot within the documented limits, but Telegram may still ask
+//! to slow down with `RetryAfter`, e.g. right after a restart. Notifications sent to many chats at
+//! once go through [`on_flood`] so that they are delayed instead of lost.
+
+use std::future::Future;
+
+use teloxide::RequestError;
+
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Sends the request, and sends it again after the delay Telegram asks for when it reports flood
+/// control.
+pub async fn on_flood<T, F, Fut>(mut send: F) -> Result<T, RequestError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, RequestError>>,
+{
+    let mut attempt = 1;
+    loop {
+        match send().await {
+            Err(RequestError::RetryAfter(seconds)) if attempt < MAX_ATTEMPTS => {
+                tracing::warn!(
+                    "Telegram flood control, retrying in {} s (attempt {})",
+                    seconds,
+                    attempt
+                );
+                tokio::time::sleep(std::time::Duration::from_secs(seconds.max(1) as u64)).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
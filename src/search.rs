@@ -0,0 +1,317 @@
+//! Search of the saved submissions for the verified volunteers, e.g. `/search евакуація Харків`.
+//!
+//! The rows of the tabs the submissions go to are read from the spreadsheets, and the ones with
+//! every word of the query, in the row or in the title of the help kind, are kept in Redis for an
+//! hour so that the pages are turned without reading the spreadsheets again. Phone numbers are
+//! masked until the volunteer takes the request.
+
+use teloxide::{
+    payloads::{AnswerCallbackQuerySetters, EditMessageTextSetters, SendMessageSetters},
+    prelude2::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+use crate::{
+    assignment, config, redis_connection,
+    sheets::{quoted_tab, rotation, schema::DEFAULT_COLUMNS},
+    texts, volunteers, AppState, ThrottledBot,
+};
+
+const CALLBACK_PREFIX: &str = "search:";
+
+const PAGE_SIZE: usize = 5;
+
+const RESULTS_TTL_SECS: usize = 60 * 60;
+
+/// Longest value shown, so that a page fits into a message.
+const MAX_VALUE_CHARS: usize = 200;
+
+/// Columns of the rows shown in the results, in order.
+const SHOWN_COLUMNS: &[&str] = &[
+    "status",
+    "full_name",
+    "phone_numbers",
+    "region",
+    "city",
+    "address",
+    "comments",
+    "created_at",
+];
+
+/// The summary of a submission row.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Found {
+    reference_id: String,
+    title: String,
+    /// The non-empty [`SHOWN_COLUMNS`] by name.
+    fields: Vec<(String, String)>,
+}
+
+impl Found {
+    /// The summary of the row with the `columns` of the help kind titled `title`; `None` for a
+    /// row without a reference id.
+    pub fn from_row(title: &str, columns: &[&str], row: &[String]) -> Option<Self> {
+        let cell = |name: &str| {
+            columns
+                .iter()
+                .position(|column| *column == name)
+                .and_then(|column| row.get(column))
+                .map(|value| value.trim().to_owned())
+                .filter(|value| !value.is_empty())
+        };
+        Some(Self {
+            reference_id: cell("reference_id")?,
+            title: title.to_owned(),
+            fields: SHOWN_COLUMNS
+                .iter()
+                .filter_map(|&name| Some((name.to_owned(), cell(name)?)))
+                .collect(),
+        })
+    }
+
+    /// The reference id and the help kind followed by a line per field, with the phone numbers
+    /// masked if `masked`.
+    pub fn render(&self, masked: bool) -> String {
+        let mut text = format!("{} ({})", self.reference_id, self.title);
+        for (name, value) in &self.fields {
+            let value = if name == "phone_numbers" && masked {
+                mask_phone_numbers(value)
+            } else {
+                value.chars().take(MAX_VALUE_CHARS).collect()
+            };
+            let header = DEFAULT_COLUMNS
+                .iter()
+                .find(|&&(column, _)| column == name)
+                .map_or(name.as_str(), |&(_, header)| header);
+            text.push_str(&format!("\n{header}: {value}"));
+        }
+        text
+    }
+}
+
+fn results_key(user_id: i64) -> String {
+    redis_connection::key(&format!("search:{user_id}"))
+}
+
+#[tracing::instrument(skip_all, fields(chat_id = msg.chat.id))]
+pub async fn handle_search(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    msg: &Message,
+    query: &str,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    // The results have the personal data of the requesters.
+    let user = match msg.from() {
+        Some(user) if msg.chat.is_private() => user,
+        _ => return Ok(()),
+    };
+    if !volunteers::is_verified(app_state.redis.clone(), user.id).await? {
+        bot.send_message(
+            msg.chat.id,
+            "Шукати заявки можуть лише підтверджені волонтери.",
+        )
+        .await?;
+        return Ok(());
+    }
+    let words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+    if words.is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            "Вкажіть, що шукати, наприклад: /search евакуація Харків",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let found = search(app_state, &words).await?;
+    let _: () = app_state
+        .redis
+        .clone()
+        .set_ex(
+            results_key(user.id),
+            serde_json::to_string(&found)?,
+            RESULTS_TTL_SECS,
+        )
+        .await?;
+    let (text, keyboard) = render_page(app_state, user.id, &found, 0).await?;
+    bot.send_message(msg.chat.id, text)
+        .reply_markup(keyboard)
+        .await?;
+    Ok(())
+}
+
+/// The rows of every tab of the submissions with all the words, the newest first.
+async fn search(app_state: &AppState, words: &[String]) -> anyhow::Result<Vec<Found>> {
+    let config = app_state.config();
+    let now = config::local_now();
+    let mut found = Vec::new();
+    for definition in &config.help_kinds {
+        let title = definition.title();
+        let columns = definition.columns();
+        for destination in
+            rotation::destinations_of(app_state.redis.clone(), &config, definition).await?
+        {
+            let tab = destination.tab(&now);
+            let result = app_state
+                .sheets_api
+                .spreadsheets()
+                .values_get(&destination.spreadsheet_id, &quoted_tab(&tab))
+                .doit()
+                .await;
+            let rows = match result {
+                Ok((_, value_range)) => value_range.values.unwrap_or_default(),
+                Err(err) => {
+                    tracing::warn!(
+                        "Failed to search {} {:?}: {:?}",
+                        destination.spreadsheet_id,
+                        tab,
+                        err
+                    );
+                    continue;
+                }
+            };
+            // The first row holds the headers.
+            for row in rows.into_iter().skip(1) {
+                let text = format!("{} {}", title, row.join(" ")).to_lowercase();
+                if !words.iter().all(|word| text.contains(word.as_str())) {
+                    continue;
+                }
+                found.extend(Found::from_row(title, &columns, &row));
+            }
+        }
+    }
+    found.reverse();
+    Ok(found)
+}
+
+async fn render_page(
+    app_state: &AppState,
+    user_id: i64,
+    found: &[Found],
+    page: usize,
+) -> anyhow::Result<(String, InlineKeyboardMarkup)> {
+    if found.is_empty() {
+        return Ok((
+            "Нічого не знайдено.".to_owned(),
+            InlineKeyboardMarkup::default(),
+        ));
+    }
+    let pages = found.len().div_ceil(PAGE_SIZE);
+    let page = page.min(pages - 1);
+    let mut text = format!(
+        "Знайдено заявок: {} (сторінка {} з {})",
+        found.len(),
+        page + 1,
+        pages
+    );
+    let texts = app_state.texts();
+    let language = texts::language_of(app_state.redis.clone(), user_id).await;
+    let mut rows = Vec::new();
+    for found in found.iter().skip(page * PAGE_SIZE).take(PAGE_SIZE) {
+        let taken = assignment::assignee(app_state.redis.clone(), &found.reference_id).await?
+            == Some(user_id);
+        text.push_str("\n\n");
+        text.push_str(&found.render(!taken));
+        if !taken {
+            rows.push(vec![assignment::take_button(
+                &texts,
+                language,
+                &found.reference_id,
+            )]);
+        }
+    }
+    let mut navigation = Vec::new();
+    if page > 0 {
+        navigation.push(InlineKeyboardButton::callback(
+            "‹ Назад".to_owned(),
+            format!("{CALLBACK_PREFIX}page:{}", page - 1),
+        ));
+    }
+    if page + 1 < pages {
+        navigation.push(InlineKeyboardButton::callback(
+            "Далі ›".to_owned(),
+            format!("{CALLBACK_PREFIX}page:{}", page + 1),
+        ));
+    }
+    if !navigation.is_empty() {
+        rows.push(navigation);
+    }
+    Ok((text, InlineKeyboardMarkup::new(rows)))
+}
+
+/// Hides all the digits but the last two.
+fn mask_phone_numbers(phone_numbers: &str) -> String {
+    let digits = phone_numbers.chars().filter(char::is_ascii_digit).count();
+    let mut seen = 0;
+    phone_numbers
+        .chars()
+        .map(|char| {
+            if !char.is_ascii_digit() {
+                return char;
+            }
+            seen += 1;
+            if seen + 2 > digits {
+                char
+            } else {
+                '•'
+            }
+        })
+        .collect()
+}
+
+pub fn is_search_callback(query: CallbackQuery) -> bool {
+    query
+        .data
+        .as_deref()
+        .is_some_and(|data| data.starts_with(CALLBACK_PREFIX))
+}
+
+#[tracing::instrument(skip_all, fields(user_id = query.from.id, data = ?query.data))]
+pub async fn handle_callback(
+    bot: ThrottledBot,
+    query: CallbackQuery,
+    app_state: std::sync::Arc<AppState>,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let data = query.data.as_deref().unwrap_or_default();
+    let (page, message) = match (
+        data.strip_prefix(CALLBACK_PREFIX)
+            .and_then(|data| data.strip_prefix("page:"))
+            .and_then(|page| page.parse::<usize>().ok()),
+        &query.message,
+    ) {
+        (Some(page), Some(message)) => (page, message),
+        _ => {
+            tracing::warn!("search: unexpected callback data {:?}", data);
+            bot.answer_callback_query(query.id).await?;
+            return Ok(());
+        }
+    };
+    if !volunteers::is_verified(app_state.redis.clone(), query.from.id).await? {
+        bot.answer_callback_query(query.id).await?;
+        return Ok(());
+    }
+    let found: Option<String> = app_state
+        .redis
+        .clone()
+        .get(results_key(query.from.id))
+        .await?;
+    let found: Vec<Found> = match found {
+        Some(found) => serde_json::from_str(&found)?,
+        None => {
+            bot.answer_callback_query(query.id)
+                .text("Результати пошуку застаріли, повторіть пошук.")
+                .await?;
+            return Ok(());
+        }
+    };
+    let (text, keyboard) = render_page(&app_state, query.from.id, &found, page).await?;
+    bot.edit_message_text(message.chat.id, message.id, text)
+        .reply_markup(keyboard)
+        .await?;
+    bot.answer_callback_query(query.id).await?;
+    Ok(())
+}
@@ -0,0 +1,50 @@
+//! Startup check that the bot can read every configured spreadsheet, so that a missing share or a
+//! revoked token is reported at boot rather than on the first submission.
+
+use crate::AppState;
+
+/// Reads the title of every spreadsheet of the config and fails listing the ones which could not
+/// be read.
+pub async fn verify_access(app_state: &AppState) -> anyhow::Result<()> {
+    let config = app_state.config();
+    let mut spreadsheet_ids: Vec<&str> = config
+        .help_kinds
+        .iter()
+        .map(|definition| &definition.destination)
+        .chain(config.destinations.iter().map(|route| &route.destination))
+        .chain(config.audit_log.as_ref())
+        .chain(config.analytics.as_ref())
+        .chain(
+            config
+                .feedback
+                .as_ref()
+                .map(|feedback| &feedback.destination),
+        )
+        .map(|destination| destination.spreadsheet_id.as_str())
+        .collect();
+    spreadsheet_ids.sort_unstable();
+    spreadsheet_ids.dedup();
+
+    let results = futures::future::join_all(spreadsheet_ids.iter().map(|&spreadsheet_id| {
+        app_state
+            .sheets_api
+            .spreadsheets()
+            .get(spreadsheet_id)
+            .param("fields", "properties.title")
+            .doit()
+    }))
+    .await;
+    let mut inaccessible = Vec::new();
+    for (spreadsheet_id, result) in spreadsheet_ids.into_iter().zip(results) {
+        if let Err(err) = result {
+            tracing::error!("Cannot read the spreadsheet {}: {}", spreadsheet_id, err);
+            inaccessible.push(spreadsheet_id);
+        }
+    }
+    anyhow::ensure!(
+        inaccessible.is_empty(),
+        "Cannot read the spreadsheets {}; share them with the service account or check its key",
+        inaccessible.join(", ")
+    );
+    Ok(())
+}
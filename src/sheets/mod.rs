@@ -0,0 +1,279 @@
+//! The spreadsheets the submissions are saved to, and the tracking of the saved submissions.
+
+use google_sheets4::Sheets;
+
+use crate::{config, redis_connection, state::HelpKind};
+
+pub mod access;
+pub mod provisioning;
+pub mod replay;
+pub mod rotation;
+pub mod schema;
+pub mod sink;
+pub mod status;
+
+pub use sink::{Appended, FoundRow, SubmissionSink};
+
+/// Redis set of chat ids which have at least one tracked submission.
+pub const SUBMISSION_CHATS_KEY: &str = "submission_chats";
+
+/// Redis hash of [`IndexedSubmission`]s keyed by reference id.
+pub const SUBMISSIONS_BY_REFERENCE_KEY: &str = "submissions:by_reference";
+
+/// Redis hash of the saved [`TrackedSubmission`]s keyed by [`crate::Contact::submission_id`].
+pub const SUBMISSIONS_BY_ID_KEY: &str = "submissions:by_id";
+
+/// A submission saved to the spreadsheet, tracked so the requester can follow it up later.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct TrackedSubmission {
+    pub reference_id: String,
+    pub help_kind: HelpKind,
+    /// `None` for submissions saved before routing by region was introduced.
+    #[serde(default)]
+    pub spreadsheet_id: Option<String>,
+    /// The appended row in A1 notation, e.g. `Sheet1!A5:G5`; empty if only logged in the dev mode.
+    pub range: String,
+    /// `None` for submissions saved before the retention period was introduced; they are kept.
+    #[serde(default)]
+    pub saved_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// `None` for submissions saved before the ids were introduced.
+    #[serde(default)]
+    pub submission_id: Option<uuid::Uuid>,
+    /// Columns of the row which read back different from the submission, see
+    /// [`sink::Appended::mismatched_columns`]; only known right after it is saved.
+    #[serde(skip)]
+    pub mismatched_columns: Vec<usize>,
+}
+
+/// A tracked submission together with the chat of its requester.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct IndexedSubmission {
+    pub chat_id: i64,
+    pub submission: TrackedSubmission,
+}
+
+/// Allocates the next sequential reference id with the given prefix, e.g. `EVA-2024-00123`.
+///
+/// The sequence is kept in Redis and restarts every year.
+#[tracing::instrument(skip(redis, now))]
+pub async fn next_reference_id(
+    mut redis: redis::aio::MultiplexedConnection,
+    prefix: &str,
+    now: &chrono::DateTime<chrono_tz::Tz>,
+) -> anyhow::Result<String> {
+    use chrono::Datelike;
+    use redis::AsyncCommands;
+
+    let year = now.year();
+    let sequence_number: u64 = redis
+        .incr(
+            redis_connection::key(&format!("reference_id:{prefix}:{year}")),
+            1,
+        )
+        .await?;
+    Ok(format!("{prefix}-{year}-{sequence_number:05}"))
+}
+
+/// The tab quoted for A1 notation, which names with spaces need, e.g. `'Sheet1 2024-03'`.
+pub fn quoted_tab(tab: &str) -> String {
+    format!("'{}'", tab.replace('\'', "''"))
+}
+
+/// The letters of the 0-based column in A1 notation, e.g. `AA` for 26.
+pub fn column_letters(column: usize) -> String {
+    let mut letters = Vec::new();
+    let mut number = column + 1;
+    while number > 0 {
+        number -= 1;
+        letters.push(b'A' + (number % 26) as u8);
+        number /= 26;
+    }
+    letters.reverse();
+    String::from_utf8(letters).expect("the letters are ASCII")
+}
+
+pub fn submissions_key(chat_id: i64) -> String {
+    redis_connection::key(&format!("submissions:{chat_id}"))
+}
+
+#[tracing::instrument(skip_all, fields(reference_id = %submission.reference_id))]
+pub async fn track_submission(
+    mut redis: redis::aio::MultiplexedConnection,
+    chat_id: i64,
+    submission: &TrackedSubmission,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let _: () = redis
+        .rpush(submissions_key(chat_id), serde_json::to_string(submission)?)
+        .await?;
+    let _: () = redis
+        .sadd(redis_connection::key(SUBMISSION_CHATS_KEY), chat_id)
+        .await?;
+    let _: () = redis
+        .hset(
+            redis_connection::key(SUBMISSIONS_BY_REFERENCE_KEY),
+            &submission.reference_id,
+            serde_json::to_string(&IndexedSubmission {
+                chat_id,
+                submission: submission.clone(),
+            })?,
+        )
+        .await?;
+    Ok(())
+}
+
+pub async fn find_submission(
+    mut redis: redis::aio::MultiplexedConnection,
+    reference_id: &str,
+) -> anyhow::Result<Option<IndexedSubmission>> {
+    use redis::AsyncCommands;
+
+    let entry: Option<String> = redis
+        .hget(
+            redis_connection::key(SUBMISSIONS_BY_REFERENCE_KEY),
+            reference_id,
+        )
+        .await?;
+    Ok(entry
+        .map(|entry| serde_json::from_str(&entry))
+        .transpose()?)
+}
+
+pub async fn tracked_submissions(
+    mut redis: redis::aio::MultiplexedConnection,
+    chat_id: i64,
+) -> anyhow::Result<Vec<TrackedSubmission>> {
+    use redis::AsyncCommands;
+
+    let entries: Vec<String> = redis.lrange(submissions_key(chat_id), 0, -1).await?;
+    entries
+        .iter()
+        .map(|entry| Ok(serde_json::from_str(entry)?))
+        .collect()
+}
+
+impl TrackedSubmission {
+    pub fn spreadsheet_id(&self) -> String {
+        // Submissions saved before routing could only go to the built-in spreadsheets.
+        self.spreadsheet_id.clone().unwrap_or_else(|| {
+            config::builtin_help_kinds()
+                .into_iter()
+                .find(|definition| definition.slug == self.help_kind)
+                .map(|definition| definition.destination.spreadsheet_id)
+                .unwrap_or_default()
+        })
+    }
+
+    /// Number of the submission row in the tab; `None` if it was only logged.
+    pub fn row_number(&self) -> Option<u64> {
+        let (_, cells) = self.range.rsplit_once('!')?;
+        cells
+            .split(':')
+            .next()?
+            .chars()
+            .filter(char::is_ascii_digit)
+            .collect::<String>()
+            .parse()
+            .ok()
+    }
+
+    /// A1 notation of a single cell in the submission row, e.g. `Sheet1!C5`.
+    pub fn cell_range(&self, column: usize) -> anyhow::Result<String> {
+        let (sheet, cells) = self
+            .range
+            .rsplit_once('!')
+            .ok_or_else(|| anyhow::anyhow!("Unexpected submission range {:?}", self.range))?;
+        let row: String = cells
+            .split(':')
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .filter(char::is_ascii_digit)
+            .collect();
+        anyhow::ensure!(
+            !row.is_empty(),
+            "Unexpected submission range {:?}",
+            self.range
+        );
+        Ok(format!("{sheet}!{}{row}", column_letters(column)))
+    }
+
+    /// Reads the status column of the submission row back from the spreadsheet.
+    ///
+    /// Returns `None` if volunteers have not set any status yet, or the tab has no status column.
+    #[tracing::instrument(skip_all, fields(reference_id = %self.reference_id))]
+    pub async fn fetch_status(
+        &self,
+        sheets_api: &Sheets,
+        config: &config::Config,
+    ) -> anyhow::Result<Option<String>> {
+        let status_column = match config.column(&self.help_kind, "status") {
+            Some(status_column) => status_column,
+            None => return Ok(None),
+        };
+        Ok(self
+            .fetch_row(sheets_api)
+            .await?
+            .and_then(|row| row.into_iter().nth(status_column))
+            .filter(|status| !status.trim().is_empty()))
+    }
+
+    /// The cells of the submission row; `None` if it was only logged.
+    pub async fn fetch_row(&self, sheets_api: &Sheets) -> anyhow::Result<Option<Vec<String>>> {
+        // Submissions logged in the dev mode have no row.
+        if self.range.is_empty() {
+            return Ok(None);
+        }
+        let (_, value_range) = sheets_api
+            .spreadsheets()
+            .values_get(&self.spreadsheet_id(), &self.range)
+            .doit()
+            .await?;
+        Ok(value_range.values.and_then(|rows| rows.into_iter().next()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::{column_letters, next_reference_id};
+    use crate::testing::Harness;
+
+    #[tokio::test]
+    async fn reference_ids_are_numbered_per_prefix_and_year() -> anyhow::Result<()> {
+        let harness = Harness::new().await?;
+        let redis = || harness.app_state.redis.clone();
+        let kyiv = chrono_tz::Europe::Kyiv;
+        let december = kyiv.with_ymd_and_hms(2024, 12, 31, 23, 0, 0).unwrap();
+        let january = kyiv.with_ymd_and_hms(2025, 1, 1, 0, 30, 0).unwrap();
+        assert_eq!(
+            next_reference_id(redis(), "EV", &december).await?,
+            "EV-2024-00001"
+        );
+        assert_eq!(
+            next_reference_id(redis(), "EV", &december).await?,
+            "EV-2024-00002"
+        );
+        assert_eq!(
+            next_reference_id(redis(), "HU", &december).await?,
+            "HU-2024-00001"
+        );
+        assert_eq!(
+            next_reference_id(redis(), "EV", &january).await?,
+            "EV-2025-00001"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn columns_are_lettered_like_in_the_sheets() {
+        assert_eq!(column_letters(0), "A");
+        assert_eq!(column_letters(25), "Z");
+        assert_eq!(column_letters(26), "AA");
+        assert_eq!(column_letters(27), "AB");
+        assert_eq!(column_letters(701), "ZZ");
+        assert_eq!(column_letters(702), "AAA");
+    }
+}
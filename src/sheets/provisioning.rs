@@ -0,0 +1,151 @@
+//! Creating the spreadsheets of the destinations configured without an id, so that a new
+//! deployment needs no spreadsheets prepared by hand.
+//!
+//! Each is created with the tab and the header row of the destination and shared with the
+//! accounts from [`config::Provisioning::share_with`]. The ids are written to the state file, which
+//! fills them in whenever the config is loaded, so the spreadsheets are created only once.
+
+use google_sheets4::api::{Sheet, SheetProperties, Spreadsheet, SpreadsheetProperties, ValueRange};
+
+use super::quoted_tab;
+use crate::{config, AppState};
+
+/// Headers of the audit log, see [`crate::audit::record`].
+const AUDIT_LOG_HEADERS: &[&str] = &["Час", "Хто", "Дія", "Об'єкт", "Деталі"];
+
+/// Creates the missing spreadsheets and reloads the config with their ids.
+pub async fn provision(app_state: &AppState) -> anyhow::Result<()> {
+    let mut config = (*app_state.config()).clone();
+    let provisioning = match config.provisioning.clone() {
+        Some(provisioning) => provisioning,
+        None => return Ok(()),
+    };
+    let header_rows: std::collections::HashMap<String, Option<Vec<String>>> = config
+        .help_kinds
+        .iter()
+        .map(|definition| {
+            (
+                definition.slug.as_str().to_owned(),
+                definition.columns.clone(),
+            )
+        })
+        .collect();
+    let analytics_headers = crate::analytics::headers(&config);
+    let mut provisioned = provisioning.load()?;
+    let mut created = 0;
+    for (key, title, destination) in config.destinations_mut() {
+        if !destination.spreadsheet_id.is_empty() {
+            continue;
+        }
+        anyhow::ensure!(
+            !app_state.skips_external_writes(),
+            "The spreadsheet of {} has no id; start once without the dry run to create it",
+            key
+        );
+        // The tabs with the built-in layout get their headers from `schema::verify_headers`.
+        let headers: Option<Vec<String>> = if key == "audit_log" {
+            Some(
+                AUDIT_LOG_HEADERS
+                    .iter()
+                    .map(|&header| header.to_owned())
+                    .collect(),
+            )
+        } else if key == "analytics" {
+            Some(analytics_headers.clone())
+        } else if key == "feedback" {
+            Some(
+                crate::feedback::HEADERS
+                    .iter()
+                    .map(|&header| header.to_owned())
+                    .collect(),
+            )
+        } else {
+            let help_kind = key.split('/').nth(1).unwrap_or_default();
+            header_rows.get(help_kind).cloned().flatten()
+        };
+        let spreadsheet_id = create(app_state, &title, destination, headers).await?;
+        share(app_state, &provisioning, &spreadsheet_id).await?;
+        tracing::info!("Created the spreadsheet {} for {}", spreadsheet_id, key);
+        provisioned.insert(key, spreadsheet_id);
+        // Stored right away, so that a failure with the next one does not lose the id.
+        provisioning.store(&provisioned)?;
+        created += 1;
+    }
+    if created > 0 {
+        app_state.reload()?;
+    }
+    Ok(())
+}
+
+async fn create(
+    app_state: &AppState,
+    title: &str,
+    destination: &config::Destination,
+    headers: Option<Vec<String>>,
+) -> anyhow::Result<String> {
+    let (_, created) = app_state
+        .sheets_api
+        .spreadsheets()
+        .create(Spreadsheet {
+            properties: Some(SpreadsheetProperties {
+                title: Some(title.to_owned()),
+                ..Default::default()
+            }),
+            sheets: Some(vec![Sheet {
+                properties: Some(SheetProperties {
+                    title: Some(destination.sheet.clone()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        })
+        .doit()
+        .await?;
+    let spreadsheet_id = created
+        .spreadsheet_id
+        .ok_or_else(|| anyhow::anyhow!("Sheets API did not return the spreadsheet id"))?;
+    if let Some(headers) = headers {
+        app_state
+            .sheets_api
+            .spreadsheets()
+            .values_update(
+                ValueRange {
+                    major_dimension: Some("ROWS".to_owned()),
+                    range: None,
+                    values: Some(vec![headers]),
+                },
+                &spreadsheet_id,
+                &format!("{}!A1", quoted_tab(&destination.sheet)),
+            )
+            .value_input_option("RAW")
+            .doit()
+            .await?;
+    }
+    Ok(spreadsheet_id)
+}
+
+async fn share(
+    app_state: &AppState,
+    provisioning: &config::Provisioning,
+    spreadsheet_id: &str,
+) -> anyhow::Result<()> {
+    for email in &provisioning.share_with {
+        app_state
+            .drive_api
+            .permissions()
+            .create(
+                google_drive3::api::Permission {
+                    role: Some("writer".to_owned()),
+                    type_: Some("user".to_owned()),
+                    email_address: Some(email.clone()),
+                    ..Default::default()
+                },
+                spreadsheet_id,
+            )
+            .supports_all_drives(true)
+            .doit()
+            .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,174 @@
+//! Rows the sink failed to append, queued so that the `replay-queue` command can send them again.
+//!
+//! A failed write is queued together with the submission id of the contact, and the requester is
+//! asked to confirm again. Whichever comes first, the requester's retry or the replay, records the
+//! submission; the other one finds it by the id and does not append the row twice. The replay
+//! only appends the rows and tracks the submissions for the follow-ups, the notifications of the
+//! volunteers are not sent.
+
+use super::{
+    sink::find_appended, track_submission, Appended, TrackedSubmission, SUBMISSIONS_BY_ID_KEY,
+};
+use crate::{
+    config, redis_connection,
+    state::{encryption, HelpKind},
+    AppState,
+};
+
+/// Redis list of the [`FailedWrite`]s, oldest first.
+const FAILED_WRITES_KEY: &str = "sheets:failed_writes";
+
+/// A row of a confirmed submission the sink failed to append; queued encrypted like the dialogue
+/// states, see [`encryption::seal`].
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct FailedWrite {
+    pub chat_id: i64,
+    pub help_kind: HelpKind,
+    pub destination: config::Destination,
+    pub reference_id: String,
+    pub row: Vec<String>,
+    pub saved_at: chrono::DateTime<chrono::Utc>,
+    pub submission_id: uuid::Uuid,
+}
+
+/// Queues the row to be sent again.
+pub async fn enqueue(app_state: &AppState, failed: &FailedWrite) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let _: () = app_state
+        .redis
+        .clone()
+        .rpush(
+            redis_connection::key(FAILED_WRITES_KEY),
+            encryption::seal(app_state.cipher.as_ref(), failed)?,
+        )
+        .await?;
+    Ok(())
+}
+
+/// Removes the queued rows of the submission, e.g. when the user deletes their data.
+pub async fn forget(app_state: &AppState, reference_id: &str) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let mut redis = app_state.redis.clone();
+    let key = redis_connection::key(FAILED_WRITES_KEY);
+    let entries: Vec<Vec<u8>> = redis.lrange(&key, 0, -1).await?;
+    for entry in entries {
+        let failed: FailedWrite = encryption::open(app_state.cipher.as_ref(), &entry)?;
+        if failed.reference_id == reference_id {
+            let _: () = redis.lrem(&key, 1, entry).await?;
+        }
+    }
+    Ok(())
+}
+
+/// What [`replay`] did with the queued rows.
+#[derive(Debug, Default)]
+pub struct Replayed {
+    pub appended: Vec<String>,
+    /// Rows of the submissions which were saved meanwhile.
+    pub skipped: usize,
+}
+
+/// Appends the queued rows in order; stops at the first one which fails again and leaves it at
+/// the head of the queue.
+pub async fn replay(app_state: &AppState) -> anyhow::Result<Replayed> {
+    use redis::AsyncCommands;
+
+    let mut redis = app_state.redis.clone();
+    let key = redis_connection::key(FAILED_WRITES_KEY);
+    let mut replayed = Replayed::default();
+    loop {
+        let entry: Option<Vec<u8>> = redis.lpop(&key).await?;
+        let entry = match entry {
+            Some(entry) => entry,
+            None => return Ok(replayed),
+        };
+        let failed: FailedWrite = encryption::open(app_state.cipher.as_ref(), &entry)?;
+        match replay_one(app_state, &failed).await {
+            Ok(Some(submission)) => replayed.appended.push(submission.reference_id),
+            Ok(None) => replayed.skipped += 1,
+            Err(err) => {
+                let _: () = redis.lpush(&key, entry).await?;
+                return Err(err.context(format!(
+                    "Failed to append the row of {} again",
+                    failed.reference_id
+                )));
+            }
+        }
+    }
+}
+
+/// Appends the row unless its submission is saved already.
+#[tracing::instrument(skip_all, fields(reference_id = %failed.reference_id))]
+async fn replay_one(
+    app_state: &AppState,
+    failed: &FailedWrite,
+) -> anyhow::Result<Option<TrackedSubmission>> {
+    use redis::AsyncCommands;
+
+    let mut redis = app_state.redis.clone();
+    let submission_id = failed.submission_id.to_string();
+    let saved: Option<String> = redis
+        .hget(redis_connection::key(SUBMISSIONS_BY_ID_KEY), &submission_id)
+        .await?;
+    if saved.is_some() {
+        tracing::info!("Submission {} is saved already", submission_id);
+        return Ok(None);
+    }
+    let config = app_state.config();
+    let definition = config.help_kind(&failed.help_kind);
+    if let Some(definition) = definition {
+        if let Some(submission) = find_appended(
+            app_state.submission_sink.as_ref(),
+            definition,
+            &failed.destination,
+            failed.submission_id,
+        )
+        .await?
+        {
+            tracing::info!("Submission {} is in the spreadsheet already", submission_id);
+            record(app_state, failed, &submission).await?;
+            return Ok(None);
+        }
+    }
+    let append = definition
+        .map(|definition| definition.append.clone())
+        .unwrap_or_default();
+    let Appended {
+        range,
+        mismatched_columns,
+    } = app_state
+        .submission_sink
+        .append_row(&failed.destination, &append, failed.row.clone())
+        .await?;
+    let submission = TrackedSubmission {
+        reference_id: failed.reference_id.clone(),
+        help_kind: failed.help_kind.clone(),
+        spreadsheet_id: (!range.is_empty()).then(|| failed.destination.spreadsheet_id.clone()),
+        range,
+        saved_at: Some(failed.saved_at),
+        submission_id: Some(failed.submission_id),
+        mismatched_columns,
+    };
+    record(app_state, failed, &submission).await?;
+    Ok(Some(submission))
+}
+
+async fn record(
+    app_state: &AppState,
+    failed: &FailedWrite,
+    submission: &TrackedSubmission,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let mut redis = app_state.redis.clone();
+    let _: () = redis
+        .hset(
+            redis_connection::key(SUBMISSIONS_BY_ID_KEY),
+            failed.submission_id.to_string(),
+            serde_json::to_string(submission)?,
+        )
+        .await?;
+    track_submission(redis, failed.chat_id, submission).await
+}
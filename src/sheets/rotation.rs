@@ -0,0 +1,203 @@
+//! Continuation spreadsheets for the destinations which are about to run out of cells.
+//!
+//! A spreadsheet holds at most 10 million cells. Once a submission lands on the row
+//! `COLLECT_VOLUNTEERS_BOT_MAX_ROWS` (250 000 by default, 6.5 million cells of a tab 26 columns
+//! wide) or further, [`check`] creates a new spreadsheet with the same tab and header row, and the
+//! admins are told where it is so that they can share it with the volunteers. The continuation is
+//! kept in Redis rather than in the config file, and [`resolve`] follows it for every submission
+//! routed to the full destination.
+
+use anyhow::Context;
+use teloxide::prelude2::*;
+
+use super::{schema, TrackedSubmission};
+use crate::{
+    audit,
+    config::{Config, Destination, HelpKindDefinition},
+    redis_connection, AppState, ThrottledBot,
+};
+
+/// Redis hash of the continuation [`Destination`]s keyed by the spreadsheet id and the tab they
+/// continue.
+const CONTINUATIONS_KEY: &str = "sheets:continuations";
+
+const DEFAULT_MAX_ROWS: u64 = 250_000;
+
+/// How many continuations of continuations are followed, in case they form a cycle.
+const MAX_CONTINUATIONS: usize = 16;
+
+fn max_rows() -> anyhow::Result<u64> {
+    match std::env::var("COLLECT_VOLUNTEERS_BOT_MAX_ROWS") {
+        Ok(value) => value.parse().context("Invalid max rows"),
+        Err(_) => Ok(DEFAULT_MAX_ROWS),
+    }
+}
+
+fn continuation_field(destination: &Destination) -> String {
+    format!("{}!{}", destination.spreadsheet_id, destination.sheet)
+}
+
+/// The destination the rows routed to `destination` are appended to.
+pub async fn resolve(
+    mut redis: redis::aio::MultiplexedConnection,
+    mut destination: Destination,
+) -> anyhow::Result<Destination> {
+    use redis::AsyncCommands;
+
+    for _ in 0..MAX_CONTINUATIONS {
+        let continuation: Option<String> = redis
+            .hget(
+                redis_connection::key(CONTINUATIONS_KEY),
+                continuation_field(&destination),
+            )
+            .await?;
+        match continuation {
+            Some(continuation) => destination = serde_json::from_str(&continuation)?,
+            None => break,
+        }
+    }
+    Ok(destination)
+}
+
+/// Every destination the rows of the help kind may have been appended to: its own, the routed
+/// ones and their continuations.
+pub async fn destinations_of(
+    redis: redis::aio::MultiplexedConnection,
+    config: &Config,
+    definition: &HelpKindDefinition,
+) -> anyhow::Result<Vec<Destination>> {
+    let routes = config
+        .destinations
+        .iter()
+        .filter(|route| route.help_kind == definition.slug)
+        .map(|route| route.destination.clone());
+    let mut destinations: Vec<Destination> = Vec::new();
+    for destination in std::iter::once(definition.destination.clone()).chain(routes) {
+        // The rows saved before the destination was continued stay in the full spreadsheet.
+        let continuation = resolve(redis.clone(), destination.clone()).await?;
+        for destination in [destination, continuation] {
+            if !destinations.contains(&destination) {
+                destinations.push(destination);
+            }
+        }
+    }
+    Ok(destinations)
+}
+
+/// Continues the destination in a new spreadsheet if the submission was appended close to the
+/// limit.
+#[tracing::instrument(skip_all, fields(reference_id = %submission.reference_id))]
+pub async fn check(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    destination: &Destination,
+    submission: &TrackedSubmission,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let row = match submission.row_number() {
+        Some(row) => row,
+        None => return Ok(()),
+    };
+    if row < max_rows()? || app_state.skips_external_writes() {
+        return Ok(());
+    }
+    let mut redis = app_state.redis.clone();
+    // Only the first of the submissions past the limit creates the continuation.
+    let claimed: bool = redis
+        .set_nx(
+            redis_connection::key(&format!(
+                "sheets:continuing:{}",
+                continuation_field(destination)
+            )),
+            submission.reference_id.as_str(),
+        )
+        .await?;
+    if !claimed {
+        return Ok(());
+    }
+
+    let (_, original) = app_state
+        .sheets_api
+        .spreadsheets()
+        .get(&destination.spreadsheet_id)
+        .param("fields", "properties.title")
+        .doit()
+        .await?;
+    let title = original
+        .properties
+        .and_then(|properties| properties.title)
+        .unwrap_or_else(|| destination.spreadsheet_id.clone());
+    let (_, created) = app_state
+        .sheets_api
+        .spreadsheets()
+        .create(google_sheets4::api::Spreadsheet {
+            properties: Some(google_sheets4::api::SpreadsheetProperties {
+                title: Some(format!(
+                    "{title} (з {})",
+                    chrono::Utc::now().format("%Y-%m-%d")
+                )),
+                ..Default::default()
+            }),
+            sheets: Some(vec![google_sheets4::api::Sheet {
+                properties: Some(google_sheets4::api::SheetProperties {
+                    title: Some(destination.sheet.clone()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        })
+        .doit()
+        .await?;
+    let continuation = Destination {
+        spreadsheet_id: created
+            .spreadsheet_id
+            .ok_or_else(|| anyhow::anyhow!("Sheets API did not return the spreadsheet id"))?,
+        ..destination.clone()
+    };
+    if let Err(err) = schema::copy_header_row(
+        &app_state.sheets_api,
+        destination,
+        &continuation.spreadsheet_id,
+        &continuation.sheet,
+    )
+    .await
+    {
+        tracing::warn!("Failed to copy the header row: {:?}", err);
+    }
+    let _: () = redis
+        .hset(
+            redis_connection::key(CONTINUATIONS_KEY),
+            continuation_field(destination),
+            serde_json::to_string(&continuation)?,
+        )
+        .await?;
+    tracing::info!(
+        "Continuing {:?} in {}",
+        destination,
+        continuation.spreadsheet_id
+    );
+    audit::record(
+        app_state,
+        "bot",
+        audit::Action::SpreadsheetContinued,
+        &continuation_field(destination),
+        &continuation.spreadsheet_id,
+    )
+    .await;
+
+    if let Some(admin_chat_id) = app_state.admin_chat_id {
+        bot.send_message(
+            admin_chat_id,
+            format!(
+                "Таблиця «{title}» майже заповнена (рядок {row}). Нові заявки з вкладки «{}» \
+                 тепер записуються до нової таблиці: \
+                 https://docs.google.com/spreadsheets/d/{}\nНадайте до неї доступ волонтерам.",
+                destination.sheet, continuation.spreadsheet_id
+            ),
+        )
+        .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,196 @@
+//! The header row of the submission tabs, checked at startup so that rows are not appended under
+//! the wrong columns.
+
+use google_sheets4::{api::ValueRange, Sheets};
+
+use super::{column_letters, quoted_tab};
+use crate::{config, AppState};
+
+/// The built-in layout of the rows: the names of the values in the columns, see
+/// [`crate::config::HelpKindDefinition::columns`], with their headers. The columns of the extra
+/// form fields follow them and are not checked.
+pub const DEFAULT_COLUMNS: &[(&str, &str)] = &[
+    ("reference_id", "Номер заявки"),
+    ("status", "Статус"),
+    ("assignee", "Виконавець"),
+    ("full_name", "ПІБ"),
+    ("phone_numbers", "Телефони"),
+    ("address", "Адреса"),
+    ("comments", "Коментар"),
+    ("created_at", "Дата"),
+    ("urgency", "Терміновість"),
+    ("passengers", "Кількість людей"),
+    ("children", "Діти"),
+    ("limited_mobility", "Літні / маломобільні"),
+    ("pets", "Тварини"),
+    ("vehicle_type", "Транспорт"),
+    ("seats", "Місць для пасажирів"),
+    ("fuel_range_km", "Запас ходу, км"),
+    ("cities_covered", "Населені пункти"),
+    ("region", "Область"),
+    ("city", "Населений пункт"),
+    ("language", "Мова"),
+    ("attachments", "Вкладення"),
+    ("consented_at", "Згода на обробку даних"),
+    ("telegram_user_id", "Telegram id"),
+    ("telegram_username", "Telegram"),
+    ("telegram_link", "Написати в Telegram"),
+    ("created_at_utc", "Дата (UTC)"),
+    ("submission_id", "UUID"),
+    ("source", "Джерело"),
+    ("trip", "Рейс"),
+    ("map_link", "Карта"),
+    ("street", "Вулиця"),
+    ("building", "Будинок, квартира"),
+    ("address_notes", "Як знайти"),
+    ("preferred_language", "Мова спілкування"),
+    ("accessibility_needs", "Доступність"),
+    ("medical_needs", "Медичні потреби"),
+    ("medical", "Медична позначка"),
+    ("help_categories", "Категорії допомоги"),
+    ("food_quantity", "Їжа, кількість"),
+    ("medicine_quantity", "Ліки, кількість"),
+    ("clothes_quantity", "Одяг, кількість"),
+    ("generator_quantity", "Генератор, кількість"),
+    ("tags", "Теги"),
+];
+
+pub fn default_columns() -> Vec<&'static str> {
+    DEFAULT_COLUMNS.iter().map(|&(name, _)| name).collect()
+}
+
+/// Checks the header row of every tab the submissions with the built-in layout may go to; the tabs
+/// with custom columns are taken as they are. Missing trailing headers are written, unless the
+/// spreadsheets are left alone, see [`AppState::skips_external_writes`]; a header which differs
+/// from the expected one is an error, so that the bot does not start appending misplaced rows.
+pub async fn verify_headers(app_state: &AppState) -> anyhow::Result<()> {
+    let config = app_state.config();
+    let mut destinations: Vec<&config::Destination> = config
+        .help_kinds
+        .iter()
+        .filter(|definition| definition.columns.is_none())
+        .flat_map(|definition| {
+            let routes = config
+                .destinations
+                .iter()
+                .filter(move |route| route.help_kind == definition.slug)
+                .map(|route| &route.destination);
+            std::iter::once(&definition.destination).chain(routes)
+        })
+        .collect();
+    destinations.sort_by(|a, b| (&a.spreadsheet_id, &a.sheet).cmp(&(&b.spreadsheet_id, &b.sheet)));
+    destinations.dedup();
+    for destination in destinations {
+        verify_destination(app_state, destination).await?;
+    }
+    Ok(())
+}
+
+#[tracing::instrument(skip(app_state))]
+async fn verify_destination(
+    app_state: &AppState,
+    destination: &config::Destination,
+) -> anyhow::Result<()> {
+    let header_range = format!("{}!1:1", quoted_tab(&destination.sheet));
+    let (_, value_range) = app_state
+        .sheets_api
+        .spreadsheets()
+        .values_get(&destination.spreadsheet_id, &header_range)
+        .doit()
+        .await?;
+    let found = value_range
+        .values
+        .and_then(|rows| rows.into_iter().next())
+        .unwrap_or_default();
+    let headers: Vec<&str> = DEFAULT_COLUMNS.iter().map(|&(_, header)| header).collect();
+    let mismatched: Vec<String> = found
+        .iter()
+        .zip(&headers)
+        .enumerate()
+        .filter(|(_, (found, expected))| found.trim() != **expected)
+        .map(|(column, (found, expected))| {
+            format!(
+                "{} is {:?} instead of {:?}",
+                column_letters(column),
+                found,
+                expected
+            )
+        })
+        .collect();
+    anyhow::ensure!(
+        mismatched.is_empty(),
+        "Unexpected headers in {:?} of {}: {}; move the columns back or fix the headers",
+        destination.sheet,
+        destination.spreadsheet_id,
+        mismatched.join(", ")
+    );
+    if found.len() >= headers.len() {
+        return Ok(());
+    }
+
+    // A new tab, or one created before the last columns were added.
+    let missing = &headers[found.len()..];
+    if app_state.skips_external_writes() {
+        tracing::warn!("Not writing the missing headers {:?}", missing);
+        return Ok(());
+    }
+    let range = format!(
+        "{}!{}1",
+        quoted_tab(&destination.sheet),
+        column_letters(found.len())
+    );
+    app_state
+        .sheets_api
+        .spreadsheets()
+        .values_update(
+            ValueRange {
+                major_dimension: Some("ROWS".to_owned()),
+                range: None,
+                values: Some(vec![missing
+                    .iter()
+                    .map(|&header| header.to_owned())
+                    .collect::<Vec<_>>()]),
+            },
+            &destination.spreadsheet_id,
+            &range,
+        )
+        .value_input_option("RAW")
+        .doit()
+        .await?;
+    tracing::info!("Wrote the missing headers {:?}", missing);
+    Ok(())
+}
+
+/// Copies the header row of the `sheet` of the destination to the tab of the spreadsheet.
+pub async fn copy_header_row(
+    sheets_api: &Sheets,
+    from: &config::Destination,
+    spreadsheet_id: &str,
+    tab: &str,
+) -> anyhow::Result<()> {
+    let (_, headers) = sheets_api
+        .spreadsheets()
+        .values_get(
+            &from.spreadsheet_id,
+            &format!("{}!1:1", quoted_tab(&from.sheet)),
+        )
+        .doit()
+        .await?;
+    if headers.values.is_none() {
+        return Ok(());
+    }
+    sheets_api
+        .spreadsheets()
+        .values_update(
+            ValueRange {
+                range: None,
+                ..headers
+            },
+            spreadsheet_id,
+            &format!("{}!A1", quoted_tab(tab)),
+        )
+        .value_input_option("RAW")
+        .doit()
+        .await?;
+    Ok(())
+}
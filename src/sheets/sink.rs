@@ -0,0 +1,644 @@
+//! Where the rows of the confirmed submissions are written.
+
+use std::collections::HashSet;
+
+use chrono::SecondsFormat;
+use futures::{future::BoxFuture, FutureExt};
+use google_sheets4::{
+    api::{AddSheetRequest, BatchUpdateSpreadsheetRequest, Request, SheetProperties, ValueRange},
+    Sheets,
+};
+
+use super::{
+    column_letters, next_reference_id, quoted_tab, replay, TrackedSubmission, SUBMISSIONS_BY_ID_KEY,
+};
+use crate::{config, redis_connection, state::Contact, texts, AppState};
+
+/// How long a retry of a failed save still looks for the row in the tab, see [`attempt_key`].
+const ATTEMPT_TTL_SECS: usize = 7 * 24 * 60 * 60;
+
+/// Marks the submission whose row is being appended, so that the retry after a failure looks for
+/// the row in the tab: the failed attempt may have appended it before Redis recorded it.
+fn attempt_key(submission_id: uuid::Uuid) -> String {
+    redis_connection::key(&format!("sheets:attempt:{submission_id}"))
+}
+
+/// Destination of the submission rows: the spreadsheets, or only the log in the dev mode.
+pub trait SubmissionSink: Send + Sync {
+    /// Appends the row to the tab of the spreadsheet.
+    fn append_row<'a>(
+        &'a self,
+        destination: &'a config::Destination,
+        options: &'a config::AppendOptions,
+        row: Vec<String>,
+    ) -> BoxFuture<'a, anyhow::Result<Appended>>;
+
+    /// Finds the row of the tab whose cell in the `column`, counted from 0, has the value.
+    fn find_row<'a>(
+        &'a self,
+        destination: &'a config::Destination,
+        column: usize,
+        value: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Option<FoundRow>>>;
+}
+
+/// The range of a found row in A1 notation and its cells.
+pub type FoundRow = (String, Vec<String>);
+
+/// Where a row was appended.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Appended {
+    /// The range in A1 notation, or an empty one if the row was not written anywhere.
+    pub range: String,
+    /// Columns, counted from 0, which read back different from the row even after it was written
+    /// again, e.g. truncated or parsed as a formula.
+    pub mismatched_columns: Vec<usize>,
+}
+
+/// Appends the rows with the Sheets API, creating the tabs of the months as needed.
+pub struct SheetsSink {
+    api: Sheets,
+    /// Tabs of the months known to exist, by spreadsheet id and title; locked while one is created
+    /// so that concurrent submissions do not create it twice.
+    monthly_tabs: tokio::sync::Mutex<HashSet<(String, String)>>,
+}
+
+impl SheetsSink {
+    pub fn new(api: Sheets) -> Self {
+        Self {
+            api,
+            monthly_tabs: Default::default(),
+        }
+    }
+
+    /// Creates the tab unless it exists, with the header row copied from the `sheet` of the
+    /// destination.
+    async fn ensure_monthly_tab(
+        &self,
+        destination: &config::Destination,
+        tab: &str,
+    ) -> anyhow::Result<()> {
+        let mut monthly_tabs = self.monthly_tabs.lock().await;
+        let key = (destination.spreadsheet_id.clone(), tab.to_owned());
+        if monthly_tabs.contains(&key) {
+            return Ok(());
+        }
+        let (_, spreadsheet) = self
+            .api
+            .spreadsheets()
+            .get(&destination.spreadsheet_id)
+            .param("fields", "sheets.properties.title")
+            .doit()
+            .await?;
+        let exists = spreadsheet.sheets.unwrap_or_default().iter().any(|sheet| {
+            sheet
+                .properties
+                .as_ref()
+                .and_then(|properties| properties.title.as_deref())
+                == Some(tab)
+        });
+        if !exists {
+            let request = BatchUpdateSpreadsheetRequest {
+                requests: Some(vec![Request {
+                    add_sheet: Some(AddSheetRequest {
+                        properties: Some(SheetProperties {
+                            title: Some(tab.to_owned()),
+                            ..Default::default()
+                        }),
+                    }),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            };
+            self.api
+                .spreadsheets()
+                .batch_update(request, &destination.spreadsheet_id)
+                .doit()
+                .await?;
+            tracing::info!("Created the tab {:?}", tab);
+            let copied = super::schema::copy_header_row(
+                &self.api,
+                destination,
+                &destination.spreadsheet_id,
+                tab,
+            )
+            .await;
+            if let Err(err) = copied {
+                tracing::warn!("Failed to copy the header row to {:?}: {:?}", tab, err);
+            }
+        }
+        monthly_tabs.insert(key);
+        Ok(())
+    }
+
+    /// Writes the row over the range it was appended to, and returns the columns which still read
+    /// back different from it.
+    async fn rewrite(
+        &self,
+        destination: &config::Destination,
+        options: &config::AppendOptions,
+        range: &str,
+        row: &[String],
+    ) -> anyhow::Result<Vec<usize>> {
+        let (_, response) = self
+            .api
+            .spreadsheets()
+            .values_update(
+                ValueRange {
+                    major_dimension: Some("ROWS".to_owned()),
+                    range: None,
+                    values: Some(vec![row.to_vec()]),
+                },
+                &destination.spreadsheet_id,
+                range,
+            )
+            .value_input_option(options.value_input_option.as_str())
+            .include_values_in_response(true)
+            .doit()
+            .await?;
+        Ok(mismatched_columns(
+            options,
+            row,
+            response.updated_data.as_ref(),
+        ))
+    }
+}
+
+/// Columns of the row which differ from the values the Sheets API read back after writing it.
+fn mismatched_columns(
+    options: &config::AppendOptions,
+    row: &[String],
+    written: Option<&ValueRange>,
+) -> Vec<usize> {
+    let written: &[String] = written
+        .and_then(|written| written.values.as_ref())
+        .and_then(|rows| rows.first())
+        .map_or(&[], Vec::as_slice);
+    row.iter()
+        .enumerate()
+        .filter(|&(column, value)| {
+            let expected = match options.value_input_option {
+                // The quote only keeps the value from being parsed.
+                config::ValueInputOption::UserEntered => value.strip_prefix('\'').unwrap_or(value),
+                config::ValueInputOption::Raw => value,
+            };
+            // Empty cells at the end of the row are not read back.
+            let found = written.get(column).map_or("", String::as_str);
+            found.trim() != expected.trim()
+        })
+        .map(|(column, _)| column)
+        .collect()
+}
+
+impl SubmissionSink for SheetsSink {
+    fn append_row<'a>(
+        &'a self,
+        destination: &'a config::Destination,
+        options: &'a config::AppendOptions,
+        row: Vec<String>,
+    ) -> BoxFuture<'a, anyhow::Result<Appended>> {
+        async move {
+            let tab = destination.tab(&config::local_now());
+            if destination.monthly {
+                self.ensure_monthly_tab(destination, &tab).await?;
+            }
+            let req = ValueRange {
+                major_dimension: Some("ROWS".to_owned()),
+                range: None,
+                values: Some(vec![row.clone()]),
+            };
+            let range = match &options.range {
+                // Named ranges cannot contain colons.
+                Some(columns) if columns.contains(':') => {
+                    format!("{}!{}", quoted_tab(&tab), columns)
+                }
+                Some(named_range) => named_range.clone(),
+                None => quoted_tab(&tab),
+            };
+            let mut call = self
+                .api
+                .spreadsheets()
+                .values_append(req, &destination.spreadsheet_id, &range)
+                .value_input_option(options.value_input_option.as_str())
+                .include_values_in_response(true);
+            if let Some(insert_data_option) = options.insert_data_option {
+                call = call.insert_data_option(insert_data_option.as_str());
+            }
+            let (_, save_response) = call.doit().await?;
+            let updates = save_response
+                .updates
+                .ok_or_else(|| anyhow::anyhow!("Sheets API did not report the appended range"))?;
+            let range = updates
+                .updated_range
+                .ok_or_else(|| anyhow::anyhow!("Sheets API did not report the appended range"))?;
+            let mut mismatched_columns =
+                mismatched_columns(options, &row, updates.updated_data.as_ref());
+            if !mismatched_columns.is_empty() {
+                tracing::warn!(
+                    "Columns {:?} of {} differ from the submitted row, writing it again",
+                    mismatched_columns,
+                    range
+                );
+                mismatched_columns = self.rewrite(destination, options, &range, &row).await?;
+                if !mismatched_columns.is_empty() {
+                    tracing::error!(
+                        "Columns {:?} of {} still differ from the submitted row",
+                        mismatched_columns,
+                        range
+                    );
+                }
+            }
+            Ok(Appended {
+                range,
+                mismatched_columns,
+            })
+        }
+        .boxed()
+    }
+
+    fn find_row<'a>(
+        &'a self,
+        destination: &'a config::Destination,
+        column: usize,
+        value: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Option<FoundRow>>> {
+        async move {
+            let tab = quoted_tab(&destination.tab(&config::local_now()));
+            let letters = column_letters(column);
+            let (_, found) = self
+                .api
+                .spreadsheets()
+                .values_get(
+                    &destination.spreadsheet_id,
+                    &format!("{tab}!{letters}:{letters}"),
+                )
+                .doit()
+                .await?;
+            let number = match found.values.unwrap_or_default().iter().position(|cells| {
+                cells
+                    .first()
+                    .map(|cell| cell.trim().trim_start_matches('\''))
+                    == Some(value)
+            }) {
+                Some(index) => index + 1,
+                None => return Ok(None),
+            };
+            let range = format!("{tab}!{number}:{number}");
+            let (_, row) = self
+                .api
+                .spreadsheets()
+                .values_get(&destination.spreadsheet_id, &range)
+                .doit()
+                .await?;
+            let cells = row
+                .values
+                .and_then(|rows| rows.into_iter().next())
+                .unwrap_or_default();
+            Ok(Some((row.range.unwrap_or(range), cells)))
+        }
+        .boxed()
+    }
+}
+
+/// Only logs the rows, so that the dev mode needs no Google credentials and the dry run leaves
+/// the spreadsheets alone.
+pub struct LogSink;
+
+impl SubmissionSink for LogSink {
+    fn append_row<'a>(
+        &'a self,
+        destination: &'a config::Destination,
+        _options: &'a config::AppendOptions,
+        row: Vec<String>,
+    ) -> BoxFuture<'a, anyhow::Result<Appended>> {
+        tracing::info!("Not saving to {:?}: {:?}", destination.sheet, row);
+        futures::future::ready(Ok(Appended::default())).boxed()
+    }
+
+    fn find_row<'a>(
+        &'a self,
+        _destination: &'a config::Destination,
+        _column: usize,
+        _value: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Option<FoundRow>>> {
+        futures::future::ready(Ok(None)).boxed()
+    }
+}
+
+impl Contact {
+    /// The row of the submission as it is appended to the spreadsheet, with the values in the
+    /// `columns` of the help kind, see [`config::HelpKindDefinition::columns`]. The built-in layout
+    /// is followed by the extra fields of the form.
+    pub fn row(
+        &self,
+        columns: Option<&[String]>,
+        reference_id: &str,
+        now: &chrono::DateTime<chrono_tz::Tz>,
+        language: texts::Language,
+    ) -> Vec<String> {
+        let cell = |column: &str| self.cell(column, reference_id, now, language);
+        match columns {
+            Some(columns) => columns.iter().map(|column| cell(column)).collect(),
+            None => {
+                let mut row: Vec<String> = super::schema::default_columns()
+                    .into_iter()
+                    .map(cell)
+                    .collect();
+                row.extend(self.extra.values().map(|value| format!("'{}", value)));
+                row
+            }
+        }
+    }
+
+    fn cell(
+        &self,
+        column: &str,
+        reference_id: &str,
+        now: &chrono::DateTime<chrono_tz::Tz>,
+        language: texts::Language,
+    ) -> String {
+        // Single quote is used to force raw data (no formula parsing):
+        // https://webapps.stackexchange.com/a/97312/114756
+        match column {
+            "reference_id" => format!("'{}", reference_id),
+            // Status, assignee and trip are filled in by volunteers
+            "status" | "assignee" | "trip" | "" => String::new(),
+            "created_at" => format!("'{}", now.to_rfc3339_opts(SecondsFormat::Secs, false)),
+            "created_at_utc" => format!(
+                "'{}",
+                now.with_timezone(&chrono::Utc)
+                    .to_rfc3339_opts(SecondsFormat::Secs, true)
+            ),
+            // Language volunteers should answer in
+            "language" => language.code().to_owned(),
+            "attachments" => self
+                .attachments
+                .iter()
+                .filter_map(|attachment| attachment.link.as_deref())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            "consented_at" => self
+                .consented_at
+                .map(|consented_at| {
+                    format!(
+                        "'{}",
+                        consented_at
+                            .with_timezone(&now.timezone())
+                            .to_rfc3339_opts(SecondsFormat::Secs, false)
+                    )
+                })
+                .unwrap_or_default(),
+            "map_link" => self.map_url().unwrap_or_default(),
+            "medical" => {
+                if crate::medical::is_flagged(self) {
+                    crate::medical::LABEL.to_owned()
+                } else {
+                    String::new()
+                }
+            }
+            "source" => self
+                .source
+                .as_ref()
+                .map(|source| format!("'{}", source))
+                .unwrap_or_default(),
+            "submission_id" => self
+                .submission_id
+                .map(|submission_id| submission_id.to_string())
+                .unwrap_or_default(),
+            "tags" => self.tags.join(", "),
+            "telegram_user_id" => self
+                .telegram_user_id
+                .map(|user_id| user_id.to_string())
+                .unwrap_or_default(),
+            "telegram_username" => self
+                .telegram_username
+                .as_ref()
+                .map(|username| format!("'@{}", username))
+                .unwrap_or_default(),
+            "telegram_link" => self
+                .telegram_user_id
+                .map(|user_id| format!("'tg://user?id={}", user_id))
+                .unwrap_or_default(),
+            "urgency" | "passengers" | "seats" | "fuel_range_km" => {
+                self.answer(column).unwrap_or_default()
+            }
+            field => format!("'{}", self.answer(field).unwrap_or_default()),
+        }
+    }
+
+    /// Allocates the reference id of the contact and appends its row to the destination
+    /// spreadsheet/tab. A contact with the [`Contact::submission_id`] of a saved submission is not
+    /// appended again; the saved submission is returned instead. The id is looked up in Redis and,
+    /// on a retry after a failed attempt, in the tab, which may have got the row before Redis
+    /// recorded it. A row which fails to be appended is queued for the `replay-queue` command, see
+    /// [`replay`].
+    #[tracing::instrument(skip_all, fields(help_kind = definition.slug.as_str()))]
+    pub async fn save(
+        &self,
+        app_state: &AppState,
+        definition: &config::HelpKindDefinition,
+        destination: &config::Destination,
+        language: texts::Language,
+        chat_id: i64,
+    ) -> anyhow::Result<TrackedSubmission> {
+        use redis::AsyncCommands;
+
+        let sink = app_state.submission_sink.as_ref();
+        let mut redis = app_state.redis.clone();
+        if let Some(submission_id) = self.submission_id {
+            let saved: Option<String> = redis
+                .hget(
+                    redis_connection::key(SUBMISSIONS_BY_ID_KEY),
+                    submission_id.to_string(),
+                )
+                .await?;
+            if let Some(saved) = saved {
+                tracing::info!("Submission {} is already saved", submission_id);
+                return Ok(serde_json::from_str(&saved)?);
+            }
+            let retried: bool = redis.exists(attempt_key(submission_id)).await?;
+            if retried {
+                if let Some(submission) =
+                    find_appended(sink, definition, destination, submission_id).await?
+                {
+                    tracing::info!("Submission {} is already in the spreadsheet", submission_id);
+                    let _: () = redis
+                        .hset(
+                            redis_connection::key(SUBMISSIONS_BY_ID_KEY),
+                            submission_id.to_string(),
+                            serde_json::to_string(&submission)?,
+                        )
+                        .await?;
+                    return Ok(submission);
+                }
+            } else {
+                let _: () = redis
+                    .set_ex(attempt_key(submission_id), 1, ATTEMPT_TTL_SECS)
+                    .await?;
+            }
+        }
+        let now = config::local_now();
+        let reference_id =
+            next_reference_id(redis.clone(), &definition.reference_prefix, &now).await?;
+        let mut row = self.row(definition.columns.as_deref(), &reference_id, &now, language);
+        if definition.append.value_input_option == config::ValueInputOption::Raw {
+            // The values are not parsed, so the quotes would be stored with them.
+            for cell in &mut row {
+                if let Some(value) = cell.strip_prefix('\'') {
+                    *cell = value.to_owned();
+                }
+            }
+        }
+        let Appended {
+            range,
+            mismatched_columns,
+        } = match sink
+            .append_row(destination, &definition.append, row.clone())
+            .await
+        {
+            Ok(appended) => appended,
+            Err(err) => {
+                if let Some(submission_id) = self.submission_id {
+                    let failed = replay::FailedWrite {
+                        chat_id,
+                        help_kind: definition.slug.clone(),
+                        destination: destination.clone(),
+                        reference_id,
+                        row,
+                        saved_at: now.with_timezone(&chrono::Utc),
+                        submission_id,
+                    };
+                    if let Err(err) = replay::enqueue(app_state, &failed).await {
+                        tracing::warn!("Failed to queue the row again: {:?}", err);
+                    }
+                }
+                return Err(err);
+            }
+        };
+        tracing::debug!("Saved {:?} to {:?}", definition.slug, range);
+
+        let submission = TrackedSubmission {
+            reference_id,
+            help_kind: definition.slug.clone(),
+            // Rows which were only logged are not in any spreadsheet.
+            spreadsheet_id: (!range.is_empty()).then(|| destination.spreadsheet_id.clone()),
+            range,
+            saved_at: Some(now.with_timezone(&chrono::Utc)),
+            submission_id: self.submission_id,
+            mismatched_columns,
+        };
+        if let Some(submission_id) = self.submission_id {
+            let _: () = redis
+                .hset(
+                    redis_connection::key(SUBMISSIONS_BY_ID_KEY),
+                    submission_id.to_string(),
+                    serde_json::to_string(&submission)?,
+                )
+                .await?;
+            let _: () = redis.del(attempt_key(submission_id)).await?;
+        }
+        Ok(submission)
+    }
+}
+
+/// The submission whose row with the id is in the destination tab.
+pub(super) async fn find_appended(
+    sink: &dyn SubmissionSink,
+    definition: &config::HelpKindDefinition,
+    destination: &config::Destination,
+    submission_id: uuid::Uuid,
+) -> anyhow::Result<Option<TrackedSubmission>> {
+    let columns = definition.columns();
+    let position = |name| columns.iter().position(|column| *column == name);
+    let (id_column, reference_id_column) =
+        match (position("submission_id"), position("reference_id")) {
+            (Some(id_column), Some(reference_id_column)) => (id_column, reference_id_column),
+            _ => return Ok(None),
+        };
+    let submission_id_text = submission_id.to_string();
+    let (range, cells) = match sink
+        .find_row(destination, id_column, &submission_id_text)
+        .await?
+    {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+    let reference_id = cells
+        .get(reference_id_column)
+        .map(|cell| cell.trim().trim_start_matches('\'').to_owned())
+        .unwrap_or_default();
+    Ok(Some(TrackedSubmission {
+        reference_id,
+        help_kind: definition.slug.clone(),
+        spreadsheet_id: Some(destination.spreadsheet_id.clone()),
+        range,
+        // Not earlier than the row was appended.
+        saved_at: Some(chrono::Utc::now()),
+        submission_id: Some(submission_id),
+        mismatched_columns: Vec::new(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use redis::AsyncCommands;
+
+    use super::*;
+    use crate::{
+        testing::{FakeSink, Harness},
+        HelpKind, Language,
+    };
+
+    #[tokio::test]
+    async fn only_retries_look_for_the_row_in_the_tab() -> anyhow::Result<()> {
+        let sink = Arc::new(FakeSink::default());
+        let harness = Harness::with_sink(config::Config::default(), sink.clone()).await?;
+        let config = harness.app_state.config();
+        let definition = config
+            .help_kind(&HelpKind::new(HelpKind::PROVIDING_USEFUL_CONTACT))
+            .expect("the built-in help kind is configured");
+        let save = |contact: Contact| {
+            let app_state = harness.app_state.clone();
+            async move {
+                contact
+                    .save(
+                        &app_state,
+                        definition,
+                        &definition.destination,
+                        Language::Uk,
+                        1,
+                    )
+                    .await
+            }
+        };
+
+        let contact = Contact {
+            submission_id: Some(uuid::Uuid::new_v4()),
+            ..Default::default()
+        };
+        let saved = save(contact.clone()).await?;
+        assert_eq!(save(contact).await?.reference_id, saved.reference_id);
+        assert_eq!((sink.rows().len(), sink.scans()), (1, 0));
+
+        // The previous attempt appended the row but failed before Redis recorded it.
+        let submission_id = uuid::Uuid::new_v4();
+        let retried = Contact {
+            submission_id: Some(submission_id),
+            ..Default::default()
+        };
+        let row = retried.row(None, "REF-1", &config::local_now(), Language::Uk);
+        sink.append_row(&definition.destination, &definition.append, row)
+            .await?;
+        let _: () = harness
+            .app_state
+            .redis
+            .clone()
+            .set(attempt_key(submission_id), 1)
+            .await?;
+        assert_eq!(save(retried).await?.reference_id, "REF-1");
+        assert_eq!((sink.rows().len(), sink.scans()), (2, 1));
+        Ok(())
+    }
+}
@@ -0,0 +1,350 @@
+//! Following up the statuses volunteers set in the spreadsheets, or coordinators with `/close`.
+//!
+//! A status set in the spreadsheet is followed up only if it is a valid transition of the
+//! [`lifecycle`]; otherwise the coordinators are asked to correct it.
+
+use google_sheets4::api::ValueRange;
+use teloxide::{payloads::SendMessageSetters, prelude2::*, types::ParseMode};
+
+use super::{find_submission, tracked_submissions, TrackedSubmission, SUBMISSION_CHATS_KEY};
+use crate::{
+    assignment, audit, feedback, forum_topics, html, lifecycle, matching,
+    outgoing_webhooks::{self, Event, Payload},
+    public_feed, redis_connection, retry, shutdown, sms, texts, AppState, ThrottledBot,
+};
+
+/// Statuses (lowercased) of the requests that no longer need the requester's attention.
+pub const CLOSED_STATUSES: &[&str] = &["виконано", "скасовано"];
+
+/// Actor of the status transitions volunteers make in the spreadsheets, for the audit log.
+const SPREADSHEET_ACTOR: &str = "таблиця";
+
+/// Statuses (lowercased) the requester gets notified about once volunteers set them.
+pub const NOTIFIED_STATUSES: &[&str] = &["взято в роботу", "виконано"];
+
+/// Periodically checks the status column of every tracked submission and notifies requesters
+/// when volunteers take their request into work or complete it.
+pub async fn poll_submission_statuses(
+    bot: ThrottledBot,
+    app_state: std::sync::Arc<AppState>,
+    interval: std::time::Duration,
+    mut shutdown: shutdown::Shutdown,
+) {
+    use redis::AsyncCommands;
+
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.requested() => return,
+        }
+        let chat_ids: Vec<i64> = match app_state
+            .redis
+            .clone()
+            .smembers(redis_connection::key(SUBMISSION_CHATS_KEY))
+            .await
+        {
+            Ok(chat_ids) => chat_ids,
+            Err(err) => {
+                tracing::warn!("Failed to load chats with tracked submissions: {:?}", err);
+                continue;
+            }
+        };
+        for chat_id in chat_ids {
+            let submissions = match tracked_submissions(app_state.redis.clone(), chat_id).await {
+                Ok(submissions) => submissions,
+                Err(err) => {
+                    tracing::warn!("Failed to load submissions of chat {}: {:?}", chat_id, err);
+                    continue;
+                }
+            };
+            for submission in submissions {
+                if let Err(err) =
+                    check_submission_status(&bot, &app_state, chat_id, &submission).await
+                {
+                    tracing::warn!(
+                        "Failed to check status of {}: {:?}",
+                        submission.reference_id,
+                        err
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[tracing::instrument(skip_all, fields(reference_id = %submission.reference_id))]
+async fn check_submission_status(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    chat_id: i64,
+    submission: &TrackedSubmission,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let mut redis = app_state.redis.clone();
+    let last_status: Option<String> = redis.get(last_status_key(&submission.reference_id)).await?;
+    if let Some(last_status) = &last_status {
+        if CLOSED_STATUSES.contains(&last_status.as_str()) {
+            return Ok(());
+        }
+    }
+
+    let status = match submission
+        .fetch_status(&app_state.sheets_api, &app_state.config())
+        .await?
+    {
+        Some(status) => status,
+        None => return Ok(()),
+    };
+    let normalized_status = status.trim().to_lowercase();
+    if last_status.as_deref() == Some(normalized_status.as_str()) {
+        return Ok(());
+    }
+    let _: () = redis
+        .set(
+            last_status_key(&submission.reference_id),
+            &normalized_status,
+        )
+        .await?;
+
+    let reference_id = &submission.reference_id;
+    let new_status = match lifecycle::Status::from_label(&status) {
+        Some(new_status) => new_status,
+        None => {
+            return forum_topics::post(
+                bot,
+                app_state,
+                reference_id,
+                format!("Заявка {reference_id}: невідомий статус «{status}» у таблиці."),
+            )
+            .await;
+        }
+    };
+    if lifecycle::current(app_state, reference_id).await? == new_status {
+        return Ok(());
+    }
+    match lifecycle::transition(app_state, reference_id, new_status, SPREADSHEET_ACTOR).await? {
+        Ok(()) => follow_up(bot, app_state, chat_id, submission, &status).await,
+        Err(invalid) => {
+            forum_topics::post(
+                bot,
+                app_state,
+                reference_id,
+                format!("Заявка {reference_id}: {invalid}, виправте статус у таблиці."),
+            )
+            .await
+        }
+    }
+}
+
+/// Sets the status column of the submission row, unless the external writes are skipped.
+pub async fn write_status(
+    app_state: &AppState,
+    submission: &TrackedSubmission,
+    status: lifecycle::Status,
+) -> anyhow::Result<()> {
+    let status_column = match app_state.config().column(&submission.help_kind, "status") {
+        Some(status_column) => status_column,
+        None => return Ok(()),
+    };
+    if app_state.skips_external_writes() {
+        tracing::info!(
+            "Not writing the status of {} to the spreadsheet",
+            submission.reference_id
+        );
+        return Ok(());
+    }
+    app_state
+        .sheets_api
+        .spreadsheets()
+        .values_update(
+            ValueRange {
+                major_dimension: Some("ROWS".to_owned()),
+                range: None,
+                values: Some(vec![vec![status.label().to_owned()]]),
+            },
+            &submission.spreadsheet_id(),
+            &submission.cell_range(status_column)?,
+        )
+        .value_input_option("USER_ENTERED")
+        .doit()
+        .await?;
+    Ok(())
+}
+
+pub(crate) fn last_status_key(reference_id: &str) -> String {
+    redis_connection::key(&format!("submission_status:{reference_id}"))
+}
+
+/// Follows up the new status of the submission, whether volunteers set it in the spreadsheet or a
+/// coordinator with a command: notifies the requester, the webhooks, the topic and the public
+/// post, and remembers it so that the status is followed up once.
+pub async fn follow_up(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    chat_id: i64,
+    submission: &TrackedSubmission,
+    status: &str,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let normalized_status = status.trim().to_lowercase();
+    let _: () = app_state
+        .redis
+        .clone()
+        .set(
+            last_status_key(&submission.reference_id),
+            &normalized_status,
+        )
+        .await?;
+    let event = match normalized_status.as_str() {
+        "взято в роботу" => Some(Event::Approved),
+        "виконано" => Some(Event::Completed),
+        _ => None,
+    };
+    if let Some(event) = event {
+        outgoing_webhooks::send(app_state, &Payload::new(event, submission));
+    }
+    if CLOSED_STATUSES.contains(&normalized_status.as_str()) {
+        // A closed request must not be offered to the drivers any more.
+        matching::close_request(app_state.redis.clone(), &submission.reference_id).await?;
+    }
+    if let Err(err) = forum_topics::post_status(
+        bot,
+        app_state,
+        &submission.reference_id,
+        status,
+        CLOSED_STATUSES.contains(&normalized_status.as_str()),
+    )
+    .await
+    {
+        tracing::warn!(
+            "Failed to post the status of {} to its topic: {:?}",
+            submission.reference_id,
+            err
+        );
+    }
+    if let Err(err) =
+        public_feed::update_status(bot, app_state, &submission.reference_id, &normalized_status)
+            .await
+    {
+        tracing::warn!(
+            "Failed to update the public post of {}: {:?}",
+            submission.reference_id,
+            err
+        );
+    }
+    if normalized_status == "виконано" {
+        if let Err(err) = feedback::schedule(app_state, chat_id, submission).await {
+            tracing::warn!(
+                "Failed to schedule the feedback survey about {}: {:?}",
+                submission.reference_id,
+                err
+            );
+        }
+    }
+
+    if NOTIFIED_STATUSES.contains(&normalized_status.as_str()) {
+        let language = texts::language_of(app_state.redis.clone(), chat_id).await;
+        let text = app_state.texts().render(
+            language,
+            "status_changed",
+            &[
+                ("reference_id", &html::code(&submission.reference_id)),
+                ("status", &html::bold(status)),
+            ],
+        );
+        retry::on_flood(|| {
+            bot.send_message(chat_id, text.clone())
+                .parse_mode(ParseMode::Html)
+        })
+        .await?;
+        sms::notify_status(app_state, language, submission, status).await;
+    }
+
+    Ok(())
+}
+
+/// Handles `/close <reference id> <виконано|скасовано> [comment]` of a coordinator: sets the status
+/// in the spreadsheet and follows it up right away.
+pub async fn handle_close_command(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    msg: &Message,
+    args: &str,
+) -> anyhow::Result<()> {
+    let mut args = args.trim().splitn(3, char::is_whitespace);
+    let reference_id = args.next().unwrap_or_default();
+    let status = match args.next().map(str::to_lowercase).as_deref() {
+        Some("виконано" | "done") => lifecycle::Status::Done,
+        Some("скасовано" | "cancelled") => lifecycle::Status::Cancelled,
+        _ => {
+            bot.send_message(
+                msg.chat.id,
+                "Використання: /close <номер заявки> <виконано|скасовано> [коментар]",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    let comment = args.next().unwrap_or_default().trim();
+    let indexed = match find_submission(app_state.redis.clone(), reference_id).await? {
+        Some(indexed) => indexed,
+        None => {
+            bot.send_message(msg.chat.id, format!("Заявку {reference_id} не знайдено."))
+                .await?;
+            return Ok(());
+        }
+    };
+    let submission = &indexed.submission;
+    let coordinator = msg.from().map_or_else(
+        || msg.chat.id.to_string(),
+        assignment::volunteer_display_name,
+    );
+    if let Err(invalid) =
+        lifecycle::transition(app_state, reference_id, status, &coordinator).await?
+    {
+        bot.send_message(msg.chat.id, format!("Заявка {reference_id}: {invalid}."))
+            .await?;
+        return Ok(());
+    }
+    write_status(app_state, submission, status).await?;
+    let status = status.label();
+    follow_up(bot, app_state, indexed.chat_id, submission, status).await?;
+    // The requester is not told about every status set in the spreadsheet, but is about a closure.
+    if !NOTIFIED_STATUSES.contains(&status.to_lowercase().as_str()) {
+        let language = texts::language_of(app_state.redis.clone(), indexed.chat_id).await;
+        bot.send_message(
+            indexed.chat_id,
+            app_state.texts().render(
+                language,
+                "status_changed",
+                &[
+                    ("reference_id", &html::code(reference_id)),
+                    ("status", &html::bold(status)),
+                ],
+            ),
+        )
+        .parse_mode(ParseMode::Html)
+        .await?;
+    }
+    audit::record(
+        app_state,
+        &coordinator,
+        audit::Action::RequestClosed,
+        reference_id,
+        &if comment.is_empty() {
+            status.to_owned()
+        } else {
+            format!("{status}: {comment}")
+        },
+    )
+    .await;
+    bot.send_message(
+        msg.chat.id,
+        format!("Заявку {reference_id} закрито зі статусом «{status}»."),
+    )
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,81 @@
+//! Graceful shutdown on SIGTERM or Ctrl-C.
+//!
+//! The dispatcher stops accepting new updates and lets the handlers which are running finish, and
+//! the background loops finish their current round. The webhook deliveries the handlers started in
+//! the background, see [`Tasks`], are awaited too, and the rows queued after failed writes get one
+//! more chance to be appended. If that takes longer than
+//! `COLLECT_VOLUNTEERS_BOT_SHUTDOWN_TIMEOUT_SECS` (30 by default), the process exits anyway.
+
+use anyhow::Context;
+use teloxide::dispatching::ShutdownToken;
+use tokio::sync::watch;
+
+/// Lets the background loops know that the process is shutting down.
+#[derive(Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+impl Shutdown {
+    /// Completes once the shutdown is requested.
+    pub async fn requested(&mut self) {
+        while !*self.0.borrow() {
+            if self.0.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Work the handlers spawn in the background, e.g. the webhook deliveries, which the shutdown
+/// waits for.
+#[derive(Default)]
+pub struct Tasks(std::sync::Mutex<tokio::task::JoinSet<()>>);
+
+impl Tasks {
+    pub fn spawn(&self, task: impl std::future::Future<Output = ()> + Send + 'static) {
+        let mut tasks = self.0.lock().unwrap();
+        // The finished ones are dropped so that the set does not grow.
+        while tasks.try_join_next().is_some() {}
+        tasks.spawn(task);
+    }
+
+    /// Completes once the spawned tasks finish.
+    pub async fn wait(&self) {
+        let mut tasks = std::mem::take(&mut *self.0.lock().unwrap());
+        while tasks.join_next().await.is_some() {}
+    }
+}
+
+pub fn channel() -> (watch::Sender<bool>, Shutdown) {
+    let (sender, receiver) = watch::channel(false);
+    (sender, Shutdown(receiver))
+}
+
+/// Requests the shutdown of the dispatcher and the background loops on SIGTERM or Ctrl-C.
+pub fn on_signal(requested: watch::Sender<bool>, dispatcher: ShutdownToken) -> anyhow::Result<()> {
+    let timeout = std::time::Duration::from_secs(
+        match std::env::var("COLLECT_VOLUNTEERS_BOT_SHUTDOWN_TIMEOUT_SECS") {
+            Ok(value) => value.parse().context("Invalid shutdown timeout")?,
+            Err(_) => 30,
+        },
+    );
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+        .context("Failed to listen for SIGTERM")?;
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = terminate.recv() => tracing::info!("Received SIGTERM, shutting down..."),
+            _ = tokio::signal::ctrl_c() => tracing::info!("Received Ctrl-C, shutting down..."),
+        }
+        let _ = requested.send(true);
+        if let Err(err) = dispatcher.shutdown() {
+            tracing::warn!("Failed to shut down the dispatcher: {:?}", err);
+        }
+        tokio::time::sleep(timeout).await;
+        tracing::error!(
+            "Pending work did not finish in {:?}, exiting anyway",
+            timeout
+        );
+        crate::telemetry::shutdown();
+        std::process::exit(1);
+    });
+    Ok(())
+}
@@ -0,0 +1,201 @@
+//! SLA timers for evacuation requests.
+//!
+//! Evacuation requests which remain unassigned are re-posted to volunteers every repost interval
+//! with an escalating urgency marker, and the coordinator is pinged once the hard deadline
+//! passes.
+
+use anyhow::Context;
+use teloxide::{payloads::SendMessageSetters, prelude2::*};
+
+use crate::{
+    assignment, redis_connection, retry, shutdown::Shutdown, texts, AppState, HelpKind,
+    ThrottledBot, TrackedSubmission,
+};
+
+/// Redis sorted set of unassigned evacuation requests scored by submission unix timestamp.
+const UNASSIGNED_KEY: &str = "sla:unassigned";
+/// Redis hash of the notification texts of the watched requests keyed by reference id.
+const TEXTS_KEY: &str = "sla:texts";
+/// Redis hash with the number of re-posts already made keyed by reference id.
+const ESCALATION_LEVELS_KEY: &str = "sla:escalation_levels";
+/// Redis set of reference ids the coordinator has already been pinged about.
+const COORDINATOR_PINGED_KEY: &str = "sla:coordinator_pinged";
+
+#[derive(Debug, Clone)]
+pub struct SlaConfig {
+    pub repost_interval: chrono::Duration,
+    pub deadline: chrono::Duration,
+    pub coordinator_chat_id: Option<i64>,
+}
+
+impl SlaConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let minutes = |name: &str, default: i64| -> anyhow::Result<chrono::Duration> {
+            let minutes = match std::env::var(name) {
+                Ok(value) => value
+                    .parse()
+                    .with_context(|| format!("Invalid number of minutes in {name}"))?,
+                Err(_) => default,
+            };
+            Ok(chrono::Duration::minutes(minutes))
+        };
+        Ok(Self {
+            repost_interval: minutes("COLLECT_VOLUNTEERS_BOT_SLA_REPOST_INTERVAL_MINS", 30)?,
+            deadline: minutes("COLLECT_VOLUNTEERS_BOT_SLA_DEADLINE_MINS", 180)?,
+            coordinator_chat_id: std::env::var("COLLECT_VOLUNTEERS_BOT_COORDINATOR_CHAT_ID")
+                .ok()
+                .map(|value| value.parse())
+                .transpose()
+                .context("Invalid coordinator chat id")?,
+        })
+    }
+}
+
+/// Starts the SLA timer of a newly posted request; only evacuation requests are watched.
+pub async fn watch(
+    mut redis: redis::aio::MultiplexedConnection,
+    submission: &TrackedSubmission,
+    text: &str,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    if !submission.help_kind.is(HelpKind::NEED_EVACUATION) {
+        return Ok(());
+    }
+    let _: () = redis
+        .hset(
+            redis_connection::key(TEXTS_KEY),
+            &submission.reference_id,
+            text,
+        )
+        .await?;
+    let _: () = redis
+        .zadd(
+            redis_connection::key(UNASSIGNED_KEY),
+            &submission.reference_id,
+            chrono::Utc::now().timestamp(),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Stops the SLA timer once the request is assigned.
+pub async fn unwatch(
+    mut redis: redis::aio::MultiplexedConnection,
+    reference_id: &str,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let _: () = redis
+        .zrem(redis_connection::key(UNASSIGNED_KEY), reference_id)
+        .await?;
+    let _: () = redis
+        .hdel(redis_connection::key(TEXTS_KEY), reference_id)
+        .await?;
+    let _: () = redis
+        .hdel(redis_connection::key(ESCALATION_LEVELS_KEY), reference_id)
+        .await?;
+    let _: () = redis
+        .srem(redis_connection::key(COORDINATOR_PINGED_KEY), reference_id)
+        .await?;
+    Ok(())
+}
+
+pub async fn run(
+    bot: ThrottledBot,
+    app_state: std::sync::Arc<AppState>,
+    config: SlaConfig,
+    mut shutdown: Shutdown,
+) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.requested() => return,
+        }
+        if let Err(err) = escalate_overdue_requests(&bot, &app_state, &config).await {
+            tracing::warn!("Failed to escalate overdue requests: {:?}", err);
+        }
+    }
+}
+
+async fn escalate_overdue_requests(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    config: &SlaConfig,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let mut redis = app_state.redis.clone();
+    let now = chrono::Utc::now().timestamp();
+    let overdue: Vec<(String, i64)> = redis
+        .zrangebyscore_withscores(
+            redis_connection::key(UNASSIGNED_KEY),
+            "-inf",
+            now - config.repost_interval.num_seconds(),
+        )
+        .await?;
+
+    for (reference_id, submitted_at) in overdue {
+        let waiting = chrono::Duration::seconds(now - submitted_at);
+        let due_level = waiting.num_seconds() / config.repost_interval.num_seconds().max(1);
+        let level: Option<i64> = redis
+            .hget(redis_connection::key(ESCALATION_LEVELS_KEY), &reference_id)
+            .await?;
+        let level = level.unwrap_or(0);
+
+        if due_level > level {
+            let text: Option<String> = redis
+                .hget(redis_connection::key(TEXTS_KEY), &reference_id)
+                .await?;
+            let text = text.unwrap_or_else(|| format!("Заявка {reference_id}"));
+            let marker = "❗".repeat(due_level.min(5) as usize);
+            let repost_chat_id = app_state.volunteers_chat_id.or(app_state.admin_chat_id);
+            if let Some(repost_chat_id) = repost_chat_id {
+                let repost = format!(
+                    "{marker} Заявка чекає вже {} хв і досі не взята!\n\n{text}",
+                    waiting.num_minutes()
+                );
+                retry::on_flood(|| {
+                    bot.send_message(repost_chat_id, repost.clone())
+                        .reply_markup(assignment::assignment_keyboard(
+                            &app_state.texts(),
+                            texts::Language::default(),
+                            &reference_id,
+                        ))
+                })
+                .await?;
+            }
+            let _: () = redis
+                .hset(
+                    redis_connection::key(ESCALATION_LEVELS_KEY),
+                    &reference_id,
+                    due_level,
+                )
+                .await?;
+        }
+
+        if waiting >= config.deadline {
+            if let Some(coordinator_chat_id) = config
+                .coordinator_chat_id
+                .and_then(|chat_id| app_state.notified_chat(chat_id))
+            {
+                let newly_pinged: bool = redis
+                    .sadd(redis_connection::key(COORDINATOR_PINGED_KEY), &reference_id)
+                    .await?;
+                if newly_pinged {
+                    bot.send_message(
+                        coordinator_chat_id,
+                        format!(
+                            "Заявка на евакуацію {reference_id} не взята вже {} хв, хоча крайній термін {} хв. Потрібне втручання координатора.",
+                            waiting.num_minutes(),
+                            config.deadline.num_minutes()
+                        ),
+                    )
+                    .await?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
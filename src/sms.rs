@@ -0,0 +1,128 @@
+//! SMS to the requesters without reliable internet: the confirmation of the submission and the
+//! later status changes, sent to the first Ukrainian number of the phone numbers they gave.
+//!
+//! The provider is picked in the config, see [`crate::config::Config::sms`]; its secret, the auth
+//! token of Twilio or the API secret of Vonage, is taken from `COLLECT_VOLUNTEERS_BOT_SMS_SECRET`.
+//! Nothing is sent in the dev mode or the dry run. A failure is logged and does not fail the
+//! notification in Telegram.
+
+use crate::{html, texts::Language, AppState, Contact, TrackedSubmission};
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(tag = "provider", rename_all = "snake_case")]
+pub enum SmsProvider {
+    Twilio {
+        account_sid: String,
+        /// The Twilio number or the alphanumeric sender id.
+        from: String,
+    },
+    Vonage {
+        api_key: String,
+        from: String,
+    },
+}
+
+fn secret() -> anyhow::Result<String> {
+    std::env::var("COLLECT_VOLUNTEERS_BOT_SMS_SECRET")
+        .map_err(|_| anyhow::anyhow!("COLLECT_VOLUNTEERS_BOT_SMS_SECRET is not set"))
+}
+
+/// Confirms the saved submission to the phone number of the contact.
+pub async fn notify_submission(
+    app_state: &AppState,
+    language: Language,
+    contact: &Contact,
+    submission: &TrackedSubmission,
+) {
+    let phone_numbers = contact.phone_numbers.as_deref().unwrap_or_default();
+    let text = app_state.texts().render(
+        language,
+        "sms_submission_saved",
+        &[("reference_id", &submission.reference_id)],
+    );
+    send(app_state, phone_numbers, &text).await;
+}
+
+/// Tells the requester about the new status, at the phone number from the submission row.
+pub async fn notify_status(
+    app_state: &AppState,
+    language: Language,
+    submission: &TrackedSubmission,
+    status: &str,
+) {
+    if app_state.config().sms.is_none() {
+        return;
+    }
+    let phone_column = match app_state
+        .config()
+        .column(&submission.help_kind, "phone_numbers")
+    {
+        Some(phone_column) => phone_column,
+        None => return,
+    };
+    let phone_numbers = match submission.fetch_row(&app_state.sheets_api).await {
+        Ok(row) => row
+            .and_then(|row| row.into_iter().nth(phone_column))
+            .unwrap_or_default(),
+        Err(err) => {
+            tracing::warn!(
+                "Failed to read the phone numbers of {}: {:?}",
+                submission.reference_id,
+                err
+            );
+            return;
+        }
+    };
+    let text = app_state.texts().render(
+        language,
+        "sms_status_changed",
+        &[
+            ("reference_id", &submission.reference_id),
+            ("status", &status),
+        ],
+    );
+    send(app_state, &phone_numbers, &text).await;
+}
+
+async fn send(app_state: &AppState, phone_numbers: &str, text: &str) {
+    let config = app_state.config();
+    let provider = match &config.sms {
+        Some(provider) => provider,
+        None => return,
+    };
+    let to = match html::first_phone_number(phone_numbers) {
+        Some(to) => to,
+        None => return,
+    };
+    if app_state.skips_external_writes() {
+        tracing::info!("Not sending the SMS {:?}", text);
+        return;
+    }
+    if let Err(err) = try_send(provider, &to, text).await {
+        tracing::error!("Failed to send an SMS: {:?}", err);
+    }
+}
+
+async fn try_send(provider: &SmsProvider, to: &str, text: &str) -> anyhow::Result<()> {
+    let client = reqwest::Client::new();
+    let request = match provider {
+        SmsProvider::Twilio { account_sid, from } => client
+            .post(format!(
+                "https://api.twilio.com/2010-04-01/Accounts/{account_sid}/Messages.json"
+            ))
+            .basic_auth(account_sid, Some(secret()?))
+            .form(&[("To", to), ("From", from.as_str()), ("Body", text)]),
+        SmsProvider::Vonage { api_key, from } => {
+            client.post("https://rest.nexmo.com/sms/json").form(&[
+                ("api_key", api_key.as_str()),
+                ("api_secret", secret()?.as_str()),
+                ("to", to.trim_start_matches('+')),
+                ("from", from.as_str()),
+                ("text", text),
+                ("type", "unicode"),
+            ])
+        }
+    };
+    request.send().await?.error_for_status()?;
+    Ok(())
+}
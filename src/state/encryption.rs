@@ -0,0 +1,127 @@
+//! Encryption of the dialogue states at rest.
+//!
+//! The states hold the names, phone numbers and addresses of the people asking for help, so once
+//! `COLLECT_VOLUNTEERS_BOT_STATE_ENCRYPTION_KEY` is set (32 bytes in base64, e.g. the output of
+//! `openssl rand -base64 32`) they are written encrypted with XChaCha20-Poly1305. States written
+//! before the key was set are still read, and get encrypted the next time they change.
+//!
+//! The other personal data kept in Redis, e.g. the questionnaires waiting for a confirmation and
+//! the rows queued to be appended again, is [`seal`]ed with the same key.
+
+use chacha20poly1305::{
+    aead::{Aead, NewAead},
+    Key, XChaCha20Poly1305, XNonce,
+};
+
+/// Marks the encrypted states, followed by the nonce and the ciphertext.
+const MAGIC: &[u8] = b"enc1";
+const NONCE_LEN: usize = 24;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("the state is encrypted but no encryption key is configured")]
+    NoKey,
+    #[error("failed to decrypt the state")]
+    Decryption,
+}
+
+#[derive(Clone)]
+pub struct Cipher(XChaCha20Poly1305);
+
+impl Cipher {
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let key = match std::env::var("COLLECT_VOLUNTEERS_BOT_STATE_ENCRYPTION_KEY") {
+            Ok(key) => base64::decode(key.trim())?,
+            Err(_) => return Ok(None),
+        };
+        anyhow::ensure!(
+            key.len() == 32,
+            "The state encryption key must be 32 bytes long, not {}",
+            key.len()
+        );
+        Ok(Some(Self(XChaCha20Poly1305::new(Key::from_slice(&key)))))
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let ciphertext = self
+            .0
+            .encrypt(XNonce::from_slice(&nonce), plaintext)
+            .expect("XChaCha20-Poly1305 encryption does not fail for states of sane sizes");
+        [MAGIC, &nonce, &ciphertext].concat()
+    }
+}
+
+/// Serializes the value to JSON, encrypted if there is a cipher.
+pub fn seal<T: serde::Serialize>(cipher: Option<&Cipher>, value: &T) -> anyhow::Result<Vec<u8>> {
+    let data = serde_json::to_vec(value)?;
+    Ok(match cipher {
+        Some(cipher) => cipher.encrypt(&data),
+        None => data,
+    })
+}
+
+/// Reads the value [`seal`]ed with the cipher, or stored in plain JSON.
+pub fn open<T: serde::de::DeserializeOwned>(
+    cipher: Option<&Cipher>,
+    data: &[u8],
+) -> anyhow::Result<T> {
+    Ok(serde_json::from_slice(&decrypt(cipher, data)?)?)
+}
+
+/// Returns the plaintext of the stored state, which may not be encrypted.
+pub fn decrypt<'a>(
+    cipher: Option<&Cipher>,
+    data: &'a [u8],
+) -> Result<std::borrow::Cow<'a, [u8]>, Error> {
+    let encrypted = match data.strip_prefix(MAGIC) {
+        Some(encrypted) if encrypted.len() >= NONCE_LEN => encrypted,
+        _ => return Ok(data.into()),
+    };
+    let cipher = cipher.ok_or(Error::NoKey)?;
+    let (nonce, ciphertext) = encrypted.split_at(NONCE_LEN);
+    cipher
+        .0
+        .decrypt(XNonce::from_slice(nonce), ciphertext)
+        .map(Into::into)
+        .map_err(|_| Error::Decryption)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher(key: u8) -> Cipher {
+        Cipher(XChaCha20Poly1305::new(Key::from_slice(&[key; 32])))
+    }
+
+    #[test]
+    fn encrypted_states_decrypt_with_the_same_key_only() {
+        let encrypted = cipher(1).encrypt("Київ, вул. Хрещатик, 1".as_bytes());
+        assert!(encrypted.starts_with(MAGIC));
+        assert_eq!(
+            &*decrypt(Some(&cipher(1)), &encrypted).unwrap(),
+            "Київ, вул. Хрещатик, 1".as_bytes()
+        );
+        assert!(matches!(
+            decrypt(Some(&cipher(2)), &encrypted),
+            Err(Error::Decryption)
+        ));
+        assert!(matches!(decrypt(None, &encrypted), Err(Error::NoKey)));
+    }
+
+    #[test]
+    fn tampered_states_are_rejected() {
+        let mut encrypted = cipher(1).encrypt(b"{}");
+        *encrypted.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            decrypt(Some(&cipher(1)), &encrypted),
+            Err(Error::Decryption)
+        ));
+    }
+
+    #[test]
+    fn plain_states_are_read_as_they_are() {
+        assert_eq!(&*decrypt(Some(&cipher(1)), b"{}").unwrap(), b"{}");
+    }
+}
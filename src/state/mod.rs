@@ -0,0 +1,624 @@
+//! The dialogue state and the answers collected in it.
+
+use teloxide::{dispatching2::dialogue::Storage, macros::DialogueState, prelude2::*};
+
+use crate::{
+    attachments,
+    handlers::{contact_form, start},
+    html,
+    texts::{Language, Texts},
+    volunteers,
+};
+
+pub mod encryption;
+pub mod storage;
+pub mod version;
+
+pub use storage::DialogueStorage;
+
+pub type MyDialogue = Dialogue<State, DialogueStorage>;
+pub type StorageError = <DialogueStorage as Storage<State>>::Error;
+
+/// Slug of a kind of help defined in [`crate::config::Config::help_kinds`].
+///
+/// The built-in kinds keep the names they had as enum variants, so the submissions and dialogues
+/// already stored in Redis stay readable.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct HelpKind(String);
+
+impl HelpKind {
+    pub const PROVIDING_DRIVER: &'static str = "ProvidingDriver";
+    pub const PROVIDING_USEFUL_CONTACT: &'static str = "ProvidingUsefulContact";
+    pub const PROVIDING_COLLECTING_HUMANITARIAN_HELP: &'static str =
+        "ProvidingCollectingHumanitarianHelp";
+    pub const NEED_EVACUATION: &'static str = "NeedEvacuation";
+    pub const NEED_HUMANITARIAN_HELP: &'static str = "NeedHumanitarianHelp";
+
+    pub fn new(slug: &str) -> Self {
+        Self(slug.to_owned())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is(&self, slug: &str) -> bool {
+        self.0 == slug
+    }
+}
+
+/// The user's message with the answer to the last question, so that editing it corrects the
+/// answer.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct LastAnswer {
+    pub message_id: i32,
+    pub field: String,
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub enum Urgency {
+    Critical,
+    WithinDay,
+    NotUrgent,
+}
+
+impl Urgency {
+    pub const ALL: [Urgency; 3] = [Urgency::Critical, Urgency::WithinDay, Urgency::NotUrgent];
+
+    /// The label the answers and the spreadsheets have, see [`Urgency::label_in`] for the one shown
+    /// to the user.
+    pub fn label(self) -> &'static str {
+        match self {
+            Urgency::Critical => "критично",
+            Urgency::WithinDay => "протягом доби",
+            Urgency::NotUrgent => "не терміново",
+        }
+    }
+
+    pub fn from_label(label: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|urgency| urgency.label() == label.trim().to_lowercase())
+    }
+
+    /// The label in the language, see `urgency_*`.
+    pub fn label_in(self, texts: &Texts, language: Language) -> &str {
+        texts.get(
+            language,
+            match self {
+                Urgency::Critical => "urgency_critical",
+                Urgency::WithinDay => "urgency_within_day",
+                Urgency::NotUrgent => "urgency_not_urgent",
+            },
+        )
+    }
+}
+
+/// Category of the humanitarian help, each with its own question about the quantity.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HelpCategory {
+    Food,
+    Medicine,
+    Clothes,
+    Generator,
+}
+
+impl HelpCategory {
+    pub const ALL: [HelpCategory; 4] = [
+        HelpCategory::Food,
+        HelpCategory::Medicine,
+        HelpCategory::Clothes,
+        HelpCategory::Generator,
+    ];
+
+    /// The label the answers and the spreadsheets have, see [`HelpCategory::label_in`] for the
+    /// one shown to the user.
+    pub fn label(self) -> &'static str {
+        match self {
+            HelpCategory::Food => "їжа",
+            HelpCategory::Medicine => "ліки",
+            HelpCategory::Clothes => "одяг",
+            HelpCategory::Generator => "генератор",
+        }
+    }
+
+    pub fn from_label(label: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|category| category.label() == label.trim().to_lowercase())
+    }
+
+    /// The label in the language, see `help_category_*`.
+    pub fn label_in(self, texts: &Texts, language: Language) -> &str {
+        texts.get(
+            language,
+            match self {
+                HelpCategory::Food => "help_category_food",
+                HelpCategory::Medicine => "help_category_medicine",
+                HelpCategory::Clothes => "help_category_clothes",
+                HelpCategory::Generator => "help_category_generator",
+            },
+        )
+    }
+
+    /// Field of the question about the quantity of the category.
+    pub fn quantity_field(self) -> &'static str {
+        match self {
+            HelpCategory::Food => "food_quantity",
+            HelpCategory::Medicine => "medicine_quantity",
+            HelpCategory::Clothes => "clothes_quantity",
+            HelpCategory::Generator => "generator_quantity",
+        }
+    }
+
+    fn from_quantity_field(field: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|category| category.quantity_field() == field)
+    }
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct Contact {
+    pub full_name: Option<String>,
+    /// Oblast as spelled in [`crate::regions::OBLASTS`].
+    pub region: Option<String>,
+    pub city: Option<String>,
+    /// Answer to the single address question of the flows defined before the address was split
+    /// into the street, the building and the notes.
+    pub address: Option<String>,
+    #[serde(default)]
+    pub street: Option<String>,
+    /// Number of the building with the apartment.
+    #[serde(default)]
+    pub building: Option<String>,
+    /// How to find the place: the entrance, the floor, the landmarks.
+    #[serde(default)]
+    pub address_notes: Option<String>,
+    pub phone_numbers: Option<String>,
+    pub comments: Option<String>,
+    pub urgency: Option<Urgency>,
+    /// Number of people to evacuate.
+    pub passengers: Option<u32>,
+    pub children: Option<String>,
+    pub limited_mobility: Option<String>,
+    pub pets: Option<String>,
+    /// Language the requester prefers to be spoken to in, if not Ukrainian.
+    #[serde(default)]
+    pub preferred_language: Option<String>,
+    /// Wheelchair, impaired hearing or sight and the like, so that a suitable volunteer is sent.
+    #[serde(default)]
+    pub accessibility_needs: Option<String>,
+    /// Urgently needed medication or medical care, see [`crate::medical`].
+    #[serde(default)]
+    pub medical_needs: Option<String>,
+    /// Categories of the humanitarian help needed.
+    #[serde(default)]
+    pub help_categories: Option<Vec<HelpCategory>>,
+    /// How much of every needed category, keyed by the field of its question, e.g.
+    /// `food_quantity`.
+    #[serde(default)]
+    pub quantities: std::collections::BTreeMap<String, String>,
+    /// Names of the tags found in the answers when the contact is confirmed, see
+    /// [`crate::volunteers::classify`].
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub vehicle_type: Option<String>,
+    /// Number of free passenger seats in the driver's vehicle.
+    pub seats: Option<u32>,
+    pub fuel_range_km: Option<u32>,
+    pub cities_covered: Option<String>,
+    /// Answers to the questions added in the config which have no dedicated field.
+    #[serde(default)]
+    pub extra: std::collections::BTreeMap<String, String>,
+    #[serde(default)]
+    pub attachments: Vec<attachments::Attachment>,
+    /// When the user agreed to the privacy notice before filling in the form.
+    #[serde(default)]
+    pub consented_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Telegram account of the user, so that volunteers can write to them directly.
+    #[serde(default)]
+    pub telegram_user_id: Option<i64>,
+    #[serde(default)]
+    pub telegram_username: Option<String>,
+    /// Assigned when the submission is first confirmed, so that confirming it again after a
+    /// failure does not append a second row.
+    #[serde(default)]
+    pub submission_id: Option<uuid::Uuid>,
+    /// Start parameter of the link the user came with, e.g. the code of a poster.
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Variants of the prompts under experiment keyed by the field, see [`crate::experiments`].
+    #[serde(default)]
+    pub prompt_variants: std::collections::BTreeMap<String, String>,
+    /// Location shared as the answer to the address question.
+    #[serde(default)]
+    pub coordinates: Option<crate::geo::Coordinates>,
+}
+
+#[derive(DialogueState, Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[handler_out(anyhow::Result<()>)]
+// The states are kept in the storage between the updates; their size does not matter.
+#[allow(clippy::large_enum_variant)]
+pub enum State {
+    #[handler(start::handle_start)]
+    #[default]
+    Start,
+
+    #[handler(start::handle_awaiting_language)]
+    AwaitingLanguage,
+
+    #[handler(start::handle_awaiting_kind_of_help_providing)]
+    AwaitingKindOfHelpProviding,
+
+    #[handler(start::handle_awaitig_kind_of_help_wanted)]
+    AwaitingKindOfHelpWanted,
+
+    #[handler(start::handle_awaiting_consent)]
+    AwaitingConsent { help_kind: HelpKind },
+
+    #[handler(contact_form::handle_awaiting_contact_information)]
+    AwaitingContactInformation {
+        help_kind: HelpKind,
+        contact: Option<Contact>,
+        /// Message with the current question, edited in place when answered with a button.
+        #[serde(default)]
+        question_message_id: Option<i32>,
+        #[serde(default)]
+        last_answer: Option<LastAnswer>,
+        /// Transcription of a voice answer waiting for the user to accept it.
+        #[serde(default)]
+        pending_transcription: Option<String>,
+    },
+
+    #[handler(volunteers::handle_awaiting_volunteer_registration)]
+    AwaitingVolunteerRegistration {
+        registration: volunteers::VolunteerRegistration,
+    },
+}
+
+impl Contact {
+    pub fn has_answer(&self, field: &str) -> bool {
+        match field {
+            "full_name" => self.full_name.is_some(),
+            "phone_numbers" => self.phone_numbers.is_some(),
+            "region" => self.region.is_some(),
+            "city" => self.city.is_some(),
+            "address" => self.address.is_some(),
+            "street" => self.street.is_some(),
+            "building" => self.building.is_some(),
+            "address_notes" => self.address_notes.is_some(),
+            "comments" => self.comments.is_some(),
+            "urgency" => self.urgency.is_some(),
+            "passengers" => self.passengers.is_some(),
+            "children" => self.children.is_some(),
+            "limited_mobility" => self.limited_mobility.is_some(),
+            "pets" => self.pets.is_some(),
+            "preferred_language" => self.preferred_language.is_some(),
+            "accessibility_needs" => self.accessibility_needs.is_some(),
+            "medical_needs" => self.medical_needs.is_some(),
+            "help_categories" => self.help_categories.is_some(),
+            field if HelpCategory::from_quantity_field(field).is_some() => {
+                self.quantities.contains_key(field)
+            }
+            "vehicle_type" => self.vehicle_type.is_some(),
+            "seats" => self.seats.is_some(),
+            "fuel_range_km" => self.fuel_range_km.is_some(),
+            "cities_covered" => self.cities_covered.is_some(),
+            _ => self.extra.contains_key(field),
+        }
+    }
+
+    /// The answer to the question about the field, as it would be typed.
+    pub fn answer(&self, field: &str) -> Option<String> {
+        match field {
+            "full_name" => self.full_name.clone(),
+            "phone_numbers" => self.phone_numbers.clone(),
+            "region" => self.region.clone(),
+            "city" => self.city.clone(),
+            "address" => self.street_address(),
+            "street" => self.street.clone(),
+            "building" => self.building.clone(),
+            "address_notes" => self.address_notes.clone(),
+            "comments" => self.comments.clone(),
+            "urgency" => self.urgency.map(|urgency| urgency.label().to_owned()),
+            "passengers" => self.passengers.map(|passengers| passengers.to_string()),
+            "children" => self.children.clone(),
+            "limited_mobility" => self.limited_mobility.clone(),
+            "pets" => self.pets.clone(),
+            "preferred_language" => self.preferred_language.clone(),
+            "accessibility_needs" => self.accessibility_needs.clone(),
+            "medical_needs" => self.medical_needs.clone(),
+            "help_categories" => self.help_categories.as_ref().map(|categories| {
+                categories
+                    .iter()
+                    .map(|category| category.label())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            }),
+            field if HelpCategory::from_quantity_field(field).is_some() => {
+                self.quantities.get(field).cloned()
+            }
+            "vehicle_type" => self.vehicle_type.clone(),
+            "seats" => self.seats.map(|seats| seats.to_string()),
+            "fuel_range_km" => self
+                .fuel_range_km
+                .map(|fuel_range_km| fuel_range_km.to_string()),
+            "cities_covered" => self.cities_covered.clone(),
+            _ => self.extra.get(field).cloned(),
+        }
+    }
+
+    /// Stores the (already validated) answer to the question about the field.
+    pub fn set_answer(&mut self, field: &str, value: String) -> anyhow::Result<()> {
+        match field {
+            "full_name" => self.full_name = Some(value),
+            "phone_numbers" => self.phone_numbers = Some(value),
+            "region" => self.region = Some(value),
+            "city" => self.city = Some(value),
+            "address" => self.address = Some(value),
+            "street" => self.street = Some(value),
+            "building" => self.building = Some(value),
+            "address_notes" => self.address_notes = Some(value),
+            "comments" => self.comments = Some(value),
+            "urgency" => {
+                self.urgency = Some(
+                    Urgency::from_label(&value)
+                        .ok_or_else(|| anyhow::anyhow!("unknown urgency {:?}", value))?,
+                )
+            }
+            "passengers" => self.passengers = Some(value.parse()?),
+            "children" => self.children = Some(value),
+            "limited_mobility" => self.limited_mobility = Some(value),
+            "pets" => self.pets = Some(value),
+            "preferred_language" => self.preferred_language = Some(value),
+            "accessibility_needs" => self.accessibility_needs = Some(value),
+            "medical_needs" => self.medical_needs = Some(value),
+            "help_categories" => {
+                self.help_categories = Some(
+                    value
+                        .split(',')
+                        .map(|label| {
+                            HelpCategory::from_label(label)
+                                .ok_or_else(|| anyhow::anyhow!("unknown help category {:?}", label))
+                        })
+                        .collect::<anyhow::Result<_>>()?,
+                )
+            }
+            field if HelpCategory::from_quantity_field(field).is_some() => {
+                self.quantities.insert(field.to_owned(), value);
+            }
+            "vehicle_type" => self.vehicle_type = Some(value),
+            "seats" => self.seats = Some(value.parse()?),
+            "fuel_range_km" => self.fuel_range_km = Some(value.parse()?),
+            "cities_covered" => self.cities_covered = Some(value),
+            _ => {
+                self.extra.insert(field.to_owned(), value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the answer to the question about the field, e.g. to ask it again, together with the
+    /// answers which depend on it: the settlement of the oblast and the quantities of the help
+    /// categories.
+    pub fn clear_answer(&mut self, field: &str) {
+        match field {
+            "full_name" => self.full_name = None,
+            "phone_numbers" => self.phone_numbers = None,
+            "region" => {
+                self.region = None;
+                self.city = None;
+            }
+            "city" => self.city = None,
+            // The shared location answers these questions.
+            "address" | "street" => {
+                if field == "address" {
+                    self.address = None;
+                } else {
+                    self.street = None;
+                }
+                self.coordinates = None;
+            }
+            "building" => self.building = None,
+            "address_notes" => self.address_notes = None,
+            "comments" => self.comments = None,
+            "urgency" => self.urgency = None,
+            "passengers" => self.passengers = None,
+            "children" => self.children = None,
+            "limited_mobility" => self.limited_mobility = None,
+            "pets" => self.pets = None,
+            "preferred_language" => self.preferred_language = None,
+            "accessibility_needs" => self.accessibility_needs = None,
+            "medical_needs" => self.medical_needs = None,
+            "help_categories" => {
+                self.help_categories = None;
+                self.quantities.clear();
+            }
+            "vehicle_type" => self.vehicle_type = None,
+            "seats" => self.seats = None,
+            "fuel_range_km" => self.fuel_range_km = None,
+            "cities_covered" => self.cities_covered = None,
+            field if HelpCategory::from_quantity_field(field).is_some() => {
+                self.quantities.remove(field);
+            }
+            _ => {
+                self.extra.remove(field);
+            }
+        }
+    }
+
+    /// Takes the name and the phone number from a Telegram contact card unless they are answered.
+    pub fn fill_from_card(&mut self, card: &teloxide::types::Contact) {
+        if self.full_name.is_none() {
+            self.full_name = Some(
+                [Some(&card.first_name), card.last_name.as_ref()]
+                    .into_iter()
+                    .flatten()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            );
+        }
+        if self.phone_numbers.is_none() {
+            // Telegram sends the numbers of the contacts without the leading plus.
+            self.phone_numbers = Some(if card.phone_number.chars().all(|c| c.is_ascii_digit()) {
+                format!("+{}", card.phone_number)
+            } else {
+                card.phone_number.clone()
+            });
+        }
+    }
+
+    /// Link to the location on the map: the shared coordinates, or the address searched for.
+    pub fn map_url(&self) -> Option<String> {
+        let query = match self.coordinates {
+            Some(coordinates) => format!("{},{}", coordinates.latitude, coordinates.longitude),
+            None if self.street_address().is_some() => self.full_address(),
+            None => return None,
+        };
+        reqwest::Url::parse_with_params(
+            "https://www.google.com/maps/search/",
+            &[("api", "1"), ("query", query.as_str())],
+        )
+        .ok()
+        .map(String::from)
+    }
+
+    /// Street, building and apartment: the answer to the single address question, or the street
+    /// and the building joined.
+    pub fn street_address(&self) -> Option<String> {
+        if self.address.is_some() {
+            return self.address.clone();
+        }
+        let parts: Vec<&str> = [&self.street, &self.building]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .filter(|part| *part != "-")
+            .collect();
+        (!parts.is_empty()).then(|| parts.join(", "))
+    }
+
+    /// Settlement, oblast and address joined into a single line.
+    pub fn full_address(&self) -> String {
+        [
+            self.city.clone(),
+            self.region.clone(),
+            self.street_address(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(", ")
+    }
+
+    /// Labelled answers in the order they are shown to people; optional ones only if given. The
+    /// labels and the options are in the language, the answers as they were given.
+    pub fn summary_fields(&self, texts: &Texts, language: Language) -> Vec<(String, String)> {
+        let label = |field: &str| texts.get(language, &format!("summary_{field}")).to_owned();
+        let mut fields = vec![
+            ("full_name", self.full_name.clone()),
+            ("phone_numbers", self.phone_numbers.clone()),
+            ("region", self.region.clone()),
+            ("city", self.city.clone()),
+            ("address", self.street_address()),
+        ]
+        .into_iter()
+        .map(|(field, value)| (label(field), value.unwrap_or_else(|| "-".to_owned())))
+        .collect::<Vec<_>>();
+        let optional = [
+            ("address_notes", self.address_notes.clone()),
+            (
+                "urgency",
+                self.urgency
+                    .map(|urgency| urgency.label_in(texts, language).to_owned()),
+            ),
+            (
+                "passengers",
+                self.passengers.map(|passengers| passengers.to_string()),
+            ),
+            ("children", self.children.clone()),
+            ("limited_mobility", self.limited_mobility.clone()),
+            ("pets", self.pets.clone()),
+            ("preferred_language", self.preferred_language.clone()),
+            ("accessibility_needs", self.accessibility_needs.clone()),
+            ("medical_needs", self.medical_needs.clone()),
+            (
+                "help_categories",
+                self.help_categories.as_ref().map(|categories| {
+                    categories
+                        .iter()
+                        .map(|category| category.label_in(texts, language))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                }),
+            ),
+            ("vehicle_type", self.vehicle_type.clone()),
+            ("seats", self.seats.map(|seats| seats.to_string())),
+            (
+                "fuel_range",
+                self.fuel_range_km.map(|fuel_range_km| {
+                    texts.render(language, "summary_fuel_range_km", &[("km", &fuel_range_km)])
+                }),
+            ),
+            ("cities_covered", self.cities_covered.clone()),
+            (
+                "attachments",
+                Some(self.attachments.len())
+                    .filter(|count| *count > 0)
+                    .map(|count| count.to_string()),
+            ),
+        ];
+        fields.extend(
+            optional
+                .into_iter()
+                .filter_map(|(field, value)| Some((label(field), value?))),
+        );
+        fields.extend(HelpCategory::ALL.into_iter().filter_map(|category| {
+            let quantity = self.quantities.get(category.quantity_field())?;
+            Some((
+                texts.render(
+                    language,
+                    "summary_quantity",
+                    &[("category", &category.label_in(texts, language))],
+                ),
+                quantity.clone(),
+            ))
+        }));
+        fields.extend(self.extra.clone());
+        fields.push((
+            label("comments"),
+            self.comments.clone().unwrap_or_else(|| "-".to_owned()),
+        ));
+        fields
+    }
+
+    /// Multiline description of the contact; optional answers are listed only if they were asked.
+    pub fn summary(&self, texts: &Texts, language: Language) -> String {
+        self.summary_fields(texts, language)
+            .into_iter()
+            .map(|(label, value)| format!("{label}: {value}"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// [`Contact::summary`] for HTML messages: bold labels and phone numbers Telegram can dial.
+    pub fn summary_html(&self, texts: &Texts, language: Language) -> String {
+        let phone_numbers = texts.get(language, "summary_phone_numbers");
+        self.summary_fields(texts, language)
+            .into_iter()
+            .map(|(label, value)| {
+                let value = if label == phone_numbers {
+                    html::phone_numbers(&value)
+                } else {
+                    html::escape(&value)
+                };
+                format!("{} {value}", html::bold(&format!("{label}:")))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
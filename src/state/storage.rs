@@ -0,0 +1,207 @@
+//! Storage of the dialogue states.
+//!
+//! The states are kept in Redis, under the keys prefixed like all the others (see
+//! [`redis_connection::key`]), unless the bot is built with the `sqlite` feature and
+//! `COLLECT_VOLUNTEERS_BOT_SQLITE_PATH` points to a database file. Either way they are serialized
+//! with [`Versioned`]. Only the dialogue states move to SQLite; the languages, submissions and the
+//! rest of the bookkeeping stay in Redis. In the dev mode the states are kept in memory and are
+//! lost on restart.
+//!
+//! The states in Redis expire `COLLECT_VOLUNTEERS_BOT_DIALOGUE_TTL_DAYS` (30 by default) after their
+//! last update, which should be longer than the forms take to expire, see [`crate::reminders`].
+
+use std::sync::Arc;
+
+use anyhow::Context;
+use futures::{future::BoxFuture, FutureExt, TryFutureExt};
+use teloxide::dispatching2::dialogue::{
+    serializer::Serializer, InMemStorage, InMemStorageError, Storage,
+};
+#[cfg(feature = "sqlite")]
+use teloxide::dispatching2::dialogue::{SqliteStorage, SqliteStorageError};
+use thiserror::Error;
+
+use super::{
+    version::{self, Versioned},
+    State,
+};
+use crate::redis_connection;
+
+pub enum DialogueStorage {
+    InMem(Arc<InMemStorage<State>>),
+    Redis(RedisDialogues),
+    #[cfg(feature = "sqlite")]
+    Sqlite(Arc<SqliteStorage<Versioned>>),
+}
+
+#[derive(Debug, Error)]
+pub enum DialogueStorageError {
+    #[error("in-memory storage error: {0}")]
+    InMem(#[from] InMemStorageError),
+    #[error("Redis storage error: {0}")]
+    Redis(#[from] redis::RedisError),
+    #[error("dialogue state serialization error: {0}")]
+    Serialization(#[from] version::Error),
+    #[cfg(feature = "sqlite")]
+    #[error("SQLite storage error: {0}")]
+    Sqlite(#[from] SqliteStorageError<version::Error>),
+}
+
+impl DialogueStorageError {
+    /// Whether the state is stored but cannot be read, as opposed to the storage being unavailable.
+    pub fn is_unreadable(&self) -> bool {
+        match self {
+            Self::Serialization(_) => true,
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(SqliteStorageError::SerdeError(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+impl DialogueStorage {
+    /// Opens the storage configured in the environment.
+    pub async fn from_env(
+        redis: redis::aio::MultiplexedConnection,
+        dev_mode: bool,
+    ) -> anyhow::Result<Arc<Self>> {
+        if dev_mode {
+            return Ok(Arc::new(Self::InMem(InMemStorage::new())));
+        }
+        let serializer = Versioned::from_env()?;
+        #[cfg(feature = "sqlite")]
+        if let Ok(path) = std::env::var("COLLECT_VOLUNTEERS_BOT_SQLITE_PATH") {
+            let storage = SqliteStorage::open(&path, serializer)
+                .await
+                .map_err(|err| anyhow::anyhow!("Failed to open {}: {:?}", path, err))?;
+            return Ok(Arc::new(Self::Sqlite(storage)));
+        }
+        let ttl_days: usize = match std::env::var("COLLECT_VOLUNTEERS_BOT_DIALOGUE_TTL_DAYS") {
+            Ok(value) => value.parse().context("Invalid dialogue TTL")?,
+            Err(_) => 30,
+        };
+        Ok(Arc::new(Self::Redis(RedisDialogues {
+            redis,
+            serializer,
+            ttl_secs: ttl_days * 24 * 60 * 60,
+        })))
+    }
+
+    /// The chats which have a stored state. The states kept in memory are not listed, they do
+    /// not outlive the process anyway.
+    pub async fn chat_ids(&self) -> anyhow::Result<Vec<i64>> {
+        match self {
+            Self::InMem(_) => Ok(Vec::new()),
+            Self::Redis(storage) => Ok(storage.chat_ids().await?),
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(_) => anyhow::bail!("The dialogue states in SQLite cannot be listed"),
+        }
+    }
+}
+
+/// The dialogue states in Redis, each under the prefixed chat id, which without a prefix is the
+/// same key teloxide's `RedisStorage` used.
+#[derive(Clone)]
+pub struct RedisDialogues {
+    redis: redis::aio::MultiplexedConnection,
+    serializer: Versioned,
+    ttl_secs: usize,
+}
+
+impl RedisDialogues {
+    fn key(chat_id: i64) -> String {
+        redis_connection::key(&chat_id.to_string())
+    }
+
+    /// Scans the keys instead of listing them with `KEYS`, which would block Redis.
+    async fn chat_ids(&self) -> Result<Vec<i64>, DialogueStorageError> {
+        use redis::AsyncCommands;
+
+        let prefix = redis_connection::key("");
+        let mut redis = self.redis.clone();
+        let mut keys: redis::AsyncIter<String> = redis.scan_match(format!("{prefix}*")).await?;
+        let mut chat_ids = Vec::new();
+        while let Some(key) = keys.next_item().await {
+            // The other keys have a name after the prefix, e.g. `submissions:42`.
+            if let Some(chat_id) = key.strip_prefix(&prefix).and_then(|id| id.parse().ok()) {
+                chat_ids.push(chat_id);
+            }
+        }
+        Ok(chat_ids)
+    }
+
+    async fn remove(mut self, chat_id: i64) -> Result<(), DialogueStorageError> {
+        use redis::AsyncCommands;
+
+        self.redis.del::<_, ()>(Self::key(chat_id)).await?;
+        Ok(())
+    }
+
+    async fn update(mut self, chat_id: i64, state: State) -> Result<(), DialogueStorageError> {
+        use redis::AsyncCommands;
+
+        let data = self.serializer.serialize(&state)?;
+        self.redis
+            .set_ex::<_, _, ()>(Self::key(chat_id), data, self.ttl_secs)
+            .await?;
+        Ok(())
+    }
+
+    async fn get(mut self, chat_id: i64) -> Result<Option<State>, DialogueStorageError> {
+        use redis::AsyncCommands;
+
+        let data: Option<Vec<u8>> = self.redis.get(Self::key(chat_id)).await?;
+        Ok(data
+            .map(|data| self.serializer.deserialize(&data))
+            .transpose()?)
+    }
+}
+
+impl Storage<State> for DialogueStorage {
+    type Error = DialogueStorageError;
+
+    fn remove_dialogue(
+        self: Arc<Self>,
+        chat_id: i64,
+    ) -> BoxFuture<'static, Result<(), Self::Error>> {
+        match &*self {
+            Self::InMem(storage) => storage.clone().remove_dialogue(chat_id).err_into().boxed(),
+            Self::Redis(storage) => storage.clone().remove(chat_id).boxed(),
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(storage) => storage.clone().remove_dialogue(chat_id).err_into().boxed(),
+        }
+    }
+
+    fn update_dialogue(
+        self: Arc<Self>,
+        chat_id: i64,
+        dialogue: State,
+    ) -> BoxFuture<'static, Result<(), Self::Error>> {
+        match &*self {
+            Self::InMem(storage) => storage
+                .clone()
+                .update_dialogue(chat_id, dialogue)
+                .err_into()
+                .boxed(),
+            Self::Redis(storage) => storage.clone().update(chat_id, dialogue).boxed(),
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(storage) => storage
+                .clone()
+                .update_dialogue(chat_id, dialogue)
+                .err_into()
+                .boxed(),
+        }
+    }
+
+    fn get_dialogue(
+        self: Arc<Self>,
+        chat_id: i64,
+    ) -> BoxFuture<'static, Result<Option<State>, Self::Error>> {
+        match &*self {
+            Self::InMem(storage) => storage.clone().get_dialogue(chat_id).err_into().boxed(),
+            Self::Redis(storage) => storage.clone().get(chat_id).boxed(),
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(storage) => storage.clone().get_dialogue(chat_id).err_into().boxed(),
+        }
+    }
+}
@@ -0,0 +1,237 @@
+//! Versioned serialization of the dialogue state kept in Redis.
+//!
+//! Every state is stored as `{"version": N, "state": ...}`. When [`crate::State`] changes in a way
+//! serde defaults cannot cover, bump the schema by appending a function to [`MIGRATIONS`] which
+//! upgrades the JSON of the previous version. States stored before versioning was introduced are
+//! plain JSON of the state and count as version 0.
+//!
+//! JSON is used unless `COLLECT_VOLUNTEERS_BOT_STATE_FORMAT` selects `cbor` or `bincode`, which
+//! take less memory and need the features of the same names. CBOR states are migrated like JSON
+//! ones. Bincode is not self-describing, so a Bincode state of an older version cannot be migrated
+//! and is reset instead. States written in JSON before the format was switched are still read.
+//!
+//! The serialized state is encrypted when a key is configured, see [`super::encryption`].
+//!
+//! A state which still cannot be read, e.g. because the entry got corrupted, would fail every
+//! update of the chat, so [`state_is_readable`] removes it and starts the conversation over.
+
+use serde_json::{json, Value};
+use teloxide::{
+    dispatching2::dialogue::{serializer::Serializer, Storage},
+    payloads::SendMessageSetters,
+    prelude2::*,
+};
+
+use super::{encryption, DialogueStorage, State};
+use crate::{chat_lock, handlers::keyboards::start_keyboard, texts, AppState, ThrottledBot};
+
+/// Upgrades of the stored state; the function at index `i` turns version `i` into version `i + 1`.
+const MIGRATIONS: &[fn(Value) -> Value] = &[
+    // Version 1 only introduced the version tag.
+    |state| state,
+];
+
+const CURRENT_VERSION: u64 = MIGRATIONS.len() as u64;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Format {
+    Json,
+    #[cfg(feature = "cbor")]
+    Cbor,
+    #[cfg(feature = "bincode")]
+    Bincode,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("encryption error: {0}")]
+    Encryption(#[from] encryption::Error),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[cfg(feature = "cbor")]
+    #[error("CBOR error: {0}")]
+    Cbor(#[from] serde_cbor::Error),
+    #[cfg(feature = "bincode")]
+    #[error("Bincode error: {0}")]
+    Bincode(#[from] bincode::Error),
+    #[cfg(feature = "bincode")]
+    #[error("Bincode state of version {0} cannot be migrated")]
+    Outdated(u64),
+}
+
+/// Serializer of [`State`] which tags it with the schema version and migrates old states.
+#[derive(Clone)]
+pub struct Versioned {
+    format: Format,
+    cipher: Option<encryption::Cipher>,
+}
+
+impl Versioned {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let format = match std::env::var("COLLECT_VOLUNTEERS_BOT_STATE_FORMAT")
+            .as_deref()
+            .unwrap_or("json")
+        {
+            "json" => Format::Json,
+            #[cfg(feature = "cbor")]
+            "cbor" => Format::Cbor,
+            #[cfg(feature = "bincode")]
+            "bincode" => Format::Bincode,
+            format => anyhow::bail!(
+                "Unsupported dialogue state format {:?}; is the bot built with its feature?",
+                format
+            ),
+        };
+        Ok(Self {
+            format,
+            cipher: encryption::Cipher::from_env()?,
+        })
+    }
+}
+
+impl Serializer<State> for Versioned {
+    type Error = Error;
+
+    fn serialize(&self, state: &State) -> Result<Vec<u8>, Self::Error> {
+        let envelope = json!({
+            "version": CURRENT_VERSION,
+            "state": state,
+        });
+        let data = match self.format {
+            Format::Json => serde_json::to_vec(&envelope)?,
+            #[cfg(feature = "cbor")]
+            Format::Cbor => serde_cbor::to_vec(&envelope)?,
+            #[cfg(feature = "bincode")]
+            Format::Bincode => bincode::serialize(&(CURRENT_VERSION, state))?,
+        };
+        Ok(match &self.cipher {
+            Some(cipher) => cipher.encrypt(&data),
+            None => data,
+        })
+    }
+
+    fn deserialize(&self, data: &[u8]) -> Result<State, Self::Error> {
+        let data = encryption::decrypt(self.cipher.as_ref(), data)?;
+        let data = &*data;
+        let from_json = || -> Result<State, Error> { Ok(migrate(serde_json::from_slice(data)?)?) };
+        match self.format {
+            Format::Json => from_json(),
+            // The state may have been written in JSON before the format was switched.
+            #[cfg(feature = "cbor")]
+            Format::Cbor => serde_cbor::from_slice(data)
+                .map_err(Error::from)
+                .and_then(|stored| Ok(migrate(stored)?))
+                .or_else(|err| from_json().map_err(|_| err)),
+            #[cfg(feature = "bincode")]
+            Format::Bincode => bincode::deserialize::<(u64, State)>(data)
+                .map_err(Error::from)
+                .and_then(|(version, state)| match version {
+                    CURRENT_VERSION => Ok(state),
+                    version => Err(Error::Outdated(version)),
+                })
+                .or_else(|err| from_json().map_err(|_| err)),
+        }
+    }
+}
+
+/// Upgrades the stored envelope, or a state stored before versioning, to the current [`State`].
+fn migrate(stored: Value) -> Result<State, serde_json::Error> {
+    let (version, mut state) = match stored {
+        Value::Object(mut envelope)
+            if envelope.contains_key("state")
+                && envelope.get("version").is_some_and(Value::is_u64) =>
+        {
+            (
+                envelope["version"].as_u64().unwrap(),
+                envelope.remove("state").unwrap(),
+            )
+        }
+        state => (0, state),
+    };
+    if version > CURRENT_VERSION {
+        // Written by a newer release running side by side during a deploy; it is read as is in the
+        // hope that the change is compatible.
+        tracing::warn!(
+            "Dialogue state of version {} is newer than {}",
+            version,
+            CURRENT_VERSION
+        );
+    }
+    for migration in MIGRATIONS.iter().skip(version as usize) {
+        state = migration(state);
+    }
+    serde_json::from_value(state)
+}
+
+/// Checks that the dialogue state of the chat of the update can be read. An unreadable state is
+/// removed, so the user is back at [`State::Start`], and the user gets an apology; the update itself
+/// is not handled then.
+pub async fn state_is_readable(
+    bot: ThrottledBot,
+    update: Update,
+    storage: std::sync::Arc<DialogueStorage>,
+    app_state: std::sync::Arc<AppState>,
+) -> bool {
+    let chat_id = match chat_lock::chat_id(&update) {
+        Some(chat_id) => chat_id,
+        None => return true,
+    };
+    let err = match storage.clone().get_dialogue(chat_id).await {
+        Err(err) if err.is_unreadable() => err,
+        // The storage being unavailable is not a reason to forget the state.
+        _ => return true,
+    };
+    tracing::error!(
+        chat_id,
+        "Failed to read the dialogue state, resetting it: {}",
+        err
+    );
+    if let Err(err) = storage.remove_dialogue(chat_id).await {
+        tracing::error!(chat_id, "Failed to remove the dialogue state: {}", err);
+    }
+    let language = texts::language_of(app_state.redis.clone(), chat_id).await;
+    let texts = app_state.texts();
+    if let Err(err) = bot
+        .send_message(chat_id, texts.get(language, "state_reset"))
+        .reply_markup(start_keyboard(&texts, language))
+        .await
+    {
+        tracing::warn!(chat_id, "Failed to apologize for the reset: {:?}", err);
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn states_stored_before_versioning_are_migrated() {
+        let stored = serde_json::to_value(State::AwaitingLanguage).unwrap();
+        assert!(matches!(migrate(stored).unwrap(), State::AwaitingLanguage));
+    }
+
+    #[test]
+    fn versioned_states_round_trip() {
+        let serializer = Versioned {
+            format: Format::Json,
+            cipher: None,
+        };
+        let data = serializer.serialize(&State::AwaitingLanguage).unwrap();
+        let envelope: Value = serde_json::from_slice(&data).unwrap();
+        assert_eq!(envelope["version"], CURRENT_VERSION);
+        assert!(matches!(
+            serializer.deserialize(&data).unwrap(),
+            State::AwaitingLanguage
+        ));
+    }
+
+    #[test]
+    fn states_of_newer_versions_are_read_as_they_are() {
+        let stored = json!({
+            "version": CURRENT_VERSION + 1,
+            "state": State::AwaitingLanguage,
+        });
+        assert!(matches!(migrate(stored).unwrap(), State::AwaitingLanguage));
+    }
+}
@@ -0,0 +1,96 @@
+//! Summaries of the new submissions posted to Slack or Discord, for the volunteer teams who
+//! coordinate outside Telegram.
+//!
+//! The incoming webhook URLs are configured per help kind, see
+//! [`crate::config::HelpKindDefinition::team_chats`].
+
+use crate::{
+    config::HelpKindDefinition, outgoing_webhooks, texts::Language, AppState, Contact,
+    TrackedSubmission,
+};
+
+/// Longest message Discord accepts.
+const DISCORD_MAX_CHARS: usize = 2000;
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(tag = "service", rename_all = "snake_case")]
+pub enum TeamChat {
+    /// Slack incoming webhook, `https://hooks.slack.com/services/...`.
+    Slack { url: String },
+    /// Discord channel webhook, `https://discord.com/api/webhooks/...`.
+    Discord { url: String },
+}
+
+/// Posts the summary of the new submission to the team chats of its help kind, in the background.
+pub fn post_submission(
+    app_state: &AppState,
+    definition: &HelpKindDefinition,
+    contact: &Contact,
+    submission: &TrackedSubmission,
+) {
+    if definition.team_chats.is_empty() {
+        return;
+    }
+    if app_state.skips_external_writes() {
+        tracing::info!("Not posting {} to the team chats", submission.reference_id);
+        return;
+    }
+    let text = format!(
+        "Нова заявка {} ({})\n\n{}",
+        submission.reference_id,
+        definition.title(),
+        contact.summary(&app_state.texts(), Language::default())
+    );
+    for team_chat in &definition.team_chats {
+        let (url, body) = message(team_chat, &text);
+        app_state.tasks.spawn(outgoing_webhooks::deliver(
+            url.to_owned(),
+            body.to_string().into_bytes(),
+            None,
+        ));
+    }
+}
+
+/// The webhook URL and the body of the message with the text. The answers go as they are, so they
+/// must not turn into mentions or links.
+fn message<'a>(team_chat: &'a TeamChat, text: &str) -> (&'a str, serde_json::Value) {
+    match team_chat {
+        // Slack's mrkdwn only needs the control characters escaped.
+        TeamChat::Slack { url } => (
+            url,
+            serde_json::json!({
+                "text": text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;"),
+            }),
+        ),
+        TeamChat::Discord { url } => (
+            url,
+            serde_json::json!({
+                "content": text.chars().take(DISCORD_MAX_CHARS).collect::<String>(),
+                "allowed_mentions": { "parse": [] },
+            }),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_do_not_mention_or_link() {
+        let text = "@everyone <!channel> <https://example.com|тут> & більше";
+        let slack = TeamChat::Slack {
+            url: "https://hooks.slack.com/services/1".to_owned(),
+        };
+        assert_eq!(
+            message(&slack, text).1["text"],
+            "@everyone &lt;!channel&gt; &lt;https://example.com|тут&gt; &amp; більше"
+        );
+        let discord = TeamChat::Discord {
+            url: "https://discord.com/api/webhooks/1".to_owned(),
+        };
+        let (_, body) = message(&discord, text);
+        assert_eq!(body["content"], text);
+        assert_eq!(body["allowed_mentions"], serde_json::json!({ "parse": [] }));
+    }
+}
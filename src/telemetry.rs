@@ -0,0 +1,50 @@
+//! Logs and traces.
+//!
+//! Events are printed to stderr filtered by `RUST_LOG`, as before, or as JSON lines when
+//! `COLLECT_VOLUNTEERS_BOT_LOG_FORMAT` is `json`. Personal data is kept out of them with
+//! [`crate::redact`]. Handling of an update is traced
+//! with spans from its handler down to the Sheets requests. When
+//! `COLLECT_VOLUNTEERS_BOT_OTLP_ENDPOINT` is set, e.g. to `http://localhost:4317`, the spans are
+//! also exported to that OpenTelemetry collector over gRPC.
+
+use opentelemetry::{sdk, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+pub fn init() {
+    let json =
+        std::env::var("COLLECT_VOLUNTEERS_BOT_LOG_FORMAT").is_ok_and(|format| format == "json");
+    let registry = tracing_subscriber::registry()
+        .with(EnvFilter::from_default_env())
+        .with((!json).then(|| tracing_subscriber::fmt::layer().with_writer(std::io::stderr)))
+        .with(json.then(|| {
+            tracing_subscriber::fmt::layer()
+                .json()
+                .with_writer(std::io::stderr)
+        }));
+    match std::env::var("COLLECT_VOLUNTEERS_BOT_OTLP_ENDPOINT") {
+        Ok(endpoint) => {
+            let tracer = opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint),
+                )
+                .with_trace_config(sdk::trace::config().with_resource(sdk::Resource::new(vec![
+                    KeyValue::new("service.name", env!("CARGO_PKG_NAME")),
+                ])))
+                .install_batch(opentelemetry::runtime::Tokio)
+                .expect("Failed to set up the OTLP exporter");
+            registry
+                .with(tracing_opentelemetry::layer().with_tracer(tracer))
+                .init();
+        }
+        Err(_) => registry.init(),
+    }
+}
+
+/// Exports the spans which have not been exported yet.
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
@@ -0,0 +1,214 @@
+//! Harness driving the handlers with synthetic updates, against a fake Telegram Bot API and a fake
+//! Redis, so that tests can assert on the messages and keyboards the bot sends.
+//!
+//! The updates go through the whole handler tree of [`handlers::schema`], the same way the
+//! dispatcher runs them, with the app state of the dev mode: the dialogue states are kept in
+//! memory and the submissions are only logged, unless [`Harness::with_redis`] is given a real
+//! Redis and a [`FakeSink`] to assert the appended rows on.
+
+use std::{
+    ops::ControlFlow,
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Arc,
+    },
+};
+
+use google_sheets4::{oauth2, Sheets};
+use serde_json::json;
+use teloxide::{dispatching2::dialogue::Storage, prelude2::*, types::Me};
+
+use crate::{
+    chat_lock::ChatLocks, config, handlers, sheets::SubmissionSink, texts, AppState,
+    DialogueStorage, State, ThrottledBot,
+};
+
+pub mod redis;
+pub mod sink;
+pub mod telegram;
+
+pub use self::{
+    redis::FakeRedis,
+    sink::{AppendedRow, FakeSink},
+    telegram::{FakeTelegram, Request, SentMessage},
+};
+
+/// The bot with its fakes, handling the updates of one test.
+pub struct Harness {
+    pub telegram: FakeTelegram,
+    pub app_state: Arc<AppState>,
+    pub storage: Arc<DialogueStorage>,
+    bot: ThrottledBot,
+    me: Me,
+    chat_locks: Arc<ChatLocks>,
+    last_update_id: AtomicI32,
+    /// Kept alive for as long as the bot talks to it.
+    _fake_redis: Option<FakeRedis>,
+}
+
+impl Harness {
+    /// The bot with the built-in config.
+    pub async fn new() -> anyhow::Result<Self> {
+        Self::with_config(config::Config::default()).await
+    }
+
+    pub async fn with_config(config: config::Config) -> anyhow::Result<Self> {
+        Self::with_sink(config, Arc::new(crate::sheets::sink::LogSink)).await
+    }
+
+    /// The bot appending the submission rows to `submission_sink`.
+    pub async fn with_sink(
+        config: config::Config,
+        submission_sink: Arc<dyn SubmissionSink>,
+    ) -> anyhow::Result<Self> {
+        let redis = FakeRedis::start().await?;
+        let connection = redis.connection().await?;
+        let storage = DialogueStorage::from_env(connection.clone(), true).await?;
+        Self::build(config, submission_sink, connection, storage, Some(redis)).await
+    }
+
+    /// The bot keeping the dialogue states and everything else in the Redis at `redis_url`, as it
+    /// does in production.
+    pub async fn with_redis(
+        config: config::Config,
+        submission_sink: Arc<dyn SubmissionSink>,
+        redis_url: &str,
+    ) -> anyhow::Result<Self> {
+        let connection = ::redis::Client::open(redis_url)?
+            .get_multiplexed_tokio_connection()
+            .await?;
+        let storage = DialogueStorage::from_env(connection.clone(), false).await?;
+        Self::build(config, submission_sink, connection, storage, None).await
+    }
+
+    async fn build(
+        config: config::Config,
+        submission_sink: Arc<dyn SubmissionSink>,
+        connection: ::redis::aio::MultiplexedConnection,
+        storage: Arc<DialogueStorage>,
+        fake_redis: Option<FakeRedis>,
+    ) -> anyhow::Result<Self> {
+        let telegram = FakeTelegram::start().await?;
+        let bot = telegram.bot();
+        let me = bot.get_me().await?;
+        // Only constructed; the fakes keep the tests away from Google.
+        let auth = oauth2::InstalledFlowAuthenticator::builder(
+            oauth2::ApplicationSecret::default(),
+            oauth2::InstalledFlowReturnMethod::HTTPRedirect,
+        )
+        .hyper_client(crate::sheets_http_client())
+        .build()
+        .await?;
+        let drive_auth = oauth2::InstalledFlowAuthenticator::builder(
+            oauth2::ApplicationSecret::default(),
+            oauth2::InstalledFlowReturnMethod::HTTPRedirect,
+        )
+        .build()
+        .await?;
+        let app_state = Arc::new(AppState {
+            config: arc_swap::ArcSwap::from_pointee(config),
+            texts: arc_swap::ArcSwap::from_pointee(texts::Texts::from_env()?),
+            sheets_api: Sheets::new(crate::sheets_http_client(), auth),
+            drive_api: google_drive3::DriveHub::new(crate::drive_http_client(), drive_auth),
+            submission_sink,
+            redis: connection,
+            cipher: None,
+            dev_mode: true,
+            dry_run: false,
+            admin_chat_id: None,
+            volunteers_chat_id: None,
+            tasks: Default::default(),
+        });
+        // `getMe` is not a part of any flow.
+        telegram.take_requests();
+        Ok(Self {
+            telegram,
+            app_state,
+            storage,
+            bot,
+            me,
+            chat_locks: Default::default(),
+            last_update_id: AtomicI32::new(0),
+            _fake_redis: fake_redis,
+        })
+    }
+
+    /// The text in the language, for comparing with what the bot sent.
+    pub fn text(&self, language: texts::Language, id: &str) -> String {
+        self.app_state.texts().get(language, id).to_owned()
+    }
+
+    /// Runs the update through the handlers and returns the messages the bot sent or edited.
+    pub async fn dispatch(&self, update: Update) -> anyhow::Result<Vec<SentMessage>> {
+        let deps = dptree::deps![
+            update,
+            self.bot.clone(),
+            self.app_state.clone(),
+            self.storage.clone(),
+            self.me.clone(),
+            self.chat_locks.clone()
+        ];
+        if let ControlFlow::Break(result) = handlers::schema().dispatch(deps).await {
+            result?;
+        }
+        Ok(self.telegram.take_messages())
+    }
+
+    /// Sends the text from the user of the private chat, whose Telegram client speaks Ukrainian.
+    pub async fn send_text(&self, chat_id: i64, text: &str) -> anyhow::Result<Vec<SentMessage>> {
+        let update = self.update(json!({ "message": message(chat_id, self.next_id(), text) }));
+        self.dispatch(update).await
+    }
+
+    /// Presses the inline button with the callback data under the message of the bot.
+    pub async fn press_button(
+        &self,
+        chat_id: i64,
+        message_id: i32,
+        data: &str,
+    ) -> anyhow::Result<Vec<SentMessage>> {
+        let mut message = message(chat_id, message_id, "-");
+        message["from"] =
+            json!({"id": telegram::BOT_USER_ID, "is_bot": true, "first_name": "Test"});
+        let update = self.update(json!({
+            "callback_query": {
+                "id": self.next_id().to_string(),
+                "from": user(chat_id),
+                "message": message,
+                "chat_instance": chat_id.to_string(),
+                "data": data,
+            },
+        }));
+        self.dispatch(update).await
+    }
+
+    /// The dialogue state of the chat.
+    pub async fn state(&self, chat_id: i64) -> anyhow::Result<Option<State>> {
+        Ok(self.storage.clone().get_dialogue(chat_id).await?)
+    }
+
+    fn next_id(&self) -> i32 {
+        self.last_update_id.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    fn update(&self, mut update: serde_json::Value) -> Update {
+        update["update_id"] = self.next_id().into();
+        // Parsed from the text: the kinds of the updates fail to deserialize from a `Value`.
+        serde_json::from_str(&update.to_string()).expect("synthetic updates are valid")
+    }
+}
+
+fn user(chat_id: i64) -> serde_json::Value {
+    json!({"id": chat_id, "is_bot": false, "first_name": "Test", "language_code": "uk"})
+}
+
+/// A text message from the user in the private chat, as Telegram sends it.
+fn message(chat_id: i64, message_id: i32, text: &str) -> serde_json::Value {
+    json!({
+        "message_id": message_id,
+        "date": chrono::Utc::now().timestamp(),
+        "chat": {"id": chat_id, "type": "private", "first_name": "Test"},
+        "from": user(chat_id),
+        "text": text,
+    })
+}
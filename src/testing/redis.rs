@@ -0,0 +1,507 @@
+//! In-memory stand-in for Redis which speaks just enough of RESP for the commands the bot uses.
+
+use std::{
+    collections::{hash_map::Entry, BTreeSet, HashMap},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+enum Value {
+    String(Vec<u8>),
+    List(Vec<Vec<u8>>),
+    Hash(HashMap<Vec<u8>, Vec<u8>>),
+    Set(BTreeSet<Vec<u8>>),
+    SortedSet(HashMap<Vec<u8>, f64>),
+}
+
+impl Value {
+    fn is_empty(&self) -> bool {
+        match self {
+            Self::String(_) => false,
+            Self::List(list) => list.is_empty(),
+            Self::Hash(hash) => hash.is_empty(),
+            Self::Set(set) => set.is_empty(),
+            Self::SortedSet(set) => set.is_empty(),
+        }
+    }
+}
+
+enum Reply {
+    Status(&'static str),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    fn wrong_type() -> Self {
+        Self::Error("WRONGTYPE Operation against a key holding the wrong kind of value".to_owned())
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Self::Status(status) => out.extend(format!("+{status}\r\n").bytes()),
+            Self::Error(message) => out.extend(format!("-{message}\r\n").bytes()),
+            Self::Integer(number) => out.extend(format!(":{number}\r\n").bytes()),
+            Self::Bulk(None) => out.extend(b"$-1\r\n"),
+            Self::Bulk(Some(data)) => {
+                out.extend(format!("${}\r\n", data.len()).bytes());
+                out.extend(data);
+                out.extend(b"\r\n");
+            }
+            Self::Array(items) => {
+                out.extend(format!("*{}\r\n", items.len()).bytes());
+                for item in items {
+                    item.encode(out);
+                }
+            }
+        }
+    }
+}
+
+type Data = Arc<Mutex<HashMap<Vec<u8>, Value>>>;
+
+/// A Redis server on a local port, with the data of all its connections in memory.
+pub struct FakeRedis {
+    addr: SocketAddr,
+}
+
+impl FakeRedis {
+    pub async fn start() -> anyhow::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let data = Data::default();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, data.clone()));
+            }
+        });
+        Ok(Self { addr })
+    }
+
+    pub fn url(&self) -> String {
+        format!("redis://{}/", self.addr)
+    }
+
+    pub async fn connection(&self) -> anyhow::Result<redis::aio::MultiplexedConnection> {
+        Ok(redis::Client::open(self.url())?
+            .get_multiplexed_tokio_connection()
+            .await?)
+    }
+}
+
+async fn serve(stream: TcpStream, data: Data) {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    // Commands between MULTI and EXEC, run together on EXEC.
+    let mut transaction: Option<Vec<Vec<Vec<u8>>>> = None;
+    while let Ok(Some(args)) = read_command(&mut reader).await {
+        let name = String::from_utf8_lossy(&args[0]).to_uppercase();
+        let reply = match name.as_str() {
+            "MULTI" => {
+                transaction = Some(Vec::new());
+                Reply::Status("OK")
+            }
+            "EXEC" => {
+                let commands = transaction.take().unwrap_or_default();
+                let mut data = data.lock().unwrap();
+                Reply::Array(
+                    commands
+                        .iter()
+                        .map(|args| execute(&mut data, args))
+                        .collect(),
+                )
+            }
+            _ => match &mut transaction {
+                Some(queued) => {
+                    queued.push(args);
+                    Reply::Status("QUEUED")
+                }
+                None => execute(&mut data.lock().unwrap(), &args),
+            },
+        };
+        let mut out = Vec::new();
+        reply.encode(&mut out);
+        if writer.write_all(&out).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Reads a command sent as an array of bulk strings; `None` once the client disconnects.
+async fn read_command<R>(reader: &mut R) -> anyhow::Result<Option<Vec<Vec<u8>>>>
+where
+    R: AsyncBufReadExt + AsyncReadExt + Unpin,
+{
+    let mut line = String::new();
+    if reader.read_line(&mut line).await? == 0 {
+        return Ok(None);
+    }
+    let count: usize = line
+        .trim_end()
+        .strip_prefix('*')
+        .ok_or_else(|| anyhow::anyhow!("Unexpected command {:?}", line))?
+        .parse()?;
+    let mut args = Vec::with_capacity(count);
+    for _ in 0..count {
+        line.clear();
+        reader.read_line(&mut line).await?;
+        let len: usize = line
+            .trim_end()
+            .strip_prefix('$')
+            .ok_or_else(|| anyhow::anyhow!("Unexpected argument {:?}", line))?
+            .parse()?;
+        let mut arg = vec![0; len + 2];
+        reader.read_exact(&mut arg).await?;
+        arg.truncate(len);
+        args.push(arg);
+    }
+    Ok(Some(args))
+}
+
+fn execute(data: &mut HashMap<Vec<u8>, Value>, args: &[Vec<u8>]) -> Reply {
+    let reply = execute_command(data, args);
+    // Like Redis, drops the collections left empty.
+    if let Some(key) = args.get(1) {
+        if data.get(key).is_some_and(Value::is_empty) {
+            data.remove(key);
+        }
+    }
+    reply
+}
+
+fn execute_command(data: &mut HashMap<Vec<u8>, Value>, args: &[Vec<u8>]) -> Reply {
+    let name = String::from_utf8_lossy(&args[0]).to_uppercase();
+    let key = args.get(1).cloned().unwrap_or_default();
+    let int = |arg: &Vec<u8>| String::from_utf8_lossy(arg).parse::<i64>().unwrap_or(0);
+    match name.as_str() {
+        "PING" => Reply::Status("PONG"),
+        "GET" => match data.get(&key) {
+            Some(Value::String(value)) => Reply::Bulk(Some(value.clone())),
+            Some(_) => Reply::wrong_type(),
+            None => Reply::Bulk(None),
+        },
+        "SET" => {
+            // The expiry options are accepted and ignored, the keys never expire.
+            let nx = args[3..].iter().any(|arg| arg.eq_ignore_ascii_case(b"NX"));
+            if nx && data.contains_key(&key) {
+                return Reply::Bulk(None);
+            }
+            data.insert(key, Value::String(args[2].clone()));
+            Reply::Status("OK")
+        }
+        "SETEX" => {
+            data.insert(key, Value::String(args[3].clone()));
+            Reply::Status("OK")
+        }
+        "EXPIRE" => Reply::Integer(data.contains_key(&key) as i64),
+        "EXISTS" => Reply::Integer(
+            args[1..]
+                .iter()
+                .filter(|key| data.contains_key(*key))
+                .count() as i64,
+        ),
+        "SCAN" => {
+            let pattern = args
+                .iter()
+                .position(|arg| arg.eq_ignore_ascii_case(b"MATCH"))
+                .and_then(|i| args.get(i + 1))
+                .map_or(&b"*"[..], Vec::as_slice);
+            let prefix = pattern.strip_suffix(b"*").unwrap_or(pattern);
+            Reply::Array(vec![
+                Reply::Bulk(Some(b"0".to_vec())),
+                Reply::Array(
+                    data.keys()
+                        .filter(|key| key.starts_with(prefix))
+                        .map(|key| Reply::Bulk(Some(key.clone())))
+                        .collect(),
+                ),
+            ])
+        }
+        "KEYS" => {
+            let prefix = key.strip_suffix(b"*").unwrap_or(&key);
+            Reply::Array(
+                data.keys()
+                    .filter(|key| key.starts_with(prefix))
+                    .map(|key| Reply::Bulk(Some(key.clone())))
+                    .collect(),
+            )
+        }
+        "TYPE" => Reply::Status(match data.get(&key) {
+            Some(Value::String(_)) => "string",
+            Some(Value::List(_)) => "list",
+            Some(Value::Hash(_)) => "hash",
+            Some(Value::Set(_)) => "set",
+            Some(Value::SortedSet(_)) => "zset",
+            None => "none",
+        }),
+        "SETNX" => match data.entry(key) {
+            Entry::Occupied(_) => Reply::Integer(0),
+            Entry::Vacant(entry) => {
+                entry.insert(Value::String(args[2].clone()));
+                Reply::Integer(1)
+            }
+        },
+        "DEL" => Reply::Integer(
+            args[1..]
+                .iter()
+                .filter(|key| data.remove(*key).is_some())
+                .count() as i64,
+        ),
+        "INCR" | "INCRBY" => {
+            let by = args.get(2).map_or(1, int);
+            let current = match data.get(&key) {
+                Some(Value::String(value)) => int(value),
+                Some(_) => return Reply::wrong_type(),
+                None => 0,
+            };
+            data.insert(key, Value::String((current + by).to_string().into_bytes()));
+            Reply::Integer(current + by)
+        }
+        "RPUSH" => match data.entry(key).or_insert_with(|| Value::List(Vec::new())) {
+            Value::List(list) => {
+                list.extend(args[2..].iter().cloned());
+                Reply::Integer(list.len() as i64)
+            }
+            _ => Reply::wrong_type(),
+        },
+        "LPUSH" => match data.entry(key).or_insert_with(|| Value::List(Vec::new())) {
+            Value::List(list) => {
+                for value in &args[2..] {
+                    list.insert(0, value.clone());
+                }
+                Reply::Integer(list.len() as i64)
+            }
+            _ => Reply::wrong_type(),
+        },
+        "LPOP" => match data.get_mut(&key) {
+            Some(Value::List(list)) if !list.is_empty() => Reply::Bulk(Some(list.remove(0))),
+            Some(Value::List(_)) | None => Reply::Bulk(None),
+            Some(_) => Reply::wrong_type(),
+        },
+        "LRANGE" => match data.get(&key) {
+            Some(Value::List(list)) => {
+                let len = list.len() as i64;
+                let index = |arg| {
+                    let index = int(arg);
+                    if index < 0 {
+                        (len + index).max(0)
+                    } else {
+                        index
+                    }
+                };
+                let (start, stop) = (index(&args[2]), index(&args[3]).min(len - 1));
+                Reply::Array(
+                    (start..=stop)
+                        .map(|i| Reply::Bulk(Some(list[i as usize].clone())))
+                        .collect(),
+                )
+            }
+            Some(_) => Reply::wrong_type(),
+            None => Reply::Array(Vec::new()),
+        },
+        "LREM" => match data.get_mut(&key) {
+            Some(Value::List(list)) => {
+                let count = int(&args[2]);
+                let limit = if count == 0 {
+                    usize::MAX
+                } else {
+                    count.unsigned_abs() as usize
+                };
+                let positions: Vec<usize> = if count < 0 {
+                    (0..list.len()).rev().collect()
+                } else {
+                    (0..list.len()).collect()
+                };
+                let mut removed = positions
+                    .into_iter()
+                    .filter(|&i| list[i] == args[3])
+                    .take(limit)
+                    .collect::<Vec<_>>();
+                removed.sort_unstable();
+                for &i in removed.iter().rev() {
+                    list.remove(i);
+                }
+                Reply::Integer(removed.len() as i64)
+            }
+            Some(_) => Reply::wrong_type(),
+            None => Reply::Integer(0),
+        },
+        "LLEN" => match data.get(&key) {
+            Some(Value::List(list)) => Reply::Integer(list.len() as i64),
+            Some(_) => Reply::wrong_type(),
+            None => Reply::Integer(0),
+        },
+        "SADD" => match data
+            .entry(key)
+            .or_insert_with(|| Value::Set(BTreeSet::new()))
+        {
+            Value::Set(set) => Reply::Integer(
+                args[2..]
+                    .iter()
+                    .filter(|member| set.insert(member.to_vec()))
+                    .count() as i64,
+            ),
+            _ => Reply::wrong_type(),
+        },
+        "SREM" => match data.get_mut(&key) {
+            Some(Value::Set(set)) => Reply::Integer(
+                args[2..]
+                    .iter()
+                    .filter(|member| set.remove(*member))
+                    .count() as i64,
+            ),
+            Some(_) => Reply::wrong_type(),
+            None => Reply::Integer(0),
+        },
+        "SMEMBERS" => match data.get(&key) {
+            Some(Value::Set(set)) => Reply::Array(
+                set.iter()
+                    .map(|member| Reply::Bulk(Some(member.clone())))
+                    .collect(),
+            ),
+            Some(_) => Reply::wrong_type(),
+            None => Reply::Array(Vec::new()),
+        },
+        "SISMEMBER" => match data.get(&key) {
+            Some(Value::Set(set)) => Reply::Integer(set.contains(&args[2]) as i64),
+            Some(_) => Reply::wrong_type(),
+            None => Reply::Integer(0),
+        },
+        "HSET" => match data
+            .entry(key)
+            .or_insert_with(|| Value::Hash(HashMap::new()))
+        {
+            Value::Hash(hash) => Reply::Integer(
+                args[2..]
+                    .chunks(2)
+                    .filter(|pair| hash.insert(pair[0].clone(), pair[1].clone()).is_none())
+                    .count() as i64,
+            ),
+            _ => Reply::wrong_type(),
+        },
+        "HGET" => match data.get(&key) {
+            Some(Value::Hash(hash)) => Reply::Bulk(hash.get(&args[2]).cloned()),
+            Some(_) => Reply::wrong_type(),
+            None => Reply::Bulk(None),
+        },
+        "HINCRBY" => match data
+            .entry(key)
+            .or_insert_with(|| Value::Hash(HashMap::new()))
+        {
+            Value::Hash(hash) => {
+                let field = hash.entry(args[2].clone()).or_insert_with(|| b"0".to_vec());
+                let value = int(field) + int(&args[3]);
+                *field = value.to_string().into_bytes();
+                Reply::Integer(value)
+            }
+            _ => Reply::wrong_type(),
+        },
+        "HGETALL" => match data.get(&key) {
+            Some(Value::Hash(hash)) => Reply::Array(
+                hash.iter()
+                    .flat_map(|(field, value)| [field.clone(), value.clone()])
+                    .map(|data| Reply::Bulk(Some(data)))
+                    .collect(),
+            ),
+            Some(_) => Reply::wrong_type(),
+            None => Reply::Array(Vec::new()),
+        },
+        "HKEYS" => match data.get(&key) {
+            Some(Value::Hash(hash)) => Reply::Array(
+                hash.keys()
+                    .map(|field| Reply::Bulk(Some(field.clone())))
+                    .collect(),
+            ),
+            Some(_) => Reply::wrong_type(),
+            None => Reply::Array(Vec::new()),
+        },
+        "HVALS" => match data.get(&key) {
+            Some(Value::Hash(hash)) => Reply::Array(
+                hash.values()
+                    .map(|value| Reply::Bulk(Some(value.clone())))
+                    .collect(),
+            ),
+            Some(_) => Reply::wrong_type(),
+            None => Reply::Array(Vec::new()),
+        },
+        "HDEL" => match data.get_mut(&key) {
+            Some(Value::Hash(hash)) => Reply::Integer(
+                args[2..]
+                    .iter()
+                    .filter(|field| hash.remove(*field).is_some())
+                    .count() as i64,
+            ),
+            Some(_) => Reply::wrong_type(),
+            None => Reply::Integer(0),
+        },
+        "ZADD" => match data
+            .entry(key)
+            .or_insert_with(|| Value::SortedSet(HashMap::new()))
+        {
+            Value::SortedSet(set) => Reply::Integer(
+                args[2..]
+                    .chunks(2)
+                    .filter(|pair| {
+                        let score = String::from_utf8_lossy(&pair[0]).parse().unwrap_or(0.0);
+                        set.insert(pair[1].clone(), score).is_none()
+                    })
+                    .count() as i64,
+            ),
+            _ => Reply::wrong_type(),
+        },
+        "ZREM" => match data.get_mut(&key) {
+            Some(Value::SortedSet(set)) => Reply::Integer(
+                args[2..]
+                    .iter()
+                    .filter(|member| set.remove(*member).is_some())
+                    .count() as i64,
+            ),
+            Some(_) => Reply::wrong_type(),
+            None => Reply::Integer(0),
+        },
+        "ZCARD" => match data.get(&key) {
+            Some(Value::SortedSet(set)) => Reply::Integer(set.len() as i64),
+            Some(_) => Reply::wrong_type(),
+            None => Reply::Integer(0),
+        },
+        "ZRANGEBYSCORE" => match data.get(&key) {
+            Some(Value::SortedSet(set)) => {
+                let bound = |arg: &Vec<u8>| match String::from_utf8_lossy(arg).as_ref() {
+                    "-inf" => f64::NEG_INFINITY,
+                    "+inf" => f64::INFINITY,
+                    bound => bound.parse().unwrap_or(0.0),
+                };
+                let (min, max) = (bound(&args[2]), bound(&args[3]));
+                let mut members: Vec<_> = set
+                    .iter()
+                    .filter(|(_, score)| (min..=max).contains(*score))
+                    .collect();
+                members.sort_by(|a, b| a.1.total_cmp(b.1).then_with(|| a.0.cmp(b.0)));
+                let with_scores = args
+                    .get(4)
+                    .is_some_and(|arg| arg.eq_ignore_ascii_case(b"WITHSCORES"));
+                Reply::Array(
+                    members
+                        .into_iter()
+                        .flat_map(|(member, score)| {
+                            let score = with_scores.then(|| score.to_string().into_bytes());
+                            [Some(member.clone()), score]
+                        })
+                        .flatten()
+                        .map(|data| Reply::Bulk(Some(data)))
+                        .collect(),
+                )
+            }
+            Some(_) => Reply::wrong_type(),
+            None => Reply::Array(Vec::new()),
+        },
+        _ => Reply::Error(format!("ERR unknown command '{name}'")),
+    }
+}
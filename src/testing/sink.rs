@@ -0,0 +1,97 @@
+//! Stand-in for the spreadsheets which keeps the appended rows in memory.
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+};
+
+use futures::{future::BoxFuture, FutureExt};
+
+use crate::{
+    config,
+    sheets::{column_letters, Appended, FoundRow, SubmissionSink},
+};
+
+/// A row appended to the tab of the spreadsheet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppendedRow {
+    pub destination: config::Destination,
+    pub row: Vec<String>,
+}
+
+/// The sink recording the rows; each is reported as appended below the previous ones, as the
+/// Sheets API does.
+#[derive(Default)]
+pub struct FakeSink {
+    rows: Mutex<Vec<AppendedRow>>,
+    scans: AtomicUsize,
+}
+
+impl FakeSink {
+    /// The rows appended so far, in order.
+    pub fn rows(&self) -> Vec<AppendedRow> {
+        self.rows.lock().unwrap().clone()
+    }
+
+    /// How many times a row was looked for in the tabs.
+    pub fn scans(&self) -> usize {
+        self.scans.load(Ordering::SeqCst)
+    }
+}
+
+impl SubmissionSink for FakeSink {
+    fn append_row<'a>(
+        &'a self,
+        destination: &'a config::Destination,
+        _options: &'a config::AppendOptions,
+        row: Vec<String>,
+    ) -> BoxFuture<'a, anyhow::Result<Appended>> {
+        let mut rows = self.rows.lock().unwrap();
+        // The first row of the tab holds the headers.
+        let number = rows.len() + 2;
+        let range = format!(
+            "{}!A{number}:{}{number}",
+            destination.sheet,
+            column_letters(row.len().saturating_sub(1))
+        );
+        rows.push(AppendedRow {
+            destination: destination.clone(),
+            row,
+        });
+        futures::future::ready(Ok(Appended {
+            range,
+            mismatched_columns: Vec::new(),
+        }))
+        .boxed()
+    }
+
+    fn find_row<'a>(
+        &'a self,
+        destination: &'a config::Destination,
+        column: usize,
+        value: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Option<FoundRow>>> {
+        self.scans.fetch_add(1, Ordering::SeqCst);
+        let rows = self.rows.lock().unwrap();
+        let found = rows.iter().enumerate().find(|(_, appended)| {
+            appended.destination == *destination
+                && appended
+                    .row
+                    .get(column)
+                    .map(|cell| cell.trim_start_matches('\''))
+                    == Some(value)
+        });
+        let found = found.map(|(index, appended)| {
+            let number = index + 2;
+            (
+                format!(
+                    "{}!A{number}:{}{number}",
+                    destination.sheet,
+                    column_letters(appended.row.len().saturating_sub(1))
+                ),
+                appended.row.clone(),
+            )
+        });
+        futures::future::ready(Ok(found)).boxed()
+    }
+}
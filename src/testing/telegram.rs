@@ -0,0 +1,174 @@
+//! Stand-in for the Telegram Bot API which records the requests and answers them with plausible
+//! results.
+
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use axum::{
+    body::Bytes,
+    extract::{Extension, Path},
+    routing::post,
+    Json, Router,
+};
+use serde_json::{json, Value};
+use teloxide::{adaptors::throttle::Limits, prelude2::*, requests::RequesterExt};
+
+use crate::ThrottledBot;
+
+/// Id of the bot user, as returned by `getMe`.
+pub const BOT_USER_ID: i64 = 1;
+
+/// A request the bot sent, with the method name as in the Bot API, e.g. `sendMessage`.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub params: Value,
+}
+
+/// A message the bot sent or edited, as the user sees it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SentMessage {
+    pub chat_id: i64,
+    pub text: String,
+    /// Labels of the buttons of the reply or the inline keyboard, row by row; empty without one.
+    pub keyboard: Vec<Vec<String>>,
+    /// Whether an earlier message was edited instead of sending a new one.
+    pub edited: bool,
+}
+
+impl SentMessage {
+    fn from_request(request: &Request) -> Option<Self> {
+        let edited = match request.method.as_str() {
+            "sendMessage" => false,
+            "editMessageText" => true,
+            _ => return None,
+        };
+        let markup = &request.params["reply_markup"];
+        let rows = markup["keyboard"]
+            .as_array()
+            .or_else(|| markup["inline_keyboard"].as_array());
+        let keyboard = rows
+            .into_iter()
+            .flatten()
+            .map(|row| {
+                row.as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|button| button["text"].as_str().unwrap_or_default().to_owned())
+                    .collect()
+            })
+            .collect();
+        Some(Self {
+            chat_id: request.params["chat_id"].as_i64()?,
+            text: request.params["text"].as_str()?.to_owned(),
+            keyboard,
+            edited,
+        })
+    }
+}
+
+#[derive(Default)]
+struct Recorder {
+    requests: Mutex<Vec<Request>>,
+    last_message_id: AtomicI32,
+}
+
+/// The Bot API server on a local port; the bot from [`FakeTelegram::bot`] sends its requests there.
+pub struct FakeTelegram {
+    addr: SocketAddr,
+    recorder: Arc<Recorder>,
+}
+
+impl FakeTelegram {
+    pub async fn start() -> anyhow::Result<Self> {
+        let recorder = Arc::new(Recorder {
+            // Far from the ids of the synthetic incoming messages.
+            last_message_id: AtomicI32::new(1_000_000),
+            ..Recorder::default()
+        });
+        let app = Router::new()
+            .route("/:token/:method", post(respond))
+            .layer(Extension(recorder.clone()));
+        let server =
+            axum::Server::try_bind(&([127, 0, 0, 1], 0).into())?.serve(app.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        Ok(Self { addr, recorder })
+    }
+
+    pub fn bot(&self) -> ThrottledBot {
+        Bot::new("test:token")
+            .set_api_url(
+                format!("http://{}", self.addr)
+                    .parse()
+                    .expect("the address of the fake server is a valid URL"),
+            )
+            .throttle(Limits::default())
+            .auto_send()
+    }
+
+    /// The requests sent since the last call.
+    pub fn take_requests(&self) -> Vec<Request> {
+        std::mem::take(&mut *self.recorder.requests.lock().unwrap())
+    }
+
+    /// The messages sent or edited since the last call; other requests are dropped.
+    pub fn take_messages(&self) -> Vec<SentMessage> {
+        self.take_requests()
+            .iter()
+            .filter_map(SentMessage::from_request)
+            .collect()
+    }
+}
+
+async fn respond(
+    Extension(recorder): Extension<Arc<Recorder>>,
+    Path((_, method)): Path<(String, String)>,
+    body: Bytes,
+) -> Json<Value> {
+    let params: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    // teloxide names the methods as its payloads, e.g. `SendMessage`; Telegram ignores the case.
+    let mut chars = method.chars();
+    let method: String = chars
+        .next()
+        .map(|first| first.to_ascii_lowercase())
+        .into_iter()
+        .chain(chars)
+        .collect();
+    let result = match method.as_str() {
+        "getMe" => json!({
+            "id": BOT_USER_ID,
+            "is_bot": true,
+            "first_name": "Test",
+            "username": "test_bot",
+            "can_join_groups": true,
+            "can_read_all_group_messages": false,
+            "supports_inline_queries": false,
+        }),
+        "sendMessage" | "editMessageText" | "editMessageReplyMarkup" => {
+            let message_id = match params["message_id"].as_i64() {
+                Some(message_id) => message_id,
+                None => (recorder.last_message_id.fetch_add(1, Ordering::SeqCst) + 1).into(),
+            };
+            json!({
+                "message_id": message_id,
+                "date": chrono::Utc::now().timestamp(),
+                "chat": {"id": params["chat_id"], "type": "private", "first_name": "Test"},
+                "from": {"id": BOT_USER_ID, "is_bot": true, "first_name": "Test"},
+                "text": params["text"].as_str().unwrap_or("-"),
+            })
+        }
+        _ => json!(true),
+    };
+    recorder
+        .requests
+        .lock()
+        .unwrap()
+        .push(Request { method, params });
+    Json(json!({"ok": true, "result": result}))
+}
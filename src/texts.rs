@@ -0,0 +1,177 @@
+//! Texts of the bot messages keyed by language and message id.
+//!
+//! The built-in texts live in `texts/{language}.json`. A deployment can override any of them with
+//! its own JSON file given by `COLLECT_VOLUNTEERS_BOT_TEXTS`, keyed by language code and then by
+//! message id; the texts it does not mention stay built-in. A text missing in a language falls back
+//! to Ukrainian. Placeholders are written as `{name}` and filled in by [`Texts::render`].
+
+use std::collections::HashMap;
+
+const BUILTIN_TEXTS: &[(Language, &str)] = &[
+    (Language::Uk, include_str!("../texts/uk.json")),
+    (Language::En, include_str!("../texts/en.json")),
+    (Language::Pl, include_str!("../texts/pl.json")),
+];
+
+/// Redis key with the language the user picked.
+pub(crate) fn language_key(chat_id: i64) -> String {
+    crate::redis_connection::key(&format!("language:{chat_id}"))
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    #[default]
+    Uk,
+    En,
+    Pl,
+}
+
+impl Language {
+    pub const ALL: [Language; 3] = [Language::Uk, Language::En, Language::Pl];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::Uk => "uk",
+            Language::En => "en",
+            Language::Pl => "pl",
+        }
+    }
+
+    /// Name of the language in the language itself, as shown on the picker keyboard.
+    pub fn label(self) -> &'static str {
+        match self {
+            Language::Uk => "Українська",
+            Language::En => "English",
+            Language::Pl => "Polski",
+        }
+    }
+
+    pub fn from_label(label: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|language| language.label() == label.trim())
+    }
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|language| language.code() == code)
+    }
+
+    /// The language of the user's Telegram client, e.g. `en-US`, if the bot speaks it.
+    pub fn detect(user: &teloxide::types::User) -> Option<Self> {
+        let code = user.language_code.as_deref()?;
+        Self::from_code(
+            code.split('-')
+                .next()
+                .unwrap_or(code)
+                .to_lowercase()
+                .as_str(),
+        )
+    }
+}
+
+pub fn language_keyboard() -> teloxide::types::KeyboardMarkup {
+    teloxide::types::KeyboardMarkup::new(vec![Language::ALL
+        .iter()
+        .map(|language| teloxide::types::KeyboardButton::new(language.label()))
+        .collect::<Vec<_>>()])
+}
+
+pub async fn stored_language(
+    mut redis: redis::aio::MultiplexedConnection,
+    chat_id: i64,
+) -> anyhow::Result<Option<Language>> {
+    use redis::AsyncCommands;
+
+    let code: Option<String> = redis.get(language_key(chat_id)).await?;
+    Ok(code.as_deref().and_then(Language::from_code))
+}
+
+pub async fn store_language(
+    mut redis: redis::aio::MultiplexedConnection,
+    chat_id: i64,
+    language: Language,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let _: () = redis.set(language_key(chat_id), language.code()).await?;
+    Ok(())
+}
+
+/// The language of the chat for the messages it gets without asking, Ukrainian if unknown.
+pub async fn language_of(redis: redis::aio::MultiplexedConnection, chat_id: i64) -> Language {
+    match stored_language(redis, chat_id).await {
+        Ok(language) => language.unwrap_or_default(),
+        Err(err) => {
+            tracing::warn!("Failed to load language of chat {}: {:?}", chat_id, err);
+            Language::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Texts {
+    templates: HashMap<Language, HashMap<String, String>>,
+}
+
+impl Texts {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mut templates = HashMap::new();
+        for (language, texts) in BUILTIN_TEXTS {
+            templates.insert(*language, serde_json::from_str(texts)?);
+        }
+        if let Ok(path) = std::env::var("COLLECT_VOLUNTEERS_BOT_TEXTS") {
+            let contents = std::fs::read_to_string(&path)
+                .map_err(|err| anyhow::anyhow!("failed to read texts {}: {}", path, err))?;
+            let overrides: HashMap<Language, HashMap<String, String>> =
+                serde_json::from_str(&contents)?;
+            for (language, overrides) in overrides {
+                templates
+                    .entry(language)
+                    .or_insert_with(HashMap::new)
+                    .extend(overrides);
+            }
+        }
+        Ok(Self { templates })
+    }
+
+    /// The text in the language itself, without the Ukrainian fallback.
+    pub fn translation(&self, language: Language, id: &str) -> Option<&str> {
+        self.templates
+            .get(&language)
+            .and_then(|templates| templates.get(id))
+            .map(String::as_str)
+    }
+
+    /// The text without placeholders; a missing text is replaced by its id so the bot keeps going.
+    pub fn get<'a>(&'a self, language: Language, id: &'a str) -> &'a str {
+        match self
+            .translation(language, id)
+            .or_else(|| self.translation(Language::Uk, id))
+        {
+            Some(template) => template,
+            None => {
+                tracing::warn!("texts: missing text {:?}", id);
+                id
+            }
+        }
+    }
+
+    /// The text with its `{name}` placeholders replaced by the values.
+    pub fn render(
+        &self,
+        language: Language,
+        id: &str,
+        values: &[(&str, &(dyn std::fmt::Display + Sync))],
+    ) -> String {
+        values
+            .iter()
+            .fold(self.get(language, id).to_owned(), |text, (name, value)| {
+                text.replace(&format!("{{{name}}}"), &value.to_string())
+            })
+    }
+}
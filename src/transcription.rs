@@ -0,0 +1,134 @@
+//! Speech-to-text for the answers sent as voice messages.
+//!
+//! The provider is picked in the config; its API key is taken from
+//! `COLLECT_VOLUNTEERS_BOT_TRANSCRIPTION_API_KEY`. The transcription is shown to the user, who
+//! accepts it as the answer or answers again.
+
+use teloxide::{net::Download, prelude2::*, types::Voice};
+
+use crate::{texts::Language, ThrottledBot};
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(tag = "provider", rename_all = "snake_case")]
+pub enum Provider {
+    /// OpenAI-compatible `audio/transcriptions` endpoint, e.g. OpenAI Whisper or a self-hosted
+    /// Whisper server.
+    Whisper {
+        #[serde(default = "default_whisper_url")]
+        url: String,
+        #[serde(default = "default_whisper_model")]
+        model: String,
+    },
+    /// Google Cloud Speech-to-Text.
+    GoogleSpeech,
+}
+
+fn default_whisper_url() -> String {
+    "https://api.openai.com/v1/audio/transcriptions".to_owned()
+}
+
+fn default_whisper_model() -> String {
+    "whisper-1".to_owned()
+}
+
+fn api_key() -> anyhow::Result<String> {
+    std::env::var("COLLECT_VOLUNTEERS_BOT_TRANSCRIPTION_API_KEY")
+        .map_err(|_| anyhow::anyhow!("COLLECT_VOLUNTEERS_BOT_TRANSCRIPTION_API_KEY is not set"))
+}
+
+/// The text of the voice message; empty if nothing was recognized.
+#[tracing::instrument(skip(bot, voice), fields(duration = voice.duration))]
+pub async fn transcribe(
+    bot: &ThrottledBot,
+    provider: &Provider,
+    voice: &Voice,
+    language: Language,
+) -> anyhow::Result<String> {
+    let file = bot.get_file(&voice.file_id).await?;
+    let mut audio = Vec::new();
+    bot.download_file(&file.file_path, &mut audio).await?;
+
+    let text = match provider {
+        Provider::Whisper { url, model } => whisper(url, model, audio, language).await?,
+        Provider::GoogleSpeech => google_speech(audio, language).await?,
+    };
+    Ok(text.trim().to_owned())
+}
+
+async fn whisper(
+    url: &str,
+    model: &str,
+    audio: Vec<u8>,
+    language: Language,
+) -> anyhow::Result<String> {
+    #[derive(serde::Deserialize)]
+    struct Response {
+        text: String,
+    }
+
+    let form = reqwest::multipart::Form::new()
+        .part(
+            "file",
+            reqwest::multipart::Part::bytes(audio)
+                .file_name("voice.ogg")
+                .mime_str("audio/ogg")?,
+        )
+        .text("model", model.to_owned())
+        .text("language", language.code());
+    let response: Response = reqwest::Client::new()
+        .post(url)
+        .bearer_auth(api_key()?)
+        .multipart(form)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(response.text)
+}
+
+async fn google_speech(audio: Vec<u8>, language: Language) -> anyhow::Result<String> {
+    #[derive(serde::Deserialize)]
+    struct Response {
+        #[serde(default)]
+        results: Vec<RecognitionResult>,
+    }
+    #[derive(serde::Deserialize)]
+    struct RecognitionResult {
+        alternatives: Vec<Alternative>,
+    }
+    #[derive(serde::Deserialize)]
+    struct Alternative {
+        transcript: String,
+    }
+
+    let language_code = match language {
+        Language::Uk => "uk-UA",
+        Language::En => "en-US",
+        Language::Pl => "pl-PL",
+    };
+    let response: Response = reqwest::Client::new()
+        .post("https://speech.googleapis.com/v1/speech:recognize")
+        .query(&[("key", api_key()?)])
+        .json(&serde_json::json!({
+            "config": {
+                // Telegram voice messages are Opus in an Ogg container.
+                "encoding": "OGG_OPUS",
+                "sampleRateHertz": 48000,
+                "languageCode": language_code,
+            },
+            "audio": { "content": base64::encode(audio) },
+        }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(response
+        .results
+        .iter()
+        .filter_map(|result| result.alternatives.first())
+        .map(|alternative| alternative.transcript.as_str())
+        .collect::<Vec<_>>()
+        .join(" "))
+}
@@ -0,0 +1,290 @@
+//! Queue of the new requests for help waiting for a coordinator to triage them.
+//!
+//! The requests are ordered by the time they were submitted, moved ahead by their urgency and by
+//! the special needs of the people: children, limited mobility, accessibility and
+//! medical needs. `/next` of a coordinator hands
+//! out the request at the head of the queue and marks it as triaged by them, see [`lifecycle`].
+//! Requests which were assigned or closed before their turn are skipped.
+
+use teloxide::prelude2::*;
+
+use crate::{
+    assignment,
+    config::HelpDirection,
+    forum_topics, lifecycle, redis_connection,
+    sheets::{self, find_submission},
+    state::{Contact, HelpKind, Urgency},
+    AppState, ThrottledBot, TrackedSubmission,
+};
+
+/// Redis sorted set of the reference ids of the queued requests scored by the unix timestamp the
+/// request is due for triage at: the submission time moved earlier by its priority.
+const QUEUE_KEY: &str = "triage:queue";
+/// Redis hash of the queued requests keyed by reference id.
+const REQUESTS_KEY: &str = "triage:requests";
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct QueuedRequest {
+    help_kind: HelpKind,
+    region: Option<String>,
+    urgency: Option<Urgency>,
+    special_needs: Vec<String>,
+    submitted_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl QueuedRequest {
+    /// How much earlier than its submission the request is due for triage.
+    fn head_start(&self) -> chrono::Duration {
+        let urgency = match self.urgency {
+            Some(Urgency::Critical) => chrono::Duration::hours(24),
+            Some(Urgency::WithinDay) => chrono::Duration::hours(6),
+            Some(Urgency::NotUrgent) | None => chrono::Duration::zero(),
+        };
+        urgency + chrono::Duration::hours(4) * self.special_needs.len() as i32
+    }
+}
+
+/// Whether the answer about children, limited mobility, accessibility or medical needs means there
+/// are any.
+pub(crate) fn is_affirmative(answer: Option<&str>) -> bool {
+    match answer.map(|answer| answer.trim().to_lowercase()) {
+        Some(answer) => !matches!(
+            answer.as_str(),
+            "" | "-" | "0" | "ні" | "немає" | "no" | "nie"
+        ),
+        None => false,
+    }
+}
+
+/// Queues the saved request for help for triage.
+pub async fn enqueue(
+    app_state: &AppState,
+    contact: &Contact,
+    submission: &TrackedSubmission,
+) -> anyhow::Result<()> {
+    let requested_help = app_state
+        .config()
+        .help_kind(&submission.help_kind)
+        .is_some_and(|definition| definition.direction == HelpDirection::Needed);
+    if !requested_help {
+        return Ok(());
+    }
+    let mut special_needs = Vec::new();
+    if is_affirmative(contact.children.as_deref()) {
+        special_needs.push("діти".to_owned());
+    }
+    if is_affirmative(contact.limited_mobility.as_deref()) {
+        special_needs.push("маломобільні".to_owned());
+    }
+    if is_affirmative(contact.accessibility_needs.as_deref()) {
+        special_needs.push("доступність".to_owned());
+    }
+    if is_affirmative(contact.medical_needs.as_deref()) {
+        special_needs.push("медичні потреби".to_owned());
+    }
+    let request = QueuedRequest {
+        help_kind: submission.help_kind.clone(),
+        region: contact.region.clone(),
+        urgency: contact.urgency,
+        special_needs,
+        submitted_at: chrono::Utc::now(),
+    };
+    let due_at = (request.submitted_at - request.head_start()).timestamp();
+    redis::pipe()
+        .atomic()
+        .hset(
+            redis_connection::key(REQUESTS_KEY),
+            &submission.reference_id,
+            serde_json::to_string(&request)?,
+        )
+        .ignore()
+        .zadd(
+            redis_connection::key(QUEUE_KEY),
+            &submission.reference_id,
+            due_at,
+        )
+        .ignore()
+        .query_async::<_, ()>(&mut app_state.redis.clone())
+        .await?;
+    Ok(())
+}
+
+/// Drops the request from the queue, e.g. once its data is deleted.
+pub async fn dequeue(app_state: &AppState, reference_id: &str) -> anyhow::Result<()> {
+    redis::pipe()
+        .atomic()
+        .zrem(redis_connection::key(QUEUE_KEY), reference_id)
+        .ignore()
+        .hdel(redis_connection::key(REQUESTS_KEY), reference_id)
+        .ignore()
+        .query_async::<_, ()>(&mut app_state.redis.clone())
+        .await?;
+    Ok(())
+}
+
+/// Handles `/next` of a coordinator.
+pub async fn handle_next(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    msg: &Message,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let coordinator = msg.from().map_or_else(
+        || msg.chat.id.to_string(),
+        assignment::volunteer_display_name,
+    );
+    let mut redis = app_state.redis.clone();
+    loop {
+        // Popped atomically, so that two coordinators never get the same request.
+        let head: Vec<(String, f64)> = redis.zpopmin(redis_connection::key(QUEUE_KEY), 1).await?;
+        let reference_id = match head.into_iter().next() {
+            Some((reference_id, _)) => reference_id,
+            None => {
+                bot.send_message(msg.chat.id, "Усі нові заявки вже розглянуто.")
+                    .await?;
+                return Ok(());
+            }
+        };
+        let request: Option<String> = redis
+            .hget(redis_connection::key(REQUESTS_KEY), &reference_id)
+            .await?;
+        let _: () = redis
+            .hdel(redis_connection::key(REQUESTS_KEY), &reference_id)
+            .await?;
+        let request: QueuedRequest = match request {
+            Some(request) => serde_json::from_str(&request)?,
+            None => continue,
+        };
+        let transition = lifecycle::transition(
+            app_state,
+            &reference_id,
+            lifecycle::Status::Triaged,
+            &coordinator,
+        )
+        .await?;
+        if transition.is_err() {
+            // Assigned or closed before its turn.
+            continue;
+        }
+        if let Some(indexed) = find_submission(redis.clone(), &reference_id).await? {
+            if let Err(err) = sheets::status::write_status(
+                app_state,
+                &indexed.submission,
+                lifecycle::Status::Triaged,
+            )
+            .await
+            {
+                tracing::warn!(
+                    "Failed to write the status of {} to the spreadsheet: {:?}",
+                    reference_id,
+                    err
+                );
+            }
+        }
+        if let Err(err) = forum_topics::post(
+            bot,
+            app_state,
+            &reference_id,
+            format!("Заявку розглядає {coordinator}."),
+        )
+        .await
+        {
+            tracing::warn!(
+                "Failed to post the triage of {} to its topic: {:?}",
+                reference_id,
+                err
+            );
+        }
+
+        let config = app_state.config();
+        let waiting = chrono::Utc::now() - request.submitted_at;
+        let mut lines = vec![
+            format!(
+                "Заявка {reference_id}: {}",
+                config.help_kind_title(&request.help_kind)
+            ),
+            format!(
+                "Область: {}",
+                request.region.as_deref().unwrap_or("не вказано")
+            ),
+            format!(
+                "Чекає: {} год {} хв",
+                waiting.num_hours(),
+                waiting.num_minutes() % 60
+            ),
+        ];
+        if let Some(urgency) = request.urgency {
+            lines.push(format!("Терміновість: {}", urgency.label()));
+        }
+        if !request.special_needs.is_empty() {
+            lines.push(format!(
+                "Особливі потреби: {}",
+                request.special_needs.join(", ")
+            ));
+        }
+        let remaining: usize = redis.zcard(redis_connection::key(QUEUE_KEY)).await?;
+        lines.push(String::new());
+        lines.push(format!(
+            "Заявку позначено як розглянуту вами. У черзі ще {remaining}."
+        ));
+        bot.send_message(msg.chat.id, lines.join("\n")).await?;
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queued(
+        submitted_hours_ago: i64,
+        urgency: Option<Urgency>,
+        special_needs: &[&str],
+    ) -> QueuedRequest {
+        QueuedRequest {
+            help_kind: HelpKind::new(HelpKind::NEED_EVACUATION),
+            region: None,
+            urgency,
+            special_needs: special_needs.iter().map(|need| need.to_string()).collect(),
+            submitted_at: chrono::Utc::now() - chrono::Duration::hours(submitted_hours_ago),
+        }
+    }
+
+    #[test]
+    fn urgency_and_special_needs_move_requests_ahead() {
+        let requests = [
+            ("old", queued(5, None, &[])),
+            ("within day", queued(0, Some(Urgency::WithinDay), &[])),
+            ("two needs", queued(0, None, &["діти", "медичні потреби"])),
+            ("critical", queued(0, Some(Urgency::Critical), &[])),
+            ("not urgent", queued(1, Some(Urgency::NotUrgent), &[])),
+        ];
+        let mut order: Vec<_> = requests
+            .iter()
+            .map(|(name, request)| (request.submitted_at - request.head_start(), *name))
+            .collect();
+        order.sort();
+        let order: Vec<_> = order.into_iter().map(|(_, name)| name).collect();
+        assert_eq!(
+            order,
+            ["critical", "two needs", "within day", "old", "not urgent"]
+        );
+    }
+
+    #[test]
+    fn only_answers_meaning_yes_are_special_needs() {
+        assert!(is_affirmative(Some("Так, двоє")));
+        assert!(is_affirmative(Some("інвалідний візок")));
+        for answer in [
+            None,
+            Some(""),
+            Some(" - "),
+            Some("Ні"),
+            Some("немає"),
+            Some("0"),
+        ] {
+            assert!(!is_affirmative(answer), "{answer:?}");
+        }
+    }
+}
@@ -0,0 +1,759 @@
+//! Registration and manual verification of volunteers.
+//!
+//! Volunteers tell who they are, who can vouch for them and send a document. The registration is
+//! posted to the admin group with verify/reject buttons, and verified volunteers are flagged in
+//! Redis, which unlocks the commands for searching and taking requests.
+//!
+//! Volunteers also declare the skills and resources they have (tags), so that new requests are
+//! sent only to the volunteers who can actually help with them.
+//!
+//! The requests get tags by the keywords in their answers, e.g. children, insulin or the occupied
+//! territories, so that coordinators can filter the spreadsheet by them and the specialized
+//! volunteer chats get the requests they handle. The request tags are configured in `tags`;
+//! without the config the built-in ones are used. A keyword matches when any answer contains it
+//! ignoring the case, so stems like `окупов` match all the word forms. The tags are written to the
+//! `tags` column of the spreadsheet, a request is posted to the chats of its tags, once per chat,
+//! and a request tag named as a volunteer tag, e.g. `медик`, sends the request to the volunteers
+//! with that tag as well as their own keywords do.
+
+use teloxide::{
+    payloads::{AnswerCallbackQuerySetters, SendMessageSetters},
+    prelude2::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup, ParseMode, User},
+};
+
+use crate::{
+    assignment, audit,
+    handlers::keyboards::{back_keyboard, start_keyboard},
+    html, redis_connection, retry, sla,
+    state::{MyDialogue, State},
+    texts::{self, Language, Texts},
+    triage, AppState, Contact, HelpKind, ThrottledBot, TrackedSubmission,
+};
+
+/// Redis set of Telegram user ids of verified volunteers.
+const VERIFIED_VOLUNTEERS_KEY: &str = "volunteers:verified";
+/// Redis hash of volunteer registrations keyed by Telegram user id.
+const REGISTRATIONS_KEY: &str = "volunteers:registrations";
+/// Redis hash of the Telegram users of the volunteers keyed by lowercased username, so that the
+/// coordinators can refer to them as `@username`.
+const USERNAMES_KEY: &str = "volunteers:usernames";
+
+const CALLBACK_PREFIX: &str = "volunteer:";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum VolunteerTag {
+    Medic,
+    VanDriver,
+    Generator,
+    Translation,
+}
+
+impl VolunteerTag {
+    const ALL: [VolunteerTag; 4] = [
+        VolunteerTag::Medic,
+        VolunteerTag::VanDriver,
+        VolunteerTag::Generator,
+        VolunteerTag::Translation,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            VolunteerTag::Medic => "медик",
+            VolunteerTag::VanDriver => "водій-бус",
+            VolunteerTag::Generator => "генератор",
+            VolunteerTag::Translation => "переклад",
+        }
+    }
+
+    /// Lowercased keywords in the request comments which indicate the tag is needed.
+    fn keywords(self) -> &'static [&'static str] {
+        match self {
+            VolunteerTag::Medic => &["лік", "медик", "інсулін", "поранен", "хвор", "лежач"],
+            VolunteerTag::VanDriver => &["бус", "вантаж", "багато речей", "візок"],
+            VolunteerTag::Generator => &["генератор", "світла", "електрик", "електроенерг"],
+            VolunteerTag::Translation => &["переклад", "english", "англійськ", "іноземець"],
+        }
+    }
+
+    fn from_label(label: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|tag| tag.label() == label)
+    }
+
+    fn volunteers_key(self) -> String {
+        redis_connection::key(&format!("volunteers:tag:{}", self.label()))
+    }
+
+    /// Infers the tags of the volunteers who can help with a request, by its own keywords or the
+    /// request tags named as it, see [`classify`].
+    fn infer(help_kind: &HelpKind, contact: &Contact) -> Vec<Self> {
+        let comments = contact
+            .comments
+            .as_deref()
+            .unwrap_or_default()
+            .to_lowercase();
+        Self::ALL
+            .into_iter()
+            .filter(|tag| {
+                (*tag == VolunteerTag::VanDriver && help_kind.is(HelpKind::NEED_EVACUATION))
+                    || contains_any(&comments, tag.keywords())
+                    || contact.tags.iter().any(|name| name == tag.label())
+            })
+            .collect()
+    }
+}
+
+/// A tag of the requests, see the module docs.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct RequestTag {
+    pub name: String,
+    /// Parts of words any answer may contain, e.g. `інсулін`.
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    /// Fields any affirmative answer to which tags the request regardless of the words, e.g. the
+    /// question about children.
+    #[serde(default)]
+    pub fields: Vec<String>,
+    /// Chat of the volunteers specialized in the requests with the tag; the bot must be its member.
+    #[serde(default)]
+    pub chat_id: Option<i64>,
+}
+
+impl RequestTag {
+    fn new(name: &str, keywords: &[&str], fields: &[&str]) -> Self {
+        Self {
+            name: name.to_owned(),
+            keywords: keywords.iter().map(|&keyword| keyword.to_owned()).collect(),
+            fields: fields.iter().map(|&field| field.to_owned()).collect(),
+            chat_id: None,
+        }
+    }
+
+    fn matches(&self, contact: &Contact, answers: &str) -> bool {
+        self.fields
+            .iter()
+            .any(|field| triage::is_affirmative(contact.answer(field).as_deref()))
+            || contains_any(answers, &self.keywords)
+    }
+}
+
+/// All the answers of the contact, lowercased, for the keywords of the request tags to be looked
+/// up in.
+fn lowercased_answers(texts: &Texts, contact: &Contact) -> String {
+    contact
+        .summary_fields(texts, Language::default())
+        .into_iter()
+        .map(|(_, answer)| answer)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .to_lowercase()
+}
+
+fn contains_any(answers: &str, keywords: &[impl AsRef<str>]) -> bool {
+    keywords
+        .iter()
+        .any(|keyword| answers.contains(&keyword.as_ref().to_lowercase()))
+}
+
+/// The request tags used when the config does not define any.
+pub fn builtin_request_tags() -> Vec<RequestTag> {
+    vec![
+        RequestTag::new(
+            "діти",
+            &["дитин", "діти", "дітей", "немовл", "малюк"],
+            &["children"],
+        ),
+        RequestTag::new("інсулін", &["інсулін", "insulin", "діабет"], &[]),
+        RequestTag::new(
+            "окупована територія",
+            &["окупов", "окупац", "occupied"],
+            &[],
+        ),
+    ]
+}
+
+/// Names of the configured request tags whose keywords the answers of the contact contain.
+pub fn classify(texts: &Texts, tags: &[RequestTag], contact: &Contact) -> Vec<String> {
+    let answers = lowercased_answers(texts, contact);
+    tags.iter()
+        .filter(|tag| tag.matches(contact, &answers))
+        .map(|tag| tag.name.clone())
+        .collect()
+}
+
+/// Posts the tagged request to the chats of its tags.
+pub async fn post_to_tag_chats(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    contact: &Contact,
+    submission: &TrackedSubmission,
+) -> anyhow::Result<()> {
+    if app_state.skips_external_writes() {
+        return Ok(());
+    }
+    let config = app_state.config();
+    let mut chats: Vec<(i64, Vec<&str>)> = Vec::new();
+    for tag in &config.tags {
+        let chat_id = match tag.chat_id {
+            Some(chat_id) if contact.tags.contains(&tag.name) => chat_id,
+            _ => continue,
+        };
+        match chats.iter_mut().find(|(id, _)| *id == chat_id) {
+            Some((_, names)) => names.push(&tag.name),
+            None => chats.push((chat_id, vec![&tag.name])),
+        }
+    }
+    for (chat_id, names) in chats {
+        bot.send_message(
+            chat_id,
+            format!(
+                "{} — заявка {} ({})\n\n{}",
+                html::bold(&names.join(", ")),
+                html::code(&submission.reference_id),
+                html::escape(config.help_kind_title(&submission.help_kind)),
+                contact.summary_html(&app_state.texts(), Language::default()),
+            ),
+        )
+        .parse_mode(ParseMode::Html)
+        .await?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct VolunteerRegistration {
+    full_name: Option<String>,
+    phone_numbers: Option<String>,
+    referral: Option<String>,
+    tags: Vec<VolunteerTag>,
+    tags_selected: bool,
+    /// Id of the message with the document photo/file, `None` if the volunteer has none.
+    documents_message_id: Option<i32>,
+}
+
+impl VolunteerRegistration {
+    fn tags_text(&self) -> String {
+        if self.tags.is_empty() {
+            "-".to_owned()
+        } else {
+            self.tags
+                .iter()
+                .map(|tag| tag.label())
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "ПІБ: {}\nКонтактні номери телефону: {}\nХто може поручитися: {}\nНавички та ресурси: {}\nДокументи: {}",
+            self.full_name.as_deref().unwrap_or("-"),
+            self.phone_numbers.as_deref().unwrap_or("-"),
+            self.referral.as_deref().unwrap_or("-"),
+            self.tags_text(),
+            if self.documents_message_id.is_some() {
+                "надіслано (див. повідомлення вище)"
+            } else {
+                "немає"
+            },
+        )
+    }
+}
+
+pub async fn is_verified(
+    mut redis: redis::aio::MultiplexedConnection,
+    user_id: i64,
+) -> anyhow::Result<bool> {
+    use redis::AsyncCommands;
+
+    Ok(redis
+        .sismember(redis_connection::key(VERIFIED_VOLUNTEERS_KEY), user_id)
+        .await?)
+}
+
+/// Remembers the user of a volunteer by the username, if the volunteer has one.
+pub async fn remember_username(
+    mut redis: redis::aio::MultiplexedConnection,
+    user: &User,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    if let Some(username) = &user.username {
+        let _: () = redis
+            .hset(
+                redis_connection::key(USERNAMES_KEY),
+                username.to_lowercase(),
+                serde_json::to_string(user)?,
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// The user of the volunteer with the username, with or without the leading `@`.
+pub async fn find_by_username(
+    mut redis: redis::aio::MultiplexedConnection,
+    username: &str,
+) -> anyhow::Result<Option<User>> {
+    use redis::AsyncCommands;
+
+    let user: Option<String> = redis
+        .hget(
+            redis_connection::key(USERNAMES_KEY),
+            username.trim_start_matches('@').to_lowercase(),
+        )
+        .await?;
+    Ok(user.map(|user| serde_json::from_str(&user)).transpose()?)
+}
+
+#[tracing::instrument(skip_all, fields(chat_id = msg.chat.id))]
+pub async fn handle_awaiting_volunteer_registration(
+    bot: ThrottledBot,
+    msg: Message,
+    app_state: std::sync::Arc<AppState>,
+    dialogue: MyDialogue,
+    language: Language,
+    (mut registration,): (VolunteerRegistration,),
+) -> anyhow::Result<()> {
+    let texts = app_state.texts();
+    let done_button = texts.get(language, "button_tags_done");
+    if msg.text() == Some(texts.get(language, "button_back")) {
+        dialogue.update(State::Start).await?;
+        bot.send_message(
+            msg.chat.id,
+            texts.get(language, "volunteer_registration_cancelled"),
+        )
+        .reply_markup(start_keyboard(&texts, language))
+        .await?;
+        return Ok(());
+    }
+
+    if registration.full_name.is_none() {
+        let text = if let Some(text) = msg.text() {
+            text
+        } else {
+            bot.send_message(msg.chat.id, texts.get(language, "volunteer_full_name"))
+                .await?;
+            return Ok(());
+        };
+        registration.full_name = Some(text.to_owned());
+        dialogue
+            .update(State::AwaitingVolunteerRegistration { registration })
+            .await?;
+        bot.send_message(msg.chat.id, texts.get(language, "volunteer_phone_numbers"))
+            .await?;
+    } else if registration.phone_numbers.is_none() {
+        let text = if let Some(text) = msg.text() {
+            text
+        } else {
+            bot.send_message(msg.chat.id, texts.get(language, "volunteer_phone_numbers"))
+                .await?;
+            return Ok(());
+        };
+        registration.phone_numbers = Some(text.to_owned());
+        dialogue
+            .update(State::AwaitingVolunteerRegistration { registration })
+            .await?;
+        bot.send_message(msg.chat.id, texts.get(language, "volunteer_referral"))
+            .await?;
+    } else if registration.referral.is_none() {
+        let text = if let Some(text) = msg.text() {
+            text
+        } else {
+            bot.send_message(msg.chat.id, texts.get(language, "volunteer_referral"))
+                .await?;
+            return Ok(());
+        };
+        registration.referral = Some(text.to_owned());
+        dialogue
+            .update(State::AwaitingVolunteerRegistration { registration })
+            .await?;
+        bot.send_message(
+            msg.chat.id,
+            texts.render(language, "volunteer_tags", &[("done_button", &done_button)]),
+        )
+        .reply_markup(tags_keyboard(&texts, language))
+        .await?;
+    } else if !registration.tags_selected {
+        match msg.text() {
+            Some(text) if text == done_button => {
+                registration.tags_selected = true;
+                dialogue
+                    .update(State::AwaitingVolunteerRegistration { registration })
+                    .await?;
+                bot.send_message(msg.chat.id, texts.get(language, "volunteer_documents"))
+                    .reply_markup(back_keyboard(&texts, language))
+                    .await?;
+            }
+            Some(label) if VolunteerTag::from_label(label).is_some() => {
+                let tag = VolunteerTag::from_label(label).unwrap();
+                if let Some(position) = registration.tags.iter().position(|t| *t == tag) {
+                    registration.tags.remove(position);
+                } else {
+                    registration.tags.push(tag);
+                }
+                let tags_text = registration.tags_text();
+                dialogue
+                    .update(State::AwaitingVolunteerRegistration { registration })
+                    .await?;
+                bot.send_message(
+                    msg.chat.id,
+                    texts.render(language, "volunteer_tags_selected", &[("tags", &tags_text)]),
+                )
+                .reply_markup(tags_keyboard(&texts, language))
+                .await?;
+            }
+            _ => {
+                bot.send_message(
+                    msg.chat.id,
+                    texts.render(
+                        language,
+                        "volunteer_tags_invalid",
+                        &[("done_button", &done_button)],
+                    ),
+                )
+                .reply_markup(tags_keyboard(&texts, language))
+                .await?;
+            }
+        }
+    } else {
+        if msg.photo().is_some() || msg.document().is_some() {
+            registration.documents_message_id = Some(msg.id);
+        } else if msg.text() != Some("-") {
+            bot.send_message(
+                msg.chat.id,
+                texts.get(language, "volunteer_documents_invalid"),
+            )
+            .await?;
+            return Ok(());
+        }
+        submit_registration(&bot, &app_state, &msg, &registration).await?;
+        dialogue.update(State::Start).await?;
+        bot.send_message(
+            msg.chat.id,
+            texts.get(language, "volunteer_registration_sent"),
+        )
+        .reply_markup(start_keyboard(&texts, language))
+        .await?;
+    }
+
+    Ok(())
+}
+
+fn tags_keyboard(texts: &Texts, language: Language) -> teloxide::types::KeyboardMarkup {
+    teloxide::types::KeyboardMarkup::new(vec![
+        VolunteerTag::ALL
+            .iter()
+            .map(|tag| teloxide::types::KeyboardButton::new(tag.label()))
+            .collect::<Vec<_>>(),
+        vec![
+            teloxide::types::KeyboardButton::new(texts.get(language, "button_tags_done")),
+            teloxide::types::KeyboardButton::new(texts.get(language, "button_back")),
+        ],
+    ])
+}
+
+async fn submit_registration(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    msg: &Message,
+    registration: &VolunteerRegistration,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let user_id = match msg.from() {
+        Some(user) => {
+            remember_username(app_state.redis.clone(), user).await?;
+            user.id
+        }
+        None => anyhow::bail!("volunteer registration message has no sender"),
+    };
+    let _: () = app_state
+        .redis
+        .clone()
+        .hset(
+            redis_connection::key(REGISTRATIONS_KEY),
+            user_id,
+            serde_json::to_string(registration)?,
+        )
+        .await?;
+
+    let admin_chat_id = match app_state.admin_chat_id {
+        Some(admin_chat_id) => admin_chat_id,
+        None => {
+            tracing::warn!(
+                "Volunteer registration of {} cannot be verified without the admin group",
+                user_id
+            );
+            return Ok(());
+        }
+    };
+    if let Some(documents_message_id) = registration.documents_message_id {
+        bot.forward_message(admin_chat_id, msg.chat.id, documents_message_id)
+            .await?;
+    }
+    bot.send_message(
+        admin_chat_id,
+        format!(
+            "Нова реєстрація волонтера (Telegram id {user_id})\n\n{}",
+            registration.summary()
+        ),
+    )
+    .reply_markup(InlineKeyboardMarkup::new(vec![vec![
+        InlineKeyboardButton::callback(
+            "Підтвердити".to_owned(),
+            format!("{CALLBACK_PREFIX}verify:{user_id}"),
+        ),
+        InlineKeyboardButton::callback(
+            "Відхилити".to_owned(),
+            format!("{CALLBACK_PREFIX}reject:{user_id}"),
+        ),
+    ]]))
+    .await?;
+    Ok(())
+}
+
+pub fn is_volunteer_callback(query: CallbackQuery) -> bool {
+    query
+        .data
+        .as_deref()
+        .is_some_and(|data| data.starts_with(CALLBACK_PREFIX))
+}
+
+#[tracing::instrument(skip_all, fields(user_id = query.from.id, data = ?query.data))]
+pub async fn handle_callback(
+    bot: ThrottledBot,
+    query: CallbackQuery,
+    app_state: std::sync::Arc<AppState>,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let message = match &query.message {
+        Some(message) if Some(message.chat.id) == app_state.admin_chat_id => message,
+        _ => {
+            bot.answer_callback_query(query.id)
+                .text("Перевіряти волонтерів можна лише в групі координаторів.")
+                .await?;
+            return Ok(());
+        }
+    };
+    let data = query.data.as_deref().unwrap_or_default();
+    let (action, user_id) = match data
+        .strip_prefix(CALLBACK_PREFIX)
+        .and_then(|data| data.split_once(':'))
+        .and_then(|(action, user_id)| Some((action, user_id.parse::<i64>().ok()?)))
+    {
+        Some(parsed) => parsed,
+        None => {
+            tracing::warn!("volunteers: unexpected callback data {:?}", data);
+            bot.answer_callback_query(query.id).await?;
+            return Ok(());
+        }
+    };
+
+    let mut redis = app_state.redis.clone();
+    let registration: Option<String> = redis
+        .hget(redis_connection::key(REGISTRATIONS_KEY), user_id)
+        .await?;
+    let tags = match registration {
+        Some(registration) => serde_json::from_str::<VolunteerRegistration>(&registration)?.tags,
+        None => Vec::new(),
+    };
+    let (outcome, audit_action) = match action {
+        "verify" => {
+            let _: () = redis
+                .sadd(redis_connection::key(VERIFIED_VOLUNTEERS_KEY), user_id)
+                .await?;
+            for tag in &tags {
+                let _: () = redis.sadd(tag.volunteers_key(), user_id).await?;
+            }
+            let language = texts::language_of(app_state.redis.clone(), user_id).await;
+            bot.send_message(
+                user_id,
+                app_state.texts().get(language, "volunteer_verified"),
+            )
+            .await?;
+            ("підтверджено", audit::Action::VolunteerApproved)
+        }
+        "reject" => {
+            let _: () = redis
+                .srem(redis_connection::key(VERIFIED_VOLUNTEERS_KEY), user_id)
+                .await?;
+            for tag in &tags {
+                let _: () = redis.srem(tag.volunteers_key(), user_id).await?;
+            }
+            let language = texts::language_of(app_state.redis.clone(), user_id).await;
+            bot.send_message(
+                user_id,
+                app_state.texts().get(language, "volunteer_rejected"),
+            )
+            .await?;
+            ("відхилено", audit::Action::VolunteerRejected)
+        }
+        _ => {
+            tracing::warn!("volunteers: unexpected callback action {:?}", action);
+            bot.answer_callback_query(query.id).await?;
+            return Ok(());
+        }
+    };
+
+    audit::record(
+        &app_state,
+        &assignment::volunteer_display_name(&query.from),
+        audit_action,
+        &user_id.to_string(),
+        &tags
+            .iter()
+            .map(|tag| tag.label())
+            .collect::<Vec<_>>()
+            .join(", "),
+    )
+    .await;
+    bot.edit_message_text(
+        message.chat.id,
+        message.id,
+        format!(
+            "{}\n\nРеєстрацію {outcome} ({})",
+            message.text().unwrap_or_default(),
+            query.from.full_name()
+        ),
+    )
+    .await?;
+    bot.answer_callback_query(query.id).await?;
+    Ok(())
+}
+
+/// Sends a new request to the verified volunteers whose tags match it, or to the volunteers chat
+/// if nobody in particular matches.
+#[tracing::instrument(skip_all, fields(reference_id = %submission.reference_id))]
+pub async fn notify_matching_volunteers(
+    bot: &ThrottledBot,
+    app_state: &AppState,
+    contact: &Contact,
+    submission: &TrackedSubmission,
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let tags = VolunteerTag::infer(&submission.help_kind, contact);
+    let mut volunteer_ids = std::collections::BTreeSet::new();
+    for tag in &tags {
+        let tagged: Vec<i64> = app_state
+            .redis
+            .clone()
+            .smembers(tag.volunteers_key())
+            .await?;
+        volunteer_ids.extend(tagged);
+    }
+
+    let texts = app_state.texts();
+    let help_kind = app_state
+        .config()
+        .help_kind_title(&submission.help_kind)
+        .to_owned();
+    let needed = tags
+        .iter()
+        .map(|tag| tag.label())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let summary = contact.summary(&texts, Language::default());
+    let map_url = contact.map_url();
+    let render = |language: Language| {
+        let mut text = texts.render(
+            language,
+            "volunteers_new_request",
+            &[
+                ("reference_id", &submission.reference_id),
+                ("help_kind", &help_kind),
+                ("tags", &needed),
+                ("summary", &summary),
+            ],
+        );
+        if let Some(map_url) = &map_url {
+            text.push('\n');
+            text.push_str(&texts.render(
+                language,
+                "volunteers_new_request_map",
+                &[("map_url", map_url)],
+            ));
+        }
+        text
+    };
+    // The reposts of the SLA watcher go to the volunteers chat, in the default language.
+    let text = render(Language::default());
+    sla::watch(app_state.redis.clone(), submission, &text).await?;
+    if volunteer_ids.is_empty() {
+        if let Some(volunteers_chat_id) = app_state.volunteers_chat_id {
+            retry::on_flood(|| {
+                bot.send_message(volunteers_chat_id, text.clone())
+                    .reply_markup(assignment::assignment_keyboard(
+                        &texts,
+                        Language::default(),
+                        &submission.reference_id,
+                    ))
+            })
+            .await?;
+        }
+        return Ok(());
+    }
+    for volunteer_id in volunteer_ids {
+        let chat_id = match app_state.notified_chat(volunteer_id) {
+            Some(chat_id) => chat_id,
+            None => continue,
+        };
+        let language = texts::language_of(app_state.redis.clone(), volunteer_id).await;
+        let text = render(language);
+        if let Err(err) =
+            retry::on_flood(|| {
+                bot.send_message(chat_id, text.clone()).reply_markup(
+                    assignment::assignment_keyboard(&texts, language, &submission.reference_id),
+                )
+            })
+            .await
+        {
+            tracing::warn!(
+                "Failed to notify volunteer {} about {}: {:?}",
+                volunteer_id,
+                submission.reference_id,
+                err
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keywords_and_affirmative_answers_tag_the_request() {
+        let texts = Texts::from_env().unwrap();
+        let contact = Contact {
+            children: Some("2, 5 і 7 років".to_owned()),
+            comments: Some("Потрібен Інсулін, село окуповане".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            classify(&texts, &builtin_request_tags(), &contact),
+            ["діти", "інсулін", "окупована територія"]
+        );
+        let contact = Contact {
+            children: Some("-".to_owned()),
+            comments: Some("-".to_owned()),
+            ..Default::default()
+        };
+        assert!(classify(&texts, &builtin_request_tags(), &contact).is_empty());
+    }
+
+    #[test]
+    fn request_tags_named_as_volunteer_tags_route_to_the_volunteers() {
+        let contact = Contact {
+            tags: vec!["переклад".to_owned()],
+            ..Default::default()
+        };
+        assert_eq!(
+            VolunteerTag::infer(&HelpKind::new("need_food"), &contact),
+            [VolunteerTag::Translation]
+        );
+    }
+}
@@ -0,0 +1,164 @@
+//! Webhook mode, in which Telegram pushes updates to an embedded HTTP server instead of the bot
+//! long polling for them, so that several instances can run behind a load balancer.
+//!
+//! The mode is enabled by `COLLECT_VOLUNTEERS_BOT_WEBHOOK_URL`, the public HTTPS URL Telegram
+//! sends updates to. The server listens on `COLLECT_VOLUNTEERS_BOT_WEBHOOK_LISTEN_ADDR`
+//! (`0.0.0.0:8080` by default) at the path of that URL. Telegram puts
+//! `COLLECT_VOLUNTEERS_BOT_WEBHOOK_SECRET` (1-256 characters `A-Z`, `a-z`, `0-9`, `_` and `-`)
+//! into a header of every request, and requests without it are rejected.
+
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::Extension,
+    http::{HeaderMap, StatusCode},
+    routing::post,
+    Json, Router,
+};
+use teloxide::{
+    dispatching::{
+        stop_token::AsyncStopToken,
+        update_listeners::{StatefulListener, UpdateListener},
+    },
+    prelude2::*,
+};
+
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+use crate::ThrottledBot;
+
+const SECRET_TOKEN_HEADER: &str = "X-Telegram-Bot-Api-Secret-Token";
+
+pub struct WebhookConfig {
+    url: reqwest::Url,
+    listen_addr: SocketAddr,
+    secret: String,
+}
+
+impl WebhookConfig {
+    /// `None` unless the webhook mode is enabled.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let url = match std::env::var("COLLECT_VOLUNTEERS_BOT_WEBHOOK_URL") {
+            Ok(url) => url.parse()?,
+            Err(_) => return Ok(None),
+        };
+        let listen_addr = match std::env::var("COLLECT_VOLUNTEERS_BOT_WEBHOOK_LISTEN_ADDR") {
+            Ok(listen_addr) => listen_addr.parse()?,
+            Err(_) => ([0, 0, 0, 0], 8080).into(),
+        };
+        let secret = std::env::var("COLLECT_VOLUNTEERS_BOT_WEBHOOK_SECRET").map_err(|_| {
+            anyhow::anyhow!("COLLECT_VOLUNTEERS_BOT_WEBHOOK_SECRET is required in the webhook mode")
+        })?;
+        let valid_secret = (1..=256).contains(&secret.len())
+            && secret
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid_secret {
+            anyhow::bail!(
+                "COLLECT_VOLUNTEERS_BOT_WEBHOOK_SECRET must be 1-256 characters of A-Z, a-z, 0-9, _ and -"
+            );
+        }
+        Ok(Some(Self {
+            url,
+            listen_addr,
+            secret,
+        }))
+    }
+}
+
+struct Receiver {
+    secret: String,
+    updates: mpsc::UnboundedSender<Result<Update, Infallible>>,
+}
+
+/// Registers the webhook with Telegram and starts the server receiving the updates.
+pub async fn listener(
+    bot: &ThrottledBot,
+    config: WebhookConfig,
+) -> anyhow::Result<impl UpdateListener<Infallible>> {
+    set_webhook(bot, &config).await?;
+
+    let (updates, received) = mpsc::unbounded_channel();
+    let app = Router::new()
+        .route(config.url.path(), post(receive))
+        .layer(Extension(Arc::new(Receiver {
+            secret: config.secret,
+            updates,
+        })));
+    let (stop_token, stop_flag) = AsyncStopToken::new_pair();
+    let server = axum::Server::try_bind(&config.listen_addr)?
+        .serve(app.into_make_service())
+        .with_graceful_shutdown(stop_flag);
+    tracing::info!("Listening for webhook requests on {}", config.listen_addr);
+    tokio::spawn(async move {
+        if let Err(err) = server.await {
+            tracing::error!("Webhook server failed: {:?}", err);
+        }
+    });
+
+    fn stream<S, T>(state: &mut (S, T)) -> &mut S {
+        &mut state.0
+    }
+    Ok(StatefulListener::new(
+        (UnboundedReceiverStream::new(received), stop_token),
+        stream,
+        |state: &mut (_, AsyncStopToken)| state.1.clone(),
+    ))
+}
+
+/// Calls `setWebhook` directly, as the secret token is not supported by the request builders of
+/// the Telegram client.
+async fn set_webhook(bot: &ThrottledBot, config: &WebhookConfig) -> anyhow::Result<()> {
+    #[derive(serde::Deserialize)]
+    struct Response {
+        ok: bool,
+        #[serde(default)]
+        description: Option<String>,
+    }
+
+    let response: Response = reqwest::Client::new()
+        .post(format!(
+            "https://api.telegram.org/bot{}/setWebhook",
+            bot.inner().inner().token()
+        ))
+        .json(&serde_json::json!({
+            "url": config.url.as_str(),
+            "secret_token": config.secret,
+        }))
+        .send()
+        .await?
+        .json()
+        .await?;
+    if !response.ok {
+        anyhow::bail!(
+            "Telegram refused to set the webhook: {}",
+            response.description.unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+async fn receive(
+    Extension(receiver): Extension<Arc<Receiver>>,
+    headers: HeaderMap,
+    Json(update): Json<serde_json::Value>,
+) -> StatusCode {
+    let secret = headers
+        .get(SECRET_TOKEN_HEADER)
+        .and_then(|secret| secret.to_str().ok());
+    if secret != Some(receiver.secret.as_str()) {
+        tracing::warn!("webhook: rejected a request without the secret token");
+        return StatusCode::UNAUTHORIZED;
+    }
+    match serde_json::from_value(update) {
+        Ok(update) => {
+            if receiver.updates.send(Ok(update)).is_err() {
+                return StatusCode::SERVICE_UNAVAILABLE;
+            }
+        }
+        // Telegram would keep resending an update which is not acknowledged.
+        Err(err) => tracing::warn!("webhook: failed to parse an update: {:?}", err),
+    }
+    StatusCode::OK
+}
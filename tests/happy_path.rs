@@ -0,0 +1,84 @@
+//! The whole dialogue of offering a useful contact, with the dialogue states and the bookkeeping in
+//! a Redis container, down to the row appended to the spreadsheet.
+
+use std::sync::Arc;
+
+use chrono::Datelike;
+use connect_volunteers_bot::{
+    config::Config,
+    testing::{FakeSink, Harness},
+    HelpKind, Language, State,
+};
+use testcontainers::{clients, images::redis::Redis};
+
+const CHAT_ID: i64 = 42;
+
+#[tokio::test]
+async fn confirmed_submission_is_appended() -> anyhow::Result<()> {
+    let docker = clients::Cli::default();
+    let redis = docker.run(Redis);
+    let redis_url = format!("redis://127.0.0.1:{}", redis.get_host_port_ipv4(6379));
+    let sink = Arc::new(FakeSink::default());
+    let harness = Harness::with_redis(Config::default(), sink.clone(), &redis_url).await?;
+
+    let config = harness.app_state.config();
+    let definition = config
+        .help_kind(&HelpKind::new(HelpKind::PROVIDING_USEFUL_CONTACT))
+        .expect("the built-in help kind is configured");
+    let label = definition
+        .label_in(&harness.app_state.texts(), Language::Uk)
+        .to_owned();
+    let text = |id: &str| harness.text(Language::Uk, id);
+
+    harness.send_text(CHAT_ID, &text("button_can_help")).await?;
+    harness.send_text(CHAT_ID, &label).await?;
+    harness.send_text(CHAT_ID, &text("button_consent")).await?;
+    for answer in [
+        "Тарас Шевченко",
+        "+380501234567",
+        "Київська",
+        "Бровари",
+        "вул. Київська",
+        "1",
+        "-",
+        "Передзвоніть після 18:00",
+    ] {
+        let sent = harness.send_text(CHAT_ID, answer).await?;
+        assert_eq!(sent.len(), 1, "one reply to {answer:?}");
+    }
+    assert!(
+        sink.rows().is_empty(),
+        "nothing is saved before confirmation"
+    );
+    harness.send_text(CHAT_ID, &text("button_confirm")).await?;
+
+    let rows = sink.rows();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(
+        rows[0].destination,
+        config.destination(definition, Some("Київська"))
+    );
+    let row = &rows[0].row;
+    let year = connect_volunteers_bot::config::local_now().year();
+    assert_eq!(
+        row[0],
+        format!("'{}-{year}-00001", definition.reference_prefix)
+    );
+    assert_eq!(row[3], "'Тарас Шевченко");
+    assert_eq!(row[4], "'+380501234567");
+    assert_eq!(row[5], "'вул. Київська, 1");
+    assert_eq!(row[6], "'Передзвоніть після 18:00");
+    assert_eq!(row[17], "'Київська");
+    assert_eq!(row[18], "'Бровари");
+    assert_eq!(row[19], "uk");
+    assert!(!row[21].is_empty(), "the consent is recorded");
+    assert_eq!(row[22], CHAT_ID.to_string());
+    assert_eq!(row[24], format!("'tg://user?id={CHAT_ID}"));
+    assert!(
+        row[29].starts_with("https://www.google.com/maps/search/?api=1&query="),
+        "the address is linked on the map"
+    );
+
+    assert!(matches!(harness.state(CHAT_ID).await?, Some(State::Start)));
+    Ok(())
+}